@@ -0,0 +1,77 @@
+//! Server announcements pushed to every connected WebSocket client.
+//!
+//! Each socket subscribes when it connects; an announcement made while no
+//! socket is connected is dropped.
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Announcements buffered per socket before a slow one starts missing them.
+const ANNOUNCEMENT_CAPACITY: usize = 16;
+
+/// How prominently clients should show an announcement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+/// A message from the operators, e.g. a maintenance notice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+    pub message: String,
+    pub severity: Severity,
+}
+
+/// Fans announcements out to every subscribed socket.
+#[derive(Clone)]
+pub struct Announcer {
+    sender: broadcast::Sender<Announcement>,
+}
+
+impl Announcer {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(ANNOUNCEMENT_CAPACITY);
+        Self { sender }
+    }
+
+    /// Send to every current subscriber, returning how many there were.
+    pub fn announce(&self, announcement: Announcement) -> usize {
+        self.sender.send(announcement).unwrap_or(0)
+    }
+
+    /// Receive announcements made from now on.
+    pub fn subscribe(&self) -> Announcements {
+        Announcements(self.sender.subscribe())
+    }
+}
+
+impl Default for Announcer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One socket's view of the announcements.
+pub struct Announcements(broadcast::Receiver<Announcement>);
+
+impl Announcements {
+    /// The next announcement.
+    ///
+    /// A socket that fell behind skips what it missed; once the announcer is
+    /// gone this never resolves.
+    pub async fn recv(&mut self) -> Announcement {
+        loop {
+            match self.0.recv().await {
+                Ok(announcement) => return announcement,
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::debug!("Socket missed {} announcements", missed);
+                }
+                Err(RecvError::Closed) => std::future::pending().await,
+            }
+        }
+    }
+}
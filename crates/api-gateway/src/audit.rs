@@ -0,0 +1,26 @@
+//! Recording user actions in the audit log.
+
+use rustyclint_common::{db::AuditRepo, models::AuditEventType};
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Record an action on a project.
+///
+/// The action has already happened, so a failure to log it is reported
+/// rather than surfaced to the client.
+pub async fn record(
+    db: &PgPool,
+    actor_id: Uuid,
+    project_id: Uuid,
+    event_type: AuditEventType,
+    details: Value,
+) {
+    if let Err(e) = AuditRepo::record(db, actor_id, Some(project_id), event_type, &details).await {
+        tracing::warn!(
+            "Failed to record {} audit event: {}",
+            event_type.as_str(),
+            e
+        );
+    }
+}
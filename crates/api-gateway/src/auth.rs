@@ -1,5 +1,7 @@
 //! Authentication and authorization.
 
+use std::{fmt, sync::Arc, time::Duration};
+
 use async_trait::async_trait;
 use axum::{
     extract::FromRequestParts,
@@ -9,9 +11,10 @@ use axum::{
 };
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-use crate::state::AppState;
+use crate::{state::AppState, store::KvStore};
 
 /// JWT claims structure.
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,6 +23,182 @@ pub struct Claims {
     pub email: String,
     pub exp: usize,
     pub iat: usize,
+    /// Kind of token; tokens issued before this claim existed are access tokens.
+    #[serde(default)]
+    pub typ: TokenKind,
+    /// Unique id of the token, used to revoke it. Nil for tokens issued
+    /// before this claim existed, which can't be revoked.
+    #[serde(default)]
+    pub jti: Uuid,
+}
+
+/// What a token may be used for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    #[default]
+    Access,
+    Refresh,
+}
+
+/// A signed JWT of a particular [`TokenKind`].
+pub trait Token: Sized {
+    const KIND: TokenKind;
+
+    fn from_raw(raw: String) -> Self;
+
+    fn as_str(&self) -> &str;
+
+    /// Parse a token, checking it has the three base64url segments of a JWT.
+    fn parse(raw: &str) -> Result<Self, AuthError> {
+        let segments: Vec<&str> = raw.split('.').collect();
+        let well_formed = segments.len() == 3
+            && segments.iter().all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+            });
+
+        if well_formed {
+            Ok(Self::from_raw(raw.to_string()))
+        } else {
+            Err(AuthError::InvalidToken)
+        }
+    }
+
+    /// Check the signature and expiry, and that the token is of this kind.
+    fn verify(&self, secret: &str) -> Result<Claims, AuthError> {
+        let token_data = decode::<Claims>(
+            self.as_str(),
+            &DecodingKey::from_secret(secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map_err(|_| AuthError::InvalidToken)?;
+
+        if token_data.claims.typ != Self::KIND {
+            return Err(AuthError::InvalidToken);
+        }
+
+        Ok(token_data.claims)
+    }
+}
+
+/// Short-lived token sent as `Authorization: Bearer` on API requests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AccessToken(String);
+
+/// Long-lived token that may only be exchanged for a new access token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RefreshToken(String);
+
+impl Token for AccessToken {
+    const KIND: TokenKind = TokenKind::Access;
+
+    fn from_raw(raw: String) -> Self {
+        Self(raw)
+    }
+
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Token for RefreshToken {
+    const KIND: TokenKind = TokenKind::Refresh;
+
+    fn from_raw(raw: String) -> Self {
+        Self(raw)
+    }
+
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl RefreshToken {
+    /// Hex SHA-256 of the token, the form it is stored in so the database
+    /// never holds usable tokens.
+    pub fn digest(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl From<String> for AccessToken {
+    fn from(raw: String) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<String> for RefreshToken {
+    fn from(raw: String) -> Self {
+        Self(raw)
+    }
+}
+
+impl fmt::Display for AccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for RefreshToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Access tokens revoked before they expire.
+///
+/// Each revoked token's id is kept only until the token would have expired
+/// anyway, after which its signature check rejects it.
+#[derive(Clone)]
+pub struct RevokedTokens {
+    store: Arc<dyn KvStore>,
+    /// Accept tokens when the store can't be checked, instead of rejecting.
+    fail_open: bool,
+}
+
+impl RevokedTokens {
+    pub fn new(store: Arc<dyn KvStore>) -> Self {
+        Self {
+            store,
+            fail_open: false,
+        }
+    }
+
+    /// Accept tokens while the store is unreachable. Keeps users signed in
+    /// through a Redis outage, but lets revoked tokens back in meanwhile.
+    pub fn with_fail_open(mut self, fail_open: bool) -> Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// Store key marking the token `jti` as revoked.
+    pub fn key(jti: Uuid) -> String {
+        format!("revoked-token:{}", jti)
+    }
+
+    /// Revoke the token `jti`, which expires at the Unix time `exp`.
+    pub async fn revoke(&self, jti: Uuid, exp: usize) -> anyhow::Result<()> {
+        let remaining = exp as i64 - chrono::Utc::now().timestamp();
+        if jti.is_nil() || remaining <= 0 {
+            return Ok(());
+        }
+        self.store
+            .set_ex(&Self::key(jti), b"1", Duration::from_secs(remaining as u64))
+            .await
+    }
+
+    /// Whether the token `jti` has been revoked.
+    pub async fn is_revoked(&self, jti: Uuid) -> anyhow::Result<bool> {
+        if jti.is_nil() {
+            return Ok(false);
+        }
+        Ok(self.store.get(&Self::key(jti)).await?.is_some())
+    }
 }
 
 /// Authenticated user extracted from JWT.
@@ -27,6 +206,10 @@ pub struct Claims {
 pub struct AuthUser {
     pub id: Uuid,
     pub email: String,
+    /// Id of the access token the request was made with.
+    pub token_id: Uuid,
+    /// When that token expires, as a Unix time.
+    pub token_expires_at: usize,
 }
 
 #[async_trait]
@@ -47,35 +230,100 @@ impl FromRequestParts<AppState> for AuthUser {
             .strip_prefix("Bearer ")
             .ok_or(AuthError::InvalidToken)?;
 
-        let token_data = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(state.config.jwt_secret.as_bytes()),
-            &Validation::default(),
+        let claims = authenticate(
+            &AccessToken::parse(token)?,
+            &state.config.jwt_secret,
+            &state.revoked_tokens,
         )
-        .map_err(|_| AuthError::InvalidToken)?;
+        .await?;
 
         Ok(AuthUser {
-            id: token_data.claims.sub,
-            email: token_data.claims.email,
+            id: claims.sub,
+            email: claims.email,
+            token_id: claims.jti,
+            token_expires_at: claims.exp,
         })
     }
 }
 
-/// Create a new JWT token for a user.
+/// Verify an access token and check it has not been revoked.
+pub async fn authenticate(
+    token: &AccessToken,
+    secret: &str,
+    revoked_tokens: &RevokedTokens,
+) -> Result<Claims, AuthError> {
+    let claims = token.verify(secret)?;
+
+    // A revoked token must not slip through while Redis is down, unless the
+    // operator chose availability over that
+    match revoked_tokens.is_revoked(claims.jti).await {
+        Ok(true) => Err(AuthError::InvalidToken),
+        Ok(false) => Ok(claims),
+        Err(e) if revoked_tokens.fail_open => {
+            tracing::warn!("Token revocation check failed; accepting token: {}", e);
+            Ok(claims)
+        }
+        Err(e) => {
+            tracing::error!("Token revocation check failed; rejecting token: {}", e);
+            Err(AuthError::Unavailable)
+        }
+    }
+}
+
+/// Authenticated user listed in `admin_user_ids`.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+
+        if !state.config.admin_user_ids.contains(&user.id) {
+            return Err(AuthError::NotAdmin);
+        }
+
+        Ok(AdminUser(user))
+    }
+}
+
+/// Create a new access token for a user.
 pub fn create_token(
     user_id: Uuid,
     email: &str,
     secret: &str,
     expiry_hours: u64,
-) -> Result<String, jsonwebtoken::errors::Error> {
+) -> Result<AccessToken, jsonwebtoken::errors::Error> {
+    issue_token(
+        user_id,
+        email,
+        secret,
+        chrono::Duration::hours(expiry_hours as i64),
+    )
+}
+
+/// Sign a token of any kind valid for `lifetime`.
+pub fn issue_token<T: Token>(
+    user_id: Uuid,
+    email: &str,
+    secret: &str,
+    lifetime: chrono::Duration,
+) -> Result<T, jsonwebtoken::errors::Error> {
     let now = chrono::Utc::now();
-    let exp = (now + chrono::Duration::hours(expiry_hours as i64)).timestamp() as usize;
+    let exp = (now + lifetime).timestamp() as usize;
 
     let claims = Claims {
         sub: user_id,
         email: email.to_string(),
         exp,
         iat: now.timestamp() as usize,
+        typ: T::KIND,
+        jti: Uuid::new_v4(),
     };
 
     encode(
@@ -83,6 +331,7 @@ pub fn create_token(
         &claims,
         &EncodingKey::from_secret(secret.as_bytes()),
     )
+    .map(T::from_raw)
 }
 
 /// Authentication errors.
@@ -90,6 +339,9 @@ pub fn create_token(
 pub enum AuthError {
     MissingToken,
     InvalidToken,
+    NotAdmin,
+    /// Whether the token was revoked could not be checked.
+    Unavailable,
 }
 
 impl IntoResponse for AuthError {
@@ -97,6 +349,11 @@ impl IntoResponse for AuthError {
         let (status, message) = match self {
             AuthError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing authentication token"),
             AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid authentication token"),
+            AuthError::NotAdmin => (StatusCode::FORBIDDEN, "Administrator access required"),
+            AuthError::Unavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Authentication is temporarily unavailable",
+            ),
         };
 
         (status, Json(serde_json::json!({ "error": message }))).into_response()
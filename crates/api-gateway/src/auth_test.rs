@@ -0,0 +1,102 @@
+//! Tests for token types.
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use uuid::Uuid;
+
+    use crate::{
+        auth::{
+            authenticate, create_token, issue_token, AccessToken, AuthError, RefreshToken,
+            RevokedTokens, Token, TokenKind,
+        },
+        store::MemoryStore,
+    };
+
+    const SECRET: &str = "test-secret";
+
+    #[test]
+    fn test_parse_rejects_malformed_tokens() {
+        assert!(AccessToken::parse("").is_err());
+        assert!(AccessToken::parse("only.two").is_err());
+        assert!(AccessToken::parse("a..c").is_err());
+        assert!(AccessToken::parse("a.b.c d").is_err());
+        assert!(RefreshToken::parse("a.b.c.d").is_err());
+
+        let token = AccessToken::parse("eyJh.eyJz.sig-_0").unwrap();
+        assert_eq!(token.to_string(), "eyJh.eyJz.sig-_0");
+    }
+
+    #[test]
+    fn test_access_token_round_trip() {
+        let user_id = Uuid::new_v4();
+        let token = create_token(user_id, "a@example.com", SECRET, 1).unwrap();
+
+        let claims = AccessToken::parse(token.as_str()).unwrap().verify(SECRET).unwrap();
+
+        assert_eq!(claims.sub, user_id);
+        assert_eq!(claims.typ, TokenKind::Access);
+        assert!(token.verify("other-secret").is_err());
+    }
+
+    #[test]
+    fn test_token_kinds_not_interchangeable() {
+        let user_id = Uuid::new_v4();
+        let refresh: RefreshToken =
+            issue_token(user_id, "a@example.com", SECRET, chrono::Duration::days(30)).unwrap();
+        let access = create_token(user_id, "a@example.com", SECRET, 1).unwrap();
+
+        assert!(refresh.verify(SECRET).is_ok());
+        assert!(AccessToken::from(refresh.to_string()).verify(SECRET).is_err());
+        assert!(RefreshToken::from(access.to_string()).verify(SECRET).is_err());
+    }
+
+    #[test]
+    fn test_refresh_token_stored_as_digest() {
+        let token = RefreshToken::from("eyJh.eyJz.sig".to_string());
+        let digest = token.digest();
+
+        assert_eq!(digest.len(), 64);
+        assert!(!digest.contains(token.as_str()));
+        assert_eq!(digest, RefreshToken::from("eyJh.eyJz.sig".to_string()).digest());
+        assert_ne!(digest, RefreshToken::from("eyJh.eyJz.other".to_string()).digest());
+    }
+
+    #[tokio::test]
+    async fn test_revoked_token_kept_for_remaining_lifetime() {
+        let store = Arc::new(MemoryStore::default());
+        let revoked = RevokedTokens::new(store.clone());
+        let token = create_token(Uuid::new_v4(), "a@example.com", SECRET, 1).unwrap();
+        let claims = token.verify(SECRET).unwrap();
+
+        assert!(!revoked.is_revoked(claims.jti).await.unwrap());
+        revoked.revoke(claims.jti, claims.exp).await.unwrap();
+        assert!(revoked.is_revoked(claims.jti).await.unwrap());
+
+        let ttl = store.ttl(&RevokedTokens::key(claims.jti)).unwrap();
+        assert!(ttl > Duration::from_secs(3590) && ttl <= Duration::from_secs(3600));
+
+        // Every token gets its own id, so revoking one leaves the others
+        let other = create_token(claims.sub, "a@example.com", SECRET, 1).unwrap();
+        let other_jti = other.verify(SECRET).unwrap().jti;
+        assert_ne!(other_jti, claims.jti);
+        assert!(!revoked.is_revoked(other_jti).await.unwrap());
+
+        // Tokens from before ids existed can't be told apart
+        revoked.revoke(Uuid::nil(), claims.exp).await.unwrap();
+        assert!(!revoked.is_revoked(Uuid::nil()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unchecked_revocation_rejected_unless_failing_open() {
+        let token = create_token(Uuid::new_v4(), "a@example.com", SECRET, 1).unwrap();
+        let revoked = RevokedTokens::new(Arc::new(MemoryStore::unreachable()));
+
+        let refused = authenticate(&token, SECRET, &revoked).await.unwrap_err();
+        assert!(matches!(refused, AuthError::Unavailable));
+
+        let revoked = revoked.with_fail_open(true);
+        assert!(authenticate(&token, SECRET, &revoked).await.is_ok());
+    }
+}
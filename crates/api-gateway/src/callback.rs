@@ -0,0 +1,159 @@
+//! Signed completion callbacks for asynchronous executions.
+
+use std::time::Duration;
+
+use axum::body::Bytes;
+use hmac::{Hmac, Mac};
+use http_body_util::Full;
+use hyper::{header, Method, Request, Uri};
+use hyper_rustls::HttpsConnectorBuilder;
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use sha2::Sha256;
+
+/// Header carrying `sha256=<hex HMAC of the body>`.
+pub const SIGNATURE_HEADER: &str = "x-rustyclint-signature";
+
+/// Time allowed for a single delivery attempt.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How failed deliveries are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total delivery attempts, including the first.
+    pub attempts: u32,
+    /// Wait before the first retry; doubled after each failure.
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            initial_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Check a callback URL against the configured allowlist.
+///
+/// An entry allows URLs with the same scheme, host and port whose path lies
+/// under the entry's path: `https://hooks.internal/ci` allows
+/// `https://hooks.internal/ci/done` but not `https://hooks.internal/cicd` or
+/// `https://hooks.internal.evil.example/ci`. Paths with `.` or `..` segments
+/// are refused, since the receiver may resolve them out of the entry's path.
+pub fn validate_callback_url(url: &str, allowlist: &[String]) -> Result<Uri, String> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|_| "Invalid callback URL".to_string())?;
+
+    if default_port(&uri).is_none() || uri.host().is_none() {
+        return Err("Callback URL must be an absolute http:// or https:// URL".into());
+    }
+
+    if uri.path().split('/').any(is_dot_segment) {
+        return Err("Callback URL path must not contain '.' or '..' segments".into());
+    }
+
+    if !allowlist.iter().any(|entry| allows(entry, &uri)) {
+        return Err("Callback URL is not allowlisted".into());
+    }
+
+    Ok(uri)
+}
+
+/// Whether an allowlist entry covers a callback URL.
+fn allows(entry: &str, uri: &Uri) -> bool {
+    let Ok(entry) = entry.parse::<Uri>() else {
+        return false;
+    };
+    let (Some(entry_host), Some(host)) = (entry.host(), uri.host()) else {
+        return false;
+    };
+
+    entry.scheme() == uri.scheme()
+        && entry_host.eq_ignore_ascii_case(host)
+        && entry.port_u16().or(default_port(&entry)) == uri.port_u16().or(default_port(uri))
+        && within_path(entry.path(), uri.path())
+}
+
+/// Port implied by an http(s) scheme; `None` for any other scheme.
+fn default_port(uri: &Uri) -> Option<u16> {
+    match uri.scheme_str() {
+        Some("http") => Some(80),
+        Some("https") => Some(443),
+        _ => None,
+    }
+}
+
+/// Whether a path segment is `.` or `..`, percent-encoded or not.
+fn is_dot_segment(segment: &str) -> bool {
+    let decoded = segment.to_ascii_lowercase().replace("%2e", ".");
+    decoded == "." || decoded == ".."
+}
+
+/// Whether `path` is `prefix` or lies beneath it on a segment boundary.
+fn within_path(prefix: &str, path: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Signature header value for a callback body.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// POST a signed JSON body, retrying with exponential backoff until the
+/// receiver answers with a 2xx status or the attempts run out.
+pub async fn deliver(
+    uri: &Uri,
+    secret: &str,
+    body: Vec<u8>,
+    policy: &RetryPolicy,
+) -> Result<(), String> {
+    let connector = HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let client = Client::builder(TokioExecutor::new()).build::<_, Full<Bytes>>(connector);
+    let signature = sign(secret, &body);
+    let body = Bytes::from(body);
+    let mut backoff = policy.initial_backoff;
+    let mut last_error = String::from("No delivery attempted");
+
+    for attempt in 1..=policy.attempts {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(Full::new(body.clone()))
+            .map_err(|e| e.to_string())?;
+
+        last_error = match tokio::time::timeout(ATTEMPT_TIMEOUT, client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => return Ok(()),
+            Ok(Ok(response)) => format!("Receiver answered {}", response.status()),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "Timed out".to_string(),
+        };
+
+        tracing::warn!(
+            "Callback to {} failed (attempt {}/{}): {}",
+            uri,
+            attempt,
+            policy.attempts,
+            last_error
+        );
+
+        if attempt < policy.attempts {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    Err(last_error)
+}
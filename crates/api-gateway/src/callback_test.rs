@@ -0,0 +1,112 @@
+//! Tests for execution callbacks.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+
+    use crate::callback::{deliver, sign, validate_callback_url, RetryPolicy, SIGNATURE_HEADER};
+
+    type Received = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    async fn receive(
+        State(received): State<Received>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+        let mut received = received.lock().unwrap();
+        received.push((signature, body.to_vec()));
+        // Fail the first delivery to exercise the retry
+        if received.len() == 1 {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    #[tokio::test]
+    async fn test_callback_delivered_and_signed() {
+        let received = Received::default();
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let url = format!("http://{}/hook", addr);
+        let uri = validate_callback_url(&url, &[format!("http://{}/", addr)]).unwrap();
+        let body = br#"{"execution_id":"x","result":null}"#.to_vec();
+        let policy = RetryPolicy {
+            attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+        };
+
+        deliver(&uri, "hook-secret", body.clone(), &policy).await.unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (signature, delivered) = &received[1];
+        assert_eq!(delivered, &body);
+        assert_eq!(signature, &sign("hook-secret", &body));
+        assert_ne!(signature, &sign("other-secret", &body));
+    }
+
+    #[test]
+    fn test_callback_url_must_be_allowlisted() {
+        let allowlist = vec!["http://hooks.internal/".to_string()];
+
+        assert!(validate_callback_url("http://hooks.internal/done", &allowlist).is_ok());
+        assert!(validate_callback_url("http://evil.example/done", &allowlist).is_err());
+        assert!(validate_callback_url("file:///etc/passwd", &allowlist).is_err());
+        assert!(validate_callback_url("http://hooks.internal/done", &[]).is_err());
+    }
+
+    #[test]
+    fn test_callback_url_accepts_https() {
+        let allowlist = vec!["https://hooks.internal".to_string()];
+
+        assert!(validate_callback_url("https://hooks.internal/done", &allowlist).is_ok());
+        assert!(validate_callback_url("https://hooks.internal:443/done", &allowlist).is_ok());
+        assert!(validate_callback_url("http://hooks.internal/done", &allowlist).is_err());
+        assert!(validate_callback_url("https://hooks.internal:8443/done", &allowlist).is_err());
+    }
+
+    #[test]
+    fn test_callback_allowlist_matches_host_and_path_segments() {
+        let allowlist = vec!["http://hooks.internal/ci/".to_string()];
+        let allowed = |url: &str| validate_callback_url(url, &allowlist).is_ok();
+
+        assert!(allowed("http://hooks.internal/ci"));
+        assert!(allowed("http://HOOKS.internal/ci/done"));
+        assert!(!allowed("http://hooks.internal/cicd"));
+        assert!(!allowed("http://hooks.internal.evil.example/ci/"));
+        assert!(!allowed("http://hooks.internal@evil.example/ci/"));
+
+        // Dot segments could climb out of the allowed path
+        assert!(!allowed("http://hooks.internal/ci/../admin"));
+        assert!(!allowed("http://hooks.internal/ci/%2E%2e/admin"));
+        assert!(!allowed("http://hooks.internal/ci/./done"));
+        assert!(allowed("http://hooks.internal/ci/v1..2"));
+    }
+
+    #[test]
+    fn test_signature_is_hex_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! HTTP response compression.
+
+use serde::{Deserialize, Serialize};
+use tower_http::compression::{
+    predicate::{And, NotForContentType, Predicate, SizeAbove},
+    CompressionLayer, CompressionLevel,
+};
+
+use crate::config::Config;
+
+/// An encoding the gateway may compress responses with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionEncoding {
+    Gzip,
+    Deflate,
+}
+
+/// Which responses get compressed: large enough, and not already-compressed
+/// or streaming content.
+pub type CompressionPredicate =
+    And<And<And<SizeAbove, NotForContentType>, NotForContentType>, NotForContentType>;
+
+/// Build the compression layer from the configured encodings, level and
+/// minimum response size.
+pub fn compression_layer(config: &Config) -> CompressionLayer<CompressionPredicate> {
+    let enabled = |encoding| config.compression_encodings.contains(&encoding);
+    let quality = config
+        .compression_level
+        .map_or(CompressionLevel::Default, CompressionLevel::Precise);
+
+    let predicate = SizeAbove::new(config.compression_min_size_bytes)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE);
+
+    CompressionLayer::new()
+        .gzip(enabled(CompressionEncoding::Gzip))
+        .deflate(enabled(CompressionEncoding::Deflate))
+        .quality(quality)
+        .compress_when(predicate)
+}
@@ -0,0 +1,73 @@
+//! Tests for response compression settings.
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        routing::get,
+        Router,
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use crate::{compression::compression_layer, config::Config};
+
+    fn app(settings: serde_json::Value) -> Router {
+        let mut config = json!({
+            "database_url": "postgres://localhost/test",
+            "redis_url": "redis://localhost",
+            "jwt_secret": "secret",
+        });
+        config
+            .as_object_mut()
+            .unwrap()
+            .extend(settings.as_object().unwrap().clone());
+        let config: Config = serde_json::from_value(config).unwrap();
+
+        Router::new()
+            .route("/small", get(|| async { "ok" }))
+            .route("/large", get(|| async { "x".repeat(4096) }))
+            .layer(compression_layer(&config))
+    }
+
+    async fn encoding(app: &Router, path: &str, accept: &str) -> Option<String> {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(path)
+                    .header(header::ACCEPT_ENCODING, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_configured_encodings_and_threshold() {
+        let app = app(json!({
+            "compression_encodings": ["deflate"],
+            "compression_level": 9,
+            "compression_min_size_bytes": 1024,
+        }));
+
+        assert_eq!(encoding(&app, "/large", "deflate").await.as_deref(), Some("deflate"));
+        // Gzip is not enabled, and small responses are left alone
+        assert_eq!(encoding(&app, "/large", "gzip").await, None);
+        assert_eq!(encoding(&app, "/small", "deflate").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_defaults_compress_with_gzip() {
+        let defaults = app(json!({}));
+        assert_eq!(encoding(&defaults, "/large", "gzip, deflate").await.as_deref(), Some("gzip"));
+
+        let disabled = app(json!({ "compression_encodings": [] }));
+        assert_eq!(encoding(&disabled, "/large", "gzip").await, None);
+    }
+}
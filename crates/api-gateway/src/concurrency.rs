@@ -0,0 +1,84 @@
+//! Per-user limits on executions in flight.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use uuid::Uuid;
+
+/// Counts each user's executions that are running or waiting to run, so
+/// one user can't hold every sandbox container.
+#[derive(Clone)]
+pub struct UserExecutions {
+    active: Arc<Mutex<HashMap<Uuid, u32>>>,
+    /// Executions one user may have in flight; 0 disables the limit.
+    max_per_user: u32,
+}
+
+/// A user already has as many executions in flight as allowed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("At most {limit} executions may run at once; wait for one to finish")]
+pub struct TooManyExecutions {
+    pub limit: u32,
+}
+
+impl UserExecutions {
+    pub fn new(max_per_user: u32) -> Self {
+        Self {
+            active: Arc::default(),
+            max_per_user,
+        }
+    }
+
+    /// Take one of `user_id`'s execution slots until the returned guard is
+    /// dropped, however the execution ends.
+    pub fn acquire(&self, user_id: Uuid) -> Result<ExecutionSlot, TooManyExecutions> {
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(user_id).or_default();
+        if self.max_per_user > 0 && *count >= self.max_per_user {
+            return Err(TooManyExecutions {
+                limit: self.max_per_user,
+            });
+        }
+        *count += 1;
+
+        Ok(ExecutionSlot {
+            active: Arc::clone(&self.active),
+            user_id,
+        })
+    }
+}
+
+/// Keeps an execution counted against its user's limit.
+pub struct ExecutionSlot {
+    active: Arc<Mutex<HashMap<Uuid, u32>>>,
+    user_id: Uuid,
+}
+
+impl Drop for ExecutionSlot {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+impl IntoResponse for TooManyExecutions {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
+}
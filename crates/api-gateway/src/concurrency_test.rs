@@ -0,0 +1,72 @@
+//! Tests for per-user execution limits.
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::sync::Barrier;
+    use uuid::Uuid;
+
+    use crate::concurrency::UserExecutions;
+
+    #[tokio::test]
+    async fn test_extra_concurrent_executions_rejected() {
+        let executions = UserExecutions::new(3);
+        let user = Uuid::new_v4();
+        let started = Arc::new(Barrier::new(6));
+
+        // Six runs at once; each holds its slot until all have tried
+        let runs: Vec<_> = (0..6)
+            .map(|_| {
+                let executions = executions.clone();
+                let started = started.clone();
+                tokio::spawn(async move {
+                    let slot = executions.acquire(user);
+                    started.wait().await;
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    slot.map(drop)
+                })
+            })
+            .collect();
+
+        let mut rejected = 0;
+        for run in runs {
+            if let Err(busy) = run.await.unwrap() {
+                assert_eq!(busy.limit, 3);
+                rejected += 1;
+            }
+        }
+        assert_eq!(rejected, 3);
+
+        // Every slot came back once the runs finished
+        let _slots: Vec<_> = (0..3).map(|_| executions.acquire(user).unwrap()).collect();
+        assert!(executions.acquire(user).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slot_released_when_execution_panics() {
+        let executions = UserExecutions::new(1);
+        let user = Uuid::new_v4();
+
+        let held = executions.clone();
+        let run = tokio::spawn(async move {
+            let _slot = held.acquire(user).unwrap();
+            panic!("execution failed");
+        });
+        assert!(run.await.is_err());
+
+        let _slot = executions.acquire(user).unwrap();
+        // Other users are limited separately
+        assert!(executions.acquire(Uuid::new_v4()).is_ok());
+        assert!(executions.acquire(user).is_err());
+    }
+
+    #[test]
+    fn test_zero_limit_disables_check() {
+        let executions = UserExecutions::new(0);
+        let user = Uuid::new_v4();
+
+        let slots: Vec<_> = (0..50).map(|_| executions.acquire(user)).collect();
+        assert!(slots.iter().all(Result::is_ok));
+    }
+}
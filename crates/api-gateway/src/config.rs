@@ -1,8 +1,77 @@
 //! Configuration management for RustyClint.
 
-use serde::Deserialize;
+use std::collections::HashMap;
 
-#[derive(Debug, Deserialize)]
+use rustyclint_common::models::{Language, PathPolicy, VersionRetention};
+use rustyclint_sandbox::{PrecheckMode, ResourceLimits};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::compression::CompressionEncoding;
+
+/// Keys whose values are exported as they are. Any other key is treated as
+/// a secret, so a new setting stays hidden until it is added here.
+pub const EXPORTED_KEYS: &[&str] = &[
+    "port",
+    "redis_key_prefix",
+    "jwt_expiry_hours",
+    "refresh_token_expiry_days",
+    "revocation_check_fail_open",
+    "admin_user_ids",
+    "sandbox_enabled",
+    "sandbox_timeout_secs",
+    "max_code_bytes",
+    "max_result_bytes",
+    "max_file_versions",
+    "file_version_max_age_secs",
+    "file_version_prune_interval_secs",
+    "max_exec_args",
+    "max_exec_args_bytes",
+    "file_path_policy",
+    "max_containers_per_user",
+    "load_shed_max_executions",
+    "load_shed_retry_after_secs",
+    "sandbox_pool_size",
+    "daily_execution_limit",
+    "max_executions_per_minute",
+    "session_idle_timeout_secs",
+    "max_session_lifetime_secs",
+    "sandbox_images",
+    "sandbox_allow_root",
+    "image_pull_timeout_secs",
+    "language_versions",
+    "resource_profiles",
+    "callback_allowlist",
+    "compression_encodings",
+    "compression_level",
+    "compression_min_size_bytes",
+    "reserved_project_names",
+    "result_cache_ttl_secs",
+    "code_precheck",
+    "lsp_disabled_languages",
+    "lsp_change_debounce_ms",
+    "lsp_max_response_bytes",
+    "lsp_response_limits",
+    "lsp_request_timeout_secs",
+    "lsp_shutdown_timeout_secs",
+    "lsp_max_servers",
+    "awareness_batch_ms",
+    "max_awareness_bytes",
+    "collab_prune_interval_secs",
+    "collab_heartbeat_timeout_secs",
+    "collab_idle_timeout_secs",
+    "doc_log_compact_after",
+    "max_rooms_per_user",
+    "max_room_participants",
+    "max_checkpoints_per_document",
+    "log_pii",
+];
+
+/// Stands in for a secret in exported configuration.
+pub const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default = "default_port")]
     pub port: u16,
@@ -10,16 +79,242 @@ pub struct Config {
     pub database_url: String,
     pub redis_url: String,
 
+    /// Prepended to every Redis key, e.g. `"staging:"`, so environments can
+    /// share an instance.
+    #[serde(default)]
+    pub redis_key_prefix: String,
+
     pub jwt_secret: String,
 
     #[serde(default = "default_jwt_expiry")]
     pub jwt_expiry_hours: u64,
 
+    /// How long a refresh token can renew access tokens before the user
+    /// must log in again.
+    #[serde(default = "default_refresh_token_expiry")]
+    pub refresh_token_expiry_days: u64,
+
+    /// Accept access tokens when Redis can't be asked whether they were
+    /// revoked. Off by default: requests fail with `503` during an outage,
+    /// whereas failing open keeps users working but lets revoked tokens in.
+    #[serde(default)]
+    pub revocation_check_fail_open: bool,
+
+    /// Users allowed to call the `/admin` routes.
+    #[serde(default)]
+    pub admin_user_ids: Vec<Uuid>,
+
+    /// Whether code execution is offered at all.
+    #[serde(default = "default_true")]
+    pub sandbox_enabled: bool,
+
     #[serde(default = "default_sandbox_timeout")]
     pub sandbox_timeout_secs: u64,
 
+    /// Largest source submitted for a single run.
+    #[serde(default = "default_max_code_bytes")]
+    pub max_code_bytes: usize,
+
+    /// Most output a single run's result may carry across stdout, stderr,
+    /// compiler, post-run and combined output; the rest is cut off. 0
+    /// leaves results uncapped.
+    #[serde(default = "default_max_result_bytes")]
+    pub max_result_bytes: usize,
+
+    /// Earlier versions kept per file; older ones are pruned whenever the
+    /// file is saved. 0 keeps every version. Projects may override it.
+    #[serde(default = "default_max_file_versions")]
+    pub max_file_versions: usize,
+
+    /// Seconds an earlier file version is kept after it was saved; 0 keeps
+    /// versions at any age. Projects may override it.
+    #[serde(default)]
+    pub file_version_max_age_secs: u64,
+
+    /// How often versions outside their project's retention are pruned in
+    /// the background; 0 leaves pruning to file saves.
+    #[serde(default = "default_file_version_prune_interval_secs")]
+    pub file_version_prune_interval_secs: u64,
+
+    /// Most program arguments accepted per run.
+    #[serde(default = "default_max_exec_args")]
+    pub max_exec_args: usize,
+
+    /// Largest combined length of a run's program arguments.
+    #[serde(default = "default_max_exec_args_bytes")]
+    pub max_exec_args_bytes: usize,
+
+    /// How strictly project file paths are checked before files are stored.
+    #[serde(default)]
+    pub file_path_policy: PathPolicy,
+
+    /// Executions one user may have running or queued at once; 0 disables
+    /// the limit.
     #[serde(default = "default_max_containers")]
     pub max_containers_per_user: u32,
+
+    /// Active executions at which new requests get `503`; 0 disables shedding.
+    #[serde(default = "default_load_shed_max_executions")]
+    pub load_shed_max_executions: usize,
+
+    /// `Retry-After` sent with shed requests.
+    #[serde(default = "default_load_shed_retry_after")]
+    pub load_shed_retry_after_secs: u64,
+
+    /// Containers kept warm per language for later runs, filled at startup;
+    /// 0 removes every container after its run.
+    #[serde(default)]
+    pub sandbox_pool_size: usize,
+
+    /// Executions each user may start per UTC day; 0 disables the quota.
+    #[serde(default)]
+    pub daily_execution_limit: u64,
+
+    /// Executions each user may start per minute; 0 disables the limit.
+    #[serde(default = "default_max_executions_per_minute")]
+    pub max_executions_per_minute: u64,
+
+    /// Seconds a sandbox session may go without activity before it is
+    /// stopped and its container removed.
+    #[serde(default = "default_session_idle_timeout")]
+    pub session_idle_timeout_secs: u64,
+
+    /// Absolute cap on a sandbox session's age, regardless of activity.
+    #[serde(default = "default_max_session_lifetime")]
+    pub max_session_lifetime_secs: u64,
+
+    /// Per-language sandbox images, ideally pinned by `@sha256:` digest.
+    #[serde(default)]
+    pub sandbox_images: HashMap<Language, String>,
+
+    /// Run code in containers even if it would run as root. Only for
+    /// trusted custom images without a `sandbox` user.
+    #[serde(default)]
+    pub sandbox_allow_root: bool,
+
+    /// Seconds a sandbox image pull may go without progress before it is
+    /// aborted.
+    #[serde(default = "default_image_pull_timeout")]
+    pub image_pull_timeout_secs: u64,
+
+    /// Allowlisted image tags users may pick per language, e.g. `["3.11", "3.12"]`.
+    #[serde(default)]
+    pub language_versions: HashMap<Language, Vec<String>>,
+
+    /// Named resource-limit profiles runs may select, e.g. "exam" or "heavy-ml",
+    /// in addition to the built-in "snippet".
+    #[serde(default)]
+    pub resource_profiles: HashMap<String, ResourceLimits>,
+
+    /// URLs that execution callbacks may be sent to; a callback must match an
+    /// entry's scheme, host and port and lie under its path.
+    #[serde(default)]
+    pub callback_allowlist: Vec<String>,
+
+    /// Secret used to sign execution callbacks; callbacks are refused without it.
+    #[serde(default)]
+    pub callback_secret: Option<String>,
+
+    /// Encodings offered for response compression; empty disables it.
+    #[serde(default = "default_compression_encodings")]
+    pub compression_encodings: Vec<CompressionEncoding>,
+
+    /// Algorithm-specific compression level (e.g. 1-9 for gzip); unset uses
+    /// the algorithm's default.
+    #[serde(default)]
+    pub compression_level: Option<i32>,
+
+    /// Responses smaller than this are sent uncompressed.
+    #[serde(default = "default_compression_min_size")]
+    pub compression_min_size_bytes: u16,
+
+    /// Secret used to sign project snapshots; export and import are refused without it.
+    #[serde(default)]
+    pub snapshot_secret: Option<String>,
+
+    /// Names projects may not be given, compared ignoring case.
+    #[serde(default)]
+    pub reserved_project_names: Vec<String>,
+
+    /// How long opted-in deterministic execution results stay cached.
+    #[serde(default = "default_result_cache_ttl")]
+    pub result_cache_ttl_secs: u64,
+
+    /// Static size/complexity check before running code (off by default).
+    #[serde(default)]
+    pub code_precheck: PrecheckMode,
+
+    /// Languages whose language server is disabled in this deployment.
+    #[serde(default)]
+    pub lsp_disabled_languages: Vec<Language>,
+
+    /// Quiet period before coalesced didChange notifications are forwarded.
+    #[serde(default = "default_lsp_change_debounce")]
+    pub lsp_change_debounce_ms: u64,
+
+    /// Largest language server response the gateway will buffer.
+    #[serde(default = "default_lsp_max_response_bytes")]
+    pub lsp_max_response_bytes: usize,
+
+    /// Per-method overrides of `lsp_max_response_bytes`, keyed by LSP method.
+    #[serde(default)]
+    pub lsp_response_limits: HashMap<String, usize>,
+
+    /// How long a language server request may take before it fails.
+    #[serde(default = "default_lsp_request_timeout")]
+    pub lsp_request_timeout_secs: u64,
+
+    /// How long language servers get to exit when the gateway shuts down.
+    #[serde(default = "default_lsp_shutdown_timeout")]
+    pub lsp_shutdown_timeout_secs: u64,
+
+    /// Most language servers running at once across all sessions; the least
+    /// recently used is evicted to start another. 0 disables the cap.
+    #[serde(default = "default_lsp_max_servers")]
+    pub lsp_max_servers: usize,
+
+    /// Window over which cursor/awareness updates are merged per room.
+    #[serde(default = "default_awareness_batch")]
+    pub awareness_batch_ms: u64,
+
+    /// Largest binary awareness frame a client may send for rebroadcast.
+    #[serde(default = "default_max_awareness_bytes")]
+    pub max_awareness_bytes: usize,
+
+    /// How often participants with dead connections are pruned; 0 disables.
+    #[serde(default = "default_collab_prune_interval")]
+    pub collab_prune_interval_secs: u64,
+
+    /// Evict collaboration participants not heard from for this long,
+    /// checked every `collab_prune_interval_secs`; 0 disables. Clients
+    /// send `Ping` messages to stay in while otherwise quiet.
+    #[serde(default)]
+    pub collab_heartbeat_timeout_secs: u64,
+
+    /// Close collaboration sockets that send no edits or cursor updates
+    /// for this long; 0 disables.
+    #[serde(default)]
+    pub collab_idle_timeout_secs: u64,
+
+    /// Logged CRDT updates per file before they are compacted into a snapshot.
+    #[serde(default = "default_doc_log_compact_after")]
+    pub doc_log_compact_after: i64,
+
+    /// Documents one user may have open for collaboration at once; 0 is unlimited.
+    #[serde(default = "default_max_rooms_per_user")]
+    pub max_rooms_per_user: usize,
+
+    /// People who may be in one collaboration room at once; 0 is unlimited.
+    #[serde(default = "default_max_room_participants")]
+    pub max_room_participants: usize,
+
+    /// Checkpoints kept per collaborative document; 0 is unlimited.
+    #[serde(default = "default_max_checkpoints_per_document")]
+    pub max_checkpoints_per_document: usize,
+
+    /// Log raw emails and usernames instead of masked values.
+    #[serde(default)]
+    pub log_pii: bool,
 }
 
 fn default_port() -> u16 {
@@ -30,14 +325,130 @@ fn default_jwt_expiry() -> u64 {
     24
 }
 
+fn default_refresh_token_expiry() -> u64 {
+    30
+}
+
+fn default_true() -> bool {
+    true
+}
+
 fn default_sandbox_timeout() -> u64 {
     300
 }
 
+fn default_max_code_bytes() -> usize {
+    100_000
+}
+
+fn default_max_result_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_max_file_versions() -> usize {
+    50
+}
+
+fn default_file_version_prune_interval_secs() -> u64 {
+    3600
+}
+
+fn default_max_exec_args() -> usize {
+    64
+}
+
+fn default_max_exec_args_bytes() -> usize {
+    8 * 1024
+}
+
 fn default_max_containers() -> u32 {
     3
 }
 
+fn default_load_shed_max_executions() -> usize {
+    64
+}
+
+fn default_load_shed_retry_after() -> u64 {
+    5
+}
+
+fn default_max_executions_per_minute() -> u64 {
+    30
+}
+
+fn default_session_idle_timeout() -> u64 {
+    30 * 60
+}
+
+fn default_max_session_lifetime() -> u64 {
+    4 * 60 * 60
+}
+
+fn default_image_pull_timeout() -> u64 {
+    120
+}
+
+fn default_result_cache_ttl() -> u64 {
+    60 * 60
+}
+
+fn default_compression_encodings() -> Vec<CompressionEncoding> {
+    vec![CompressionEncoding::Gzip]
+}
+
+fn default_compression_min_size() -> u16 {
+    256
+}
+
+fn default_lsp_change_debounce() -> u64 {
+    300
+}
+
+fn default_lsp_max_response_bytes() -> usize {
+    rustyclint_lsp_proxy::framing::DEFAULT_MAX_RESPONSE_BYTES
+}
+
+fn default_lsp_request_timeout() -> u64 {
+    10
+}
+
+fn default_lsp_shutdown_timeout() -> u64 {
+    10
+}
+
+fn default_lsp_max_servers() -> usize {
+    32
+}
+
+fn default_awareness_batch() -> u64 {
+    50
+}
+
+fn default_max_awareness_bytes() -> usize {
+    rustyclint_collab::room::DEFAULT_MAX_AWARENESS_BYTES
+}
+
+fn default_collab_prune_interval() -> u64 {
+    30
+}
+
+fn default_doc_log_compact_after() -> i64 {
+    500
+}
+
+fn default_max_rooms_per_user() -> usize {
+    rustyclint_collab::room::DEFAULT_MAX_ROOMS_PER_USER
+}
+
+fn default_max_room_participants() -> usize {
+    rustyclint_collab::room::DEFAULT_MAX_PARTICIPANTS
+}
+
+fn default_max_checkpoints_per_document() -> usize {
+    rustyclint_collab::document::DEFAULT_MAX_CHECKPOINTS
+}
+
 impl Config {
     pub fn load() -> anyhow::Result<Self> {
         let config = config::Config::builder()
@@ -48,4 +459,130 @@ impl Config {
 
         Ok(config.try_deserialize()?)
     }
+
+    /// The configuration as JSON with every secret replaced by [`REDACTED`].
+    ///
+    /// Unset optional secrets stay `null`, so the export shows whether one
+    /// is configured without revealing it.
+    pub fn redacted(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            for (key, value) in object.iter_mut() {
+                if !EXPORTED_KEYS.contains(&key.as_str()) && !value.is_null() {
+                    *value = Value::String(REDACTED.into());
+                }
+            }
+        }
+        value
+    }
+
+    /// The deployment's file version retention, which projects may override.
+    pub fn version_retention(&self) -> VersionRetention {
+        VersionRetention {
+            max_versions: Some(i32::try_from(self.max_file_versions).unwrap_or(i32::MAX)),
+            max_age_secs: Some(i64::try_from(self.file_version_max_age_secs).unwrap_or(i64::MAX)),
+        }
+    }
+
+    /// Check a configuration document without applying it.
+    ///
+    /// Secrets are usually left out of shared configuration, or exported as
+    /// [`REDACTED`], so missing required secrets are not reported.
+    pub fn validate_document(document: Value) -> Vec<String> {
+        let Value::Object(mut object) = document else {
+            return vec!["Configuration must be a JSON object".into()];
+        };
+        for key in ["database_url", "redis_url", "jwt_secret"] {
+            object
+                .entry(key)
+                .or_insert_with(|| Value::String(REDACTED.into()));
+        }
+
+        match serde_json::from_value::<Config>(Value::Object(object)) {
+            Ok(config) => config.validate(),
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    /// Problems with values that parse but would not work.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if self.sandbox_timeout_secs == 0 {
+            errors.push("sandbox_timeout_secs must be positive".to_string());
+        }
+        if self.jwt_expiry_hours == 0 {
+            errors.push("jwt_expiry_hours must be positive".to_string());
+        }
+        if self.refresh_token_expiry_days == 0 {
+            errors.push("refresh_token_expiry_days must be positive".to_string());
+        }
+        if self.session_idle_timeout_secs == 0 {
+            errors.push("session_idle_timeout_secs must be positive".to_string());
+        }
+        if self.image_pull_timeout_secs == 0 {
+            errors.push("image_pull_timeout_secs must be positive".to_string());
+        }
+
+        for (language, image) in &self.sandbox_images {
+            if image.trim().is_empty() || image.contains(char::is_whitespace) {
+                errors.push(format!(
+                    "sandbox_images.{:?}: invalid image {:?}",
+                    language, image
+                ));
+            }
+        }
+
+        for (language, versions) in &self.language_versions {
+            for version in versions {
+                let valid = !version.is_empty()
+                    && version
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+                if !valid {
+                    errors.push(format!(
+                        "language_versions.{:?}: invalid tag {:?}",
+                        language, version
+                    ));
+                }
+            }
+        }
+
+        for (name, limits) in &self.resource_profiles {
+            let positive = [
+                ("memory_bytes", limits.memory_bytes > 0),
+                ("cpu_quota", limits.cpu_quota > 0),
+                ("pids_limit", limits.pids_limit > 0),
+                ("timeout_secs", limits.timeout_secs > 0),
+                (
+                    "compile_timeout_secs",
+                    limits.compile_timeout_secs != Some(0),
+                ),
+                ("run_timeout_secs", limits.run_timeout_secs != Some(0)),
+                ("max_output_bytes", limits.max_output_bytes > 0),
+            ];
+            for (field, ok) in positive {
+                if !ok {
+                    errors.push(format!(
+                        "resource_profiles.{}: {} must be positive",
+                        name, field
+                    ));
+                }
+            }
+            if let Err(e) = limits.capabilities() {
+                errors.push(format!("resource_profiles.{}: {}", name, e));
+            }
+        }
+
+        for prefix in &self.callback_allowlist {
+            if !prefix.starts_with("https://") && !prefix.starts_with("http://") {
+                errors.push(format!(
+                    "callback_allowlist: {:?} is not an http(s) URL",
+                    prefix
+                ));
+            }
+        }
+
+        errors
+    }
 }
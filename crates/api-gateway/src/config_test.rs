@@ -0,0 +1,72 @@
+//! Tests for configuration export and validation.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::config::{Config, EXPORTED_KEYS, REDACTED};
+
+    fn config() -> Config {
+        serde_json::from_value(json!({
+            "database_url": "postgres://app:hunter2@db/rustyclint",
+            "redis_url": "redis://:hunter2@cache",
+            "jwt_secret": "jwt-hunter2",
+            "callback_secret": "cb-hunter2",
+            "sandbox_images": { "python": "registry/python@sha256:abc" }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_export_redacts_secrets() {
+        let exported = config().redacted();
+
+        assert!(!exported.to_string().contains("hunter2"));
+        for key in ["database_url", "redis_url", "jwt_secret", "callback_secret"] {
+            assert_eq!(exported[key], REDACTED, "{key} not redacted");
+        }
+        // Unset secrets stay visibly unset
+        assert!(exported["snapshot_secret"].is_null());
+        assert_eq!(exported["sandbox_images"]["python"], "registry/python@sha256:abc");
+        // Every exported key is a real setting
+        assert!(EXPORTED_KEYS.iter().all(|key| exported.get(*key).is_some()));
+        assert_eq!(exported["sandbox_timeout_secs"], 300);
+    }
+
+    #[test]
+    fn test_exported_config_validates() {
+        assert!(Config::validate_document(config().redacted()).is_empty());
+        // Shared config usually leaves secrets out entirely
+        assert!(Config::validate_document(json!({ "sandbox_timeout_secs": 60 })).is_empty());
+    }
+
+    #[test]
+    fn test_bad_config_reports_errors() {
+        let errors = Config::validate_document(json!({ "sandbox_timeout_secs": "soon" }));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("invalid type"), "{errors:?}");
+
+        let errors = Config::validate_document(json!({
+            "sandbox_timeout_secs": 0,
+            "language_versions": { "python": ["3.12", "latest; rm -rf /"] },
+            "resource_profiles": {
+                "exam": {
+                    "memory_bytes": 0,
+                    "cpu_quota": 50000,
+                    "pids_limit": 32,
+                    "timeout_secs": 10,
+                    "max_output_bytes": 65536,
+                    "network_enabled": false,
+                    "cap_add": ["SYS_ADMIN"]
+                }
+            },
+            "callback_allowlist": ["ftp://hooks.example.com"]
+        }));
+        assert_eq!(errors.len(), 5, "{errors:?}");
+        assert!(errors.iter().any(|e| e.contains("sandbox_timeout_secs")));
+        assert!(errors.iter().any(|e| e.contains("resource_profiles.exam: memory_bytes")));
+        assert!(errors.iter().any(|e| e.contains("SYS_ADMIN")));
+
+        assert_eq!(Config::validate_document(json!([])).len(), 1);
+    }
+}
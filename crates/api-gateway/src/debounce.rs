@@ -0,0 +1,133 @@
+//! Debouncing of LSP `textDocument/didChange` notifications.
+//!
+//! Editors send a change on every keystroke. Changes for the same document
+//! are coalesced and only the latest text is forwarded to the language server
+//! once the document has been quiet for a short period.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rustyclint_common::models::Language;
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+
+/// A full-text document change destined for a language server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentChange {
+    pub session_id: Uuid,
+    pub language: Language,
+    pub uri: String,
+    pub version: i32,
+    pub text: String,
+}
+
+type DocumentKey = (Uuid, String);
+
+#[derive(Default)]
+struct DebounceState {
+    /// Latest unsent change per document and when it arrived.
+    pending: HashMap<DocumentKey, (DocumentChange, Instant)>,
+    /// Last version forwarded per document, to keep versions monotonic.
+    forwarded: HashMap<DocumentKey, i32>,
+}
+
+/// Coalesces rapid document changes before they reach the language server.
+pub struct ChangeDebouncer {
+    quiet_period: Duration,
+    state: Arc<Mutex<DebounceState>>,
+    sender: mpsc::UnboundedSender<DocumentChange>,
+}
+
+impl ChangeDebouncer {
+    /// Create a debouncer and the receiver that debounced changes are sent to.
+    pub fn new(quiet_period: Duration) -> (Self, mpsc::UnboundedReceiver<DocumentChange>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let debouncer = Self {
+            quiet_period,
+            state: Arc::new(Mutex::new(DebounceState::default())),
+            sender,
+        };
+        (debouncer, receiver)
+    }
+
+    /// Record a change, replacing any pending change for the same document.
+    ///
+    /// Changes older than the pending or already forwarded version are ignored.
+    pub async fn push(&self, change: DocumentChange) {
+        let key = (change.session_id, change.uri.clone());
+        let mut state = self.state.lock().await;
+
+        if state
+            .forwarded
+            .get(&key)
+            .is_some_and(|&version| change.version <= version)
+        {
+            return;
+        }
+
+        match state.pending.get_mut(&key) {
+            Some((pending, last_change)) => {
+                if change.version > pending.version {
+                    *pending = change;
+                }
+                *last_change = Instant::now();
+            }
+            None => {
+                state.pending.insert(key.clone(), (change, Instant::now()));
+                self.spawn_timer(key);
+            }
+        }
+    }
+
+    /// Take the pending change for a document immediately, bypassing the delay.
+    ///
+    /// Used before position requests so completions see the freshest text.
+    pub async fn flush(&self, session_id: Uuid, uri: &str) -> Option<DocumentChange> {
+        let key = (session_id, uri.to_string());
+        let mut state = self.state.lock().await;
+        let (change, _) = state.pending.remove(&key)?;
+        state.forwarded.insert(key, change.version);
+        Some(change)
+    }
+
+    /// Drop everything tracked for a session's documents once it has ended.
+    pub async fn forget_session(&self, session_id: Uuid) {
+        let mut state = self.state.lock().await;
+        state.pending.retain(|(id, _), _| *id != session_id);
+        state.forwarded.retain(|(id, _), _| *id != session_id);
+    }
+
+    fn spawn_timer(&self, key: DocumentKey) {
+        let quiet_period = self.quiet_period;
+        let state = Arc::clone(&self.state);
+        let sender = self.sender.clone();
+
+        tokio::spawn(async move {
+            let mut wait = quiet_period;
+            loop {
+                tokio::time::sleep(wait).await;
+
+                let mut state = state.lock().await;
+                let Some((_, last_change)) = state.pending.get(&key) else {
+                    // Flushed by a position request
+                    return;
+                };
+
+                let elapsed = last_change.elapsed();
+                if elapsed < quiet_period {
+                    wait = quiet_period - elapsed;
+                    continue;
+                }
+
+                if let Some((change, _)) = state.pending.remove(&key) {
+                    state.forwarded.insert(key, change.version);
+                    let _ = sender.send(change);
+                }
+                return;
+            }
+        });
+    }
+}
@@ -0,0 +1,95 @@
+//! Tests for didChange debouncing.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rustyclint_common::models::Language;
+    use uuid::Uuid;
+
+    use crate::debounce::{ChangeDebouncer, DocumentChange};
+
+    fn change(session_id: Uuid, version: i32, text: &str) -> DocumentChange {
+        DocumentChange {
+            session_id,
+            language: Language::Rust,
+            uri: "file:///code/main.rs".to_string(),
+            version,
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rapid_changes_coalesced() {
+        let (debouncer, mut rx) = ChangeDebouncer::new(Duration::from_millis(50));
+        let session_id = Uuid::new_v4();
+
+        for version in 1..=5 {
+            debouncer
+                .push(change(session_id, version, &"x".repeat(version as usize)))
+                .await;
+        }
+
+        let forwarded = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(forwarded.version, 5);
+        assert_eq!(forwarded.text, "xxxxx");
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_flush_returns_latest_and_keeps_versions_monotonic() {
+        let (debouncer, mut rx) = ChangeDebouncer::new(Duration::from_millis(50));
+        let session_id = Uuid::new_v4();
+
+        debouncer.push(change(session_id, 1, "a")).await;
+        debouncer.push(change(session_id, 2, "ab")).await;
+
+        let flushed = debouncer
+            .flush(session_id, "file:///code/main.rs")
+            .await
+            .unwrap();
+        assert_eq!(flushed.text, "ab");
+
+        // A late, older change must not be forwarded after the flush
+        debouncer.push(change(session_id, 1, "a")).await;
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forget_session_drops_pending_and_forwarded() {
+        let (debouncer, mut rx) = ChangeDebouncer::new(Duration::from_millis(50));
+        let ended = Uuid::new_v4();
+        let live = Uuid::new_v4();
+
+        debouncer.push(change(ended, 3, "abc")).await;
+        debouncer
+            .flush(ended, "file:///code/main.rs")
+            .await
+            .unwrap();
+        debouncer.push(change(ended, 4, "abcd")).await;
+        debouncer.push(change(live, 1, "a")).await;
+
+        debouncer.forget_session(ended).await;
+
+        // Only the live session's change is still sent
+        let forwarded = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(forwarded.session_id, live);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+
+        // The ended session's forwarded version was dropped with it
+        debouncer.push(change(ended, 1, "a")).await;
+        let flushed = debouncer.flush(ended, "file:///code/main.rs").await;
+        assert_eq!(flushed.map(|change| change.version), Some(1));
+    }
+}
@@ -0,0 +1,166 @@
+//! Incremental persistence of collaborative documents.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use rustyclint_collab::{CollabDocument, DEFAULT_FIELD};
+use rustyclint_common::{
+    db::{DocCheckpointRepo, DocUpdateRepo, FileRepo},
+    models::FileEncoding,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A file's append-only CRDT update log.
+///
+/// Every applied update is appended instead of rewriting the whole file, and
+/// once the log holds more than `compact_after` entries it is folded into a
+/// single snapshot. The file's stored content is only rewritten when its
+/// room closes; the log stays the source of truth for reopened rooms.
+#[derive(Clone)]
+pub struct DocumentLog {
+    db: PgPool,
+    compact_after: i64,
+    saves: SaveOrder,
+}
+
+/// Keeps background saves of closed rooms in the order they were queued.
+#[derive(Clone, Default)]
+struct SaveOrder {
+    next: Arc<AtomicU64>,
+    /// The latest save queued for each file, until it is written.
+    latest: Arc<Mutex<HashMap<Uuid, u64>>>,
+    /// Held while a save is written, so saves land one at a time.
+    writing: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl DocumentLog {
+    pub fn new(db: PgPool, compact_after: i64) -> Self {
+        Self {
+            db,
+            compact_after,
+            saves: SaveOrder::default(),
+        }
+    }
+
+    /// Rebuild a file's document from its log.
+    ///
+    /// A file never edited collaboratively starts from its stored text, which
+    /// is logged as the first update so later edits replay on top of it.
+    /// `None` if the file does not exist or is binary.
+    pub async fn load(&self, file_id: Uuid) -> anyhow::Result<Option<CollabDocument>> {
+        let updates = DocUpdateRepo::list(&self.db, file_id).await?;
+        if !updates.is_empty() {
+            let document =
+                CollabDocument::from_updates(file_id, updates.into_iter().map(|u| u.data)).await?;
+            self.load_checkpoints(&document).await?;
+            return Ok(Some(document));
+        }
+
+        let Some((file, content)) = FileRepo::find_by_id_with_content(&self.db, file_id).await?
+        else {
+            return Ok(None);
+        };
+        if file.encoding != FileEncoding::Utf8 {
+            return Ok(None);
+        }
+        let document = CollabDocument::seeded(file_id, &content);
+        DocUpdateRepo::append(&self.db, file_id, &document.encode_state().await).await?;
+        self.load_checkpoints(&document).await?;
+        Ok(Some(document))
+    }
+
+    /// Register a file's stored checkpoints on its document.
+    async fn load_checkpoints(&self, document: &CollabDocument) -> anyhow::Result<()> {
+        for checkpoint in DocCheckpointRepo::list(&self.db, document.id()).await? {
+            document.load_checkpoint(&checkpoint.name, checkpoint.state);
+        }
+        Ok(())
+    }
+
+    /// Store a checkpoint so it survives the room closing.
+    pub async fn save_checkpoint(
+        &self,
+        file_id: Uuid,
+        name: &str,
+        state: &[u8],
+    ) -> anyhow::Result<()> {
+        DocCheckpointRepo::save(&self.db, file_id, name, state).await?;
+        Ok(())
+    }
+
+    /// [`Self::save`] in the background.
+    ///
+    /// Saves of one file land in the order they were queued: one overtaken
+    /// by a later save of the same file is skipped rather than written over
+    /// the newer text.
+    pub fn queue_save(&self, document: CollabDocument) {
+        let file_id = document.id();
+        let queued = self.saves.next.fetch_add(1, Ordering::Relaxed);
+        self.saves.latest.lock().unwrap().insert(file_id, queued);
+
+        let log = self.clone();
+        tokio::spawn(async move {
+            let _writing = log.saves.writing.lock().await;
+            let latest = log.saves.latest.lock().unwrap().get(&file_id).copied();
+            if latest != Some(queued) {
+                tracing::debug!("Save of file {} superseded by a later one", file_id);
+                return;
+            }
+
+            if let Err(e) = log.save(&document).await {
+                tracing::warn!("Failed to save file {}: {}", file_id, e);
+            }
+
+            let mut latest = log.saves.latest.lock().unwrap();
+            if latest.get(&file_id) == Some(&queued) {
+                latest.remove(&file_id);
+            }
+        });
+    }
+
+    /// Write a closed room's text back to the file's stored content, keeping
+    /// the text it replaces as a version.
+    pub async fn save(&self, document: &CollabDocument) -> anyhow::Result<()> {
+        let content = document.get_content(DEFAULT_FIELD).await;
+        if !FileRepo::update_content(&self.db, document.id(), &content).await? {
+            tracing::debug!(
+                "File {} gone or binary; collaborative edits not saved",
+                document.id()
+            );
+        }
+        Ok(())
+    }
+
+    /// Append an applied update, compacting the log if it has grown too long.
+    pub async fn append(&self, file_id: Uuid, update: &[u8]) -> anyhow::Result<()> {
+        let entries = DocUpdateRepo::append(&self.db, file_id, update).await?;
+        if entries > self.compact_after {
+            self.compact(file_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Replace the logged entries with a snapshot of the state they build.
+    pub async fn compact(&self, file_id: Uuid) -> anyhow::Result<()> {
+        let updates = DocUpdateRepo::list(&self.db, file_id).await?;
+        let Some(through_id) = updates.last().map(|u| u.id) else {
+            return Ok(());
+        };
+
+        let entries = updates.len();
+        let snapshot = CollabDocument::from_updates(file_id, updates.into_iter().map(|u| u.data))
+            .await?
+            .encode_state()
+            .await;
+        DocUpdateRepo::compact(&self.db, file_id, through_id, &snapshot).await?;
+
+        tracing::debug!("Compacted {} updates for file {}", entries, file_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+//! Load shedding for when the server is saturated.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+/// Number of sandbox executions running or waiting for an executor.
+#[derive(Clone, Default)]
+pub struct LoadSignal(Arc<AtomicUsize>);
+
+impl LoadSignal {
+    /// Count an execution until the returned guard is dropped.
+    pub fn track(&self) -> LoadGuard {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        LoadGuard(self.0.clone())
+    }
+
+    /// Current number of active executions.
+    pub fn current(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps an execution counted in its [`LoadSignal`].
+pub struct LoadGuard(Arc<AtomicUsize>);
+
+impl Drop for LoadGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Decides when new requests are turned away.
+#[derive(Clone)]
+pub struct LoadShedder {
+    pub signal: LoadSignal,
+    /// Active executions at which new requests are rejected; 0 disables shedding.
+    pub max_active_executions: usize,
+    pub retry_after_secs: u64,
+}
+
+impl LoadShedder {
+    fn overloaded(&self) -> bool {
+        self.max_active_executions > 0 && self.signal.current() >= self.max_active_executions
+    }
+}
+
+/// Middleware answering `503 Service Unavailable` with `Retry-After` while
+/// overloaded. Requests already being handled are unaffected.
+///
+/// Only layered on the routes that start executions; everything else, such
+/// as editing files or probing health, is served regardless.
+pub async fn shed_load(
+    State(shedder): State<LoadShedder>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if shedder.overloaded() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, shedder.retry_after_secs.to_string())],
+            Json(serde_json::json!({ "error": "Server is busy, try again shortly" })),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
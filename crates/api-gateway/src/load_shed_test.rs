@@ -0,0 +1,76 @@
+//! Tests for load shedding.
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use crate::load_shed::{shed_load, LoadShedder, LoadSignal};
+
+    fn app(signal: LoadSignal) -> Router {
+        let shedder = LoadShedder {
+            signal,
+            max_active_executions: 2,
+            retry_after_secs: 5,
+        };
+        Router::new()
+            .route("/api/v1/sandbox/run", post(|| async { "{}" }))
+            .route_layer(middleware::from_fn_with_state(shedder, shed_load))
+            .route("/health", get(|| async { "ok" }))
+            .route("/api/v1/projects", get(|| async { "[]" }))
+    }
+
+    async fn status(app: &Router, method: &str, path: &str) -> (StatusCode, Option<String>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status(), retry_after)
+    }
+
+    #[tokio::test]
+    async fn test_requests_shed_under_high_load() {
+        let signal = LoadSignal::default();
+        let app = app(signal.clone());
+
+        let first = signal.track();
+        assert_eq!(
+            status(&app, "POST", "/api/v1/sandbox/run").await.0,
+            StatusCode::OK
+        );
+
+        let second = signal.track();
+        assert_eq!(
+            status(&app, "POST", "/api/v1/sandbox/run").await,
+            (StatusCode::SERVICE_UNAVAILABLE, Some("5".to_string()))
+        );
+
+        // Only new executions are turned away
+        assert_eq!(status(&app, "GET", "/health").await.0, StatusCode::OK);
+        assert_eq!(
+            status(&app, "GET", "/api/v1/projects").await.0,
+            StatusCode::OK
+        );
+
+        // Finishing work lifts the shedding
+        drop(second);
+        drop(first);
+        assert_eq!(signal.current(), 0);
+        assert_eq!(
+            status(&app, "POST", "/api/v1/sandbox/run").await.0,
+            StatusCode::OK
+        );
+    }
+}
@@ -1,20 +1,35 @@
 //! RustyClint API Gateway - Main entry point for the online IDE.
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 
 use axum::{routing::get, Router};
 use tower_http::{
-    compression::CompressionLayer,
     cors::{Any, CorsLayer},
     trace::TraceLayer,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod announce;
+mod audit;
 mod auth;
+mod callback;
+mod compression;
+mod concurrency;
 mod config;
+mod debounce;
+mod doc_log;
+mod load_shed;
+mod privacy;
+mod quota;
+mod rate_limit;
+mod result_cache;
 mod routes;
+mod shutdown;
+mod snapshot;
 mod state;
+mod store;
 
+use load_shed::LoadShedder;
 use state::AppState;
 
 #[tokio::main]
@@ -29,31 +44,52 @@ async fn main() -> anyhow::Result<()> {
 
     // Load configuration
     let config = config::Config::load()?;
+    privacy::init(config.log_pii);
 
     // Initialize application state
     let state = AppState::new(&config).await?;
 
+    // Warm containers in the background so startup isn't held up by pulls
+    let prewarm_state = state.clone();
+    tokio::spawn(async move { routes::prewarm_executor_pool(&prewarm_state).await });
+
+    // Turn new executions away while too many are in flight
+    let shedder = LoadShedder {
+        signal: state.load.clone(),
+        max_active_executions: config.load_shed_max_executions,
+        retry_after_secs: config.load_shed_retry_after_secs,
+    };
+
     // Build router
     let app = Router::new()
         .route("/health", get(routes::health_check))
-        .nest("/api/v1", routes::api_routes())
+        .nest("/api/v1", routes::api_routes(shedder))
         .nest("/ws", routes::ws_routes())
         .layer(TraceLayer::new_for_http())
-        .layer(CompressionLayer::new())
+        .layer(compression::compression_layer(&config))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
                 .allow_headers(Any),
         )
-        .with_state(state);
+        .with_state(state.clone());
 
     // Start server
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     tracing::info!("RustyClint starting on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown::signal())
+        .await?;
+
+    shutdown::stop_language_servers(
+        &state,
+        Duration::from_secs(config.lsp_shutdown_timeout_secs),
+    )
+    .await;
+    routes::drain_executor_pool().await;
 
     Ok(())
 }
@@ -0,0 +1,89 @@
+//! Logging policy for personally identifiable information.
+//!
+//! Unless `log_pii` is enabled, emails are masked and usernames are replaced
+//! by a stable fingerprint, so log lines can still be correlated without
+//! exposing who they belong to. Credentials are always redacted.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static LOG_PII: AtomicBool = AtomicBool::new(false);
+
+/// Apply the configured logging policy.
+pub fn init(log_pii: bool) {
+    LOG_PII.store(log_pii, Ordering::Relaxed);
+}
+
+fn log_pii() -> bool {
+    LOG_PII.load(Ordering::Relaxed)
+}
+
+/// Mask the local part of an email, keeping its first character and domain.
+pub fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().unwrap_or('*');
+            format!("{}***@{}", first, domain)
+        }
+        None => "***".to_string(),
+    }
+}
+
+/// Short, stable fingerprint of an identifier for log correlation.
+///
+/// This is not a cryptographic hash; it only keeps raw values out of logs.
+pub fn fingerprint(value: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("anon-{:08x}", hasher.finish() as u32)
+}
+
+/// Email as it may appear in logs under the current policy.
+pub fn email(email: &str) -> String {
+    if log_pii() {
+        email.to_string()
+    } else {
+        mask_email(email)
+    }
+}
+
+/// Username or other user-chosen identifier as it may appear in logs.
+pub fn identifier(value: &str) -> String {
+    if log_pii() {
+        value.to_string()
+    } else {
+        fingerprint(value)
+    }
+}
+
+/// Scrub free-form text before logging it.
+///
+/// Bearer tokens and JWT-looking words are always redacted; email addresses
+/// are masked unless PII logging is enabled.
+pub fn scrub(text: &str) -> String {
+    let mut redact_next = false;
+
+    text.split(' ')
+        .map(|word| {
+            if redact_next {
+                redact_next = false;
+                return "[redacted]".to_string();
+            }
+            if word.eq_ignore_ascii_case("bearer") {
+                redact_next = true;
+                return word.to_string();
+            }
+            if word.starts_with("eyJ") && word.matches('.').count() == 2 {
+                return "[redacted]".to_string();
+            }
+            if word.contains('@') && !log_pii() {
+                return mask_email(word);
+            }
+            word.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
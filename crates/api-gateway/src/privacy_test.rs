@@ -0,0 +1,31 @@
+//! Tests for PII scrubbing.
+
+#[cfg(test)]
+mod tests {
+    use crate::privacy::{fingerprint, mask_email, scrub};
+
+    #[test]
+    fn test_email_is_masked() {
+        assert_eq!(mask_email("alice@example.com"), "a***@example.com");
+        assert_eq!(
+            scrub("login failed for alice@example.com"),
+            "login failed for a***@example.com"
+        );
+    }
+
+    #[test]
+    fn test_tokens_are_redacted() {
+        let scrubbed = scrub("Authorization: Bearer abc.def.ghi");
+        assert_eq!(scrubbed, "Authorization: Bearer [redacted]");
+
+        let scrubbed = scrub("token eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxIn0.sig");
+        assert_eq!(scrubbed, "token [redacted]");
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        assert_eq!(fingerprint("alice"), fingerprint("alice"));
+        assert_ne!(fingerprint("alice"), fingerprint("bob"));
+        assert!(!fingerprint("alice").contains("alice"));
+    }
+}
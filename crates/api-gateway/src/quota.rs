@@ -0,0 +1,105 @@
+//! Per-user daily execution quotas.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+use crate::store::KvStore;
+
+/// Counts each user's executions per UTC day.
+#[derive(Clone)]
+pub struct DailyQuota {
+    store: Arc<dyn KvStore>,
+    /// Executions allowed per user per day; 0 disables the quota.
+    limit: u64,
+}
+
+/// A user has used up today's executions.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Daily limit of {limit} executions reached")]
+pub struct QuotaExceeded {
+    pub limit: u64,
+    pub resets_at: DateTime<Utc>,
+}
+
+impl DailyQuota {
+    pub fn new(store: Arc<dyn KvStore>, limit: u64) -> Self {
+        Self { store, limit }
+    }
+
+    /// Counter key for a user's executions on `date`.
+    pub fn key(user_id: Uuid, date: NaiveDate) -> String {
+        format!("exec-quota:{}:{}", user_id, date)
+    }
+
+    /// Start of the UTC day after `now`, when counters roll over.
+    pub fn reset_time(now: DateTime<Utc>) -> DateTime<Utc> {
+        let tomorrow = now.date_naive().succ_opt().unwrap_or(NaiveDate::MAX);
+        tomorrow.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+    }
+
+    /// Count one execution for `user_id` at `now`, failing once the day's
+    /// limit has been used.
+    ///
+    /// Store errors are logged and the execution allowed, so an unavailable
+    /// Redis does not take execution down with it.
+    pub async fn consume(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<(), QuotaExceeded> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+
+        let resets_at = Self::reset_time(now);
+        // Keep the counter a little past midnight so late requests still see it
+        let ttl = (resets_at - now).to_std().unwrap_or_default() + Duration::from_secs(60);
+
+        match self
+            .store
+            .incr_ex(&Self::key(user_id, now.date_naive()), ttl)
+            .await
+        {
+            Ok(count) if count > self.limit => Err(QuotaExceeded {
+                limit: self.limit,
+                resets_at,
+            }),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("Execution quota check failed: {}", e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Give back `runs` executions counted by [`Self::consume`] at `now`
+    /// that did not go ahead.
+    pub async fn refund(&self, user_id: Uuid, now: DateTime<Utc>, runs: u64) {
+        if self.limit == 0 || runs == 0 {
+            return;
+        }
+
+        let key = Self::key(user_id, now.date_naive());
+        if let Err(e) = self.store.decr_by(&key, runs).await {
+            tracing::warn!("Execution quota refund failed: {}", e);
+        }
+    }
+}
+
+impl IntoResponse for QuotaExceeded {
+    fn into_response(self) -> Response {
+        let retry_after = (self.resets_at - Utc::now()).num_seconds().max(0);
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(serde_json::json!({
+                "error": self.to_string(),
+                "resets_at": self.resets_at,
+            })),
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,58 @@
+//! Tests for daily execution quotas.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use crate::{quota::DailyQuota, store::MemoryStore};
+
+    #[tokio::test]
+    async fn test_limit_enforced_until_next_day() {
+        let quota = DailyQuota::new(Arc::new(MemoryStore::default()), 3);
+        let user = Uuid::new_v4();
+        let evening = Utc.with_ymd_and_hms(2024, 5, 1, 23, 59, 0).unwrap();
+
+        for _ in 0..3 {
+            assert!(quota.consume(user, evening).await.is_ok());
+        }
+        let exceeded = quota.consume(user, evening).await.unwrap_err();
+        assert_eq!(exceeded.limit, 3);
+        assert_eq!(exceeded.resets_at, Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap());
+
+        // Other users have their own counters
+        assert!(quota.consume(Uuid::new_v4(), evening).await.is_ok());
+
+        // The counter starts over after midnight
+        let morning = Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 1).unwrap();
+        assert!(quota.consume(user, morning).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_zero_limit_disables_quota() {
+        let quota = DailyQuota::new(Arc::new(MemoryStore::default()), 0);
+        let user = Uuid::new_v4();
+
+        for _ in 0..100 {
+            assert!(quota.consume(user, Utc::now()).await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refunded_runs_can_be_used_again() {
+        let quota = DailyQuota::new(Arc::new(MemoryStore::default()), 2);
+        let user = Uuid::new_v4();
+        let now = Utc::now();
+
+        assert!(quota.consume(user, now).await.is_ok());
+        assert!(quota.consume(user, now).await.is_ok());
+        assert!(quota.consume(user, now).await.is_err());
+
+        // Both the refused run and one admitted run are given back
+        quota.refund(user, now, 2).await;
+        assert!(quota.consume(user, now).await.is_ok());
+        assert!(quota.consume(user, now).await.is_err());
+    }
+}
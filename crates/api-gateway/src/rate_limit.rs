@@ -0,0 +1,144 @@
+//! Per-user execution rate limiting.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::store::KvStore;
+
+/// Length of the window the rate is measured over.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits how many executions each user starts per minute.
+///
+/// Uses a sliding window approximated from two fixed one-minute counters:
+/// the previous minute's count is weighted by how much of it still falls
+/// within the last 60 seconds. Rejected attempts are counted too, so a
+/// client that ignores `Retry-After` stays limited.
+#[derive(Clone)]
+pub struct ExecutionRateLimit {
+    store: Arc<dyn KvStore>,
+    /// Executions allowed per user per minute; 0 disables the limit.
+    limit: u64,
+}
+
+/// A user has started too many executions within the last minute.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Limit of {limit} executions per minute reached")]
+pub struct RateLimited {
+    pub limit: u64,
+    pub retry_after: Duration,
+}
+
+impl ExecutionRateLimit {
+    pub fn new(store: Arc<dyn KvStore>, limit: u64) -> Self {
+        Self { store, limit }
+    }
+
+    /// Counter key for a user's executions in the minute numbered `window`.
+    pub fn key(user_id: Uuid, window: i64) -> String {
+        format!("exec-rate:{}:{}", user_id, window)
+    }
+
+    /// Count one execution for `user_id` at `now`, failing if that puts the
+    /// user over the limit.
+    ///
+    /// Store errors are logged and the execution allowed, like the daily
+    /// quota.
+    pub async fn check(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<(), RateLimited> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+
+        let window_secs = WINDOW.as_secs() as i64;
+        let window = now.timestamp().div_euclid(window_secs);
+        let elapsed = Duration::from_millis(
+            (now.timestamp_millis() - window * window_secs * 1000).max(0) as u64,
+        );
+
+        // The current counter must outlive its minute to be the previous one
+        let (current_key, previous_key) =
+            (Self::key(user_id, window), Self::key(user_id, window - 1));
+        let current = self.store.incr_ex(&current_key, WINDOW * 2);
+        let previous = self.store.get(&previous_key);
+        let (current, previous) = match tokio::try_join!(current, previous) {
+            Ok((current, previous)) => (current, parse_count(previous.as_deref())),
+            Err(e) => {
+                tracing::warn!("Execution rate limit check failed: {}", e);
+                return Ok(());
+            }
+        };
+
+        if estimated_count(previous, current, elapsed) <= self.limit as f64 {
+            return Ok(());
+        }
+        Err(RateLimited {
+            limit: self.limit,
+            retry_after: retry_after(previous, current, self.limit, elapsed),
+        })
+    }
+
+    /// Uncount `runs` executions admitted by [`Self::check`] at `now` that
+    /// did not go ahead.
+    pub async fn refund(&self, user_id: Uuid, now: DateTime<Utc>, runs: u64) {
+        if self.limit == 0 || runs == 0 {
+            return;
+        }
+
+        let window = now.timestamp().div_euclid(WINDOW.as_secs() as i64);
+        if let Err(e) = self.store.decr_by(&Self::key(user_id, window), runs).await {
+            tracing::warn!("Execution rate limit refund failed: {}", e);
+        }
+    }
+}
+
+/// A stored counter's value; missing or unreadable counters are 0.
+fn parse_count(value: Option<&[u8]>) -> u64 {
+    value
+        .and_then(|v| std::str::from_utf8(v).ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Executions within the 60 seconds up to `elapsed` into the current minute.
+fn estimated_count(previous: u64, current: u64, elapsed: Duration) -> f64 {
+    let overlap = 1.0 - elapsed.as_secs_f64() / WINDOW.as_secs_f64();
+    previous as f64 * overlap + current as f64
+}
+
+/// How long until one more execution would be allowed.
+pub fn retry_after(previous: u64, current: u64, limit: u64, elapsed: Duration) -> Duration {
+    let window = WINDOW.as_secs_f64();
+    let next = current + 1;
+
+    // Within this minute, once enough of the previous one has slid out
+    let wait = if next <= limit && previous > 0 {
+        let needed = 1.0 - (limit - next) as f64 / previous as f64;
+        needed * window - elapsed.as_secs_f64()
+    } else {
+        // Otherwise into the next minute, once enough of this one has
+        let needed = 1.0 - limit.saturating_sub(1) as f64 / current.max(1) as f64;
+        window - elapsed.as_secs_f64() + needed.max(0.0) * window
+    };
+
+    Duration::from_secs(wait.ceil().max(1.0) as u64)
+}
+
+impl IntoResponse for RateLimited {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, self.retry_after.as_secs().to_string())],
+            Json(serde_json::json!({
+                "error": self.to_string(),
+            })),
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,65 @@
+//! Tests for per-minute execution rate limiting.
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use crate::{
+        rate_limit::{retry_after, ExecutionRateLimit},
+        store::MemoryStore,
+    };
+
+    #[tokio::test]
+    async fn test_limit_slides_across_minutes() {
+        let limit = ExecutionRateLimit::new(Arc::new(MemoryStore::default()), 4);
+        let user = Uuid::new_v4();
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 30).unwrap();
+
+        for _ in 0..4 {
+            assert!(limit.check(user, start).await.is_ok());
+        }
+        let limited = limit.check(user, start).await.unwrap_err();
+        assert_eq!(limited.limit, 4);
+        assert!(limited.retry_after >= Duration::from_secs(1));
+
+        // Other users have their own counters
+        assert!(limit.check(Uuid::new_v4(), start).await.is_ok());
+
+        // Just into the next minute, most of the last one still counts
+        let next_minute = Utc.with_ymd_and_hms(2024, 5, 1, 12, 1, 0).unwrap();
+        assert!(limit.check(user, next_minute).await.is_err());
+
+        // Two minutes on, nothing recent is left
+        let later = Utc.with_ymd_and_hms(2024, 5, 1, 12, 2, 30).unwrap();
+        assert!(limit.check(user, later).await.is_ok());
+    }
+
+    #[test]
+    fn test_retry_after_waits_for_old_executions_to_slide_out() {
+        // 10 last minute, 2 so far this minute, at most 6: the next run fits
+        // once 7 of last minute's 10 have slid out, 42s in
+        assert_eq!(
+            retry_after(10, 2, 6, Duration::from_secs(6)),
+            Duration::from_secs(36)
+        );
+
+        // This minute alone is at the limit: wait into the next minute
+        assert_eq!(
+            retry_after(0, 6, 6, Duration::from_secs(45)),
+            Duration::from_secs(15 + 10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zero_limit_disables_rate_limit() {
+        let limit = ExecutionRateLimit::new(Arc::new(MemoryStore::default()), 0);
+        let user = Uuid::new_v4();
+
+        for _ in 0..100 {
+            assert!(limit.check(user, Utc::now()).await.is_ok());
+        }
+    }
+}
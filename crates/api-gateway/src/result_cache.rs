@@ -0,0 +1,79 @@
+//! Caching of complete execution results for deterministic snippets.
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use rustyclint_sandbox::{ExecutionRequest, ExecutionResult};
+use sha2::{Digest, Sha256};
+
+use crate::store::KvStore;
+
+/// Execution results keyed by a hash of everything that affects the output.
+#[derive(Clone)]
+pub struct ResultCache {
+    store: Arc<dyn KvStore>,
+    ttl: Duration,
+}
+
+impl ResultCache {
+    pub fn new(store: Arc<dyn KvStore>, ttl: Duration) -> Self {
+        Self { store, ttl }
+    }
+
+    /// Cache key for a request run on the image with `image_digest`.
+    ///
+    /// The whole request is hashed, so language, code, stdin, arguments and
+    /// output options all distinguish entries.
+    pub fn key(request: &ExecutionRequest, image_digest: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(request).unwrap_or_default());
+        hasher.update([0]);
+        hasher.update(image_digest.as_bytes());
+        format!("exec-result:{:x}", hasher.finalize())
+    }
+
+    /// Look up a cached result. Store errors are treated as misses.
+    pub async fn get(&self, key: &str) -> Option<ExecutionResult> {
+        match self.store.get(key).await {
+            Ok(Some(bytes)) => serde_json::from_slice(&bytes).ok(),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("Result cache lookup failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Store a result. Timed-out runs are not cached.
+    pub async fn put(&self, key: &str, result: &ExecutionResult) {
+        if result.timed_out {
+            return;
+        }
+
+        let Ok(bytes) = serde_json::to_vec(result) else {
+            return;
+        };
+        if let Err(e) = self.store.set_ex(key, &bytes, self.ttl).await {
+            tracing::warn!("Result cache write failed: {}", e);
+        }
+    }
+
+    /// Return the cached result for `key`, or run `execute` and cache its
+    /// result. The flag is true when the result came from the cache.
+    pub async fn get_or_execute<F, Fut, E>(
+        &self,
+        key: &str,
+        execute: F,
+    ) -> Result<(ExecutionResult, bool), E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ExecutionResult, E>>,
+    {
+        if let Some(result) = self.get(key).await {
+            return Ok((result, true));
+        }
+
+        let result = execute().await?;
+        self.put(key, &result).await;
+        Ok((result, false))
+    }
+}
@@ -0,0 +1,85 @@
+//! Tests for the execution result cache.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use rustyclint_common::models::Language;
+    use rustyclint_sandbox::{ExecutionRequest, ExecutionResult};
+
+    use crate::{result_cache::ResultCache, store::MemoryStore};
+
+    fn request(code: &str) -> ExecutionRequest {
+        ExecutionRequest {
+            code: code.into(),
+            language: Language::Python,
+            stdin: None,
+            stdin_encoding: Default::default(),
+            line_endings: Default::default(),
+            args: vec![],
+            post_run: None,
+            memory_bytes: None,
+            strip_ansi: None,
+            callback_url: None,
+            profile: None,
+            seed: None,
+            coverage: false,
+            combined_output: false,
+        }
+    }
+
+    fn result(stdout: &str) -> ExecutionResult {
+        ExecutionResult {
+            stdout: stdout.into(),
+            stderr: String::new(),
+            exit_code: Some(0),
+            execution_time_ms: 42,
+            timed_out: false,
+            truncated: false,
+            coverage: None,
+            compile_stderr: None,
+            compile_failed: false,
+            post_run_output: None,
+            image: "sandbox-python:latest".into(),
+            image_digest: Some("sha256:abc".into()),
+            combined_output: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_identical_request_served_from_cache() {
+        let cache = ResultCache::new(Arc::new(MemoryStore::default()), Duration::from_secs(60));
+        let counter = AtomicUsize::new(0);
+        let executions = &counter;
+        let execute = || async move {
+            executions.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, ()>(result("hi\n"))
+        };
+
+        let key = ResultCache::key(&request("print('hi')"), "sha256:abc");
+        let (first, first_cached) = cache.get_or_execute(&key, execute).await.unwrap();
+        let (second, second_cached) = cache.get_or_execute(&key, execute).await.unwrap();
+
+        assert!(!first_cached);
+        assert!(second_cached);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        assert_eq!(second.stdout, first.stdout);
+        assert_eq!(second.exit_code, Some(0));
+        assert_eq!(second.execution_time_ms, 42);
+    }
+
+    #[test]
+    fn test_key_depends_on_code_and_image() {
+        let key = ResultCache::key(&request("print(1)"), "sha256:abc");
+
+        assert_eq!(key, ResultCache::key(&request("print(1)"), "sha256:abc"));
+        assert_ne!(key, ResultCache::key(&request("print(2)"), "sha256:abc"));
+        assert_ne!(key, ResultCache::key(&request("print(1)"), "sha256:def"));
+    }
+}
@@ -0,0 +1,165 @@
+//! Administrative routes.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use rustyclint_sandbox::ContainerManager;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    announce::{Announcement, Severity},
+    auth::AdminUser,
+    config::Config,
+    state::AppState,
+};
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct KillExecutionsResponse {
+    pub cancelled: usize,
+    /// Containers that were removed.
+    pub killed_containers: Vec<String>,
+    /// Containers that could not be removed and may still be running.
+    pub failed_containers: Vec<String>,
+}
+
+/// Cancel every execution a user has in flight.
+///
+/// Safe to repeat: a user with nothing running reports zero. Nothing is
+/// cancelled if Docker cannot be reached.
+pub async fn kill_executions(
+    State(state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<KillExecutionsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let containers = ContainerManager::new().map_err(|e| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse {
+                error: format!("Sandbox unavailable: {}", e),
+            }),
+        )
+    })?;
+    let cancelled = state.executions.cancel_user(user_id);
+
+    let mut killed_containers = Vec::new();
+    let mut failed_containers = Vec::new();
+    for container_id in cancelled.container_ids {
+        match containers.remove_container(&container_id).await {
+            Ok(()) => killed_containers.push(container_id),
+            Err(e) => {
+                tracing::error!("Failed to remove container {}: {}", container_id, e);
+                failed_containers.push(container_id);
+            }
+        }
+    }
+
+    tracing::info!(
+        admin_id = %admin.id,
+        user_id = %user_id,
+        cancelled = cancelled.executions,
+        killed = killed_containers.len(),
+        failed = failed_containers.len(),
+        "Cancelled user executions"
+    );
+
+    Ok(Json(KillExecutionsResponse {
+        cancelled: cancelled.executions,
+        killed_containers,
+        failed_containers,
+    }))
+}
+
+/// The effective configuration, with secrets redacted.
+pub async fn export_config(State(state): State<AppState>, _admin: AdminUser) -> Json<Value> {
+    Json(state.config.redacted())
+}
+
+#[derive(Serialize)]
+pub struct LspStatsResponse {
+    pub active_servers: usize,
+    /// Cap on running servers; 0 if unlimited.
+    pub max_servers: usize,
+}
+
+/// Language servers currently running across all sessions.
+pub async fn lsp_stats(State(state): State<AppState>, _admin: AdminUser) -> Json<LspStatsResponse> {
+    let manager = state.lsp.lock().await;
+    Json(LspStatsResponse {
+        active_servers: manager.active_count(),
+        max_servers: manager.max_proxies(),
+    })
+}
+
+#[derive(Serialize)]
+pub struct ConfigValidationResponse {
+    pub valid: bool,
+    pub errors: Vec<String>,
+}
+
+/// Check a configuration document before deploying it; nothing is applied.
+pub async fn validate_config(
+    _admin: AdminUser,
+    Json(document): Json<Value>,
+) -> Json<ConfigValidationResponse> {
+    let errors = Config::validate_document(document);
+
+    Json(ConfigValidationResponse {
+        valid: errors.is_empty(),
+        errors,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct AnnounceRequest {
+    pub message: String,
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+#[derive(Serialize)]
+pub struct AnnounceResponse {
+    /// Sockets connected when the announcement was sent.
+    pub delivered: usize,
+}
+
+/// Push a notice to every connected WebSocket client.
+///
+/// Clients that connect afterwards do not see it.
+pub async fn announce(
+    State(state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Json(request): Json<AnnounceRequest>,
+) -> Result<Json<AnnounceResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let message = request.message.trim();
+    if message.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Announcement message is empty".into(),
+            }),
+        ));
+    }
+
+    let delivered = state.announcements.announce(Announcement {
+        message: message.to_string(),
+        severity: request.severity,
+    });
+
+    tracing::info!(
+        admin_id = %admin.id,
+        severity = ?request.severity,
+        delivered,
+        "Sent server announcement"
+    );
+
+    Ok(Json(AnnounceResponse { delivered }))
+}
@@ -0,0 +1,75 @@
+//! Deployment capability discovery.
+
+use axum::{extract::State, Json};
+use rustyclint_sandbox::ResourceLimits;
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    routes::{
+        languages::{has_lsp, language_info, LanguageResponse},
+        sandbox::MAX_BATCH_CASES,
+    },
+    state::AppState,
+};
+
+#[derive(Serialize)]
+pub struct CapabilitiesResponse {
+    pub service: &'static str,
+    pub version: &'static str,
+    pub api_version: &'static str,
+    pub languages: Vec<LanguageResponse>,
+    pub limits: LimitsResponse,
+    pub features: FeaturesResponse,
+}
+
+#[derive(Serialize)]
+pub struct LimitsResponse {
+    pub max_code_bytes: usize,
+    /// Timeout of a run that does not select a profile.
+    pub execution_timeout_secs: u64,
+    pub max_batch_cases: usize,
+    /// Runs per user per UTC day; absent when unlimited.
+    pub daily_execution_limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct FeaturesResponse {
+    pub sandbox: bool,
+    pub collaboration: bool,
+    pub lsp: bool,
+    pub debug: bool,
+    pub snapshots: bool,
+}
+
+/// Describe what this deployment offers, so clients need not guess.
+pub fn capabilities(config: &Config) -> CapabilitiesResponse {
+    let languages = language_info(config);
+    let lsp = languages.iter().any(|info| has_lsp(config, info.language));
+
+    CapabilitiesResponse {
+        service: "rustyclint",
+        version: env!("CARGO_PKG_VERSION"),
+        api_version: "v1",
+        languages,
+        limits: LimitsResponse {
+            max_code_bytes: config.max_code_bytes,
+            execution_timeout_secs: ResourceLimits::snippet().timeout_secs,
+            max_batch_cases: MAX_BATCH_CASES,
+            daily_execution_limit: (config.daily_execution_limit > 0)
+                .then_some(config.daily_execution_limit),
+        },
+        features: FeaturesResponse {
+            sandbox: config.sandbox_enabled,
+            collaboration: true,
+            lsp,
+            // No debugger is offered yet
+            debug: false,
+            snapshots: config.snapshot_secret.is_some(),
+        },
+    }
+}
+
+pub async fn get(State(state): State<AppState>) -> Json<CapabilitiesResponse> {
+    Json(capabilities(&state.config))
+}
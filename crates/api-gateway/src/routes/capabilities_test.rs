@@ -0,0 +1,55 @@
+//! Tests for capability discovery.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+    use serde_json::{json, Value};
+
+    use crate::config::Config;
+    use crate::routes::capabilities::capabilities;
+
+    fn config(overrides: Value) -> Config {
+        let mut value = json!({
+            "database_url": "postgres://localhost/test",
+            "redis_url": "redis://localhost",
+            "jwt_secret": "secret",
+        });
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(overrides.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_defaults_reported() {
+        let document = capabilities(&config(json!({})));
+
+        assert_eq!(document.api_version, "v1");
+        assert_eq!(document.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(document.languages.len(), Language::all().len());
+        assert_eq!(document.limits.max_code_bytes, 100_000);
+        assert_eq!(document.limits.daily_execution_limit, None);
+        assert!(document.features.sandbox);
+        assert!(document.features.lsp);
+        assert!(!document.features.snapshots);
+    }
+
+    #[test]
+    fn test_document_follows_config() {
+        let document = capabilities(&config(json!({
+            "sandbox_enabled": false,
+            "max_code_bytes": 2048,
+            "daily_execution_limit": 50,
+            "lsp_disabled_languages": Language::all(),
+            "snapshot_secret": "s3cret",
+        })));
+
+        assert!(!document.features.sandbox);
+        assert!(!document.features.lsp);
+        assert!(document.features.snapshots);
+        assert_eq!(document.limits.max_code_bytes, 2048);
+        assert_eq!(document.limits.daily_execution_limit, Some(50));
+        assert!(document.languages.iter().all(|info| !info.has_lsp));
+    }
+}
@@ -1,18 +1,25 @@
 //! File management routes.
 
+use std::convert::Infallible;
+
 use axum::{
+    body::{Body, Bytes},
     extract::{Path, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::stream;
 use rustyclint_common::{
     db::{FileRepo, ProjectRepo},
-    models::Language,
+    models::{normalize_path, AuditEventType, File, FileEncoding, Language, Project},
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use uuid::Uuid;
 
-use crate::{auth::AuthUser, state::AppState};
+use crate::{audit, auth::AuthUser, state::AppState};
 
 #[derive(Deserialize)]
 pub struct CreateFileRequest {
@@ -20,11 +27,21 @@ pub struct CreateFileRequest {
     pub path: String,
     pub language: Language,
     pub content: String,
+    /// Encoding of `content` in this request; binary files are sent as base64.
+    #[serde(default)]
+    pub encoding: FileEncoding,
 }
 
 #[derive(Deserialize)]
 pub struct UpdateFileRequest {
     pub content: String,
+    #[serde(default)]
+    pub encoding: FileEncoding,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateLanguageRequest {
+    pub language: Language,
 }
 
 #[derive(Serialize)]
@@ -34,6 +51,24 @@ pub struct FileResponse {
     pub path: String,
     pub language: Language,
     pub content: String,
+    pub encoding: FileEncoding,
+    pub content_type: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct FileVersionResponse {
+    pub version: i32,
+    pub encoding: FileEncoding,
+    pub created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct FileVersionContentResponse {
+    pub version: i32,
+    pub content: String,
+    pub encoding: FileEncoding,
+    pub content_type: &'static str,
+    pub created_at: String,
 }
 
 #[derive(Serialize)]
@@ -41,6 +76,176 @@ pub struct ErrorResponse {
     pub error: String,
 }
 
+/// Refuse paths whose extension the project does not allow.
+pub fn check_extension(
+    project: &Project,
+    path: &str,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if project.allows_path(path) {
+        return Ok(());
+    }
+
+    let allowed = project.allowed_extensions.as_deref().unwrap_or_default();
+    Err((
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(ErrorResponse {
+            error: format!(
+                "File extension not allowed in this project (allowed: {})",
+                allowed.join(", ")
+            ),
+        }),
+    ))
+}
+
+/// Convert submitted content into its stored form.
+///
+/// Content is decoded according to the request encoding and then classified:
+/// valid UTF-8 without NUL bytes is stored as text, anything else base64.
+pub fn normalize_content(
+    content: &str,
+    encoding: FileEncoding,
+) -> Result<(String, FileEncoding), String> {
+    let bytes = match encoding {
+        FileEncoding::Utf8 => content.as_bytes().to_vec(),
+        FileEncoding::Base64 => STANDARD
+            .decode(content)
+            .map_err(|_| "Invalid base64 content".to_string())?,
+    };
+
+    match FileEncoding::detect(&bytes) {
+        FileEncoding::Utf8 => Ok((
+            String::from_utf8_lossy(&bytes).into_owned(),
+            FileEncoding::Utf8,
+        )),
+        FileEncoding::Base64 => Ok((STANDARD.encode(&bytes), FileEncoding::Base64)),
+    }
+}
+
+/// Content type reported for a file.
+pub fn content_type(path: &str, encoding: FileEncoding) -> &'static str {
+    if encoding == FileEncoding::Utf8 {
+        return "text/plain; charset=utf-8";
+    }
+
+    let extension = path
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Size of the slices raw file content is sent in.
+const RAW_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Parse a `Range` header into an inclusive byte range.
+///
+/// Only single ranges are supported (`bytes=start-end`, `bytes=start-` and
+/// the suffix form `bytes=-n`). Returns `Ok(None)` when no range applies and
+/// `Err(())` when the range cannot be satisfied.
+pub fn parse_range(header: &str, len: usize) -> Result<Option<(usize, usize)>, ()> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+
+    // Multipart ranges fall back to the full body
+    if spec.contains(',') {
+        return Ok(None);
+    }
+
+    let (start, end) = spec.split_once('-').ok_or(())?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().map_err(|_| ())?;
+            if suffix == 0 {
+                return Err(());
+            }
+            (len.saturating_sub(suffix), len.checked_sub(1).ok_or(())?)
+        }
+        (start, "") => (start.parse().map_err(|_| ())?, len.saturating_sub(1)),
+        (start, end) => {
+            let end: usize = end.parse().map_err(|_| ())?;
+            (
+                start.parse().map_err(|_| ())?,
+                end.min(len.saturating_sub(1)),
+            )
+        }
+    };
+
+    if start >= len || start > end {
+        return Err(());
+    }
+
+    Ok(Some((start, end)))
+}
+
+/// Build a response for raw file bytes, honouring `Range`.
+///
+/// Files are stored whole in the database, so the content is already in
+/// memory; the body hands it out in [`RAW_CHUNK_SIZE`] slices without
+/// copying it again.
+pub fn raw_response(bytes: Vec<u8>, content_type: &'static str, headers: &HeaderMap) -> Response {
+    let bytes = Bytes::from(bytes);
+    let len = bytes.len();
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_range(value, len))
+        .unwrap_or(Ok(None));
+
+    let (status, body, content_range) = match range {
+        Ok(Some((start, end))) => (
+            StatusCode::PARTIAL_CONTENT,
+            bytes.slice(start..=end),
+            Some(format!("bytes {}-{}/{}", start, end, len)),
+        ),
+        Ok(None) => (StatusCode::OK, bytes, None),
+        Err(()) => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [
+                    (header::ACCEPT_RANGES, "bytes".to_string()),
+                    (header::CONTENT_RANGE, format!("bytes */{}", len)),
+                ],
+            )
+                .into_response();
+        }
+    };
+
+    let content_length = body.len();
+    let chunks = (0..content_length)
+        .step_by(RAW_CHUNK_SIZE)
+        .map(move |start| {
+            let end = (start + RAW_CHUNK_SIZE).min(content_length);
+            Ok::<_, Infallible>(body.slice(start..end))
+        });
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, content_length)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(content_range) = content_range {
+        response = response.header(header::CONTENT_RANGE, content_range);
+    }
+
+    response
+        .body(Body::from_stream(stream::iter(chunks)))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
 pub async fn create(
     State(state): State<AppState>,
     user: AuthUser,
@@ -66,32 +271,46 @@ pub async fn create(
         ));
     }
 
-    // Validate path
-    if body.path.trim().is_empty() {
-        return Err((
+    // Checked on the resolved segments so traversal cannot slip through
+    let path = normalize_path(&body.path, state.config.file_path_policy).map_err(|e| {
+        (
             StatusCode::BAD_REQUEST,
             Json(ErrorResponse {
-                error: "File path cannot be empty".into(),
+                error: e.to_string(),
             }),
-        ));
-    }
+        )
+    })?;
 
-    // Prevent path traversal
-    if body.path.contains("..") {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid file path".into(),
-            }),
-        ));
-    }
+    let project = ProjectRepo::find_by_id(&state.db, body.project_id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Project not found".into(),
+                }),
+            )
+        })?;
+    check_extension(&project, &path)?;
+
+    let (content, encoding) = normalize_content(&body.content, body.encoding)
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
 
-    let file = FileRepo::upsert(
+    let file = FileRepo::upsert_with_encoding(
         &state.db,
         body.project_id,
-        &body.path,
+        &path,
         body.language,
-        &body.content,
+        &content,
+        encoding,
     )
     .await
     .map_err(|e| {
@@ -103,14 +322,27 @@ pub async fn create(
         )
     })?;
 
+    prune_versions(&state, file.id).await;
+
+    audit::record(
+        &state.db,
+        user.id,
+        file.project_id,
+        AuditEventType::FileCreated,
+        json!({ "file_id": file.id, "path": file.path }),
+    )
+    .await;
+
     Ok((
         StatusCode::CREATED,
         Json(FileResponse {
             id: file.id,
             project_id: file.project_id,
+            content_type: content_type(&file.path, file.encoding),
             path: file.path,
             language: file.language,
-            content: body.content,
+            content,
+            encoding: file.encoding,
         }),
     ))
 }
@@ -162,12 +394,79 @@ pub async fn get(
     Ok(Json(FileResponse {
         id: file.id,
         project_id: file.project_id,
+        content_type: content_type(&file.path, file.encoding),
         path: file.path,
         language: file.language,
         content,
+        encoding: file.encoding,
     }))
 }
 
+/// Stream raw file content, supporting byte range requests.
+pub async fn raw(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let (file, content) = FileRepo::find_by_id_with_content(&state.db, id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "File not found".into(),
+                }),
+            )
+        })?;
+
+    // Check project access
+    if !ProjectRepo::user_has_access(&state.db, file.project_id, user.id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    let bytes = match file.encoding {
+        FileEncoding::Utf8 => content.into_bytes(),
+        FileEncoding::Base64 => STANDARD.decode(content).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: format!("Stored content is corrupt: {}", e),
+                }),
+            )
+        })?,
+    };
+
+    Ok(raw_response(
+        bytes,
+        content_type(&file.path, file.encoding),
+        &headers,
+    ))
+}
+
 pub async fn update(
     State(state): State<AppState>,
     user: AuthUser,
@@ -213,13 +512,17 @@ pub async fn update(
         ));
     }
 
+    let (content, encoding) = normalize_content(&body.content, body.encoding)
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+
     // Update file content
-    let updated = FileRepo::upsert(
+    let updated = FileRepo::upsert_with_encoding(
         &state.db,
         file.project_id,
         &file.path,
         file.language,
-        &body.content,
+        &content,
+        encoding,
     )
     .await
     .map_err(|e| {
@@ -231,12 +534,110 @@ pub async fn update(
         )
     })?;
 
+    prune_versions(&state, updated.id).await;
+
+    audit::record(
+        &state.db,
+        user.id,
+        updated.project_id,
+        AuditEventType::FileUpdated,
+        json!({ "file_id": updated.id, "path": updated.path }),
+    )
+    .await;
+
     Ok(Json(FileResponse {
         id: updated.id,
         project_id: updated.project_id,
+        content_type: content_type(&updated.path, updated.encoding),
         path: updated.path,
         language: updated.language,
-        content: body.content,
+        content,
+        encoding: updated.encoding,
+    }))
+}
+
+/// Override a file's language; it then drives LSP and execution for the file.
+pub async fn update_language(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<UpdateLanguageRequest>,
+) -> Result<Json<FileResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (file, content) = FileRepo::find_by_id_with_content(&state.db, id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "File not found".into(),
+                }),
+            )
+        })?;
+
+    // Check project access
+    if !ProjectRepo::user_has_access(&state.db, file.project_id, user.id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    let updated = FileRepo::update_language(&state.db, id, body.language)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "File not found".into(),
+                }),
+            )
+        })?;
+
+    audit::record(
+        &state.db,
+        user.id,
+        updated.project_id,
+        AuditEventType::FileUpdated,
+        json!({ "file_id": updated.id, "path": updated.path, "language": updated.language }),
+    )
+    .await;
+
+    Ok(Json(FileResponse {
+        id: updated.id,
+        project_id: updated.project_id,
+        content_type: content_type(&updated.path, updated.encoding),
+        path: updated.path,
+        language: updated.language,
+        content,
+        encoding: updated.encoding,
     }))
 }
 
@@ -293,5 +694,133 @@ pub async fn delete(
         )
     })?;
 
+    audit::record(
+        &state.db,
+        user.id,
+        file.project_id,
+        AuditEventType::FileDeleted,
+        json!({ "file_id": file.id, "path": file.path }),
+    )
+    .await;
+
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Drop a file's versions outside its project's retention. Failures only
+/// leave extra versions behind, so they are logged rather than returned.
+async fn prune_versions(state: &AppState, file_id: Uuid) {
+    let defaults = state.config.version_retention();
+    let pruned = FileRepo::enforce_version_retention(&state.db, Some(file_id), defaults).await;
+    if let Err(e) = pruned {
+        tracing::warn!("Failed to prune versions of file {}: {}", file_id, e);
+    }
+}
+
+/// A file the user may access through its project.
+async fn find_accessible_file(
+    state: &AppState,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<File, (StatusCode, Json<ErrorResponse>)> {
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    };
+
+    let (file, _) = FileRepo::find_by_id_with_content(&state.db, id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "File not found".into(),
+                }),
+            )
+        })?;
+
+    if !ProjectRepo::user_has_access(&state.db, file.project_id, user_id)
+        .await
+        .map_err(db_error)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    Ok(file)
+}
+
+/// A file's earlier versions, newest first.
+pub async fn list_versions(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<FileVersionResponse>>, (StatusCode, Json<ErrorResponse>)> {
+    let file = find_accessible_file(&state, user.id, id).await?;
+
+    let versions = FileRepo::list_versions(&state.db, file.id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?;
+
+    Ok(Json(
+        versions
+            .into_iter()
+            .map(|v| FileVersionResponse {
+                version: v.version,
+                encoding: v.encoding,
+                created_at: v.created_at.to_rfc3339(),
+            })
+            .collect(),
+    ))
+}
+
+/// One earlier version of a file with its content.
+pub async fn get_version(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, version)): Path<(Uuid, i32)>,
+) -> Result<Json<FileVersionContentResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let file = find_accessible_file(&state, user.id, id).await?;
+
+    let (version, content) = FileRepo::get_version(&state.db, file.id, version)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Version not found".into(),
+                }),
+            )
+        })?;
+
+    Ok(Json(FileVersionContentResponse {
+        version: version.version,
+        content,
+        encoding: version.encoding,
+        content_type: content_type(&file.path, version.encoding),
+        created_at: version.created_at.to_rfc3339(),
+    }))
+}
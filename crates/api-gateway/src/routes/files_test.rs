@@ -0,0 +1,128 @@
+//! Tests for file content handling.
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::to_bytes,
+        http::{header, HeaderMap, HeaderValue, StatusCode},
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use chrono::Utc;
+    use rustyclint_common::models::{normalize_extensions, FileEncoding, Language, Project};
+    use uuid::Uuid;
+
+    use crate::routes::files::{
+        check_extension, content_type, normalize_content, parse_range, raw_response,
+    };
+
+    #[test]
+    fn test_text_content_stored_as_is() {
+        let (content, encoding) = normalize_content("print('hi')", FileEncoding::Utf8).unwrap();
+
+        assert_eq!(content, "print('hi')");
+        assert_eq!(encoding, FileEncoding::Utf8);
+        assert_eq!(content_type("main.py", encoding), "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let png: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0xff];
+        let submitted = STANDARD.encode(png);
+
+        let (stored, encoding) = normalize_content(&submitted, FileEncoding::Base64).unwrap();
+
+        assert_eq!(encoding, FileEncoding::Base64);
+        assert_eq!(STANDARD.decode(stored).unwrap(), png);
+        assert_eq!(content_type("logo.png", encoding), "image/png");
+    }
+
+    #[test]
+    fn test_base64_text_detected_as_text() {
+        let submitted = STANDARD.encode("fn main() {}");
+
+        let (stored, encoding) = normalize_content(&submitted, FileEncoding::Base64).unwrap();
+
+        assert_eq!(encoding, FileEncoding::Utf8);
+        assert_eq!(stored, "fn main() {}");
+    }
+
+    #[test]
+    fn test_invalid_base64_rejected() {
+        assert!(normalize_content("not base64!", FileEncoding::Base64).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_raw_full_fetch() {
+        let content = b"hello, raw world".to_vec();
+
+        let response = raw_response(
+            content.clone(),
+            "text/plain; charset=utf-8",
+            &HeaderMap::new(),
+        );
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert!(response.headers().get(header::CONTENT_RANGE).is_none());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), content.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_raw_byte_range_fetch() {
+        let content = vec![7u8; 200 * 1024];
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=100-65635"));
+
+        let response = raw_response(content, "application/octet-stream", &headers);
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            "bytes 100-65635/204800"
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.len(), 65536);
+    }
+
+    #[test]
+    fn test_parse_range_forms() {
+        assert_eq!(parse_range("bytes=0-9", 100), Ok(Some((0, 9))));
+        assert_eq!(parse_range("bytes=90-", 100), Ok(Some((90, 99))));
+        assert_eq!(parse_range("bytes=-10", 100), Ok(Some((90, 99))));
+        assert_eq!(parse_range("bytes=50-500", 100), Ok(Some((50, 99))));
+        assert_eq!(parse_range("bytes=100-", 100), Err(()));
+        assert_eq!(parse_range("items=0-1", 100), Ok(None));
+    }
+
+    #[test]
+    fn test_disallowed_extension_rejected() {
+        let allowed = normalize_extensions(&[".PY".to_string(), "txt".to_string()]).unwrap();
+        let project = Project {
+            id: Uuid::new_v4(),
+            name: "Intro to Python".into(),
+            owner_id: Uuid::new_v4(),
+            default_language: Language::Python,
+            allowed_extensions: allowed,
+            resource_profile: None,
+            version_retention: Default::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        assert!(check_extension(&project, "src/main.py").is_ok());
+        assert!(check_extension(&project, "NOTES.TXT").is_ok());
+
+        let (status, body) = check_extension(&project, "main.rs").unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.error.contains("py, txt"));
+        assert!(check_extension(&project, "Makefile").is_err());
+        assert!(check_extension(&project, "py/.py").is_err());
+
+        let unrestricted = Project {
+            allowed_extensions: None,
+            ..project
+        };
+        assert!(check_extension(&unrestricted, "main.rs").is_ok());
+    }
+}
@@ -0,0 +1,63 @@
+//! Supported language metadata routes.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use rustyclint_common::models::Language;
+use serde::Serialize;
+
+use crate::{config::Config, state::AppState};
+
+#[derive(Serialize)]
+pub struct LanguageResponse {
+    pub language: Language,
+    pub extension: &'static str,
+    pub has_lsp: bool,
+}
+
+/// Whether a language server is available for a language in this deployment.
+pub fn has_lsp(config: &Config, language: Language) -> bool {
+    rustyclint_lsp_proxy::lsp_command(language).is_some()
+        && !config.lsp_disabled_languages.contains(&language)
+}
+
+/// Describe every supported language and the features available for it.
+pub fn language_info(config: &Config) -> Vec<LanguageResponse> {
+    Language::all()
+        .iter()
+        .map(|&language| LanguageResponse {
+            language,
+            extension: language.extension(),
+            has_lsp: has_lsp(config, language),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct LanguageVersionsResponse {
+    pub language: Language,
+    pub versions: Vec<String>,
+}
+
+/// Image tags available for a language; just `latest` if none are configured.
+pub fn language_versions(config: &Config, language: Language) -> Vec<String> {
+    match config.language_versions.get(&language) {
+        Some(versions) if !versions.is_empty() => versions.clone(),
+        _ => vec!["latest".to_string()],
+    }
+}
+
+pub async fn list(State(state): State<AppState>) -> Json<Vec<LanguageResponse>> {
+    Json(language_info(&state.config))
+}
+
+pub async fn versions(
+    State(state): State<AppState>,
+    Path(language): Path<Language>,
+) -> Json<LanguageVersionsResponse> {
+    Json(LanguageVersionsResponse {
+        language,
+        versions: language_versions(&state.config, language),
+    })
+}
@@ -0,0 +1,64 @@
+//! Tests for language metadata.
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use rustyclint_common::models::Language;
+    use serde_json::json;
+
+    use crate::config::Config;
+    use crate::routes::languages::{has_lsp, language_info, language_versions};
+    use crate::routes::lsp::{ensure_lsp_available, lsp_error_response};
+
+    fn config_without_lsp_for(languages: &[Language]) -> Config {
+        serde_json::from_value(json!({
+            "database_url": "postgres://localhost/test",
+            "redis_url": "redis://localhost",
+            "jwt_secret": "secret",
+            "lsp_disabled_languages": languages,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_all_languages_listed() {
+        let config = config_without_lsp_for(&[]);
+        let info = language_info(&config);
+
+        assert_eq!(info.len(), Language::all().len());
+        assert!(info.iter().all(|l| l.has_lsp));
+    }
+
+    #[test]
+    fn test_language_without_lsp() {
+        let config = config_without_lsp_for(&[Language::Kotlin]);
+
+        assert!(!has_lsp(&config, Language::Kotlin));
+        assert!(has_lsp(&config, Language::Rust));
+
+        let kotlin = language_info(&config)
+            .into_iter()
+            .find(|l| l.language == Language::Kotlin)
+            .unwrap();
+        assert!(!kotlin.has_lsp);
+
+        let err = ensure_lsp_available(&config, Language::Kotlin).unwrap_err();
+        let (status, body) = lsp_error_response(err);
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body.code, "lsp_unavailable");
+    }
+
+    #[test]
+    fn test_configured_versions_listed() {
+        let config: Config = serde_json::from_value(json!({
+            "database_url": "postgres://localhost/test",
+            "redis_url": "redis://localhost",
+            "jwt_secret": "secret",
+            "language_versions": { "python": ["3.11", "3.12"] },
+        }))
+        .unwrap();
+
+        assert_eq!(language_versions(&config, Language::Python), vec!["3.11", "3.12"]);
+        assert_eq!(language_versions(&config, Language::Rust), vec!["latest"]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Language server routes for code intelligence.
+
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use rustyclint_common::{db::FileRepo, models::Language};
+use rustyclint_lsp_proxy::{manager::LspError, LspManager, LspProxy, Workspace};
+use rustyclint_sandbox::{ContainerManager, ImageOverrides};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{MappedMutexGuard, MutexGuard};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser, debounce::DocumentChange, routes::languages::has_lsp, state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct PositionRequest {
+    pub session_id: Uuid,
+    pub language: Language,
+    pub uri: String,
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Deserialize)]
+pub struct DidChangeRequest {
+    pub session_id: Uuid,
+    pub language: Language,
+    pub uri: String,
+    pub version: i32,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub struct DidOpenRequest {
+    pub session_id: Uuid,
+    pub language: Language,
+    pub uri: String,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: &'static str,
+}
+
+type LspResult<T> = Result<T, (StatusCode, Json<ErrorResponse>)>;
+
+/// Map an LSP error to a response with a stable error code.
+pub fn lsp_error_response(error: LspError) -> (StatusCode, Json<ErrorResponse>) {
+    let (status, code) = match &error {
+        LspError::UnsupportedLanguage(_) => (StatusCode::UNPROCESSABLE_ENTITY, "lsp_unavailable"),
+        LspError::StartFailed(_) => (StatusCode::SERVICE_UNAVAILABLE, "lsp_start_failed"),
+        LspError::Communication(_) => (StatusCode::BAD_GATEWAY, "lsp_communication"),
+        LspError::ServerCrashed => (StatusCode::BAD_GATEWAY, "lsp_server_crashed"),
+        LspError::InvalidEdit(_) => (StatusCode::UNPROCESSABLE_ENTITY, "lsp_invalid_edit"),
+    };
+
+    (
+        status,
+        Json(ErrorResponse {
+            error: error.to_string(),
+            code,
+        }),
+    )
+}
+
+/// Reject languages without a language server before touching any container.
+pub fn ensure_lsp_available(
+    config: &crate::config::Config,
+    language: Language,
+) -> Result<(), LspError> {
+    if has_lsp(config, language) {
+        Ok(())
+    } else {
+        Err(LspError::UnsupportedLanguage(language))
+    }
+}
+
+/// Refuse sessions that don't exist or belong to someone else, before a
+/// language server is started for them.
+fn ensure_session_owner(state: &AppState, session_id: Uuid, user_id: Uuid) -> LspResult<()> {
+    let refuse = |status, error: &str, code| {
+        let error = error.to_string();
+        Err((status, Json(ErrorResponse { error, code })))
+    };
+    match state.sessions.get(session_id) {
+        Some(session) if session.user_id == user_id => Ok(()),
+        Some(_) => refuse(StatusCode::FORBIDDEN, "Access denied", "forbidden"),
+        None => refuse(StatusCode::NOT_FOUND, "Session not found", "not_found"),
+    }
+}
+
+pub async fn completion(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<PositionRequest>,
+) -> LspResult<Json<Value>> {
+    position_request(&state, user.id, "textDocument/completion", body).await
+}
+
+pub async fn hover(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<PositionRequest>,
+) -> LspResult<Json<Value>> {
+    position_request(&state, user.id, "textDocument/hover", body).await
+}
+
+pub async fn definition(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<PositionRequest>,
+) -> LspResult<Json<Value>> {
+    position_request(&state, user.id, "textDocument/definition", body).await
+}
+
+/// Open a document on the session's language server.
+///
+/// The server is told the document's LSP language id, which is not always the
+/// name the API uses for the language.
+pub async fn did_open(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<DidOpenRequest>,
+) -> LspResult<StatusCode> {
+    ensure_session_owner(&state, body.session_id, user.id)?;
+    ensure_lsp_available(&state.config, body.language).map_err(lsp_error_response)?;
+
+    let proxy = session_proxy(&state, body.session_id, body.language).await?;
+    proxy
+        .did_open(&body.uri, body.language, &body.text)
+        .await
+        .map_err(lsp_error_response)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Queue a document change; rapid changes are coalesced before forwarding.
+pub async fn did_change(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<DidChangeRequest>,
+) -> LspResult<StatusCode> {
+    ensure_session_owner(&state, body.session_id, user.id)?;
+    ensure_lsp_available(&state.config, body.language).map_err(lsp_error_response)?;
+
+    state
+        .lsp_changes
+        .push(DocumentChange {
+            session_id: body.session_id,
+            language: body.language,
+            uri: body.uri,
+            version: body.version,
+            text: body.text,
+        })
+        .await;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Language roots of the project open in a sandbox session.
+async fn session_workspace(state: &AppState, session_id: Uuid) -> Workspace {
+    let Some(session) = state.sessions.get(session_id) else {
+        return Workspace::default();
+    };
+
+    match FileRepo::list_for_project(&state.db, session.project_id).await {
+        Ok(files) => Workspace::detect(
+            rustyclint_lsp_proxy::workspace::DEFAULT_ROOT_URI,
+            files.iter().map(|file| file.path.as_str()),
+        ),
+        Err(e) => {
+            tracing::warn!("Cannot list files for session {}: {}", session_id, e);
+            Workspace::default()
+        }
+    }
+}
+
+/// The session's language server, started on first use.
+///
+/// A new server's container is started without holding the manager lock, so
+/// a slow image pull doesn't hold up every other session's requests.
+async fn session_proxy(
+    state: &AppState,
+    session_id: Uuid,
+    language: Language,
+) -> LspResult<MappedMutexGuard<'_, LspProxy>> {
+    let mut started = None;
+    loop {
+        let mut manager = state.lsp.lock().await;
+        let running = manager.container_id(session_id, language);
+        let (container_id, fresh) = match (running, started.take()) {
+            (Some(running), started) => {
+                // Another request got a server going while this one waited
+                remove_containers(started.into_iter().collect());
+                (running, false)
+            }
+            (None, Some(container_id)) => (container_id, true),
+            (None, None) => {
+                drop(manager);
+                started = Some(start_session_container(state, language).await?);
+                continue;
+            }
+        };
+
+        let workspace = match manager.get_mut(session_id, language) {
+            Some(_) => Workspace::default(),
+            None => session_workspace(state, session_id).await,
+        };
+
+        if let Err(e) = manager
+            .get_or_create(&container_id, session_id, language, &workspace)
+            .await
+        {
+            // Nothing else knows about a container started for this request
+            if fresh {
+                remove_containers(vec![container_id]);
+            }
+            return Err(lsp_error_response(e));
+        }
+
+        // Servers evicted to stay under the cap leave their containers behind
+        remove_containers(manager.take_evicted_containers());
+
+        return MutexGuard::try_map(manager, |manager| manager.get_mut(session_id, language))
+            .map_err(|_| lsp_error_response(LspError::ServerCrashed));
+    }
+}
+
+/// Start a container to host a language server for `language`.
+async fn start_session_container(state: &AppState, language: Language) -> LspResult<String> {
+    let images = ImageOverrides::new(state.config.sandbox_images.clone());
+    let pull_timeout = Duration::from_secs(state.config.image_pull_timeout_secs);
+    let containers = ContainerManager::with_images(images)
+        .map(|containers| {
+            containers
+                .with_allow_root(state.config.sandbox_allow_root)
+                .with_pull_timeout(pull_timeout)
+        })
+        .map_err(|e| lsp_error_response(LspError::StartFailed(e.to_string())))?;
+    LspManager::start_container(&containers, language)
+        .await
+        .map_err(lsp_error_response)
+}
+
+/// Remove language server containers in the background.
+fn remove_containers(container_ids: Vec<String>) {
+    if container_ids.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        if let Ok(containers) = ContainerManager::new() {
+            for container_id in &container_ids {
+                let _ = containers.remove_container(container_id).await;
+            }
+        }
+    });
+}
+
+async fn position_request(
+    state: &AppState,
+    user_id: Uuid,
+    method: &str,
+    body: PositionRequest,
+) -> LspResult<Json<Value>> {
+    ensure_session_owner(state, body.session_id, user_id)?;
+    ensure_lsp_available(&state.config, body.language).map_err(lsp_error_response)?;
+
+    let proxy = session_proxy(state, body.session_id, body.language).await?;
+
+    // Make sure the server sees the latest text before answering
+    if let Some(change) = state.lsp_changes.flush(body.session_id, &body.uri).await {
+        proxy
+            .did_change(&change.uri, change.version, &change.text)
+            .await
+            .map_err(lsp_error_response)?;
+    }
+
+    let result = match method {
+        "textDocument/hover" => proxy.hover(&body.uri, body.line, body.character).await,
+        "textDocument/definition" => proxy.definition(&body.uri, body.line, body.character).await,
+        _ => proxy.completion(&body.uri, body.line, body.character).await,
+    }
+    .map_err(lsp_error_response)?;
+
+    Ok(Json(result))
+}
@@ -1,19 +1,29 @@
 //! API route definitions.
 
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{get, patch, post},
     Json, Router,
 };
 use serde_json::{json, Value};
 
-use crate::state::AppState;
+use crate::{
+    load_shed::{shed_load, LoadShedder},
+    state::AppState,
+};
 
+mod admin;
+mod capabilities;
 mod files;
+mod languages;
+mod lsp;
 mod projects;
 mod sandbox;
 mod users;
 mod ws;
 
+pub use sandbox::{drain_executor_pool, prewarm_executor_pool};
+
 /// Health check endpoint.
 pub async fn health_check() -> Json<Value> {
     Json(json!({
@@ -23,13 +33,21 @@ pub async fn health_check() -> Json<Value> {
     }))
 }
 
-/// API v1 routes.
-pub fn api_routes() -> Router<AppState> {
+/// API v1 routes; those starting executions are shed by `shedder` when the
+/// server is overloaded.
+pub fn api_routes(shedder: LoadShedder) -> Router<AppState> {
     Router::new()
+        .merge(execution_routes(shedder))
         // Auth routes
         .route("/auth/register", post(users::register))
         .route("/auth/login", post(users::login))
+        .route("/auth/refresh", post(users::refresh))
+        .route("/auth/logout", post(users::logout))
         .route("/auth/me", get(users::me))
+        .route(
+            "/auth/me/settings",
+            get(users::get_settings).put(users::put_settings),
+        )
         // Project routes
         .route("/projects", get(projects::list).post(projects::create))
         .route(
@@ -39,24 +57,73 @@ pub fn api_routes() -> Router<AppState> {
                 .delete(projects::delete),
         )
         .route("/projects/:id/files", get(projects::list_files))
+        .route("/projects/:id/fork", post(projects::fork))
+        .route("/projects/:id/snapshot", get(projects::snapshot))
+        .route("/projects/:id/activity", get(projects::activity))
+        .route("/projects/:id/search", get(projects::search))
+        .route(
+            "/projects/:id/collaborators",
+            get(projects::list_collaborators).post(projects::add_collaborator),
+        )
+        .route(
+            "/projects/:id/collaborators/:user_id",
+            delete(projects::remove_collaborator),
+        )
+        .route("/projects/import-snapshot", post(projects::import_snapshot))
         // File routes
         .route("/files", post(files::create))
         .route(
             "/files/:id",
-            get(files::get)
-                .put(files::update)
-                .delete(files::delete),
+            get(files::get).put(files::update).delete(files::delete),
         )
+        .route("/files/:id/raw", get(files::raw))
+        .route("/files/:id/versions", get(files::list_versions))
+        .route("/files/:id/versions/:version", get(files::get_version))
+        .route("/files/:id/language", patch(files::update_language))
+        // Capability discovery
+        .route("/capabilities", get(capabilities::get))
+        // Language routes
+        .route("/languages", get(languages::list))
+        .route("/languages/:language/versions", get(languages::versions))
+        // LSP routes
+        .route("/lsp/completion", post(lsp::completion))
+        .route("/lsp/hover", post(lsp::hover))
+        .route("/lsp/definition", post(lsp::definition))
+        .route("/lsp/did_open", post(lsp::did_open))
+        .route("/lsp/did_change", post(lsp::did_change))
         // Sandbox routes
-        .route("/sandbox/run", post(sandbox::run_code))
-        .route("/sandbox/sessions", get(sandbox::list_sessions))
+        .route("/sandbox/run/:id/tail", get(sandbox::tail_execution))
+        .route("/sandbox/env/:language", get(sandbox::environment))
+        .route(
+            "/sandbox/sessions",
+            get(sandbox::list_sessions).post(sandbox::create_session),
+        )
         .route("/sandbox/sessions/:id", delete(sandbox::stop_session))
+        // Admin routes
+        .route(
+            "/admin/users/:id/kill-executions",
+            post(admin::kill_executions),
+        )
+        .route("/admin/config", get(admin::export_config))
+        .route("/admin/config/validate", post(admin::validate_config))
+        .route("/admin/announce", post(admin::announce))
+        .route("/admin/lsp", get(admin::lsp_stats))
+}
+
+/// Routes that start executions, the only ones turned away under load.
+fn execution_routes(shedder: LoadShedder) -> Router<AppState> {
+    Router::new()
+        .route("/sandbox/run", post(sandbox::run_code))
+        .route("/sandbox/batch", post(sandbox::run_batch))
+        .route_layer(middleware::from_fn_with_state(shedder, shed_load))
 }
 
 /// WebSocket routes for real-time features.
 pub fn ws_routes() -> Router<AppState> {
     Router::new()
+        .route("/collab", get(ws::multi_collab_handler))
         .route("/collab/:file_id", get(ws::collab_handler))
+        .route("/scratch/:room_id", get(ws::scratch_handler))
         .route("/terminal/:session_id", get(ws::terminal_handler))
         .route("/signaling/:room_id", get(ws::signaling_handler))
 }
@@ -1,18 +1,29 @@
 //! Project management routes.
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
 use rustyclint_common::{
-    db::{FileRepo, ProjectRepo},
-    models::Language,
+    db::{self, AuditRepo, FileRepo, ProjectRepo, UserRepo},
+    models::{
+        normalize_extensions, AuditEventType, Collaborator, FileSearchHit, Language, Project,
+        VersionRetention,
+    },
 };
+use rustyclint_sandbox::SandboxError;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::{auth::AuthUser, state::AppState};
+use crate::{
+    audit,
+    auth::AuthUser,
+    config::Config,
+    snapshot::{self, SignedSnapshot},
+    state::AppState,
+};
 
 #[derive(Deserialize)]
 pub struct CreateProjectRequest {
@@ -24,6 +35,20 @@ pub struct CreateProjectRequest {
 pub struct UpdateProjectRequest {
     pub name: Option<String>,
     pub default_language: Option<Language>,
+    /// Restrict the file extensions the project may contain; an empty list
+    /// lifts the restriction.
+    pub allowed_extensions: Option<Vec<String>>,
+    /// Operator-configured resource profile runs in the project default to;
+    /// an empty string restores the deployment default.
+    pub resource_profile: Option<String>,
+    /// How long earlier file versions are kept; limits left unset fall back
+    /// to the deployment default.
+    pub version_retention: Option<VersionRetention>,
+}
+
+#[derive(Deserialize)]
+pub struct ForkProjectRequest {
+    pub name: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -32,6 +57,9 @@ pub struct ProjectResponse {
     pub name: String,
     pub owner_id: Uuid,
     pub default_language: Language,
+    pub allowed_extensions: Option<Vec<String>>,
+    pub resource_profile: Option<String>,
+    pub version_retention: VersionRetention,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -43,6 +71,87 @@ pub struct FileResponse {
     pub language: Language,
 }
 
+/// Projects returned per page unless `limit` says otherwise.
+const DEFAULT_PROJECT_LIMIT: i64 = 50;
+
+/// Largest project page a client may request.
+const MAX_PROJECT_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct ListProjectsQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ProjectListResponse {
+    pub projects: Vec<ProjectResponse>,
+    /// Number of projects across all pages.
+    pub total_count: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+/// Events returned per activity page unless `limit` says otherwise.
+const DEFAULT_ACTIVITY_LIMIT: i64 = 50;
+
+/// Largest activity page a client may request.
+const MAX_ACTIVITY_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct ActivityQuery {
+    /// Comma-separated event types to include, e.g. `file_updated,session_started`.
+    pub types: Option<String>,
+    /// Only return events older than this event id.
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ActivityEventResponse {
+    pub id: i64,
+    pub event_type: AuditEventType,
+    pub actor_id: Option<Uuid>,
+    pub details: Value,
+    pub created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct ActivityResponse {
+    pub events: Vec<ActivityEventResponse>,
+    /// Pass as `before` to fetch the next page; absent on the last page.
+    pub next_before: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct AddCollaboratorRequest {
+    pub user_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct CollaboratorResponse {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: String,
+    pub added_at: String,
+}
+
+impl From<Collaborator> for CollaboratorResponse {
+    fn from(collaborator: Collaborator) -> Self {
+        Self {
+            user_id: collaborator.user_id,
+            username: collaborator.username,
+            role: collaborator.role,
+            added_at: collaborator.created_at.to_rfc3339(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct ErrorResponse {
     pub error: String,
@@ -51,8 +160,15 @@ pub struct ErrorResponse {
 pub async fn list(
     State(state): State<AppState>,
     user: AuthUser,
-) -> Result<Json<Vec<ProjectResponse>>, (StatusCode, Json<ErrorResponse>)> {
-    let projects = ProjectRepo::list_for_user(&state.db, user.id)
+    Query(query): Query<ListProjectsQuery>,
+) -> Result<Json<ProjectListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PROJECT_LIMIT)
+        .clamp(1, MAX_PROJECT_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let page = ProjectRepo::list_for_user_paginated(&state.db, user.id, limit, offset)
         .await
         .map_err(|e| {
             (
@@ -63,19 +179,65 @@ pub async fn list(
             )
         })?;
 
-    let response: Vec<ProjectResponse> = projects
+    let projects = page
+        .projects
         .into_iter()
         .map(|p| ProjectResponse {
             id: p.id,
             name: p.name,
             owner_id: p.owner_id,
             default_language: p.default_language,
+            allowed_extensions: p.allowed_extensions,
+            resource_profile: p.resource_profile,
+            version_retention: p.version_retention,
             created_at: p.created_at.to_rfc3339(),
             updated_at: p.updated_at.to_rfc3339(),
         })
         .collect();
 
-    Ok(Json(response))
+    Ok(Json(ProjectListResponse {
+        projects,
+        total_count: page.total_count,
+        limit,
+        offset,
+    }))
+}
+
+/// Punctuation allowed in project names besides letters, digits and spaces.
+const NAME_PUNCTUATION: &[char] = &['-', '_', '.', '(', ')', '+', '#', '\''];
+
+/// Check a project name, returning it trimmed.
+///
+/// Names are limited to letters, digits, spaces and [`NAME_PUNCTUATION`], so
+/// control characters and look-alike symbols are refused, as are the
+/// configured reserved names.
+pub fn check_project_name(
+    config: &Config,
+    name: &str,
+) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let name = name.trim();
+    let error = if name.is_empty() {
+        "Project name cannot be empty".to_string()
+    } else if name.len() > 255 {
+        "Project name too long".to_string()
+    } else if name.chars().any(char::is_control) {
+        "Project name cannot contain control characters".to_string()
+    } else if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_alphanumeric() && *c != ' ' && !NAME_PUNCTUATION.contains(c))
+    {
+        format!("Project name cannot contain {:?}", c)
+    } else if config
+        .reserved_project_names
+        .iter()
+        .any(|reserved| reserved.trim().to_lowercase() == name.to_lowercase())
+    {
+        format!("Project name {:?} is reserved", name)
+    } else {
+        return Ok(name.to_string());
+    };
+
+    Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))
 }
 
 pub async fn create(
@@ -83,26 +245,9 @@ pub async fn create(
     user: AuthUser,
     Json(body): Json<CreateProjectRequest>,
 ) -> Result<(StatusCode, Json<ProjectResponse>), (StatusCode, Json<ErrorResponse>)> {
-    // Validate name
-    if body.name.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Project name cannot be empty".into(),
-            }),
-        ));
-    }
-
-    if body.name.len() > 255 {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Project name too long".into(),
-            }),
-        ));
-    }
+    let name = check_project_name(&state.config, &body.name)?;
 
-    let project = ProjectRepo::create(&state.db, &body.name, user.id, body.default_language)
+    let project = ProjectRepo::create(&state.db, &name, user.id, body.default_language)
         .await
         .map_err(|e| {
             (
@@ -120,6 +265,9 @@ pub async fn create(
             name: project.name,
             owner_id: project.owner_id,
             default_language: project.default_language,
+            allowed_extensions: project.allowed_extensions,
+            resource_profile: project.resource_profile,
+            version_retention: project.version_retention,
             created_at: project.created_at.to_rfc3339(),
             updated_at: project.updated_at.to_rfc3339(),
         }),
@@ -175,6 +323,9 @@ pub async fn get(
         name: project.name,
         owner_id: project.owner_id,
         default_language: project.default_language,
+        allowed_extensions: project.allowed_extensions,
+        resource_profile: project.resource_profile,
+        version_retention: project.version_retention,
         created_at: project.created_at.to_rfc3339(),
         updated_at: project.updated_at.to_rfc3339(),
     }))
@@ -215,39 +366,117 @@ pub async fn update(
         ));
     }
 
-    // Validate name if provided
-    if let Some(ref name) = body.name {
-        if name.trim().is_empty() {
+    let name = body
+        .name
+        .as_deref()
+        .map(|name| check_project_name(&state.config, name))
+        .transpose()?;
+
+    if let Some(ref extensions) = body.allowed_extensions {
+        let allowed_extensions = normalize_extensions(extensions).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?;
+
+        ProjectRepo::set_allowed_extensions(&state.db, id, allowed_extensions.as_deref())
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: e.to_string(),
+                    }),
+                )
+            })?;
+    }
+
+    if let Some(ref profile) = body.resource_profile {
+        let profile = Some(profile.as_str()).filter(|profile| !profile.is_empty());
+        // Only profiles the operator configured; the built-ins stay theirs
+        let configured = &state.config.resource_profiles;
+        if let Some(name) = profile.filter(|name| !configured.contains_key(*name)) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: SandboxError::UnknownProfile(name.to_string()).to_string(),
+                }),
+            ));
+        }
+
+        ProjectRepo::set_resource_profile(&state.db, id, profile)
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: e.to_string(),
+                    }),
+                )
+            })?;
+    }
+
+    if let Some(retention) = body.version_retention {
+        if retention.max_versions.is_some_and(|max| max < 0)
+            || retention.max_age_secs.is_some_and(|max| max < 0)
+        {
             return Err((
                 StatusCode::BAD_REQUEST,
                 Json(ErrorResponse {
-                    error: "Project name cannot be empty".into(),
+                    error: "Version retention limits must not be negative".into(),
                 }),
             ));
         }
+
+        ProjectRepo::set_version_retention(&state.db, id, retention)
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: e.to_string(),
+                    }),
+                )
+            })?;
     }
 
-    let updated = ProjectRepo::update(
+    let updated = ProjectRepo::update(&state.db, id, name.as_deref(), body.default_language)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?;
+
+    audit::record(
         &state.db,
-        id,
-        body.name.as_deref(),
-        body.default_language,
+        user.id,
+        updated.id,
+        AuditEventType::ProjectUpdated,
+        json!({
+            "name": updated.name,
+            "default_language": updated.default_language,
+            "allowed_extensions": updated.allowed_extensions,
+            "resource_profile": updated.resource_profile,
+            "version_retention": updated.version_retention,
+        }),
     )
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+    .await;
 
     Ok(Json(ProjectResponse {
         id: updated.id,
         name: updated.name,
         owner_id: updated.owner_id,
         default_language: updated.default_language,
+        allowed_extensions: updated.allowed_extensions,
+        resource_profile: updated.resource_profile,
+        version_retention: updated.version_retention,
         created_at: updated.created_at.to_rfc3339(),
         updated_at: updated.updated_at.to_rfc3339(),
     }))
@@ -287,18 +516,127 @@ pub async fn delete(
         ));
     }
 
-    ProjectRepo::delete(&state.db, id).await.map_err(|e| {
+    // Remove files and the project atomically
+    let db_error = |e: rustyclint_common::Error| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
         )
-    })?;
+    };
+
+    let mut tx = db::begin(&state.db).await.map_err(db_error)?;
+    FileRepo::delete_for_project_tx(&mut tx, id)
+        .await
+        .map_err(db_error)?;
+    ProjectRepo::delete_tx(&mut tx, id)
+        .await
+        .map_err(db_error)?;
+    db::commit(tx).await.map_err(db_error)?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+pub async fn fork(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    body: Option<Json<ForkProjectRequest>>,
+) -> Result<(StatusCode, Json<ProjectResponse>), (StatusCode, Json<ErrorResponse>)> {
+    // Check access
+    if !ProjectRepo::user_has_access(&state.db, id, user.id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    let source = ProjectRepo::find_by_id(&state.db, id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Project not found".into(),
+                }),
+            )
+        })?;
+
+    let name = body
+        .and_then(|Json(b)| b.name)
+        .unwrap_or_else(|| format!("{} (fork)", source.name));
+
+    // Validate name
+    if name.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Project name cannot be empty".into(),
+            }),
+        ));
+    }
+
+    if name.len() > 255 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Project name too long".into(),
+            }),
+        ));
+    }
+
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    };
+
+    let mut tx = db::begin(&state.db).await.map_err(db_error)?;
+    let project = ProjectRepo::fork_tx(&mut tx, id, user.id, &name)
+        .await
+        .map_err(db_error)?;
+    db::commit(tx).await.map_err(db_error)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ProjectResponse {
+            id: project.id,
+            name: project.name,
+            owner_id: project.owner_id,
+            default_language: project.default_language,
+            allowed_extensions: project.allowed_extensions,
+            resource_profile: project.resource_profile,
+            version_retention: project.version_retention,
+            created_at: project.created_at.to_rfc3339(),
+            updated_at: project.updated_at.to_rfc3339(),
+        }),
+    ))
+}
+
 pub async fn list_files(
     State(state): State<AppState>,
     user: AuthUser,
@@ -346,3 +684,384 @@ pub async fn list_files(
 
     Ok(Json(response))
 }
+
+/// Parse a comma-separated `types` filter.
+pub fn parse_event_types(types: &str) -> Result<Vec<AuditEventType>, String> {
+    types
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| {
+            serde_json::from_value(Value::String(t.to_string()))
+                .map_err(|_| format!("Unknown event type: {}", t))
+        })
+        .collect()
+}
+
+/// Find the files in a project containing a string, ignoring case.
+pub async fn search(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<FileSearchHit>>, (StatusCode, Json<ErrorResponse>)> {
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    };
+
+    // Check access
+    if !ProjectRepo::user_has_access(&state.db, id, user.id)
+        .await
+        .map_err(db_error)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    if query.q.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Search query cannot be empty".into(),
+            }),
+        ));
+    }
+
+    let hits = FileRepo::search_in_project(&state.db, id, &query.q)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(hits))
+}
+
+/// Chronological feed of a project's audit events, newest first.
+pub async fn activity(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ActivityQuery>,
+) -> Result<Json<ActivityResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    };
+
+    // Check access
+    if !ProjectRepo::user_has_access(&state.db, id, user.id)
+        .await
+        .map_err(db_error)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    let event_types = parse_event_types(query.types.as_deref().unwrap_or_default())
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_ACTIVITY_LIMIT)
+        .clamp(1, MAX_ACTIVITY_LIMIT);
+
+    let events = AuditRepo::list_for_project(&state.db, id, &event_types, query.before, limit)
+        .await
+        .map_err(db_error)?;
+
+    let next_before = match events.last() {
+        Some(last) if events.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+
+    Ok(Json(ActivityResponse {
+        events: events
+            .into_iter()
+            .map(|e| ActivityEventResponse {
+                id: e.id,
+                event_type: e.event_type,
+                actor_id: e.actor_id,
+                details: e.details,
+                created_at: e.created_at.to_rfc3339(),
+            })
+            .collect(),
+        next_before,
+    }))
+}
+
+/// The project `id`, if `user_id` owns it.
+async fn owned_project(
+    state: &AppState,
+    id: Uuid,
+    user_id: Uuid,
+    action: &str,
+) -> Result<Project, (StatusCode, Json<ErrorResponse>)> {
+    let project = ProjectRepo::find_by_id(&state.db, id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Project not found".into(),
+                }),
+            )
+        })?;
+
+    if project.owner_id != user_id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: format!("Only the owner can {}", action),
+            }),
+        ));
+    }
+
+    Ok(project)
+}
+
+/// People other than the owner with access to a project.
+pub async fn list_collaborators(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<CollaboratorResponse>>, (StatusCode, Json<ErrorResponse>)> {
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    };
+
+    // Check access
+    if !ProjectRepo::user_has_access(&state.db, id, user.id)
+        .await
+        .map_err(db_error)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    let collaborators = ProjectRepo::list_collaborators(&state.db, id)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(collaborators.into_iter().map(Into::into).collect()))
+}
+
+/// Give another user access to a project. Adding an existing collaborator
+/// succeeds without changing anything.
+pub async fn add_collaborator(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(body): Json<AddCollaboratorRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    };
+
+    let project = owned_project(&state, id, user.id, "add collaborators").await?;
+    if body.user_id == project.owner_id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "The owner already has access to this project".into(),
+            }),
+        ));
+    }
+
+    if UserRepo::find_by_id(&state.db, body.user_id)
+        .await
+        .map_err(db_error)?
+        .is_none()
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "User not found".into(),
+            }),
+        ));
+    }
+
+    ProjectRepo::add_collaborator(&state.db, id, body.user_id)
+        .await
+        .map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Revoke a collaborator's access to a project.
+pub async fn remove_collaborator(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    owned_project(&state, id, user.id, "remove collaborators").await?;
+
+    let removed = ProjectRepo::remove_collaborator(&state.db, id, user_id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?;
+    if !removed {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "User is not a collaborator on this project".into(),
+            }),
+        ));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Secret for signing snapshots, or an error if snapshots are disabled.
+fn snapshot_secret(state: &AppState) -> Result<&str, (StatusCode, Json<ErrorResponse>)> {
+    state.config.snapshot_secret.as_deref().ok_or_else(|| {
+        (
+            StatusCode::NOT_IMPLEMENTED,
+            Json(ErrorResponse {
+                error: "Project snapshots are not enabled".into(),
+            }),
+        )
+    })
+}
+
+pub async fn snapshot(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SignedSnapshot>, (StatusCode, Json<ErrorResponse>)> {
+    let secret = snapshot_secret(&state)?;
+
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    };
+
+    // Check access
+    if !ProjectRepo::user_has_access(&state.db, id, user.id)
+        .await
+        .map_err(db_error)?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    let project = ProjectRepo::find_by_id(&state.db, id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Project not found".into(),
+                }),
+            )
+        })?;
+
+    let captured = snapshot::capture(&state.db, &project)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(SignedSnapshot::sign(captured, secret)))
+}
+
+pub async fn import_snapshot(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<SignedSnapshot>,
+) -> Result<(StatusCode, Json<ProjectResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let secret = snapshot_secret(&state)?;
+
+    let verified = body.verify(secret).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+    })?;
+
+    // Validate name
+    if verified.name.trim().is_empty() || verified.name.len() > 255 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Invalid project name in snapshot".into(),
+            }),
+        ));
+    }
+
+    let project = snapshot::restore(&state.db, user.id, &verified)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ProjectResponse {
+            id: project.id,
+            name: project.name,
+            owner_id: project.owner_id,
+            default_language: project.default_language,
+            allowed_extensions: project.allowed_extensions,
+            resource_profile: project.resource_profile,
+            version_retention: project.version_retention,
+            created_at: project.created_at.to_rfc3339(),
+            updated_at: project.updated_at.to_rfc3339(),
+        }),
+    ))
+}
@@ -0,0 +1,62 @@
+//! Tests for project routes.
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::StatusCode, Json};
+    use serde_json::json;
+
+    use crate::{config::Config, routes::projects::check_project_name};
+
+    fn config() -> Config {
+        serde_json::from_value(json!({
+            "database_url": "postgres://localhost/test",
+            "redis_url": "redis://localhost",
+            "jwt_secret": "secret",
+            "reserved_project_names": ["admin", "System"],
+        }))
+        .unwrap()
+    }
+
+    fn accepted(name: &str) -> String {
+        match check_project_name(&config(), name) {
+            Ok(name) => name,
+            Err((_, Json(body))) => panic!("{:?} rejected: {}", name, body.error),
+        }
+    }
+
+    fn rejected(name: &str) -> String {
+        let Err((status, Json(body))) = check_project_name(&config(), name) else {
+            panic!("{:?} accepted", name);
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        body.error
+    }
+
+    #[test]
+    fn test_valid_names_trimmed() {
+        assert_eq!(accepted("  My App "), "My App");
+        assert_eq!(accepted("C++ notes (fork)"), "C++ notes (fork)");
+        assert_eq!(accepted("Übungen_2024"), "Übungen_2024");
+    }
+
+    #[test]
+    fn test_control_characters_rejected() {
+        assert_eq!(
+            rejected("demo\u{0}project"),
+            "Project name cannot contain control characters"
+        );
+        assert_eq!(
+            rejected("line\nbreak"),
+            "Project name cannot contain control characters"
+        );
+        assert_eq!(rejected("<script>"), "Project name cannot contain '<'");
+        assert_eq!(rejected("   "), "Project name cannot be empty");
+    }
+
+    #[test]
+    fn test_reserved_names_rejected() {
+        assert_eq!(rejected("Admin"), "Project name \"Admin\" is reserved");
+        assert_eq!(rejected(" system "), "Project name \"system\" is reserved");
+        assert_eq!(accepted("admin tools"), "admin tools");
+    }
+}
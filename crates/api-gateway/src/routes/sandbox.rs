@@ -1,34 +1,198 @@
 //! Code execution sandbox routes.
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use rustyclint_common::models::Language;
-use rustyclint_sandbox::{ExecutionRequest, ResourceLimits, SandboxExecutor};
+use rustyclint_common::{
+    db::{ProjectRepo, SandboxSessionRepo},
+    models::{AuditEventType, Language, Project, SandboxSession},
+};
+use rustyclint_sandbox::{
+    executor::validate_post_run, output::TAIL_MAX_LINES, CaseReport, Complexity, ContainerManager,
+    ExecutionRequest, ExecutionResult, ExpiryReason, ImageOverrides, LineCoverage, LineEndings,
+    PrecheckMode, ResourceLimits, SandboxError, SandboxExecutor, StdinEncoding, TestReport,
+    ToolchainInfo,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
-use crate::{auth::AuthUser, state::AppState};
+use crate::{
+    audit,
+    auth::AuthUser,
+    callback::{self, RetryPolicy},
+    concurrency::{ExecutionSlot, TooManyExecutions},
+    config::Config,
+    quota::QuotaExceeded,
+    rate_limit::RateLimited,
+    result_cache::ResultCache,
+    state::AppState,
+};
 
 #[derive(Deserialize)]
 pub struct RunCodeRequest {
     pub code: String,
     pub language: Language,
     pub stdin: Option<String>,
+    #[serde(default)]
+    pub stdin_encoding: StdinEncoding,
+    #[serde(default)]
+    pub line_endings: LineEndings,
+    /// Command-line arguments passed to the program.
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub post_run: Option<Vec<String>>,
+    #[serde(default)]
+    pub strip_ansi: Option<bool>,
+    /// The program's output depends only on its input, so an earlier
+    /// identical run's result may be returned instead of executing.
+    #[serde(default)]
+    pub deterministic: bool,
+    /// Run in the background and POST the signed result here when done.
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Named resource profile configured by the operator; defaults to the
+    /// project's profile, then `snippet`.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Project the run belongs to, whose defaults apply.
+    #[serde(default)]
+    pub project_id: Option<Uuid>,
+    /// Seed for reproducible runs; see `rustyclint_sandbox::seed`.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Report which lines ran, for languages with a coverage tool.
+    #[serde(default)]
+    pub coverage: bool,
+    /// Also return stdout and stderr interleaved in the order written.
+    #[serde(default)]
+    pub combined_output: bool,
+}
+
+/// Body POSTed to a run's callback URL.
+#[derive(Serialize)]
+pub struct CallbackPayload {
+    pub execution_id: Uuid,
+    pub result: Option<ExecutionResult>,
+    pub error: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct RunCodeResponse {
     pub stdout: String,
     pub stderr: String,
-    pub exit_code: i64,
+    /// `null` if the program's exit code could not be determined.
+    pub exit_code: Option<i64>,
     pub execution_time_ms: u64,
     pub timed_out: bool,
+    /// Output exceeded the run's limit and was cut short.
+    pub truncated: bool,
+    /// Lines that ran, when coverage was requested and is supported.
+    pub coverage: Option<Vec<LineCoverage>>,
+    /// Compiler diagnostics, for compiled languages.
+    pub compile_stderr: Option<String>,
+    /// The program failed to compile and did not run.
+    pub compile_failed: bool,
+    pub post_run_output: Option<String>,
+    pub image: String,
+    pub image_digest: Option<String>,
+    /// Findings from the advisory pre-check.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Result was served from the result cache.
+    pub cached: bool,
+}
+
+/// Maximum number of cases in one batch run.
+pub const MAX_BATCH_CASES: usize = 20;
+
+#[derive(Deserialize)]
+pub struct BatchCase {
+    pub name: String,
+    pub stdin: Option<String>,
+    /// Output the case must print to pass.
+    pub expected_stdout: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchRunRequest {
+    /// Suite name used in reports.
+    #[serde(default = "default_suite_name")]
+    pub name: String,
+    pub code: String,
+    pub language: Language,
+    pub cases: Vec<BatchCase>,
+    /// Named resource profile configured by the operator; defaults to the
+    /// project's profile, then `snippet`.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Project the run belongs to, whose defaults apply.
+    #[serde(default)]
+    pub project_id: Option<Uuid>,
+    /// Seed for reproducible runs; see `rustyclint_sandbox::seed`.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_suite_name() -> String {
+    "submission".into()
+}
+
+/// Format of a batch run's response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// JSON test report.
+    Json,
+    /// JUnit XML, for CI tooling.
+    Junit,
+}
+
+#[derive(Deserialize)]
+pub struct BatchQuery {
+    pub format: Option<ReportFormat>,
+}
+
+/// Pick the report format from `?format=`, falling back to the `Accept` header.
+pub fn report_format(query: Option<ReportFormat>, headers: &HeaderMap) -> ReportFormat {
+    if let Some(format) = query {
+        return format;
+    }
+
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if accept.contains("application/xml") || accept.contains("text/xml") {
+        ReportFormat::Junit
+    } else {
+        ReportFormat::Json
+    }
+}
+
+/// Render a report in the requested format.
+pub fn report_response(report: &TestReport, format: ReportFormat) -> Response {
+    match format {
+        ReportFormat::Json => Json(report).into_response(),
+        ReportFormat::Junit => (
+            [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+            report.to_junit_xml(),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateSessionRequest {
+    pub project_id: Uuid,
+    pub language: Language,
 }
 
 #[derive(Serialize)]
@@ -39,49 +203,282 @@ pub struct SessionResponse {
     pub expires_at: String,
 }
 
+impl From<SandboxSession> for SessionResponse {
+    fn from(session: SandboxSession) -> Self {
+        Self {
+            id: session.id,
+            language: session.language,
+            created_at: session.created_at.to_rfc3339(),
+            expires_at: session.expires_at.to_rfc3339(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct ErrorResponse {
     pub error: String,
 }
 
+/// Map a sandbox failure to a response.
+///
+/// A missing runtime image or an image running code as root is a deployment
+/// problem rather than a failed run, so it is reported as unavailable with
+/// the sandbox's own message.
+pub fn sandbox_error_response(
+    context: &str,
+    error: SandboxError,
+) -> (StatusCode, Json<ErrorResponse>) {
+    match error {
+        SandboxError::ImageUnavailable(_) | SandboxError::InsecureContainer(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse {
+                error: error.to_string(),
+            }),
+        ),
+        SandboxError::Cancelled => (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: error.to_string(),
+            }),
+        ),
+        SandboxError::InvalidStdin(_) | SandboxError::UnknownProfile(_) => (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: error.to_string(),
+            }),
+        ),
+        SandboxError::Docker(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: format!("{}: {}", context, e),
+            }),
+        ),
+    }
+}
+
 // Lazy-initialized executor
-static EXECUTOR: std::sync::OnceLock<Arc<Mutex<Option<SandboxExecutor>>>> = std::sync::OnceLock::new();
+static EXECUTOR: std::sync::OnceLock<Arc<Mutex<Option<SandboxExecutor>>>> =
+    std::sync::OnceLock::new();
 
-fn get_executor() -> &'static Arc<Mutex<Option<SandboxExecutor>>> {
+pub fn get_executor() -> &'static Arc<Mutex<Option<SandboxExecutor>>> {
     EXECUTOR.get_or_init(|| Arc::new(Mutex::new(None)))
 }
 
-pub async fn run_code(
-    State(_state): State<AppState>,
-    _user: AuthUser,
-    Json(body): Json<RunCodeRequest>,
-) -> Result<Json<RunCodeResponse>, (StatusCode, Json<ErrorResponse>)> {
-    // Validate code size
-    if body.code.len() > 100_000 {
-        return Err((
-            StatusCode::BAD_REQUEST,
+/// Fill the shared executor's pool for every language, so the first runs
+/// after startup don't each wait for a container.
+///
+/// The executor lock is taken per language, letting runs in between.
+pub async fn prewarm_executor_pool(state: &AppState) {
+    if !state.config.sandbox_enabled || state.config.sandbox_pool_size == 0 {
+        return;
+    }
+
+    for &language in Language::all() {
+        let mut executor_guard = get_executor().lock().await;
+        if let Err((_, Json(e))) = ensure_executor(state, &mut executor_guard) {
+            tracing::warn!("Cannot prewarm the container pool: {}", e.error);
+            return;
+        }
+        match executor_guard.as_ref().unwrap().prewarm(language).await {
+            Ok(added) => tracing::info!("Prewarmed {} {:?} containers", added, language),
+            Err(e) => tracing::warn!("Failed to prewarm {:?} containers: {}", language, e),
+        }
+    }
+}
+
+/// Remove the shared executor's pooled containers.
+pub async fn drain_executor_pool() {
+    if let Some(executor) = get_executor().lock().await.as_ref() {
+        executor.drain_pool().await;
+    }
+}
+
+/// Profile a run uses: the one it asks for, else its project's default.
+///
+/// A project default is only honoured while the operator still configures
+/// it in `profiles`; anything else falls back to the deployment default.
+pub fn effective_profile(
+    requested: Option<String>,
+    project: Option<&Project>,
+    profiles: &HashMap<String, ResourceLimits>,
+) -> Option<String> {
+    requested.or_else(|| {
+        project
+            .and_then(|project| project.resource_profile.clone())
+            .filter(|profile| profiles.contains_key(profile))
+    })
+}
+
+/// Load a project the user may run code in.
+async fn find_project(
+    state: &AppState,
+    user_id: Uuid,
+    project_id: Uuid,
+) -> Result<Project, (StatusCode, Json<ErrorResponse>)> {
+    let db_error = |e: rustyclint_common::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
-                error: "Code too large (max 100KB)".into(),
+                error: e.to_string(),
             }),
-        ));
-    }
+        )
+    };
 
-    if body.code.trim().is_empty() {
+    if !ProjectRepo::user_has_access(&state.db, project_id, user_id)
+        .await
+        .map_err(db_error)?
+    {
         return Err((
-            StatusCode::BAD_REQUEST,
+            StatusCode::FORBIDDEN,
             Json(ErrorResponse {
-                error: "Code cannot be empty".into(),
+                error: "Access denied".into(),
             }),
         ));
     }
 
-    // Initialize executor if needed
-    let executor_lock = get_executor();
-    let mut executor_guard = executor_lock.lock().await;
+    ProjectRepo::find_by_id(&state.db, project_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Project not found".into(),
+                }),
+            )
+        })
+}
+
+/// Refuse sandbox work when execution is switched off in this deployment.
+pub fn ensure_sandbox_enabled(config: &Config) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if config.sandbox_enabled {
+        return Ok(());
+    }
+
+    Err((
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(ErrorResponse {
+            error: "Code execution is disabled on this server".into(),
+        }),
+    ))
+}
+
+/// Refuse submissions larger than `max_code_bytes`.
+pub fn check_code_size(
+    config: &Config,
+    code: &str,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if code.len() <= config.max_code_bytes {
+        return Ok(());
+    }
 
-    if executor_guard.is_none() {
-        *executor_guard = Some(
-            SandboxExecutor::with_limits(ResourceLimits::snippet()).map_err(|e| {
+    Err((
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: format!("Code too large (max {} bytes)", config.max_code_bytes),
+        }),
+    ))
+}
+
+/// Refuse more than `max_exec_args` program arguments, or arguments longer
+/// than `max_exec_args_bytes` combined.
+pub fn check_args(
+    config: &Config,
+    args: &[String],
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let error = if args.len() > config.max_exec_args {
+        format!("Too many arguments (max {})", config.max_exec_args)
+    } else if args.iter().map(String::len).sum::<usize>() > config.max_exec_args_bytes {
+        format!(
+            "Arguments too long (max {} bytes combined)",
+            config.max_exec_args_bytes
+        )
+    } else {
+        return Ok(());
+    };
+
+    Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))
+}
+
+/// Why a user's executions were not admitted.
+#[derive(Debug, thiserror::Error)]
+pub enum ExecutionRefused {
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+    #[error(transparent)]
+    Busy(#[from] TooManyExecutions),
+    #[error(transparent)]
+    QuotaExceeded(#[from] QuotaExceeded),
+}
+
+impl IntoResponse for ExecutionRefused {
+    fn into_response(self) -> Response {
+        match self {
+            Self::RateLimited(limited) => limited.into_response(),
+            Self::Busy(busy) => busy.into_response(),
+            Self::QuotaExceeded(exceeded) => exceeded.into_response(),
+        }
+    }
+}
+
+/// Count `runs` executions by `user_id` against the per-minute limit and
+/// daily quota, and take one of the user's execution slots for them.
+///
+/// The slot is held until the returned guard drops, even if a run fails.
+/// If admission is refused, the runs already counted are given back, so a
+/// batch refused partway doesn't use up the limits of the runs before it.
+/// A run the rate limit rejects still counts, as it does on its own.
+pub async fn admit_executions(
+    state: &AppState,
+    user_id: Uuid,
+    runs: usize,
+) -> Result<ExecutionSlot, ExecutionRefused> {
+    let now = chrono::Utc::now();
+    // Checked first so throttled attempts don't use up the daily quota
+    for checked in 0..runs as u64 {
+        if let Err(limited) = state.rate_limit.check(user_id, now).await {
+            state.rate_limit.refund(user_id, now, checked).await;
+            return Err(limited.into());
+        }
+    }
+    let slot = match state.user_executions.acquire(user_id) {
+        Ok(slot) => slot,
+        Err(busy) => {
+            state.rate_limit.refund(user_id, now, runs as u64).await;
+            return Err(busy.into());
+        }
+    };
+    for consumed in 1..=runs as u64 {
+        if let Err(exceeded) = state.quota.consume(user_id, now).await {
+            state.rate_limit.refund(user_id, now, runs as u64).await;
+            state.quota.refund(user_id, now, consumed).await;
+            return Err(exceeded.into());
+        }
+    }
+    Ok(slot)
+}
+
+/// Create the shared executor on first use.
+pub fn ensure_executor(
+    state: &AppState,
+    slot: &mut Option<SandboxExecutor>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if slot.is_none() {
+        *slot = Some(
+            SandboxExecutor::with_images(
+                ResourceLimits::snippet(),
+                ImageOverrides::new(state.config.sandbox_images.clone()),
+            )
+            .map(|executor| {
+                executor
+                    .with_profiles(state.config.resource_profiles.clone())
+                    .with_tracker(state.executions.clone())
+                    .with_pool(state.config.sandbox_pool_size)
+                    .with_allow_root(state.config.sandbox_allow_root)
+                    .with_pull_timeout(Duration::from_secs(state.config.image_pull_timeout_secs))
+                    .with_max_result_bytes(state.config.max_result_bytes)
+            })
+            .map_err(|e| {
                 (
                     StatusCode::SERVICE_UNAVAILABLE,
                     Json(ErrorResponse {
@@ -92,26 +489,198 @@ pub async fn run_code(
         );
     }
 
-    let executor = executor_guard.as_ref().unwrap();
+    Ok(())
+}
+
+// Toolchain info keyed by image digest (or reference when unpinned)
+static ENV_CACHE: std::sync::OnceLock<Mutex<HashMap<String, ToolchainInfo>>> =
+    std::sync::OnceLock::new();
+
+fn get_env_cache() -> &'static Mutex<HashMap<String, ToolchainInfo>> {
+    ENV_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub async fn run_code(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<RunCodeRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    ensure_sandbox_enabled(&state.config)?;
+    check_code_size(&state.config, &body.code)?;
+    check_args(&state.config, &body.args)?;
+
+    if body.code.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Code cannot be empty".into(),
+            }),
+        ));
+    }
+
+    if let Some(ref post_run) = body.post_run {
+        validate_post_run(post_run)
+            .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+    }
+
+    let project = match body.project_id {
+        Some(project_id) => Some(find_project(&state, user.id, project_id).await?),
+        None => None,
+    };
+    let profile = effective_profile(
+        body.profile,
+        project.as_ref(),
+        &state.config.resource_profiles,
+    );
+
+    // Reject unknown profiles before the run counts against the quota
+    ResourceLimits::profile(profile.as_deref(), &state.config.resource_profiles)
+        .map_err(|e| sandbox_error_response("Invalid profile", e))?;
+
+    // Cheap static check before spending a container
+    let warnings = match state.config.code_precheck {
+        PrecheckMode::Off => Vec::new(),
+        mode => {
+            let warnings = Complexity::estimate(&body.code, body.language).warnings();
+            if mode == PrecheckMode::Strict && !warnings.is_empty() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse {
+                        error: format!("Submission rejected: {}", warnings.join("; ")),
+                    }),
+                ));
+            }
+            warnings
+        }
+    };
 
-    // Execute code
     let request = ExecutionRequest {
         code: body.code,
         language: body.language,
         stdin: body.stdin,
-        args: vec![],
+        stdin_encoding: body.stdin_encoding,
+        line_endings: body.line_endings,
+        args: body.args,
+        post_run: body.post_run,
+        memory_bytes: None,
+        strip_ansi: body.strip_ansi,
+        callback_url: body.callback_url,
+        profile,
+        seed: body.seed,
+        coverage: body.coverage,
+        combined_output: body.combined_output,
     };
 
-    // Note: In production, you'd want to use a pool of executors
-    // and implement proper rate limiting per user
-    let result = executor.execute(request).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: format!("Execution failed: {}", e),
-            }),
+    // Decoded up front so malformed stdin is rejected before it counts
+    request
+        .stdin_bytes()
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+
+    // Checked before admission so a rejected callback doesn't count
+    let callback = match request.callback_url {
+        Some(ref url) => {
+            let uri = callback::validate_callback_url(url, &state.config.callback_allowlist)
+                .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+            let secret = state.config.callback_secret.clone().ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse {
+                        error: "Execution callbacks are not enabled".into(),
+                    }),
+                )
+            })?;
+            Some((uri, secret))
+        }
+        None => None,
+    };
+
+    // Held until the run (or its callback task) ends
+    let slot = match admit_executions(&state, user.id, 1).await {
+        Ok(slot) => slot,
+        Err(refused) => return Ok(refused.into_response()),
+    };
+
+    // Counted for load shedding until the run (or its callback task) ends
+    let load = state.load.track();
+
+    // Fire-and-forget: run in the background and report via the callback
+    if let Some((uri, secret)) = callback {
+        // Registered now so the id can be used to tail the run right away
+        let handle = state.executions.register(user.id);
+        let execution_id = handle.id();
+        tokio::spawn(async move {
+            let _load = load;
+            let _slot = slot;
+            let outcome = {
+                let mut executor_guard = get_executor().lock().await;
+                match ensure_executor(&state, &mut executor_guard) {
+                    Ok(()) => executor_guard
+                        .as_ref()
+                        .unwrap()
+                        .execute_tracked(handle, request)
+                        .await
+                        .map_err(|e| format!("Execution failed: {}", e)),
+                    Err((_, Json(e))) => Err(e.error),
+                }
+            };
+
+            let (result, error) = match outcome {
+                Ok(result) => (Some(result), None),
+                Err(error) => (None, Some(error)),
+            };
+            let payload = CallbackPayload {
+                execution_id,
+                result,
+                error,
+            };
+            let body = serde_json::to_vec(&payload).unwrap_or_default();
+            if let Err(e) = callback::deliver(&uri, &secret, body, &RetryPolicy::default()).await {
+                tracing::error!(
+                    "Giving up on callback for execution {}: {}",
+                    execution_id,
+                    e
+                );
+            }
+        });
+
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({ "execution_id": execution_id, "warnings": warnings })),
         )
-    })?;
+            .into_response());
+    }
+
+    // Initialize executor if needed
+    let executor_lock = get_executor();
+    let mut executor_guard = executor_lock.lock().await;
+    ensure_executor(&state, &mut executor_guard)?;
+
+    let executor = executor_guard.as_ref().unwrap();
+
+    // Only runs on a digest-pinned image are reproducible enough to cache
+    let cache_key = match body.deterministic {
+        true => executor
+            .resolve_image(request.language)
+            .await
+            .digest
+            .map(|digest| ResultCache::key(&request, &digest)),
+        false => None,
+    };
+
+    // Note: In production, you'd want to use a pool of executors
+    let (result, cached) = match cache_key {
+        Some(key) => {
+            state
+                .results
+                .get_or_execute(&key, || executor.execute_for_user(user.id, request))
+                .await
+        }
+        None => executor
+            .execute_for_user(user.id, request)
+            .await
+            .map(|result| (result, false)),
+    }
+    .map_err(|e| sandbox_error_response("Execution failed", e))?;
 
     Ok(Json(RunCodeResponse {
         stdout: result.stdout,
@@ -119,31 +688,303 @@ pub async fn run_code(
         exit_code: result.exit_code,
         execution_time_ms: result.execution_time_ms,
         timed_out: result.timed_out,
+        truncated: result.truncated,
+        coverage: result.coverage,
+        compile_stderr: result.compile_stderr,
+        compile_failed: result.compile_failed,
+        post_run_output: result.post_run_output,
+        image: result.image,
+        image_digest: result.image_digest,
+        warnings,
+        cached,
+    })
+    .into_response())
+}
+
+/// Run one program against several inputs and report pass/fail per case.
+pub async fn run_batch(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<BatchQuery>,
+    headers: HeaderMap,
+    Json(body): Json<BatchRunRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    ensure_sandbox_enabled(&state.config)?;
+    check_code_size(&state.config, &body.code)?;
+
+    if body.cases.is_empty() || body.cases.len() > MAX_BATCH_CASES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("A batch needs between 1 and {} cases", MAX_BATCH_CASES),
+            }),
+        ));
+    }
+
+    let project = match body.project_id {
+        Some(project_id) => Some(find_project(&state, user.id, project_id).await?),
+        None => None,
+    };
+    let profile = effective_profile(
+        body.profile,
+        project.as_ref(),
+        &state.config.resource_profiles,
+    );
+
+    ResourceLimits::profile(profile.as_deref(), &state.config.resource_profiles)
+        .map_err(|e| sandbox_error_response("Invalid profile", e))?;
+
+    // Each case is a run of its own, so a batch can't sidestep the limits
+    let _slot = match admit_executions(&state, user.id, body.cases.len()).await {
+        Ok(slot) => slot,
+        Err(refused) => return Ok(refused.into_response()),
+    };
+    let _load = state.load.track();
+    let executor_lock = get_executor();
+    let mut executor_guard = executor_lock.lock().await;
+    ensure_executor(&state, &mut executor_guard)?;
+
+    let executor = executor_guard.as_ref().unwrap();
+
+    let mut cases = Vec::with_capacity(body.cases.len());
+    for case in body.cases {
+        let request = ExecutionRequest {
+            code: body.code.clone(),
+            language: body.language,
+            stdin: case.stdin,
+            stdin_encoding: StdinEncoding::Text,
+            line_endings: LineEndings::Preserve,
+            args: vec![],
+            post_run: None,
+            memory_bytes: None,
+            strip_ansi: Some(true),
+            callback_url: None,
+            profile: profile.clone(),
+            seed: body.seed,
+            coverage: false,
+            combined_output: false,
+        };
+
+        let result = executor
+            .execute_for_user(user.id, request)
+            .await
+            .map_err(|e| sandbox_error_response("Execution failed", e))?;
+
+        cases.push(CaseReport::grade(
+            case.name,
+            case.expected_stdout.as_deref(),
+            &result,
+        ));
+    }
+
+    let report = TestReport::new(body.name, cases);
+    Ok(report_response(
+        &report,
+        report_format(query.format, &headers),
+    ))
+}
+
+/// Lines returned by a tail request that does not ask for a number.
+pub const DEFAULT_TAIL_LINES: usize = 50;
+
+#[derive(Deserialize)]
+pub struct TailQuery {
+    pub lines: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct TailResponse {
+    pub execution_id: Uuid,
+    /// Most recent output lines, oldest first.
+    pub lines: Vec<String>,
+}
+
+/// Recent output of one of the user's running executions.
+///
+/// At most [`TAIL_MAX_LINES`] are kept per execution; asking for more
+/// returns what is buffered.
+pub async fn tail_execution(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(execution_id): Path<Uuid>,
+    Query(query): Query<TailQuery>,
+) -> Result<Json<TailResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let lines = query
+        .lines
+        .unwrap_or(DEFAULT_TAIL_LINES)
+        .min(TAIL_MAX_LINES);
+
+    let lines = state
+        .executions
+        .tail(user.id, execution_id, lines)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "No running execution with that id".into(),
+                }),
+            )
+        })?;
+
+    Ok(Json(TailResponse {
+        execution_id,
+        lines,
     }))
 }
 
-pub async fn list_sessions(
-    State(_state): State<AppState>,
+/// Report OS and toolchain versions of a language's sandbox image.
+pub async fn environment(
+    State(state): State<AppState>,
     _user: AuthUser,
+    Path(language): Path<Language>,
+) -> Result<Json<ToolchainInfo>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_sandbox_enabled(&state.config)?;
+
+    let executor_lock = get_executor();
+    let mut executor_guard = executor_lock.lock().await;
+    ensure_executor(&state, &mut executor_guard)?;
+
+    let executor = executor_guard.as_ref().unwrap();
+
+    // Probing starts a container, so only do it once per image
+    let image = executor.resolve_image(language).await;
+    let key = image.digest.unwrap_or(image.image);
+    if let Some(info) = get_env_cache().lock().await.get(&key) {
+        return Ok(Json(info.clone()));
+    }
+
+    let info = executor
+        .probe_environment(language)
+        .await
+        .map_err(|e| sandbox_error_response("Environment probe failed", e))?;
+
+    get_env_cache().lock().await.insert(key, info.clone());
+
+    Ok(Json(info))
+}
+
+pub async fn create_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<CreateSessionRequest>,
+) -> Result<(StatusCode, Json<SessionResponse>), (StatusCode, Json<ErrorResponse>)> {
+    ensure_sandbox_enabled(&state.config)?;
+
+    // Check project access
+    if !ProjectRepo::user_has_access(&state.db, body.project_id, user.id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Access denied".into(),
+            }),
+        ));
+    }
+
+    let containers =
+        ContainerManager::with_images(ImageOverrides::new(state.config.sandbox_images.clone()))
+            .map(|containers| {
+                containers
+                    .with_allow_root(state.config.sandbox_allow_root)
+                    .with_pull_timeout(Duration::from_secs(state.config.image_pull_timeout_secs))
+            })
+            .map_err(|e| {
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(ErrorResponse {
+                        error: format!("Sandbox unavailable: {}", e),
+                    }),
+                )
+            })?;
+
+    let container_id = containers
+        .create_container(body.language, &ResourceLimits::default())
+        .await
+        .map_err(|e| sandbox_error_response("Failed to start session", e))?;
+
+    let session = state.sessions.create(
+        user.id,
+        body.project_id,
+        container_id,
+        body.language,
+        chrono::Utc::now(),
+    );
+
+    // Recorded so a restarted gateway still finds the container
+    if let Err(e) = SandboxSessionRepo::create(&state.db, &session).await {
+        state.sessions.remove(session.id, ExpiryReason::Stopped);
+        let _ = containers.remove_container(&session.container_id).await;
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ));
+    }
+
+    audit::record(
+        &state.db,
+        user.id,
+        body.project_id,
+        AuditEventType::SessionStarted,
+        json!({ "session_id": session.id, "language": body.language }),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(session.into())))
+}
+
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    user: AuthUser,
 ) -> Result<Json<Vec<SessionResponse>>, (StatusCode, Json<ErrorResponse>)> {
-    // TODO: Query sandbox_sessions table for active user sessions
-    // For now, return empty list since we're using ephemeral containers
-    Ok(Json(vec![]))
+    let sessions = state
+        .sessions
+        .list_for_user(user.id)
+        .into_iter()
+        .map(SessionResponse::from)
+        .collect();
+
+    Ok(Json(sessions))
 }
 
 pub async fn stop_session(
-    State(_state): State<AppState>,
-    _user: AuthUser,
-    Path(_id): Path<Uuid>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    // TODO: Implement session management
-    // 1. Verify session belongs to user
-    // 2. Stop the container
-    // 3. Remove from database
-    Err((
-        StatusCode::NOT_IMPLEMENTED,
-        Json(ErrorResponse {
-            error: "Session management not yet implemented".into(),
-        }),
-    ))
+    // Verify session belongs to user
+    let session = state
+        .sessions
+        .get(id)
+        .filter(|session| session.user_id == user.id)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Session not found".into(),
+                }),
+            )
+        })?;
+
+    state.sessions.remove(id, ExpiryReason::Stopped);
+    state.lsp_changes.forget_session(id).await;
+
+    if let Ok(containers) = ContainerManager::new() {
+        let _ = containers.remove_container(&session.container_id).await;
+    }
+    if let Err(e) = SandboxSessionRepo::delete(&state.db, id).await {
+        tracing::warn!("Failed to forget session {}: {}", id, e);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
 }
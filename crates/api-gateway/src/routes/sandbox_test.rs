@@ -0,0 +1,111 @@
+//! Tests for sandbox route helpers.
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+    use chrono::Utc;
+    use rustyclint_common::models::{Language, Project};
+    use rustyclint_sandbox::{ResourceLimits, SandboxError};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::{
+        config::Config,
+        routes::sandbox::{
+            check_args, effective_profile, report_format, sandbox_error_response, ReportFormat,
+        },
+    };
+
+    #[test]
+    fn test_report_format_from_query_or_accept() {
+        let mut xml = HeaderMap::new();
+        xml.insert(header::ACCEPT, HeaderValue::from_static("application/xml"));
+
+        assert_eq!(report_format(None, &HeaderMap::new()), ReportFormat::Json);
+        assert_eq!(report_format(None, &xml), ReportFormat::Junit);
+        assert_eq!(report_format(Some(ReportFormat::Json), &xml), ReportFormat::Json);
+    }
+
+    #[test]
+    fn test_missing_image_is_service_unavailable() {
+        let (status, body) = sandbox_error_response(
+            "Execution failed",
+            SandboxError::ImageUnavailable(Language::Rust),
+        );
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body.error, "runtime for Rust is not installed");
+
+        let (status, _) = sandbox_error_response(
+            "Execution failed",
+            SandboxError::from(std::io::Error::other("connection reset")),
+        );
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_project_profile_used_by_default() {
+        let project = Project {
+            id: Uuid::new_v4(),
+            name: "notebooks".into(),
+            owner_id: Uuid::new_v4(),
+            default_language: Language::Python,
+            allowed_extensions: None,
+            resource_profile: Some("heavy-ml".into()),
+            version_retention: Default::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let profiles = HashMap::from([("heavy-ml".to_string(), ResourceLimits::snippet())]);
+
+        assert_eq!(
+            effective_profile(None, Some(&project), &profiles).as_deref(),
+            Some("heavy-ml")
+        );
+        // An explicit profile still wins
+        assert_eq!(
+            effective_profile(Some("snippet".into()), Some(&project), &profiles).as_deref(),
+            Some("snippet")
+        );
+        assert_eq!(effective_profile(None, None, &profiles), None);
+
+        // Once the operator drops the profile, the project default lapses
+        assert_eq!(
+            effective_profile(None, Some(&project), &HashMap::new()),
+            None
+        );
+
+        let unconfigured = Project {
+            resource_profile: None,
+            version_retention: Default::default(),
+            ..project
+        };
+        assert!(effective_profile(None, Some(&unconfigured), &profiles).is_none());
+    }
+
+    #[test]
+    fn test_args_count_and_length_capped() {
+        let config: Config = serde_json::from_value(json!({
+            "database_url": "postgres://localhost/test",
+            "redis_url": "redis://localhost",
+            "jwt_secret": "secret",
+            "max_exec_args": 3,
+            "max_exec_args_bytes": 10,
+        }))
+        .unwrap();
+        let args = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+        assert!(check_args(&config, &[]).is_ok());
+        assert!(check_args(&config, &args(&["-v", "in.txt", "9"])).is_ok());
+
+        let (status, body) = check_args(&config, &args(&["a", "b", "c", "d"])).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "Too many arguments (max 3)");
+
+        let (status, body) = check_args(&config, &args(&["--verbose", "out"])).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "Arguments too long (max 10 bytes combined)");
+    }
+}
@@ -4,13 +4,23 @@ use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
-use axum::{extract::State, http::StatusCode, Json};
-use rustyclint_common::db::UserRepo;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rustyclint_common::{
+    db::{RefreshTokenRepo, UserRepo},
+    models::normalize_email,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
 use crate::{
-    auth::{create_token, AuthUser},
+    auth::{create_token, issue_token, AccessToken, AuthUser, RefreshToken, Token},
+    privacy,
     state::AppState,
 };
 
@@ -29,10 +39,22 @@ pub struct LoginRequest {
 
 #[derive(Serialize)]
 pub struct AuthResponse {
-    pub token: String,
+    pub token: AccessToken,
+    /// Exchanged at `/auth/refresh` for a new access token.
+    pub refresh_token: RefreshToken,
     pub user: UserResponse,
 }
 
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct RefreshResponse {
+    pub token: AccessToken,
+}
+
 #[derive(Serialize)]
 pub struct UserResponse {
     pub id: Uuid,
@@ -45,40 +67,94 @@ pub struct ErrorResponse {
     pub error: String,
 }
 
-pub async fn register(
-    State(state): State<AppState>,
-    Json(body): Json<RegisterRequest>,
-) -> Result<(StatusCode, Json<AuthResponse>), (StatusCode, Json<ErrorResponse>)> {
-    // Validate input
-    if body.email.is_empty() || !body.email.contains('@') {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid email address".into(),
-            }),
-        ));
-    }
+/// A problem with one submitted field.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Body of a 400 listing every invalid field of a form.
+#[derive(Serialize)]
+pub struct ValidationErrorResponse {
+    pub error: String,
+    pub errors: Vec<FieldError>,
+}
 
+/// Check a registration form, reporting every invalid field at once.
+///
+/// Returns the normalized email on success.
+pub fn validate_registration(body: &RegisterRequest) -> Result<String, Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let mut invalid = |field, message: &str| {
+        errors.push(FieldError {
+            field,
+            message: message.into(),
+        })
+    };
+
+    let email = normalize_email(&body.email);
+    if email.is_err() {
+        invalid("email", "Invalid email address");
+    }
     if body.username.len() < 3 {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Username must be at least 3 characters".into(),
-            }),
-        ));
+        invalid("username", "Username must be at least 3 characters");
     }
-
     if body.password.len() < 8 {
-        return Err((
+        invalid("password", "Password must be at least 8 characters");
+    }
+
+    match email {
+        Ok(email) if errors.is_empty() => Ok(email),
+        _ => Err(errors),
+    }
+}
+
+/// Issue a refresh token for a user and store its digest.
+async fn issue_refresh_token(
+    state: &AppState,
+    user_id: Uuid,
+    email: &str,
+) -> Result<RefreshToken, (StatusCode, Json<ErrorResponse>)> {
+    let internal_error = |error: String| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error }),
+        )
+    };
+
+    let lifetime = chrono::Duration::days(state.config.refresh_token_expiry_days as i64);
+    let token: RefreshToken = issue_token(user_id, email, &state.config.jwt_secret, lifetime)
+        .map_err(|e| internal_error(format!("Token generation failed: {}", e)))?;
+    RefreshTokenRepo::store(
+        &state.db,
+        &token.digest(),
+        user_id,
+        chrono::Utc::now() + lifetime,
+    )
+    .await
+    .map_err(|e| internal_error(e.to_string()))?;
+
+    Ok(token)
+}
+
+pub async fn register(
+    State(state): State<AppState>,
+    Json(body): Json<RegisterRequest>,
+) -> Result<(StatusCode, Json<AuthResponse>), Response> {
+    let email = validate_registration(&body).map_err(|errors| {
+        (
             StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Password must be at least 8 characters".into(),
+            Json(ValidationErrorResponse {
+                error: "Invalid registration".into(),
+                errors,
             }),
-        ));
-    }
+        )
+            .into_response()
+    })?;
 
     // Check if email exists
-    if UserRepo::email_exists(&state.db, &body.email)
+    if UserRepo::email_exists(&state.db, &email)
         .await
         .map_err(|e| {
             (
@@ -87,6 +163,7 @@ pub async fn register(
                     error: e.to_string(),
                 }),
             )
+                .into_response()
         })?
     {
         return Err((
@@ -94,7 +171,8 @@ pub async fn register(
             Json(ErrorResponse {
                 error: "Email already registered".into(),
             }),
-        ));
+        )
+            .into_response());
     }
 
     // Check if username exists
@@ -107,6 +185,7 @@ pub async fn register(
                     error: e.to_string(),
                 }),
             )
+                .into_response()
         })?
     {
         return Err((
@@ -114,7 +193,8 @@ pub async fn register(
             Json(ErrorResponse {
                 error: "Username already taken".into(),
             }),
-        ));
+        )
+            .into_response());
     }
 
     // Hash password
@@ -129,11 +209,12 @@ pub async fn register(
                     error: format!("Password hashing failed: {}", e),
                 }),
             )
+                .into_response()
         })?
         .to_string();
 
     // Create user
-    let user = UserRepo::create(&state.db, &body.email, &body.username, &password_hash)
+    let user = UserRepo::create(&state.db, &email, &body.username, &password_hash)
         .await
         .map_err(|e| {
             (
@@ -142,8 +223,15 @@ pub async fn register(
                     error: e.to_string(),
                 }),
             )
+                .into_response()
         })?;
 
+    tracing::info!(
+        "Registered user {} <{}>",
+        privacy::identifier(&user.username),
+        privacy::email(&user.email)
+    );
+
     // Generate token
     let token = create_token(
         user.id,
@@ -158,12 +246,17 @@ pub async fn register(
                 error: format!("Token generation failed: {}", e),
             }),
         )
+            .into_response()
     })?;
+    let refresh_token = issue_refresh_token(&state, user.id, &user.email)
+        .await
+        .map_err(IntoResponse::into_response)?;
 
     Ok((
         StatusCode::CREATED,
         Json(AuthResponse {
             token,
+            refresh_token,
             user: UserResponse {
                 id: user.id,
                 email: user.email,
@@ -177,8 +270,20 @@ pub async fn login(
     State(state): State<AppState>,
     Json(body): Json<LoginRequest>,
 ) -> Result<Json<AuthResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let invalid_credentials = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                error: "Invalid email or password".into(),
+            }),
+        )
+    };
+
+    // A malformed address cannot belong to an account
+    let email = normalize_email(&body.email).map_err(|_| invalid_credentials())?;
+
     // Find user by email
-    let user = UserRepo::find_by_email(&state.db, &body.email)
+    let user = UserRepo::find_by_email(&state.db, &email)
         .await
         .map_err(|e| {
             (
@@ -189,12 +294,8 @@ pub async fn login(
             )
         })?
         .ok_or_else(|| {
-            (
-                StatusCode::UNAUTHORIZED,
-                Json(ErrorResponse {
-                    error: "Invalid email or password".into(),
-                }),
-            )
+            tracing::info!("Login failed for unknown {}", privacy::email(&email));
+            invalid_credentials()
         })?;
 
     // Verify password
@@ -210,12 +311,11 @@ pub async fn login(
     Argon2::default()
         .verify_password(body.password.as_bytes(), &parsed_hash)
         .map_err(|_| {
-            (
-                StatusCode::UNAUTHORIZED,
-                Json(ErrorResponse {
-                    error: "Invalid email or password".into(),
-                }),
-            )
+            tracing::info!(
+                "Login failed for {}: wrong password",
+                privacy::email(&email)
+            );
+            invalid_credentials()
         })?;
 
     // Generate token
@@ -233,9 +333,13 @@ pub async fn login(
             }),
         )
     })?;
+    let refresh_token = issue_refresh_token(&state, user.id, &user.email).await?;
+
+    tracing::info!("User {} logged in", privacy::identifier(&user.username));
 
     Ok(Json(AuthResponse {
         token,
+        refresh_token,
         user: UserResponse {
             id: user.id,
             email: user.email,
@@ -244,6 +348,87 @@ pub async fn login(
     }))
 }
 
+/// Exchange a refresh token for a new access token.
+pub async fn refresh(
+    State(state): State<AppState>,
+    Json(body): Json<RefreshRequest>,
+) -> Result<Json<RefreshResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let invalid_token = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                error: "Invalid or expired refresh token".into(),
+            }),
+        )
+    };
+
+    let refresh_token = RefreshToken::parse(&body.refresh_token).map_err(|_| invalid_token())?;
+    refresh_token
+        .verify(&state.config.jwt_secret)
+        .map_err(|_| invalid_token())?;
+
+    // A validly signed token still stops working once revoked
+    let user = RefreshTokenRepo::find_user(&state.db, &refresh_token.digest())
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?
+        .ok_or_else(invalid_token)?;
+
+    let token = create_token(
+        user.id,
+        &user.email,
+        &state.config.jwt_secret,
+        state.config.jwt_expiry_hours,
+    )
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: format!("Token generation failed: {}", e),
+            }),
+        )
+    })?;
+
+    Ok(Json(RefreshResponse { token }))
+}
+
+/// Revoke a refresh token, and the access token the request carries, if
+/// any, for the rest of its lifetime. Revoking an unknown or already
+/// revoked token succeeds too, so logging out twice is harmless.
+pub async fn logout(
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+    Json(body): Json<RefreshRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let internal_error = |error: String| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error }),
+        )
+    };
+
+    let refresh_token = RefreshToken::from(body.refresh_token);
+    RefreshTokenRepo::revoke(&state.db, &refresh_token.digest())
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+
+    if let Some(user) = user {
+        state
+            .revoked_tokens
+            .revoke(user.token_id, user.token_expires_at)
+            .await
+            .map_err(|e| internal_error(format!("Failed to revoke access token: {}", e)))?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 pub async fn me(
     State(state): State<AppState>,
     user: AuthUser,
@@ -274,3 +459,46 @@ pub async fn me(
         username: db_user.username,
     }))
 }
+
+/// Get the current user's editor settings.
+pub async fn get_settings(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
+    let settings = UserRepo::get_settings(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?;
+
+    Ok(Json(settings))
+}
+
+/// Replace the current user's editor settings.
+pub async fn put_settings(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(settings): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
+    UserRepo::set_settings(&state.db, user.id, &settings)
+        .await
+        .map_err(|e| {
+            let status = match e {
+                rustyclint_common::Error::Validation(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (
+                status,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })?;
+
+    Ok(Json(settings))
+}
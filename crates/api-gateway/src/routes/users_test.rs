@@ -0,0 +1,39 @@
+//! Tests for user registration.
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::users::{validate_registration, FieldError, RegisterRequest};
+
+    fn form(email: &str, username: &str, password: &str) -> RegisterRequest {
+        RegisterRequest {
+            email: email.into(),
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    #[test]
+    fn test_all_invalid_fields_reported_together() {
+        let errors = validate_registration(&form("not-an-email", "ab", "short")).unwrap_err();
+
+        let fields: Vec<_> = errors.iter().map(|error| error.field).collect();
+        assert_eq!(fields, vec!["email", "username", "password"]);
+        assert_eq!(
+            errors[2],
+            FieldError {
+                field: "password",
+                message: "Password must be at least 8 characters".into(),
+            }
+        );
+
+        let errors = validate_registration(&form("Ada@Example.com", "ab", "long enough")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "username");
+    }
+
+    #[test]
+    fn test_valid_form_yields_normalized_email() {
+        let email = validate_registration(&form(" Ada@Example.com ", "ada", "long enough")).unwrap();
+        assert_eq!(email, "ada@example.com");
+    }
+}
@@ -1,28 +1,52 @@
 //! WebSocket handlers for real-time features.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
     },
     response::Response,
+    Json,
+};
+use futures_util::stream::{SplitSink, SplitStream};
+use rustyclint_collab::{
+    CollabRoom, DocFrame, FollowedCursor, MultiplexedConnection, RoomClosed, RoomError, RoomManager,
+};
+use rustyclint_common::{
+    db::{FileRepo, ProjectRepo, UserRepo},
+    models::{Language, SandboxSession},
+};
+use rustyclint_sandbox::{
+    ContainerManager, ExecutionRequest, ExpiryReason, OutputChunk, PtySession, StdStream,
+    TerminalSize, Utf8StreamDecoder,
 };
-use rustyclint_collab::RoomManager;
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
+use sqlx::PgPool;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{mpsc, RwLock},
+};
 use uuid::Uuid;
 
-use crate::state::AppState;
+use super::sandbox::{
+    admit_executions, check_args, check_code_size, ensure_executor, ensure_sandbox_enabled,
+    get_executor, ErrorResponse,
+};
+use crate::{
+    announce::{Announcement, Announcements, Severity},
+    auth::{authenticate, AccessToken, RevokedTokens},
+    config::Config,
+    doc_log::DocumentLog,
+    privacy,
+    state::AppState,
+};
 
 // y-websocket protocol constants
 const MSG_SYNC: u8 = 0;
-#[allow(dead_code)]
-const MSG_AWARENESS: u8 = 1;
 const SYNC_STEP1: u8 = 0;
 const SYNC_STEP2: u8 = 1;
-const SYNC_UPDATE: u8 = 2;
 
 /// Write a variable-length unsigned integer (lib0 encoding)
 fn write_var_uint(buf: &mut Vec<u8>, mut value: usize) {
@@ -89,18 +113,273 @@ fn encode_sync_step2(update: &[u8]) -> Vec<u8> {
 
 /// Encode a sync update message
 fn encode_sync_update(update: &[u8]) -> Vec<u8> {
-    let mut buf = Vec::new();
-    buf.push(MSG_SYNC);
-    buf.push(SYNC_UPDATE);
-    write_var_uint8_array(&mut buf, update);
-    buf
+    rustyclint_collab::sync::encode_update_message(update)
 }
 
 // Global room manager (in production, this would be in AppState)
 static ROOM_MANAGER: std::sync::OnceLock<Arc<RwLock<RoomManager>>> = std::sync::OnceLock::new();
 
-fn get_room_manager() -> &'static Arc<RwLock<RoomManager>> {
-    ROOM_MANAGER.get_or_init(|| Arc::new(RwLock::new(RoomManager::new())))
+// Scratch rooms are kept apart so their ids never collide with file ids
+static SCRATCH_ROOM_MANAGER: std::sync::OnceLock<Arc<RwLock<RoomManager>>> =
+    std::sync::OnceLock::new();
+
+fn get_room_manager(state: &AppState) -> &'static Arc<RwLock<RoomManager>> {
+    ROOM_MANAGER.get_or_init(|| {
+        let log = DocumentLog::new(state.db.clone(), state.config.doc_log_compact_after);
+        new_room_manager(&state.config, Some(save_on_close(log)))
+    })
+}
+
+fn get_scratch_room_manager(config: &Config) -> &'static Arc<RwLock<RoomManager>> {
+    SCRATCH_ROOM_MANAGER.get_or_init(|| new_room_manager(config, None))
+}
+
+/// Write a file room's text back to the file once its last editor leaves.
+///
+/// Rooms never loaded from the log started empty, so saving them would
+/// blank the file; they are skipped.
+fn save_on_close(log: DocumentLog) -> RoomClosed {
+    Arc::new(move |room: Arc<CollabRoom>| {
+        if !room.is_loaded() {
+            tracing::debug!("Room {} was never loaded; not saved", room.document.id());
+            return;
+        }
+        log.queue_save(room.document.clone());
+    })
+}
+
+fn new_room_manager(config: &Config, on_close: Option<RoomClosed>) -> Arc<RwLock<RoomManager>> {
+    {
+        let mut manager =
+            RoomManager::with_awareness_interval(Duration::from_millis(config.awareness_batch_ms))
+                .with_max_rooms_per_user(config.max_rooms_per_user)
+                .with_max_participants(config.max_room_participants);
+        if let Some(on_close) = on_close {
+            manager = manager.with_on_close(on_close);
+        }
+        let manager = Arc::new(RwLock::new(manager));
+
+        // Prune participants whose connection died without leaving, and
+        // evict those whose connection silently stopped delivering
+        if config.collab_prune_interval_secs > 0 {
+            let manager = Arc::clone(&manager);
+            let period = Duration::from_secs(config.collab_prune_interval_secs);
+            let heartbeat_timeout = (config.collab_heartbeat_timeout_secs > 0)
+                .then(|| Duration::from_secs(config.collab_heartbeat_timeout_secs));
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    let manager = manager.read().await;
+                    let pruned = manager.prune_stale();
+                    if pruned > 0 {
+                        tracing::info!("Pruned {} stale collaboration participants", pruned);
+                    }
+                    if let Some(timeout) = heartbeat_timeout {
+                        let evicted = manager.sweep_stale(timeout);
+                        if evicted > 0 {
+                            tracing::info!("Evicted {} silent collaboration participants", evicted);
+                        }
+                    }
+                }
+            });
+        }
+
+        manager
+    }
+}
+
+/// Longest checkpoint name, in characters.
+const MAX_CHECKPOINT_NAME_CHARS: usize = 100;
+
+/// A checkpoint name with surrounding whitespace removed, if usable.
+pub(crate) fn checkpoint_name(name: &str) -> Option<&str> {
+    let name = name.trim();
+    (!name.is_empty() && name.chars().count() <= MAX_CHECKPOINT_NAME_CHARS).then_some(name)
+}
+
+/// Limits applied to each collaboration socket.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CollabLimits {
+    pub max_awareness_bytes: usize,
+    /// Close sockets with no edits or cursor moves for this long, if set.
+    pub idle_timeout: Option<Duration>,
+    /// Checkpoints a document may have; 0 is unlimited.
+    pub max_checkpoints: usize,
+}
+
+impl CollabLimits {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            max_awareness_bytes: config.max_awareness_bytes,
+            idle_timeout: (config.collab_idle_timeout_secs > 0)
+                .then(|| Duration::from_secs(config.collab_idle_timeout_secs)),
+            max_checkpoints: config.max_checkpoints_per_document,
+        }
+    }
+}
+
+/// Time a collaboration socket has to send its `Auth` message.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checks who opened a collaboration socket.
+///
+/// The first message must be `Auth` with an access token; for a file room
+/// the user must also have access to the file's project.
+#[derive(Clone)]
+pub(crate) struct CollabAuth {
+    pub jwt_secret: String,
+    pub revoked_tokens: RevokedTokens,
+    pub db: PgPool,
+}
+
+impl CollabAuth {
+    fn new(state: &AppState) -> Self {
+        Self {
+            jwt_secret: state.config.jwt_secret.clone(),
+            revoked_tokens: state.revoked_tokens.clone(),
+            db: state.db.clone(),
+        }
+    }
+
+    /// The id and display name of the user sending `first`, or the close
+    /// code and reason to refuse the socket with. `file_id` is the file to
+    /// check access to, `None` for scratch rooms.
+    async fn authenticate(
+        &self,
+        first: Option<Message>,
+        file_id: Option<Uuid>,
+    ) -> Result<(Uuid, String), (u16, String)> {
+        let refuse = |reason: &str| (close_code::POLICY, reason.to_string());
+
+        let token = match first {
+            Some(Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(CollabMessage::Auth { token }) => token,
+                _ => return Err(refuse("Authentication required")),
+            },
+            _ => return Err(refuse("Authentication required")),
+        };
+        let claims = authenticate(&token, &self.jwt_secret, &self.revoked_tokens)
+            .await
+            .map_err(|_| refuse("Invalid authentication token"))?;
+
+        if let Some(file_id) = file_id {
+            self.check_access(claims.sub, file_id).await?;
+        }
+
+        // The name only labels the participant, so a lookup failure need
+        // not keep them out
+        let username = match UserRepo::find_by_id(&self.db, claims.sub).await {
+            Ok(Some(user)) => user.username,
+            Ok(None) => return Err(refuse("Unknown user")),
+            Err(e) => {
+                tracing::warn!("Failed to look up user {}: {}", claims.sub, e);
+                format!("User-{}", &claims.sub.to_string()[..8])
+            }
+        };
+
+        Ok((claims.sub, username))
+    }
+
+    /// Check that `user_id` may edit `file_id`, giving the close code and
+    /// reason to refuse with if not.
+    async fn check_access(&self, user_id: Uuid, file_id: Uuid) -> Result<(), (u16, String)> {
+        let refuse = |reason: &str| (close_code::POLICY, reason.to_string());
+        let unavailable = |e: rustyclint_common::Error| (close_code::ERROR, e.to_string());
+
+        let (file, _) = FileRepo::find_by_id_with_content(&self.db, file_id)
+            .await
+            .map_err(unavailable)?
+            .ok_or_else(|| refuse("File not found"))?;
+        if !ProjectRepo::user_has_access(&self.db, file.project_id, user_id)
+            .await
+            .map_err(unavailable)?
+        {
+            return Err(refuse("Access denied"));
+        }
+        Ok(())
+    }
+
+    /// Wait for a socket's `Auth` message and authenticate it, refusing the
+    /// socket with a failed `AuthResult` and a close frame if that fails.
+    ///
+    /// Success is signalled by whatever the socket sends next, as y-websocket
+    /// clients expect binary frames only.
+    async fn accept(
+        &self,
+        sender: &mut SplitSink<WebSocket, Message>,
+        receiver: &mut SplitStream<WebSocket>,
+        file_id: Option<Uuid>,
+    ) -> Option<(Uuid, String)> {
+        use futures_util::{SinkExt, StreamExt};
+
+        let first = tokio::time::timeout(AUTH_TIMEOUT, receiver.next())
+            .await
+            .ok()
+            .flatten()
+            .and_then(Result::ok);
+        let (code, reason) = match self.authenticate(first, file_id).await {
+            Ok(user) => return Some(user),
+            Err(refusal) => refusal,
+        };
+
+        tracing::info!("Refused collaboration socket: {}", reason);
+        let auth_result = ServerMessage::AuthResult {
+            success: false,
+            error: Some(reason.clone()),
+        };
+        if let Ok(json) = serde_json::to_string(&auth_result) {
+            let _ = sender.send(Message::Text(json)).await;
+        }
+        let _ = sender
+            .send(Message::Close(Some(CloseFrame {
+                code,
+                reason: reason.into(),
+            })))
+            .await;
+        None
+    }
+}
+
+/// Closes a collaboration socket that has gone quiet.
+///
+/// Only edits and cursor updates count as activity; keepalive pings do not,
+/// so an open but unused editor still gives up its room.
+struct IdleTimer {
+    timeout: Option<Duration>,
+    deadline: tokio::time::Instant,
+}
+
+impl IdleTimer {
+    /// A timer expiring after `timeout` without activity; `None` never expires.
+    fn new(timeout: Option<Duration>) -> Self {
+        let mut timer = Self {
+            timeout,
+            deadline: tokio::time::Instant::now(),
+        };
+        timer.touch();
+        timer
+    }
+
+    /// Record activity, pushing the deadline back.
+    fn touch(&mut self) {
+        if let Some(timeout) = self.timeout {
+            self.deadline = tokio::time::Instant::now() + timeout;
+        }
+    }
+
+    /// Resolve once the socket has been idle for the whole timeout.
+    async fn expired(&self) {
+        match self.timeout {
+            Some(_) => tokio::time::sleep_until(self.deadline).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn close_reason(&self) -> String {
+        let secs = self.timeout.unwrap_or_default().as_secs();
+        format!("Closed after {} seconds without edits", secs)
+    }
 }
 
 /// Client message types for collaboration.
@@ -108,13 +387,23 @@ fn get_room_manager() -> &'static Arc<RwLock<RoomManager>> {
 #[serde(tag = "type")]
 enum CollabMessage {
     /// Authentication with JWT token.
-    Auth { token: String },
+    Auth { token: AccessToken },
     /// Sync request with state vector.
     Sync { state_vector: Vec<u8> },
     /// Document update.
     Update { data: Vec<u8> },
-    /// Cursor/selection awareness update.
-    Awareness { user_id: String, cursor: Option<CursorPosition> },
+    /// Track another participant's cursor.
+    Follow { target_user_id: Uuid },
+    /// Stop tracking.
+    Unfollow,
+    /// Heartbeat keeping the participant in the room while otherwise quiet.
+    Ping,
+    /// Save the document's current state under a name.
+    Checkpoint { name: String },
+    /// Reset the document to a named checkpoint for everyone in the room.
+    RestoreCheckpoint { name: String },
+    /// Ask for the names of the document's checkpoints.
+    ListCheckpoints,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -128,47 +417,178 @@ struct CursorPosition {
 #[serde(tag = "type")]
 enum ServerMessage {
     /// Authentication result.
-    AuthResult { success: bool, error: Option<String> },
+    AuthResult {
+        success: bool,
+        error: Option<String>,
+    },
     /// Initial document state.
     InitialState { data: Vec<u8> },
     /// Document update from another client.
     Update { data: Vec<u8> },
-    /// Awareness update from another client.
-    Awareness { user_id: String, cursor: Option<CursorPosition> },
     /// User joined the room.
     UserJoined { user_id: String, username: String },
     /// User left the room.
     UserLeft { user_id: String },
+    /// Cursor of the participant being followed.
+    FollowingCursor {
+        user_id: String,
+        cursor: Option<CursorPosition>,
+    },
+    /// Reply to a heartbeat.
+    Pong,
+    /// Names of the document's checkpoints.
+    Checkpoints { names: Vec<String> },
+    /// Notice from the operators, sent to every connected client.
+    Announcement { message: String, severity: Severity },
     /// Error message.
     Error { message: String },
 }
 
+impl From<Announcement> for ServerMessage {
+    fn from(announcement: Announcement) -> Self {
+        ServerMessage::Announcement {
+            message: announcement.message,
+            severity: announcement.severity,
+        }
+    }
+}
+
 /// WebSocket handler for collaborative editing.
 pub async fn collab_handler(
     ws: WebSocketUpgrade,
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(file_id): Path<Uuid>,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_collab(socket, file_id))
+    let room_manager = get_room_manager(&state);
+    let log = DocumentLog::new(state.db.clone(), state.config.doc_log_compact_after);
+    let auth = CollabAuth::new(&state);
+    let limits = CollabLimits::from_config(&state.config);
+    let announcements = state.announcements.subscribe();
+    ws.on_upgrade(move |socket| {
+        handle_collab(
+            socket,
+            file_id,
+            room_manager,
+            Some(log),
+            auth,
+            limits,
+            announcements,
+        )
+    })
 }
 
-async fn handle_collab(socket: WebSocket, file_id: Uuid) {
+/// WebSocket handler for an ephemeral scratchpad shared without a file.
+///
+/// Scratch rooms are created on first join, exist only in memory, and are
+/// dropped once the last participant leaves; nothing is written to the
+/// database.
+pub async fn scratch_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Path(room_id): Path<Uuid>,
+) -> Response {
+    let room_manager = get_scratch_room_manager(&state.config);
+    let auth = CollabAuth::new(&state);
+    let limits = CollabLimits::from_config(&state.config);
+    let announcements = state.announcements.subscribe();
+    ws.on_upgrade(move |socket| {
+        handle_collab(
+            socket,
+            room_id,
+            room_manager,
+            None,
+            auth,
+            limits,
+            announcements,
+        )
+    })
+}
+
+/// Open a file's room from its update log, unless it is already open.
+///
+/// A new room starts from the file's logged edits, or its stored text if it
+/// was never edited here. If loading fails the room is left to start empty,
+/// and is not persisted.
+async fn open_file_room(room_manager: &Arc<RwLock<RoomManager>>, log: &DocumentLog, file_id: Uuid) {
+    if room_manager.read().await.get(&file_id).is_some() {
+        return;
+    }
+    match log.load(file_id).await {
+        Ok(Some(document)) => {
+            room_manager.read().await.insert_document(document);
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to replay update log for file {}: {}", file_id, e),
+    }
+}
+
+/// Record an applied update in the file's log, if the room has one.
+/// Failures are logged only, so a database hiccup does not interrupt editing.
+async fn persist_update(log: Option<&DocumentLog>, file_id: Uuid, update: &[u8]) {
+    let Some(log) = log else {
+        return;
+    };
+    if let Err(e) = log.append(file_id, update).await {
+        tracing::warn!("Failed to log update for file {}: {}", file_id, e);
+    }
+}
+
+pub(crate) async fn handle_collab(
+    socket: WebSocket,
+    file_id: Uuid,
+    room_manager: &'static Arc<RwLock<RoomManager>>,
+    log: Option<DocumentLog>,
+    auth: CollabAuth,
+    limits: CollabLimits,
+    mut announcements: Announcements,
+) {
     let (mut sender, mut receiver) = socket.split();
     use futures_util::{SinkExt, StreamExt};
 
-    // Get or create room
-    let room_manager = get_room_manager();
-    let room = {
-        let manager = room_manager.write().await;
-        manager.get_or_create(file_id, None)
+    // Nothing is shared until the client proves who it is
+    let room_file = log.is_some().then_some(file_id);
+    let Some((user_id, username)) = auth.accept(&mut sender, &mut receiver, room_file).await else {
+        return;
     };
 
-    // Generate temporary user ID (in production, authenticate first)
-    let user_id = Uuid::new_v4();
-    let username = format!("User-{}", &user_id.to_string()[..8]);
+    // Scratch rooms have no log and start empty
+    if let Some(log) = &log {
+        open_file_room(room_manager, log, file_id).await;
+    }
 
-    // Join room and get broadcast receiver
-    let mut broadcast_rx = room.join(user_id, username.clone());
+    // Join room (creating it if needed) and get broadcast receiver
+    let joined = {
+        let manager = room_manager.write().await;
+        manager.join(file_id, user_id, username.clone())
+    };
+    let (room, mut broadcast_rx) = match joined {
+        Ok(joined) => joined,
+        Err(e) => {
+            let error_msg = ServerMessage::Error {
+                message: e.to_string(),
+            };
+            if let Ok(json) = serde_json::to_string(&error_msg) {
+                let _ = sender.send(Message::Text(json)).await;
+            }
+            let code = match e {
+                RoomError::Full { .. } => close_code::AGAIN,
+                RoomError::TooManyRooms(_) => close_code::POLICY,
+            };
+            let _ = sender
+                .send(Message::Close(Some(CloseFrame {
+                    code,
+                    reason: e.to_string().into(),
+                })))
+                .await;
+            return;
+        }
+    };
+    let connection_id = broadcast_rx.connection_id();
+    // Edits to a room that was never loaded would be logged without the
+    // file's text under them
+    let log = log.as_ref().filter(|_| room.is_loaded());
+    let mut follow_rx: Option<mpsc::UnboundedReceiver<FollowedCursor>> = None;
+    let mut idle = IdleTimer::new(limits.idle_timeout);
 
     // Send initial sync step 1 (server's state vector)
     // y-websocket protocol: [messageType, syncType, VarUint8Array(payload)]
@@ -176,20 +596,42 @@ async fn handle_collab(socket: WebSocket, file_id: Uuid) {
     let sync_step1 = encode_sync_step1(&state_vector);
     let _ = sender.send(Message::Binary(sync_step1)).await;
 
+    // Then everyone's current cursors and selections
+    if let Some(awareness) = room.awareness_snapshot() {
+        let _ = sender.send(Message::Binary(awareness)).await;
+    }
+
     // Note: UserJoined/UserLeft notifications are not part of y-websocket protocol
     // They would need a separate signaling channel if needed
-    tracing::info!("User {} ({}) joined room {}", username, user_id, file_id);
+    tracing::info!(
+        "User {} ({}) joined room {}",
+        privacy::identifier(&username),
+        user_id,
+        file_id
+    );
 
     // Handle messages
     loop {
         tokio::select! {
             // Receive from client
             Some(msg) = receiver.next() => {
+                // Anything from the client shows it is still there
+                if msg.is_ok() && !room.touch(&user_id) {
+                    tracing::info!("Evicted silent participant {} from room {}", user_id, file_id);
+                    let _ = sender
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::NORMAL,
+                            reason: "Removed from the room after missing heartbeats".into(),
+                        })))
+                        .await;
+                    break;
+                }
                 match msg {
                     Ok(Message::Text(text)) => {
                         if let Ok(collab_msg) = serde_json::from_str::<CollabMessage>(&text) {
                             match collab_msg {
                                 CollabMessage::Update { data } => {
+                                    idle.touch();
                                     // Apply update to document
                                     if let Err(e) = room.document.apply_update(&data).await {
                                         let error_msg = ServerMessage::Error {
@@ -200,10 +642,11 @@ async fn handle_collab(socket: WebSocket, file_id: Uuid) {
                                         }
                                         continue;
                                     }
+                                    persist_update(log, file_id, &data).await;
 
                                     // Broadcast to others using proper lib0 encoding
                                     let broadcast_data = encode_sync_update(&data);
-                                    room.broadcast_update(broadcast_data);
+                                    room.broadcast_from(connection_id, broadcast_data);
                                 }
 
                                 CollabMessage::Sync { state_vector } => {
@@ -219,23 +662,93 @@ async fn handle_collab(socket: WebSocket, file_id: Uuid) {
                                     }
                                 }
 
-                                CollabMessage::Awareness { user_id: _, cursor } => {
-                                    // Update cursor position
-                                    if let Some(ref pos) = cursor {
-                                        room.update_cursor(&user_id, pos.line);
+                                CollabMessage::Follow { target_user_id } => {
+                                    follow_rx = room.follow(user_id, target_user_id);
+                                    if follow_rx.is_none() {
+                                        let error_msg = ServerMessage::Error {
+                                            message: "Cannot follow that user".into(),
+                                        };
+                                        if let Ok(json) = serde_json::to_string(&error_msg) {
+                                            let _ = sender.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                }
+
+                                CollabMessage::Unfollow => {
+                                    room.unfollow(&user_id);
+                                    follow_rx = None;
+                                }
+
+                                CollabMessage::Ping => {
+                                    if let Ok(json) = serde_json::to_string(&ServerMessage::Pong) {
+                                        let _ = sender.send(Message::Text(json)).await;
+                                    }
+                                }
+
+                                CollabMessage::Checkpoint { name } => {
+                                    let reply = match checkpoint_name(&name) {
+                                        Some(name) => match room.document.checkpoint(name, limits.max_checkpoints).await {
+                                            Some(state) => {
+                                                if let Some(log) = log {
+                                                    if let Err(e) = log.save_checkpoint(file_id, name, &state).await {
+                                                        tracing::warn!("Failed to store checkpoint for file {}: {}", file_id, e);
+                                                    }
+                                                }
+                                                ServerMessage::Checkpoints {
+                                                    names: room.document.list_checkpoints(),
+                                                }
+                                            }
+                                            None => ServerMessage::Error {
+                                                message: format!(
+                                                    "A document can have at most {} checkpoints",
+                                                    limits.max_checkpoints
+                                                ),
+                                            },
+                                        },
+                                        None => ServerMessage::Error {
+                                            message: format!(
+                                                "Checkpoint names must be 1 to {} characters",
+                                                MAX_CHECKPOINT_NAME_CHARS
+                                            ),
+                                        },
+                                    };
+                                    if let Ok(json) = serde_json::to_string(&reply) {
+                                        let _ = sender.send(Message::Text(json)).await;
+                                    }
+                                }
+
+                                CollabMessage::RestoreCheckpoint { name } => {
+                                    idle.touch();
+                                    // The room sends the restoring update to
+                                    // everyone, this client included
+                                    let message = match room.restore_checkpoint(name.trim()).await {
+                                        Ok(Some(update)) => {
+                                            persist_update(log, file_id, &update).await;
+                                            continue;
+                                        }
+                                        Ok(None) => format!("No checkpoint named '{}'", name.trim()),
+                                        Err(e) => format!("Failed to restore checkpoint: {}", e),
+                                    };
+                                    let error_msg = ServerMessage::Error { message };
+                                    if let Ok(json) = serde_json::to_string(&error_msg) {
+                                        let _ = sender.send(Message::Text(json)).await;
+                                    }
+                                }
+
+                                CollabMessage::ListCheckpoints => {
+                                    let reply = ServerMessage::Checkpoints {
+                                        names: room.document.list_checkpoints(),
+                                    };
+                                    if let Ok(json) = serde_json::to_string(&reply) {
+                                        let _ = sender.send(Message::Text(json)).await;
                                     }
-                                    // Note: For proper y-websocket awareness, client should send
-                                    // binary awareness messages (type 1), not JSON
-                                    tracing::debug!("Received JSON awareness update from {}", user_id);
                                 }
 
                                 CollabMessage::Auth { token: _ } => {
-                                    // In production, verify JWT and get real user info
-                                    let auth_result = ServerMessage::AuthResult {
-                                        success: true,
-                                        error: None,
+                                    let error_msg = ServerMessage::Error {
+                                        message: "Already authenticated".into(),
                                     };
-                                    if let Ok(json) = serde_json::to_string(&auth_result) {
+                                    if let Ok(json) = serde_json::to_string(&error_msg) {
                                         let _ = sender.send(Message::Text(json)).await;
                                     }
                                 }
@@ -282,16 +795,19 @@ async fn handle_collab(socket: WebSocket, file_id: Uuid) {
                                         }
                                     }
                                     1 => {
+                                        idle.touch();
                                         // Sync step 2: apply the update (diff from server)
                                         let Some(update) = read_var_uint8_array(&data, &mut pos) else {
                                             tracing::debug!("Failed to read update");
                                             continue;
                                         };
-                                        if let Err(e) = room.document.apply_update(update).await {
-                                            tracing::error!("Failed to apply sync step 2: {}", e);
+                                        match room.document.apply_update(update).await {
+                                            Ok(()) => persist_update(log, file_id, update).await,
+                                            Err(e) => tracing::error!("Failed to apply sync step 2: {}", e),
                                         }
                                     }
                                     2 => {
+                                        idle.touch();
                                         // Update: apply and broadcast
                                         let Some(update) = read_var_uint8_array(&data, &mut pos) else {
                                             tracing::debug!("Failed to read update");
@@ -301,10 +817,11 @@ async fn handle_collab(socket: WebSocket, file_id: Uuid) {
                                             tracing::error!("Failed to apply update: {}", e);
                                             continue;
                                         }
+                                        persist_update(log, file_id, update).await;
 
                                         // Broadcast to others using proper lib0 encoding
                                         let broadcast_data = encode_sync_update(update);
-                                        room.broadcast_update(broadcast_data);
+                                        room.broadcast_from(connection_id, broadcast_data);
                                     }
                                     _ => {
                                         tracing::debug!("Unknown sync sub-type: {}", sync_type);
@@ -312,8 +829,21 @@ async fn handle_collab(socket: WebSocket, file_id: Uuid) {
                                 }
                             }
                             1 => {
-                                // Awareness message - broadcast to others as-is
-                                room.broadcast_update(data.to_vec());
+                                idle.touch();
+                                // Awareness message - merged into the room's states and rebroadcast
+                                let Some(update) = read_var_uint8_array(&data, &mut pos) else {
+                                    tracing::debug!("Failed to read awareness update");
+                                    continue;
+                                };
+                                if let Err(e) = room.apply_awareness(user_id, update, limits.max_awareness_bytes) {
+                                    tracing::debug!("Dropped awareness from {}: {}", user_id, e);
+                                    let error_msg = ServerMessage::Error {
+                                        message: e.to_string(),
+                                    };
+                                    if let Ok(json) = serde_json::to_string(&error_msg) {
+                                        let _ = sender.send(Message::Text(json)).await;
+                                    }
+                                }
                             }
                             _ => {
                                 tracing::debug!("Unknown y-websocket message type: {}", msg_type);
@@ -332,45 +862,681 @@ async fn handle_collab(socket: WebSocket, file_id: Uuid) {
                 // Forward to this client
                 let _ = sender.send(Message::Binary(data)).await;
             }
+
+            // Cursor moves of the followed participant, sent without batching
+            followed = async {
+                match follow_rx.as_mut() {
+                    Some(rx) => rx.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                let Some(followed) = followed else {
+                    // The followed participant left
+                    follow_rx = None;
+                    continue;
+                };
+                let message = ServerMessage::FollowingCursor {
+                    user_id: followed.user_id.to_string(),
+                    cursor: followed.cursor.map(|cursor| CursorPosition {
+                        line: cursor.line,
+                        column: cursor.column,
+                    }),
+                };
+                if let Ok(json) = serde_json::to_string(&message) {
+                    let _ = sender.send(Message::Text(json)).await;
+                }
+            }
+
+            announcement = announcements.recv() => {
+                if let Ok(json) = serde_json::to_string(&ServerMessage::from(announcement)) {
+                    let _ = sender.send(Message::Text(json)).await;
+                }
+            }
+
+            // No edits for the whole idle timeout
+            _ = idle.expired() => {
+                tracing::info!("Closing idle collaboration socket of {} in room {}", user_id, file_id);
+                let _ = sender
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::NORMAL,
+                        reason: idle.close_reason().into(),
+                    })))
+                    .await;
+                break;
+            }
         }
     }
 
-    // Leave room
-    room.leave(&user_id);
+    // Leave room, removing it if empty
+    let manager = room_manager.write().await;
+    manager.leave(file_id, user_id);
 
     // Note: UserLeft notifications are not part of y-websocket protocol
     tracing::info!("User {} left room {}", user_id, file_id);
+}
 
-    // Cleanup empty room
-    let manager = room_manager.write().await;
-    manager.cleanup(&file_id);
+/// Client messages on a multi-document collaboration connection.
+///
+/// Server frames are binary: the 16-byte document id followed by the same
+/// y-websocket frame a single-document connection would receive.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum MultiDocMessage {
+    /// Subscribe to a document's room.
+    Join { doc_id: Uuid },
+    /// Unsubscribe from a document's room.
+    Leave { doc_id: Uuid },
+    /// Sync request with state vector.
+    Sync { doc_id: Uuid, state_vector: Vec<u8> },
+    /// Document update.
+    Update { doc_id: Uuid, data: Vec<u8> },
+    /// Heartbeat keeping the connection in its rooms while otherwise quiet.
+    Ping,
+}
+
+/// Prefix a frame with the id of the document it belongs to.
+fn encode_doc_frame(doc_id: Uuid, frame: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(16 + frame.len());
+    buf.extend_from_slice(doc_id.as_bytes());
+    buf.extend_from_slice(frame);
+    buf
+}
+
+/// WebSocket handler for editing several documents over one connection.
+pub async fn multi_collab_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    let room_manager = get_room_manager(&state);
+    let log = DocumentLog::new(state.db.clone(), state.config.doc_log_compact_after);
+    let auth = CollabAuth::new(&state);
+    let announcements = state.announcements.subscribe();
+    ws.on_upgrade(move |socket| handle_multi_collab(socket, room_manager, log, auth, announcements))
+}
+
+pub(crate) async fn handle_multi_collab(
+    socket: WebSocket,
+    room_manager: &'static Arc<RwLock<RoomManager>>,
+    log: DocumentLog,
+    auth: CollabAuth,
+    mut announcements: Announcements,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    use futures_util::{SinkExt, StreamExt};
+
+    // Access is checked per document as each is joined
+    let Some((user_id, username)) = auth.accept(&mut sender, &mut receiver, None).await else {
+        return;
+    };
+    let mut connection = MultiplexedConnection::new(user_id, username);
+
+    loop {
+        tokio::select! {
+            msg = receiver.next() => {
+                if let Some(Ok(_)) = msg {
+                    // Rooms that evicted this connection for going quiet
+                    for doc_id in connection.touch() {
+                        connection.leave(&*room_manager.read().await, doc_id);
+                        let error_msg = ServerMessage::Error {
+                            message: format!("Removed from {} after missing heartbeats", doc_id),
+                        };
+                        if let Ok(json) = serde_json::to_string(&error_msg) {
+                            let _ = sender.send(Message::Text(json)).await;
+                        }
+                    }
+                }
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    _ => continue,
+                };
+
+                let Ok(message) = serde_json::from_str::<MultiDocMessage>(&text) else {
+                    tracing::debug!("Invalid multi-document message from {}", user_id);
+                    continue;
+                };
+
+                match message {
+                    MultiDocMessage::Join { doc_id } => {
+                        if let Err((_, reason)) = auth.check_access(user_id, doc_id).await {
+                            let error_msg = ServerMessage::Error {
+                                message: format!("Cannot open {}: {}", doc_id, reason),
+                            };
+                            if let Ok(json) = serde_json::to_string(&error_msg) {
+                                let _ = sender.send(Message::Text(json)).await;
+                            }
+                            continue;
+                        }
+                        open_file_room(room_manager, &log, doc_id).await;
+                        let joined = {
+                            let manager = room_manager.read().await;
+                            connection.join(&manager, doc_id)
+                        };
+                        let room = match joined {
+                            Ok(room) => room,
+                            Err(e) => {
+                                let error_msg = ServerMessage::Error {
+                                    message: e.to_string(),
+                                };
+                                if let Ok(json) = serde_json::to_string(&error_msg) {
+                                    let _ = sender.send(Message::Text(json)).await;
+                                }
+                                continue;
+                            }
+                        };
+                        let state_vector = room.document.state_vector().await;
+                        let frame = encode_doc_frame(doc_id, &encode_sync_step1(&state_vector));
+                        let _ = sender.send(Message::Binary(frame)).await;
+                    }
+
+                    MultiDocMessage::Leave { doc_id } => {
+                        let manager = room_manager.read().await;
+                        connection.leave(&manager, doc_id);
+                    }
+
+                    MultiDocMessage::Sync { doc_id, state_vector } => {
+                        let Some(room) = connection.room(&doc_id) else {
+                            continue;
+                        };
+                        match room.document.encode_diff(&state_vector).await {
+                            Ok(diff) => {
+                                let frame = encode_doc_frame(doc_id, &encode_sync_step2(&diff));
+                                let _ = sender.send(Message::Binary(frame)).await;
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to encode diff: {}", e);
+                            }
+                        }
+                    }
+
+                    MultiDocMessage::Update { doc_id, data } => {
+                        let Some(room) = connection.room(&doc_id) else {
+                            continue;
+                        };
+                        if let Err(e) = room.document.apply_update(&data).await {
+                            let error_msg = ServerMessage::Error {
+                                message: format!("Failed to apply update: {}", e),
+                            };
+                            if let Ok(json) = serde_json::to_string(&error_msg) {
+                                let _ = sender.send(Message::Text(json)).await;
+                            }
+                            continue;
+                        }
+                        let log = Some(&log).filter(|_| room.is_loaded());
+                        persist_update(log, doc_id, &data).await;
+                        connection.broadcast(&doc_id, encode_sync_update(&data));
+                    }
+
+                    MultiDocMessage::Ping => {
+                        if let Ok(json) = serde_json::to_string(&ServerMessage::Pong) {
+                            let _ = sender.send(Message::Text(json)).await;
+                        }
+                    }
+                }
+            }
+
+            // Broadcasts from any joined document
+            Some(DocFrame { doc_id, data }) = connection.recv() => {
+                let _ = sender.send(Message::Binary(encode_doc_frame(doc_id, &data))).await;
+            }
+
+            announcement = announcements.recv() => {
+                if let Ok(json) = serde_json::to_string(&ServerMessage::from(announcement)) {
+                    let _ = sender.send(Message::Text(json)).await;
+                }
+            }
+
+            else => break,
+        }
+    }
+
+    let manager = room_manager.read().await;
+    connection.leave_all(&manager);
+}
+
+/// Shell attached when a terminal reports its size.
+const TERMINAL_SHELL: &str = "/bin/sh";
+
+/// Terminal size given when connecting, e.g. `?cols=120&rows=40`.
+#[derive(Debug, Default, Deserialize)]
+pub struct TerminalQuery {
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+}
+
+impl TerminalQuery {
+    /// The size, if both dimensions were given.
+    pub(crate) fn size(&self) -> Option<TerminalSize> {
+        Some(TerminalSize {
+            cols: self.cols?,
+            rows: self.rows?,
+        })
+    }
 }
 
 /// WebSocket handler for terminal sessions.
 pub async fn terminal_handler(
     ws: WebSocketUpgrade,
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(session_id): Path<Uuid>,
+    Query(query): Query<TerminalQuery>,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_terminal(socket, session_id))
+    ws.on_upgrade(move |socket| handle_terminal(socket, state, session_id, query.size()))
 }
 
-async fn handle_terminal(mut socket: WebSocket, _session_id: Uuid) {
-    // TODO: Connect to container's PTY
-    // 1. Authenticate user from first message
-    // 2. Verify session ownership
-    // 3. Attach to container stdin/stdout using bollard
-    // 4. Relay input/output
+/// Client messages on a terminal connection; any other text is echoed.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum TerminalMessage {
+    /// Authentication with JWT token; must be the first message.
+    Auth { token: AccessToken },
+    /// Run a program, streaming its output back as it is produced.
+    Run {
+        language: Language,
+        code: String,
+        #[serde(default)]
+        stdin: Option<String>,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// The terminal's size. Attaches a shell sized to it if none is
+    /// attached yet, otherwise resizes the shell's TTY.
+    Resize { cols: u16, rows: u16 },
+}
 
-    // For now, echo messages back
-    while let Some(Ok(msg)) = socket.recv().await {
-        match msg {
-            Message::Text(text) => {
-                let response = format!("Terminal echo: {}", text);
-                let _ = socket.send(Message::Text(response)).await;
+/// Server messages for a streamed run.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub(crate) enum TerminalEvent {
+    /// The run was registered; its recent output can be fetched by id.
+    Started { execution_id: Uuid },
+    /// Program output as it arrives, e.g.
+    /// `{"type":"Output","stream":"stderr","seq":3,"data":"oops\n"}`.
+    ///
+    /// `seq` counts up from 0 across both streams, so clients can restore
+    /// the interleaving and spot gaps.
+    Output {
+        stream: StdStream,
+        seq: u64,
+        data: String,
+    },
+    /// The program finished.
+    Exit {
+        exit_code: Option<i64>,
+        timed_out: bool,
+        truncated: bool,
+        compile_stderr: Option<String>,
+    },
+    /// The run could not be started or failed.
+    Error { message: String },
+    /// Notice from the operators, sent to every connected client.
+    Announcement { message: String, severity: Severity },
+}
+
+/// Frames a run's raw output chunks as numbered `Output` events.
+///
+/// Chunks may split characters, so each stream is decoded separately.
+#[derive(Default)]
+pub(crate) struct OutputFrames {
+    stdout: Utf8StreamDecoder,
+    stderr: Utf8StreamDecoder,
+    seq: u64,
+}
+
+impl OutputFrames {
+    /// The event for a chunk, or `None` if it only holds part of a character.
+    pub(crate) fn push(&mut self, chunk: &OutputChunk) -> Option<TerminalEvent> {
+        let decoder = match chunk.stream {
+            StdStream::Stdout => &mut self.stdout,
+            StdStream::Stderr => &mut self.stderr,
+        };
+        let data = decoder.push(&chunk.data);
+        self.frame(chunk.stream, data)
+    }
+
+    /// Events for whatever is left once the output has ended.
+    pub(crate) fn finish(mut self) -> Vec<TerminalEvent> {
+        let stdout = self.stdout.finish();
+        let stderr = self.stderr.finish();
+        [(StdStream::Stdout, stdout), (StdStream::Stderr, stderr)]
+            .into_iter()
+            .filter_map(|(stream, data)| self.frame(stream, data))
+            .collect()
+    }
+
+    fn frame(&mut self, stream: StdStream, data: String) -> Option<TerminalEvent> {
+        if data.is_empty() {
+            return None;
+        }
+        let seq = self.seq;
+        self.seq += 1;
+        Some(TerminalEvent::Output { stream, seq, data })
+    }
+}
+
+async fn send_event(socket: &mut WebSocket, event: &TerminalEvent) {
+    if let Ok(json) = serde_json::to_string(event) {
+        let _ = socket.send(Message::Text(json)).await;
+    }
+}
+
+/// Run a program for `user_id` on the shared executor, forwarding its
+/// output to the terminal as it is produced.
+///
+/// The run counts against the same limits as one started over HTTP.
+async fn stream_run(
+    socket: &mut WebSocket,
+    state: &AppState,
+    user_id: Uuid,
+    request: ExecutionRequest,
+) -> Result<(), String> {
+    let reject = |(_, Json(e)): (_, Json<ErrorResponse>)| e.error;
+    ensure_sandbox_enabled(&state.config).map_err(reject)?;
+    check_code_size(&state.config, &request.code).map_err(reject)?;
+    check_args(&state.config, &request.args).map_err(reject)?;
+    request.stdin_bytes()?;
+
+    let _slot = admit_executions(state, user_id, 1)
+        .await
+        .map_err(|refused| refused.to_string())?;
+    let _load = state.load.track();
+
+    // Events queue up here so a slow client never holds the executor lock;
+    // output is capped, so the queue is too
+    let (events, mut queued) = mpsc::unbounded_channel();
+    let run = async move {
+        let mut executor_guard = get_executor().lock().await;
+        ensure_executor(state, &mut executor_guard).map_err(reject)?;
+        let executor = executor_guard.as_ref().unwrap();
+
+        // Tracked under the session's owner, who can tail or cancel it
+        let handle = state.executions.register(user_id);
+        let _ = events.send(TerminalEvent::Started {
+            execution_id: handle.id(),
+        });
+
+        let (mut chunks, execution) = executor.execute_streaming_tracked(request, handle);
+        let relay = async {
+            let mut frames = OutputFrames::default();
+            while let Some(chunk) = chunks.recv().await {
+                if let Some(event) = frames.push(&chunk) {
+                    let _ = events.send(event);
+                }
+            }
+            for event in frames.finish() {
+                let _ = events.send(event);
+            }
+        };
+
+        let (result, ()) = tokio::join!(execution, relay);
+        result.map_err(|e| format!("Execution failed: {}", e))
+    };
+    let forward = async {
+        while let Some(event) = queued.recv().await {
+            send_event(socket, &event).await;
+        }
+    };
+
+    let (result, ()) = tokio::join!(run, forward);
+    let result = result?;
+    send_event(
+        socket,
+        &TerminalEvent::Exit {
+            exit_code: result.exit_code,
+            timed_out: result.timed_out,
+            truncated: result.truncated,
+            compile_stderr: result.compile_stderr,
+        },
+    )
+    .await;
+    Ok(())
+}
+
+/// A shell running on a TTY in a session's container.
+struct Shell {
+    containers: ContainerManager,
+    pty: PtySession,
+}
+
+/// The session, if it exists and belongs to `user_id`.
+fn owned_session(
+    state: &AppState,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<SandboxSession, String> {
+    let session = state
+        .sessions
+        .get(session_id)
+        .ok_or_else(|| "Session not found".to_string())?;
+    if session.user_id != user_id {
+        return Err("Access denied".into());
+    }
+    Ok(session)
+}
+
+/// Attach a shell in `user_id`'s session container on a TTY of `size`.
+///
+/// The TTY has its size before the shell starts, so full-screen programs
+/// lay themselves out correctly from the first frame.
+async fn attach_shell(
+    state: &AppState,
+    session_id: Uuid,
+    user_id: Uuid,
+    size: TerminalSize,
+) -> Result<Shell, String> {
+    if !size.is_valid() {
+        return Err(format!("Invalid terminal size {}x{}", size.cols, size.rows));
+    }
+    // Only ever on behalf of the session's authenticated owner
+    let session = owned_session(state, session_id, user_id)?;
+
+    let containers = ContainerManager::new()
+        .map(|containers| {
+            containers.with_pull_timeout(Duration::from_secs(state.config.image_pull_timeout_secs))
+        })
+        .map_err(|e| e.to_string())?;
+    let pty = containers
+        .attach_pty(&session.container_id, &[TERMINAL_SHELL.to_string()], size)
+        .await
+        .map_err(|e| format!("Failed to attach terminal: {}", e))?;
+    Ok(Shell { containers, pty })
+}
+
+/// Next output from the attached shell; `None` once it has exited.
+async fn shell_output(shell: &mut Option<Shell>) -> Option<Vec<u8>> {
+    use futures_util::StreamExt;
+
+    let shell = shell.as_mut()?;
+    match shell.pty.output.next().await? {
+        Ok(log) => Some(log.into_bytes().to_vec()),
+        Err(_) => None,
+    }
+}
+
+/// Wait for a terminal's `Auth` message, returning the user it
+/// authenticates if they own the session.
+async fn authenticate_terminal(
+    socket: &mut WebSocket,
+    state: &AppState,
+    session_id: Uuid,
+) -> Result<Uuid, String> {
+    let first = tokio::time::timeout(AUTH_TIMEOUT, socket.recv())
+        .await
+        .ok()
+        .flatten()
+        .and_then(Result::ok);
+    let token = match first {
+        Some(Message::Text(text)) => match serde_json::from_str(&text) {
+            Ok(TerminalMessage::Auth { token }) => token,
+            _ => return Err("Authentication required".into()),
+        },
+        _ => return Err("Authentication required".into()),
+    };
+    let claims = authenticate(&token, &state.config.jwt_secret, &state.revoked_tokens)
+        .await
+        .map_err(|_| "Invalid authentication token".to_string())?;
+
+    owned_session(state, session_id, claims.sub)?;
+    Ok(claims.sub)
+}
+
+async fn handle_terminal(
+    mut socket: WebSocket,
+    state: AppState,
+    session_id: Uuid,
+    initial_size: Option<TerminalSize>,
+) {
+    // Nothing reaches the session's container until its owner is known
+    let user_id = match authenticate_terminal(&mut socket, &state, session_id).await {
+        Ok(user_id) => user_id,
+        Err(message) => {
+            tracing::info!("Refused terminal for session {}: {}", session_id, message);
+            let event = TerminalEvent::Error {
+                message: message.clone(),
+            };
+            send_event(&mut socket, &event).await;
+            let _ = socket
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::POLICY,
+                    reason: message.into(),
+                })))
+                .await;
+            return;
+        }
+    };
+
+    let Some(mut expired) = state.sessions.watch(session_id) else {
+        let _ = socket
+            .send(Message::Close(Some(CloseFrame {
+                code: close_code::POLICY,
+                reason: "Session not found".into(),
+            })))
+            .await;
+        return;
+    };
+    let mut announcements = state.announcements.subscribe();
+
+    let mut shell = None;
+    if let Some(size) = initial_size {
+        match attach_shell(&state, session_id, user_id, size).await {
+            Ok(attached) => shell = Some(attached),
+            Err(message) => send_event(&mut socket, &TerminalEvent::Error { message }).await,
+        }
+    }
+
+    // Without a shell, other text is echoed back
+    loop {
+        tokio::select! {
+            msg = socket.recv() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        state.sessions.touch(session_id, chrono::Utc::now());
+
+                        let message = serde_json::from_str(&text);
+                        if let Ok(TerminalMessage::Auth { .. }) = message {
+                            let message = "Already authenticated".to_string();
+                            send_event(&mut socket, &TerminalEvent::Error { message }).await;
+                            continue;
+                        }
+                        if let Ok(TerminalMessage::Resize { cols, rows }) = message {
+                            let size = TerminalSize { cols, rows };
+                            let result = match &shell {
+                                Some(_) if !size.is_valid() => {
+                                    Err(format!("Invalid terminal size {}x{}", cols, rows))
+                                }
+                                Some(Shell { containers, pty }) => containers
+                                    .resize_pty(&pty.exec_id, size)
+                                    .await
+                                    .map_err(|e| format!("Failed to resize terminal: {}", e)),
+                                None => attach_shell(&state, session_id, user_id, size)
+                                    .await
+                                    .map(|attached| shell = Some(attached)),
+                            };
+                            if let Err(message) = result {
+                                send_event(&mut socket, &TerminalEvent::Error { message }).await;
+                            }
+                            continue;
+                        }
+
+                        if let Ok(TerminalMessage::Run { language, code, stdin, args }) = message {
+                            let request = ExecutionRequest {
+                                code,
+                                language,
+                                stdin,
+                                stdin_encoding: Default::default(),
+                                line_endings: Default::default(),
+                                args,
+                                post_run: None,
+                                memory_bytes: None,
+                                strip_ansi: None,
+                                callback_url: None,
+                                profile: None,
+                                seed: None,
+                                coverage: false,
+                                combined_output: false,
+                            };
+                            if let Err(message) = stream_run(&mut socket, &state, user_id, request).await {
+                                send_event(&mut socket, &TerminalEvent::Error { message }).await;
+                            }
+                            continue;
+                        }
+
+                        if let Some(shell) = &mut shell {
+                            let _ = shell.pty.input.write_all(text.as_bytes()).await;
+                            continue;
+                        }
+
+                        let response = format!("Terminal echo: {}", text);
+                        let _ = socket.send(Message::Text(response)).await;
+                    }
+                    Some(Ok(Message::Binary(data))) => {
+                        if let Some(shell) = &mut shell {
+                            state.sessions.touch(session_id, chrono::Utc::now());
+                            let _ = shell.pty.input.write_all(&data).await;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                }
+            }
+
+            output = shell_output(&mut shell), if shell.is_some() => {
+                match output {
+                    Some(data) => {
+                        let _ = socket.send(Message::Binary(data)).await;
+                    }
+                    // The shell exited
+                    None => {
+                        let Some(Shell { containers, pty }) = shell.take() else { continue };
+                        let exit_code = containers.exec_exit_code(&pty.exec_id).await.ok().flatten();
+                        send_event(
+                            &mut socket,
+                            &TerminalEvent::Exit {
+                                exit_code,
+                                timed_out: false,
+                                truncated: false,
+                                compile_stderr: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+            }
+
+            announcement = announcements.recv() => {
+                let Announcement { message, severity } = announcement;
+                send_event(&mut socket, &TerminalEvent::Announcement { message, severity }).await;
+            }
+
+            // Session reaped or stopped while attached
+            _ = expired.changed() => {
+                let reason = (*expired.borrow()).unwrap_or(ExpiryReason::Stopped);
+                let _ = socket.send(Message::Text(reason.message().to_string())).await;
+                let _ = socket
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::NORMAL,
+                        reason: reason.message().into(),
+                    })))
+                    .await;
+                break;
             }
-            Message::Close(_) => break,
-            _ => {}
         }
     }
 }
@@ -411,7 +1577,10 @@ async fn handle_signaling(mut socket: WebSocket, _room_id: Uuid) {
             Message::Text(text) => {
                 // Parse and relay signaling message
                 if let Ok(sig_msg) = serde_json::from_str::<SignalingMessage>(&text) {
-                    tracing::debug!("Signaling message: {:?}", sig_msg);
+                    tracing::debug!(
+                        "Signaling message: {}",
+                        privacy::scrub(&format!("{:?}", sig_msg))
+                    );
                     // In production, relay to target peer
                     let _ = socket.send(Message::Text(text)).await;
                 }
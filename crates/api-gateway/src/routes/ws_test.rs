@@ -0,0 +1,471 @@
+//! Tests for the collaboration WebSockets.
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::{
+        extract::{ws::WebSocketUpgrade, Path},
+        routing::get,
+        Router,
+    };
+    use futures_util::{SinkExt, StreamExt};
+    use rustyclint_collab::{CollabDocument, RoomManager, DEFAULT_FIELD};
+    use rustyclint_sandbox::{OutputChunk, StdStream};
+    use sqlx::postgres::PgPoolOptions;
+    use tokio::sync::RwLock;
+    use tokio_tungstenite::{
+        tungstenite::{protocol::frame::coding::CloseCode, Message},
+        MaybeTlsStream, WebSocketStream,
+    };
+    use uuid::Uuid;
+
+    use crate::{
+        announce::{Announcement, Announcer, Severity},
+        auth::{create_token, RevokedTokens, Token},
+        doc_log::DocumentLog,
+        routes::ws::{
+            checkpoint_name, handle_collab, handle_multi_collab, CollabAuth, CollabLimits,
+            OutputFrames, TerminalQuery,
+        },
+        store::MemoryStore,
+    };
+
+    type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+    const SECRET: &str = "test-secret";
+
+    type Rooms = &'static Arc<RwLock<RoomManager>>;
+
+    fn rooms() -> Rooms {
+        Box::leak(Box::new(Arc::new(RwLock::new(RoomManager::new()))))
+    }
+
+    /// A pool that fails fast: lookups error and are only logged, or refuse
+    /// the socket where they guard access.
+    fn unreachable_db() -> sqlx::PgPool {
+        PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://localhost:1/none")
+            .unwrap()
+    }
+
+    /// Serve the collab socket on a free port, returning the URL prefix
+    /// room ids are appended to.
+    async fn serve(
+        room_manager: Rooms,
+        log: Option<DocumentLog>,
+        idle_timeout: Option<Duration>,
+        announcer: Announcer,
+    ) -> String {
+        serve_with_auth(room_manager, log, idle_timeout, announcer, revoked_tokens()).await
+    }
+
+    fn revoked_tokens() -> RevokedTokens {
+        RevokedTokens::new(Arc::new(MemoryStore::default()))
+    }
+
+    async fn serve_with_auth(
+        room_manager: Rooms,
+        log: Option<DocumentLog>,
+        idle_timeout: Option<Duration>,
+        announcer: Announcer,
+        revoked_tokens: RevokedTokens,
+    ) -> String {
+        let auth = collab_auth(revoked_tokens);
+        let limits = CollabLimits {
+            max_awareness_bytes: 16 * 1024,
+            idle_timeout,
+            max_checkpoints: 0,
+        };
+        let app = Router::new().route(
+            "/collab/:file_id",
+            get(
+                move |ws: WebSocketUpgrade, Path(file_id): Path<Uuid>| async move {
+                    let announcements = announcer.subscribe();
+                    ws.on_upgrade(move |socket| {
+                        handle_collab(
+                            socket,
+                            file_id,
+                            room_manager,
+                            log,
+                            auth,
+                            limits,
+                            announcements,
+                        )
+                    })
+                },
+            ),
+        );
+        format!("ws://{}/collab/", listen(app).await)
+    }
+
+    /// Serve the multi-document socket, returning its URL.
+    async fn serve_multi(room_manager: Rooms) -> String {
+        let auth = collab_auth(revoked_tokens());
+        let log = DocumentLog::new(unreachable_db(), 500);
+        let announcer = Announcer::new();
+        let app = Router::new().route(
+            "/collab",
+            get(move |ws: WebSocketUpgrade| async move {
+                let announcements = announcer.subscribe();
+                ws.on_upgrade(move |socket| {
+                    handle_multi_collab(socket, room_manager, log, auth, announcements)
+                })
+            }),
+        );
+        format!("ws://{}/collab", listen(app).await)
+    }
+
+    fn collab_auth(revoked_tokens: RevokedTokens) -> CollabAuth {
+        CollabAuth {
+            jwt_secret: SECRET.into(),
+            revoked_tokens,
+            db: unreachable_db(),
+        }
+    }
+
+    async fn listen(app: Router) -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn auth_message(token: impl std::fmt::Display) -> Message {
+        Message::Text(serde_json::json!({ "type": "Auth", "token": token.to_string() }).to_string())
+    }
+
+    /// Connect to `url` and authenticate as a new user.
+    async fn connect(url: &str) -> Socket {
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let token = create_token(Uuid::new_v4(), "a@example.com", SECRET, 1).unwrap();
+        socket.send(auth_message(token)).await.unwrap();
+        socket
+    }
+
+    /// The close frame ending `socket`, after an `AuthResult` failure.
+    async fn refusal(socket: &mut Socket) -> (String, CloseCode) {
+        let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+            panic!("expected an auth result");
+        };
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["type"], "AuthResult");
+        assert_eq!(json["success"], false);
+
+        let Message::Close(Some(frame)) = socket.next().await.unwrap().unwrap() else {
+            panic!("expected a close frame");
+        };
+        (json["error"].as_str().unwrap().to_string(), frame.code)
+    }
+
+    #[tokio::test]
+    async fn test_idle_socket_closed() {
+        let url = serve(
+            rooms(),
+            None,
+            Some(Duration::from_millis(300)),
+            Announcer::new(),
+        )
+        .await;
+        let mut socket = connect(&format!("{}{}", url, Uuid::new_v4())).await;
+
+        let close = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(message) = socket.next().await {
+                if let Message::Close(frame) = message.unwrap() {
+                    return frame;
+                }
+            }
+            None
+        })
+        .await
+        .expect("idle socket was not closed");
+
+        let frame = close.unwrap();
+        assert_eq!(frame.code, CloseCode::Normal);
+        assert!(frame.reason.contains("without edits"));
+    }
+
+    #[tokio::test]
+    async fn test_no_timeout_by_default() {
+        let url = serve(rooms(), None, None, Announcer::new()).await;
+        let mut socket = connect(&format!("{}{}", url, Uuid::new_v4())).await;
+
+        // Only the initial sync step arrives; the socket stays open
+        let first = socket.next().await.unwrap().unwrap();
+        assert!(matches!(first, Message::Binary(_)));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), socket.next())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scratch_room_collaborates_without_database() {
+        let room_manager = rooms();
+        let room_id = Uuid::new_v4();
+        let url = format!(
+            "{}{}",
+            serve(room_manager, None, None, Announcer::new()).await,
+            room_id
+        );
+
+        let mut alice = connect(&url).await;
+        let mut bob = connect(&url).await;
+        // Both start with the server's sync step 1
+        alice.next().await.unwrap().unwrap();
+        bob.next().await.unwrap().unwrap();
+
+        let update = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "hello")
+            .encode_state()
+            .await;
+        assert!(update.len() < 0x80);
+        let mut frame = vec![0, 2, update.len() as u8];
+        frame.extend_from_slice(&update);
+        alice.send(Message::Binary(frame.clone())).await.unwrap();
+
+        // Relayed to the other participant and applied to the shared document
+        let relayed = tokio::time::timeout(Duration::from_secs(5), bob.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(relayed, Message::Binary(frame));
+        let room = room_manager.read().await.get(&room_id).unwrap();
+        assert_eq!(room.document.get_content(DEFAULT_FIELD).await, "hello");
+        drop(room);
+
+        alice.close(None).await.unwrap();
+        bob.close(None).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while room_manager.read().await.get(&room_id).is_some() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("empty scratch room was not removed");
+    }
+
+    #[tokio::test]
+    async fn test_announcement_reaches_every_room() {
+        let announcer = Announcer::new();
+        let url = serve(rooms(), None, None, announcer.clone()).await;
+
+        let mut alice = connect(&format!("{}{}", url, Uuid::new_v4())).await;
+        let mut bob = connect(&format!("{}{}", url, Uuid::new_v4())).await;
+        // Both are in their rooms once the initial sync step arrives
+        alice.next().await.unwrap().unwrap();
+        bob.next().await.unwrap().unwrap();
+
+        let delivered = announcer.announce(Announcement {
+            message: "Maintenance in 10 minutes".into(),
+            severity: Severity::Warning,
+        });
+        assert_eq!(delivered, 2);
+
+        for socket in [&mut alice, &mut bob] {
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            let Message::Text(text) = message else {
+                panic!("expected a text frame, got {:?}", message);
+            };
+            let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(json["type"], "Announcement");
+            assert_eq!(json["message"], "Maintenance in 10 minutes");
+            assert_eq!(json["severity"], "warning");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_room_closes_socket() {
+        let room_manager: Rooms = Box::leak(Box::new(Arc::new(RwLock::new(
+            RoomManager::new().with_max_participants(1),
+        ))));
+        let url = format!(
+            "{}{}",
+            serve(room_manager, None, None, Announcer::new()).await,
+            Uuid::new_v4()
+        );
+
+        let mut alice = connect(&url).await;
+        alice.next().await.unwrap().unwrap();
+
+        let mut bob = connect(&url).await;
+        let Message::Text(text) = bob.next().await.unwrap().unwrap() else {
+            panic!("expected an error message");
+        };
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["type"], "Error");
+        assert!(json["message"].as_str().unwrap().contains("full"));
+
+        let Message::Close(Some(frame)) = bob.next().await.unwrap().unwrap() else {
+            panic!("expected a close frame");
+        };
+        assert_eq!(frame.code, CloseCode::Again);
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_socket_refused() {
+        let room_manager = rooms();
+        let revoked = revoked_tokens();
+        let url =
+            serve_with_auth(room_manager, None, None, Announcer::new(), revoked.clone()).await;
+        let room_id = Uuid::new_v4();
+        let url = format!("{}{}", url, room_id);
+
+        // Sync before authenticating
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        socket.send(Message::Binary(vec![0, 0, 0])).await.unwrap();
+        let (error, code) = refusal(&mut socket).await;
+        assert_eq!(error, "Authentication required");
+        assert_eq!(code, CloseCode::Policy);
+
+        // A token signed with another secret
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        let forged = create_token(Uuid::new_v4(), "a@example.com", "other-secret", 1).unwrap();
+        socket.send(auth_message(forged)).await.unwrap();
+        let (error, code) = refusal(&mut socket).await;
+        assert_eq!(error, "Invalid authentication token");
+        assert_eq!(code, CloseCode::Policy);
+
+        // A token revoked at logout
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        let token = create_token(Uuid::new_v4(), "a@example.com", SECRET, 1).unwrap();
+        let claims = token.verify(SECRET).unwrap();
+        revoked.revoke(claims.jti, claims.exp).await.unwrap();
+        socket.send(auth_message(token)).await.unwrap();
+        let (error, _) = refusal(&mut socket).await;
+        assert_eq!(error, "Invalid authentication token");
+
+        // None of them joined the room
+        assert!(room_manager.read().await.get(&room_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_multi_document_socket_checks_each_join() {
+        let room_manager = rooms();
+        let url = serve_multi(room_manager).await;
+
+        // Joining before authenticating closes the socket
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        let doc_id = Uuid::new_v4();
+        let join = serde_json::json!({ "type": "Join", "doc_id": doc_id });
+        socket.send(Message::Text(join.to_string())).await.unwrap();
+        let (error, code) = refusal(&mut socket).await;
+        assert_eq!(error, "Authentication required");
+        assert_eq!(code, CloseCode::Policy);
+
+        // Authenticated, but the file's project can't be checked
+        let mut socket = connect(&url).await;
+        socket.send(Message::Text(join.to_string())).await.unwrap();
+        let Message::Text(text) = socket.next().await.unwrap().unwrap() else {
+            panic!("expected an error message");
+        };
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["type"], "Error");
+        assert!(json["message"].as_str().unwrap().contains("Cannot open"));
+        assert!(room_manager.read().await.get(&doc_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_room_refused_when_access_unknown() {
+        let db = unreachable_db();
+        let url = serve(
+            rooms(),
+            Some(DocumentLog::new(db, 500)),
+            None,
+            Announcer::new(),
+        )
+        .await;
+
+        // The project can't be checked, so the file stays closed
+        let mut socket = connect(&format!("{}{}", url, Uuid::new_v4())).await;
+        let (_, code) = refusal(&mut socket).await;
+        assert_eq!(code, CloseCode::Error);
+    }
+
+    #[test]
+    fn test_initial_terminal_size_from_query() {
+        let size = |uri: &str| {
+            let uri: axum::http::Uri = uri.parse().unwrap();
+            axum::extract::Query::<TerminalQuery>::try_from_uri(&uri)
+                .unwrap()
+                .0
+                .size()
+        };
+
+        let sized = size("/ws/terminal/1?cols=132&rows=43").unwrap();
+        assert_eq!((sized.cols, sized.rows), (132, 43));
+        // Both dimensions are needed to attach straight away
+        assert!(size("/ws/terminal/1?cols=132").is_none());
+        assert!(size("/ws/terminal/1").is_none());
+    }
+
+    #[test]
+    fn test_output_frames_tagged_by_stream() {
+        let chunk = |stream, data: &[u8]| OutputChunk {
+            stream,
+            data: data.to_vec(),
+        };
+        let mut frames = OutputFrames::default();
+
+        let mut events = vec![
+            frames.push(&chunk(StdStream::Stdout, b"hello\n")),
+            frames.push(&chunk(StdStream::Stderr, b"oops\n")),
+            // Half a character is held back until the rest arrives
+            frames.push(&chunk(StdStream::Stderr, b"\xc3")),
+            frames.push(&chunk(StdStream::Stdout, b"done")),
+        ];
+        events.extend(frames.finish().into_iter().map(Some));
+
+        let json: Vec<serde_json::Value> = events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect();
+        assert_eq!(json[2], serde_json::Value::Null);
+        let frames: Vec<(&str, u64, &str)> = json
+            .iter()
+            .filter(|event| !event.is_null())
+            .map(|event| {
+                assert_eq!(event["type"], "Output");
+                (
+                    event["stream"].as_str().unwrap(),
+                    event["seq"].as_u64().unwrap(),
+                    event["data"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                ("stdout", 0, "hello\n"),
+                ("stderr", 1, "oops\n"),
+                ("stdout", 2, "done"),
+                ("stderr", 3, "\u{fffd}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_names_trimmed_and_bounded() {
+        assert_eq!(
+            checkpoint_name("  before refactor "),
+            Some("before refactor")
+        );
+        assert_eq!(checkpoint_name("   "), None);
+        assert_eq!(checkpoint_name(&"é".repeat(100)).map(str::len), Some(200));
+        assert_eq!(checkpoint_name(&"x".repeat(101)), None);
+    }
+}
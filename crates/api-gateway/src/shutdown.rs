@@ -0,0 +1,67 @@
+//! Graceful shutdown.
+
+use std::time::Duration;
+
+use rustyclint_sandbox::ContainerManager;
+
+use crate::state::AppState;
+
+/// Resolve on Ctrl+C or, on Unix, SIGTERM.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+
+    tracing::info!("Shutdown signal received");
+}
+
+/// Stop every language server and remove its container, within `timeout`.
+pub async fn stop_language_servers(state: &AppState, timeout: Duration) {
+    let stop = async {
+        let report = state.lsp.lock().await.shutdown_all(timeout).await;
+
+        if let Ok(containers) = ContainerManager::new() {
+            for container_id in &report.container_ids {
+                let _ = containers.remove_container(container_id).await;
+            }
+        }
+
+        tracing::info!(
+            stopped = report.stopped,
+            failed = report.failed,
+            "Language servers shut down"
+        );
+    };
+
+    // Container removal shares the deadline with the servers themselves
+    if tokio::time::timeout(timeout, stop).await.is_err() {
+        tracing::warn!(
+            "Language server shutdown did not finish within {:?}",
+            timeout
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Signed, self-contained project snapshots.
+
+use std::collections::HashSet;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rustyclint_common::{
+    db::{self, FileRepo, ProjectRepo},
+    models::{normalize_path, File, FileEncoding, Language, PathPolicy, Project},
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Format version written into new snapshots.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A file captured in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    pub path: String,
+    pub language: Language,
+    pub encoding: FileEncoding,
+    pub content: String,
+}
+
+/// A project's metadata and file contents at a point in time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectSnapshot {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub name: String,
+    pub default_language: Language,
+    pub files: Vec<SnapshotFile>,
+}
+
+/// A snapshot together with the HMAC of its serialized form.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedSnapshot {
+    pub snapshot: ProjectSnapshot,
+    /// `sha256=<hex HMAC of the snapshot JSON>`.
+    pub signature: String,
+}
+
+/// Why a snapshot was rejected on import.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SnapshotError {
+    #[error("Snapshot signature does not match")]
+    BadSignature,
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid file path in snapshot: {0:?}")]
+    InvalidPath(String),
+    #[error("Duplicate file path in snapshot: {0:?}")]
+    DuplicatePath(String),
+    #[error("File {0:?} is not valid base64")]
+    InvalidContent(String),
+}
+
+impl ProjectSnapshot {
+    /// Build a snapshot from a project and its files with their content.
+    pub fn from_project(project: &Project, files: Vec<(File, String)>) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now(),
+            name: project.name.clone(),
+            default_language: project.default_language,
+            files: files
+                .into_iter()
+                .map(|(file, content)| SnapshotFile {
+                    path: file.path,
+                    language: file.language,
+                    encoding: file.encoding,
+                    content,
+                })
+                .collect(),
+        }
+    }
+
+    /// Check the version, file paths and binary contents.
+    pub fn validate(&self) -> Result<(), SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+
+        let mut seen = HashSet::new();
+        for file in &self.files {
+            validate_path(&file.path)?;
+            if !seen.insert(file.path.as_str()) {
+                return Err(SnapshotError::DuplicatePath(file.path.clone()));
+            }
+            if file.encoding == FileEncoding::Base64 && STANDARD.decode(&file.content).is_err() {
+                return Err(SnapshotError::InvalidContent(file.path.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn mac(&self, secret: &str) -> Hmac<Sha256> {
+        let body = serde_json::to_vec(self).expect("snapshot serializes to JSON");
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(&body);
+        mac
+    }
+}
+
+impl SignedSnapshot {
+    /// Sign a snapshot with the server's snapshot secret.
+    pub fn sign(snapshot: ProjectSnapshot, secret: &str) -> Self {
+        let signature = format!("sha256={:x}", snapshot.mac(secret).finalize().into_bytes());
+        Self {
+            snapshot,
+            signature,
+        }
+    }
+
+    /// Check the signature in constant time, then validate the contents.
+    pub fn verify(self, secret: &str) -> Result<ProjectSnapshot, SnapshotError> {
+        let digest = self
+            .signature
+            .strip_prefix("sha256=")
+            .and_then(decode_hex)
+            .ok_or(SnapshotError::BadSignature)?;
+        self.snapshot
+            .mac(secret)
+            .verify_slice(&digest)
+            .map_err(|_| SnapshotError::BadSignature)?;
+
+        self.snapshot.validate()?;
+        Ok(self.snapshot)
+    }
+}
+
+/// Reject paths that are empty, absolute, or could escape the project.
+///
+/// Snapshots are checked strictly: paths are restored exactly as written.
+pub fn validate_path(path: &str) -> Result<(), SnapshotError> {
+    normalize_path(path, PathPolicy::Strict)
+        .map(|_| ())
+        .map_err(|_| SnapshotError::InvalidPath(path.to_string()))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Capture a project's current files.
+pub async fn capture(
+    pool: &PgPool,
+    project: &Project,
+) -> rustyclint_common::Result<ProjectSnapshot> {
+    let files = FileRepo::list_with_content(pool, project.id).await?;
+    Ok(ProjectSnapshot::from_project(project, files))
+}
+
+/// Recreate a verified snapshot as a new project owned by `owner_id`.
+///
+/// The project and all of its files are written in one transaction.
+pub async fn restore(
+    pool: &PgPool,
+    owner_id: Uuid,
+    snapshot: &ProjectSnapshot,
+) -> rustyclint_common::Result<Project> {
+    let mut tx = db::begin(pool).await?;
+    let project =
+        ProjectRepo::create_tx(&mut tx, &snapshot.name, owner_id, snapshot.default_language)
+            .await?;
+    for file in &snapshot.files {
+        FileRepo::upsert_tx(
+            &mut tx,
+            project.id,
+            &file.path,
+            file.language,
+            &file.content,
+            file.encoding,
+        )
+        .await?;
+    }
+    db::commit(tx).await?;
+    Ok(project)
+}
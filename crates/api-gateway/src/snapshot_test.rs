@@ -0,0 +1,96 @@
+//! Tests for project snapshots.
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use rustyclint_common::models::{File, FileEncoding, Language, Project};
+    use uuid::Uuid;
+
+    use crate::snapshot::{validate_path, ProjectSnapshot, SignedSnapshot, SnapshotError};
+
+    const SECRET: &str = "snapshot-secret";
+
+    fn project_with_files() -> (Project, Vec<(File, String)>) {
+        let project = Project {
+            id: Uuid::new_v4(),
+            name: "demo".into(),
+            owner_id: Uuid::new_v4(),
+            default_language: Language::Python,
+            allowed_extensions: None,
+            resource_profile: None,
+            version_retention: Default::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let file = |path: &str, encoding, content: &str| {
+            (
+                File {
+                    id: Uuid::new_v4(),
+                    project_id: project.id,
+                    path: path.into(),
+                    language: Language::Python,
+                    encoding,
+                    created_at: Utc::now(),
+                    updated_at: Utc::now(),
+                },
+                content.to_string(),
+            )
+        };
+        let files = vec![
+            file("main.py", FileEncoding::Utf8, "print('hi')\n"),
+            file("assets/logo.png", FileEncoding::Base64, "iVBORw0KGgo="),
+        ];
+        (project, files)
+    }
+
+    #[test]
+    fn test_snapshot_restores_equivalent_project() {
+        let (project, files) = project_with_files();
+        let snapshot = ProjectSnapshot::from_project(&project, files.clone());
+
+        // Round-trip through the wire format, as export then import would
+        let json = serde_json::to_string(&SignedSnapshot::sign(snapshot.clone(), SECRET)).unwrap();
+        let imported: SignedSnapshot = serde_json::from_str(&json).unwrap();
+        let restored = imported.verify(SECRET).unwrap();
+
+        assert_eq!(restored, snapshot);
+        assert_eq!(restored.name, project.name);
+        assert_eq!(restored.default_language, project.default_language);
+        for ((file, content), restored) in files.iter().zip(&restored.files) {
+            assert_eq!(restored.path, file.path);
+            assert_eq!(restored.language, file.language);
+            assert_eq!(restored.encoding, file.encoding);
+            assert_eq!(&restored.content, content);
+        }
+    }
+
+    #[test]
+    fn test_tampered_snapshot_rejected() {
+        let (project, files) = project_with_files();
+        let mut signed =
+            SignedSnapshot::sign(ProjectSnapshot::from_project(&project, files), SECRET);
+        signed.snapshot.files[0].content = "import os; os.system('id')".into();
+        assert_eq!(signed.verify(SECRET).unwrap_err(), SnapshotError::BadSignature);
+
+        let (project, files) = project_with_files();
+        let signed = SignedSnapshot::sign(ProjectSnapshot::from_project(&project, files), SECRET);
+        assert_eq!(signed.verify("other").unwrap_err(), SnapshotError::BadSignature);
+    }
+
+    #[test]
+    fn test_unsafe_paths_rejected() {
+        for path in ["", "/etc/passwd", "../escape.py", "a/../../b", "a//b", "a\\b", "./x", "a\0"] {
+            assert!(validate_path(path).is_err(), "{path:?} should be rejected");
+        }
+        assert!(validate_path("src/lib/util.py").is_ok());
+
+        // A correctly signed snapshot is still rejected if a path is unsafe
+        let (project, mut files) = project_with_files();
+        files[0].0.path = "../../etc/cron.d/job".into();
+        let signed = SignedSnapshot::sign(ProjectSnapshot::from_project(&project, files), SECRET);
+        assert!(matches!(
+            signed.verify(SECRET),
+            Err(SnapshotError::InvalidPath(_))
+        ));
+    }
+}
@@ -1,10 +1,25 @@
 //! Application state management.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use rustyclint_common::db::{FileRepo, SandboxSessionRepo};
+use rustyclint_lsp_proxy::{LspManager, ResponseLimits};
+use rustyclint_sandbox::{ContainerManager, ExecutionTracker, SessionPolicy, SessionRegistry};
 use sqlx::PgPool;
+use tokio::sync::Mutex;
 
-use crate::config::Config;
+use crate::{
+    announce::Announcer,
+    auth::RevokedTokens,
+    concurrency::UserExecutions,
+    config::Config,
+    debounce::ChangeDebouncer,
+    load_shed::LoadSignal,
+    quota::DailyQuota,
+    rate_limit::ExecutionRateLimit,
+    result_cache::ResultCache,
+    store::{KeyPrefix, RedisStore},
+};
 
 /// Shared application state.
 #[derive(Clone)]
@@ -12,6 +27,17 @@ pub struct AppState {
     pub db: PgPool,
     pub redis: redis::aio::ConnectionManager,
     pub config: Arc<Config>,
+    pub lsp: Arc<Mutex<LspManager>>,
+    pub lsp_changes: Arc<ChangeDebouncer>,
+    pub sessions: Arc<SessionRegistry>,
+    pub executions: ExecutionTracker,
+    pub user_executions: UserExecutions,
+    pub results: ResultCache,
+    pub quota: DailyQuota,
+    pub rate_limit: ExecutionRateLimit,
+    pub revoked_tokens: RevokedTokens,
+    pub load: LoadSignal,
+    pub announcements: Announcer,
 }
 
 impl AppState {
@@ -25,6 +51,102 @@ impl AppState {
         let redis = redis::aio::ConnectionManager::new(redis_client).await?;
         tracing::info!("Connected to Redis");
 
+        let store = Arc::new(RedisStore::new(
+            redis.clone(),
+            KeyPrefix::new(config.redis_key_prefix.clone()),
+        ));
+        let results = ResultCache::new(
+            store.clone(),
+            Duration::from_secs(config.result_cache_ttl_secs),
+        );
+        let quota = DailyQuota::new(store.clone(), config.daily_execution_limit);
+        let rate_limit = ExecutionRateLimit::new(store.clone(), config.max_executions_per_minute);
+        let revoked_tokens =
+            RevokedTokens::new(store).with_fail_open(config.revocation_check_fail_open);
+
+        // Forward debounced document changes to language servers
+        let response_limits = config.lsp_response_limits.iter().fold(
+            ResponseLimits::new(config.lsp_max_response_bytes),
+            |limits, (method, max)| limits.with_method(method.as_str(), *max),
+        );
+        let lsp = Arc::new(Mutex::new(
+            LspManager::with_response_limits(response_limits)
+                .with_request_timeout(Duration::from_secs(config.lsp_request_timeout_secs))
+                .with_max_proxies(config.lsp_max_servers),
+        ));
+        let (lsp_changes, mut changes) =
+            ChangeDebouncer::new(Duration::from_millis(config.lsp_change_debounce_ms));
+        let lsp_changes = Arc::new(lsp_changes);
+        let forward_lsp = Arc::clone(&lsp);
+        tokio::spawn(async move {
+            while let Some(change) = changes.recv().await {
+                let mut manager = forward_lsp.lock().await;
+                if let Some(proxy) = manager.get_mut(change.session_id, change.language) {
+                    if let Err(e) = proxy
+                        .did_change(&change.uri, change.version, &change.text)
+                        .await
+                    {
+                        tracing::warn!("Failed to forward didChange for {}: {}", change.uri, e);
+                    }
+                }
+            }
+        });
+
+        // Reap idle and over-age sandbox sessions, starting with those
+        // recorded before a restart
+        let sessions = Arc::new(SessionRegistry::new(SessionPolicy {
+            idle_timeout: chrono::Duration::seconds(config.session_idle_timeout_secs as i64),
+            max_lifetime: chrono::Duration::seconds(config.max_session_lifetime_secs as i64),
+        }));
+        restore_sessions(&db, &sessions).await?;
+        let reaper_sessions = Arc::clone(&sessions);
+        let reaper_db = db.clone();
+        let reaper_changes = Arc::clone(&lsp_changes);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                let reaped = reaper_sessions.reap(chrono::Utc::now());
+                if reaped.is_empty() {
+                    continue;
+                }
+
+                let containers = match ContainerManager::new() {
+                    Ok(containers) => containers,
+                    Err(e) => {
+                        tracing::error!("Cannot remove reaped session containers: {}", e);
+                        continue;
+                    }
+                };
+                for (session, reason) in reaped {
+                    tracing::info!("Reaping sandbox session {} ({:?})", session.id, reason);
+                    reaper_changes.forget_session(session.id).await;
+                    let _ = containers.remove_container(&session.container_id).await;
+                    if let Err(e) = SandboxSessionRepo::delete(&reaper_db, session.id).await {
+                        tracing::warn!("Failed to forget session {}: {}", session.id, e);
+                    }
+                }
+            }
+        });
+
+        // Prune file versions that fell outside their project's retention
+        if config.file_version_prune_interval_secs > 0 {
+            let db = db.clone();
+            let period = Duration::from_secs(config.file_version_prune_interval_secs);
+            let defaults = config.version_retention();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    match FileRepo::enforce_version_retention(&db, None, defaults).await {
+                        Ok(0) => {}
+                        Ok(pruned) => tracing::info!("Pruned {} old file versions", pruned),
+                        Err(e) => tracing::warn!("Failed to prune file versions: {}", e),
+                    }
+                }
+            });
+        }
+
         Ok(Self {
             db,
             redis,
@@ -32,11 +154,104 @@ impl AppState {
                 port: config.port,
                 database_url: config.database_url.clone(),
                 redis_url: config.redis_url.clone(),
+                redis_key_prefix: config.redis_key_prefix.clone(),
                 jwt_secret: config.jwt_secret.clone(),
                 jwt_expiry_hours: config.jwt_expiry_hours,
+                refresh_token_expiry_days: config.refresh_token_expiry_days,
+                revocation_check_fail_open: config.revocation_check_fail_open,
+                admin_user_ids: config.admin_user_ids.clone(),
+                sandbox_enabled: config.sandbox_enabled,
                 sandbox_timeout_secs: config.sandbox_timeout_secs,
+                max_code_bytes: config.max_code_bytes,
+                max_result_bytes: config.max_result_bytes,
+                max_file_versions: config.max_file_versions,
+                file_version_max_age_secs: config.file_version_max_age_secs,
+                file_version_prune_interval_secs: config.file_version_prune_interval_secs,
+                max_exec_args: config.max_exec_args,
+                max_exec_args_bytes: config.max_exec_args_bytes,
+                file_path_policy: config.file_path_policy,
                 max_containers_per_user: config.max_containers_per_user,
+                load_shed_max_executions: config.load_shed_max_executions,
+                load_shed_retry_after_secs: config.load_shed_retry_after_secs,
+                sandbox_pool_size: config.sandbox_pool_size,
+                daily_execution_limit: config.daily_execution_limit,
+                max_executions_per_minute: config.max_executions_per_minute,
+                session_idle_timeout_secs: config.session_idle_timeout_secs,
+                max_session_lifetime_secs: config.max_session_lifetime_secs,
+                sandbox_images: config.sandbox_images.clone(),
+                sandbox_allow_root: config.sandbox_allow_root,
+                image_pull_timeout_secs: config.image_pull_timeout_secs,
+                language_versions: config.language_versions.clone(),
+                resource_profiles: config.resource_profiles.clone(),
+                callback_allowlist: config.callback_allowlist.clone(),
+                callback_secret: config.callback_secret.clone(),
+                compression_encodings: config.compression_encodings.clone(),
+                compression_level: config.compression_level,
+                compression_min_size_bytes: config.compression_min_size_bytes,
+                snapshot_secret: config.snapshot_secret.clone(),
+                reserved_project_names: config.reserved_project_names.clone(),
+                result_cache_ttl_secs: config.result_cache_ttl_secs,
+                code_precheck: config.code_precheck,
+                lsp_disabled_languages: config.lsp_disabled_languages.clone(),
+                lsp_change_debounce_ms: config.lsp_change_debounce_ms,
+                lsp_max_response_bytes: config.lsp_max_response_bytes,
+                lsp_response_limits: config.lsp_response_limits.clone(),
+                lsp_request_timeout_secs: config.lsp_request_timeout_secs,
+                lsp_shutdown_timeout_secs: config.lsp_shutdown_timeout_secs,
+                lsp_max_servers: config.lsp_max_servers,
+                awareness_batch_ms: config.awareness_batch_ms,
+                max_awareness_bytes: config.max_awareness_bytes,
+                collab_prune_interval_secs: config.collab_prune_interval_secs,
+                collab_heartbeat_timeout_secs: config.collab_heartbeat_timeout_secs,
+                collab_idle_timeout_secs: config.collab_idle_timeout_secs,
+                doc_log_compact_after: config.doc_log_compact_after,
+                max_rooms_per_user: config.max_rooms_per_user,
+                max_room_participants: config.max_room_participants,
+                max_checkpoints_per_document: config.max_checkpoints_per_document,
+                log_pii: config.log_pii,
             }),
+            lsp,
+            lsp_changes,
+            sessions,
+            executions: ExecutionTracker::new(),
+            user_executions: UserExecutions::new(config.max_containers_per_user),
+            results,
+            quota,
+            rate_limit,
+            revoked_tokens,
+            load: LoadSignal::default(),
+            announcements: Announcer::new(),
         })
     }
 }
+
+/// Track the sandbox sessions recorded before a restart, removing the
+/// containers of those that ended in the meantime.
+async fn restore_sessions(db: &PgPool, sessions: &SessionRegistry) -> anyhow::Result<()> {
+    let stale = SandboxSessionRepo::delete_stale(db).await?;
+    if !stale.is_empty() {
+        match ContainerManager::new() {
+            Ok(containers) => {
+                for container_id in &stale {
+                    let _ = containers.remove_container(container_id).await;
+                }
+            }
+            Err(e) => tracing::error!("Cannot remove ended session containers: {}", e),
+        }
+    }
+
+    let restored = SandboxSessionRepo::list(db).await?;
+    if !restored.is_empty() || !stale.is_empty() {
+        tracing::info!(
+            "Restored {} sandbox sessions, removed {} ended ones",
+            restored.len(),
+            stale.len()
+        );
+    }
+    let now = chrono::Utc::now();
+    for session in restored {
+        sessions.restore(session, now);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Key-value storage for short-lived gateway data.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::AsyncCommands;
+
+/// Byte-valued store with per-key expiry.
+#[async_trait]
+pub trait KvStore: Send + Sync {
+    /// Fetch a value, or `None` if it is missing or expired.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Store a value that expires after `ttl`.
+    async fn set_ex(&self, key: &str, value: &[u8], ttl: Duration) -> anyhow::Result<()>;
+
+    /// Increment a counter, creating it at 1, and set it to expire after
+    /// `ttl`. Returns the new value.
+    async fn incr_ex(&self, key: &str, ttl: Duration) -> anyhow::Result<u64>;
+
+    /// Take `by` back off a counter, keeping its expiry. Missing counters
+    /// are left alone so a late refund can't create one without a TTL.
+    async fn decr_by(&self, key: &str, by: u64) -> anyhow::Result<()>;
+}
+
+/// Namespace prepended to every key written to a shared store, so that
+/// several deployments can use one Redis without colliding.
+#[derive(Debug, Clone, Default)]
+pub struct KeyPrefix(String);
+
+impl KeyPrefix {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self(prefix.into())
+    }
+
+    /// The key as stored.
+    pub fn apply(&self, key: &str) -> String {
+        format!("{}{}", self.0, key)
+    }
+}
+
+/// [`KvStore`] backed by the shared Redis connection.
+#[derive(Clone)]
+pub struct RedisStore {
+    conn: redis::aio::ConnectionManager,
+    prefix: KeyPrefix,
+}
+
+impl RedisStore {
+    pub fn new(conn: redis::aio::ConnectionManager, prefix: KeyPrefix) -> Self {
+        Self { conn, prefix }
+    }
+}
+
+#[async_trait]
+impl KvStore for RedisStore {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.get(self.prefix.apply(key)).await?)
+    }
+
+    async fn set_ex(&self, key: &str, value: &[u8], ttl: Duration) -> anyhow::Result<()> {
+        let mut conn = self.conn.clone();
+        conn.set_ex::<_, _, ()>(self.prefix.apply(key), value, ttl.as_secs().max(1))
+            .await?;
+        Ok(())
+    }
+
+    async fn incr_ex(&self, key: &str, ttl: Duration) -> anyhow::Result<u64> {
+        let mut conn = self.conn.clone();
+        let key = self.prefix.apply(key);
+        let (count,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, ttl.as_secs().max(1) as i64)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(count)
+    }
+
+    async fn decr_by(&self, key: &str, by: u64) -> anyhow::Result<()> {
+        let mut conn = self.conn.clone();
+        redis::Script::new(
+            "if redis.call('EXISTS', KEYS[1]) == 1 then redis.call('DECRBY', KEYS[1], ARGV[1]) end",
+        )
+        .key(self.prefix.apply(key))
+        .arg(by)
+        .invoke_async::<_, ()>(&mut conn)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub use memory::MemoryStore;
+
+#[cfg(test)]
+mod memory {
+    use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+    use async_trait::async_trait;
+
+    use super::KvStore;
+
+    /// In-memory [`KvStore`] for tests.
+    ///
+    /// Keys never expire; the TTL each was last written with is kept for
+    /// checking instead. Counters are stored as decimal text, as Redis
+    /// returns them.
+    #[derive(Default)]
+    pub struct MemoryStore {
+        values: Mutex<HashMap<String, (Vec<u8>, Duration)>>,
+        unreachable: bool,
+    }
+
+    impl MemoryStore {
+        /// A store whose every call fails, like an unreachable Redis.
+        pub fn unreachable() -> Self {
+            Self {
+                unreachable: true,
+                ..Self::default()
+            }
+        }
+
+        /// The TTL `key` was last written with, if it is set.
+        pub fn ttl(&self, key: &str) -> Option<Duration> {
+            self.values.lock().unwrap().get(key).map(|(_, ttl)| *ttl)
+        }
+
+        fn reach(&self) -> anyhow::Result<()> {
+            if self.unreachable {
+                anyhow::bail!("store unreachable");
+            }
+            Ok(())
+        }
+    }
+
+    fn count(value: &[u8]) -> u64 {
+        std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    }
+
+    #[async_trait]
+    impl KvStore for MemoryStore {
+        async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+            self.reach()?;
+            Ok(self.values.lock().unwrap().get(key).map(|(v, _)| v.clone()))
+        }
+
+        async fn set_ex(&self, key: &str, value: &[u8], ttl: Duration) -> anyhow::Result<()> {
+            self.reach()?;
+            self.values
+                .lock()
+                .unwrap()
+                .insert(key.into(), (value.to_vec(), ttl));
+            Ok(())
+        }
+
+        async fn incr_ex(&self, key: &str, ttl: Duration) -> anyhow::Result<u64> {
+            self.reach()?;
+            let mut values = self.values.lock().unwrap();
+            let next = values.get(key).map_or(0, |(v, _)| count(v)) + 1;
+            values.insert(key.into(), (next.to_string().into_bytes(), ttl));
+            Ok(next)
+        }
+
+        async fn decr_by(&self, key: &str, by: u64) -> anyhow::Result<()> {
+            self.reach()?;
+            if let Some((value, _)) = self.values.lock().unwrap().get_mut(key) {
+                *value = count(value).saturating_sub(by).to_string().into_bytes();
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Tests for store key namespacing.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{config::Config, store::KeyPrefix};
+
+    #[test]
+    fn test_keys_prefixed_as_configured() {
+        let config: Config = serde_json::from_value(json!({
+            "database_url": "postgres://localhost/test",
+            "redis_url": "redis://localhost",
+            "jwt_secret": "secret",
+            "redis_key_prefix": "staging:",
+        }))
+        .unwrap();
+
+        let prefix = KeyPrefix::new(config.redis_key_prefix);
+        assert_eq!(prefix.apply("exec-result:abc"), "staging:exec-result:abc");
+        assert_eq!(
+            KeyPrefix::new("prod:").apply("exec-result:abc"),
+            "prod:exec-result:abc"
+        );
+
+        // Unprefixed by default
+        assert_eq!(KeyPrefix::default().apply("quota:x"), "quota:x");
+    }
+}
@@ -1,68 +1,219 @@
 //! Awareness protocol for cursor positions and presence.
+//!
+//! Follows the y-protocols awareness format: an update is a lib0 varuint
+//! count followed by, per client, its varuint client id, varuint clock and
+//! its state as a JSON varstring, where `null` means the client has gone.
 
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
-/// Awareness state for a single client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AwarenessState {
-    pub user_id: Uuid,
-    pub username: String,
-    pub color: String,
-    pub cursor: Option<CursorState>,
-    pub selection: Option<SelectionState>,
+/// y-websocket message type of an awareness frame.
+pub const MSG_AWARENESS: u8 = 1;
+
+/// One client's entry in an awareness update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientAwareness {
+    pub client_id: u64,
+    pub clock: u64,
+    /// The client's state, e.g. its user name, color and selection; `None`
+    /// once the client has gone.
+    pub state: Option<Value>,
 }
 
 /// Cursor position in the document.
+///
+/// Reported by clients as a `cursor` object with `line` and `column` in
+/// their awareness state.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CursorState {
     pub line: u32,
     pub column: u32,
 }
 
-/// Text selection in the document.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SelectionState {
-    pub start_line: u32,
-    pub start_column: u32,
-    pub end_line: u32,
-    pub end_column: u32,
+impl CursorState {
+    /// The cursor in an awareness state, if it reports one.
+    pub fn from_state(state: &Value) -> Option<Self> {
+        serde_json::from_value(state.get("cursor")?.clone()).ok()
+    }
+}
+
+/// Decode an awareness update, or `None` if it is malformed.
+pub fn decode_update(data: &[u8]) -> Option<Vec<ClientAwareness>> {
+    let mut pos = 0;
+    let count = read_var_uint(data, &mut pos)?;
+    let mut clients = Vec::new();
+    for _ in 0..count {
+        let client_id = read_var_uint(data, &mut pos)?;
+        let clock = read_var_uint(data, &mut pos)?;
+        let len = usize::try_from(read_var_uint(data, &mut pos)?).ok()?;
+        let json = data.get(pos..pos.checked_add(len)?)?;
+        pos += len;
+
+        let state = match serde_json::from_slice(json).ok()? {
+            Value::Null => None,
+            state => Some(state),
+        };
+        clients.push(ClientAwareness {
+            client_id,
+            clock,
+            state,
+        });
+    }
+    Some(clients)
+}
+
+/// Encode an awareness update.
+pub fn encode_update(clients: &[ClientAwareness]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_var_uint(&mut buf, clients.len() as u64);
+    for client in clients {
+        write_var_uint(&mut buf, client.client_id);
+        write_var_uint(&mut buf, client.clock);
+        let json = match &client.state {
+            Some(state) => state.to_string(),
+            None => "null".to_string(),
+        };
+        write_var_uint(&mut buf, json.len() as u64);
+        buf.extend_from_slice(json.as_bytes());
+    }
+    buf
+}
+
+/// Wrap an awareness update in a y-websocket frame.
+pub fn encode_message(update: &[u8]) -> Vec<u8> {
+    let mut buf = vec![MSG_AWARENESS];
+    write_var_uint(&mut buf, update.len() as u64);
+    buf.extend_from_slice(update);
+    buf
+}
+
+pub(crate) fn write_var_uint(buf: &mut Vec<u8>, mut value: u64) {
+    while value > 0x7f {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_var_uint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut result: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        if shift >= 64 {
+            return None;
+        }
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(result);
+        }
+        shift += 7;
+    }
+}
+
+struct ClientEntry {
+    /// Participant whose connection reported the client.
+    owner: Uuid,
+    clock: u64,
+    state: Option<Value>,
 }
 
 /// Manages awareness states for all clients in a room.
 #[derive(Default)]
 pub struct AwarenessManager {
-    states: HashMap<Uuid, AwarenessState>,
+    clients: HashMap<u64, ClientEntry>,
 }
 
 impl AwarenessManager {
     /// Create a new awareness manager.
     pub fn new() -> Self {
         Self {
-            states: HashMap::new(),
+            clients: HashMap::new(),
         }
     }
 
-    /// Update a client's awareness state.
-    pub fn update(&mut self, client_id: Uuid, state: AwarenessState) {
-        self.states.insert(client_id, state);
+    /// Merge an update received from `owner`'s connection, returning the
+    /// ids of the clients whose state changed.
+    ///
+    /// An entry applies if its clock is newer than the one held, or if it
+    /// removes the state at the same clock. Clients currently reported by
+    /// another participant are left alone.
+    pub fn apply(&mut self, owner: Uuid, update: Vec<ClientAwareness>) -> Vec<u64> {
+        let mut changed = Vec::new();
+        for client in update {
+            if let Some(entry) = self.clients.get(&client.client_id) {
+                if entry.state.is_some() && entry.owner != owner {
+                    continue;
+                }
+                let newer = entry.clock < client.clock
+                    || (entry.clock == client.clock
+                        && client.state.is_none()
+                        && entry.state.is_some());
+                if !newer {
+                    continue;
+                }
+            }
+
+            self.clients.insert(
+                client.client_id,
+                ClientEntry {
+                    owner,
+                    clock: client.clock,
+                    state: client.state,
+                },
+            );
+            changed.push(client.client_id);
+        }
+        changed
+    }
+
+    /// Mark every client reported by `owner` as gone, returning their ids.
+    pub fn remove(&mut self, owner: &Uuid) -> Vec<u64> {
+        let mut removed = Vec::new();
+        for (client_id, entry) in &mut self.clients {
+            if entry.owner == *owner && entry.state.is_some() {
+                entry.clock += 1;
+                entry.state = None;
+                removed.push(*client_id);
+            }
+        }
+        removed
     }
 
-    /// Remove a client's awareness state.
-    pub fn remove(&mut self, client_id: &Uuid) {
-        self.states.remove(client_id);
+    /// State of a client, if it is present.
+    pub fn state(&self, client_id: u64) -> Option<&Value> {
+        self.clients.get(&client_id)?.state.as_ref()
     }
 
-    /// Get all awareness states.
-    pub fn get_all(&self) -> Vec<&AwarenessState> {
-        self.states.values().collect()
+    /// Encode the current entries of `client_ids` as an update.
+    pub fn encode(&self, client_ids: &[u64]) -> Vec<u8> {
+        let clients: Vec<ClientAwareness> = client_ids
+            .iter()
+            .filter_map(|client_id| {
+                let entry = self.clients.get(client_id)?;
+                Some(ClientAwareness {
+                    client_id: *client_id,
+                    clock: entry.clock,
+                    state: entry.state.clone(),
+                })
+            })
+            .collect();
+        encode_update(&clients)
     }
 
-    /// Encode all states for broadcast.
-    pub fn encode(&self) -> Vec<u8> {
-        serde_json::to_vec(&self.states).unwrap_or_default()
+    /// Encode every present state, or `None` if there are none.
+    pub fn encode_all(&self) -> Option<Vec<u8>> {
+        let client_ids: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|(_, entry)| entry.state.is_some())
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        (!client_ids.is_empty()).then(|| self.encode(&client_ids))
     }
 
     /// Generate a random color for a user.
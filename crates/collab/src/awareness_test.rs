@@ -0,0 +1,85 @@
+//! Tests for the awareness protocol.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::awareness::{
+        decode_update, encode_message, encode_update, AwarenessManager, ClientAwareness,
+        CursorState,
+    };
+
+    fn client(client_id: u64, clock: u64, line: Option<u32>) -> ClientAwareness {
+        ClientAwareness {
+            client_id,
+            clock,
+            state: line.map(|line| json!({ "cursor": { "line": line, "column": 2 } })),
+        }
+    }
+
+    #[test]
+    fn test_update_wire_format() {
+        let update = encode_update(&[ClientAwareness {
+            client_id: 300,
+            clock: 1,
+            state: Some(json!({ "a": 1 })),
+        }]);
+        let mut expected = vec![1, 0xac, 0x02, 1, 7];
+        expected.extend_from_slice(br#"{"a":1}"#);
+        assert_eq!(update, expected);
+
+        let removal = encode_update(&[client(300, 2, None)]);
+        assert_eq!(decode_update(&removal).unwrap(), [client(300, 2, None)]);
+
+        assert_eq!(&encode_message(&update)[..2], [1, update.len() as u8]);
+    }
+
+    #[test]
+    fn test_malformed_updates_rejected() {
+        let update = encode_update(&[client(1, 1, Some(3)), client(2, 1, Some(4))]);
+        assert!(decode_update(&update[..update.len() - 1]).is_none());
+        assert!(decode_update(&[]).is_none());
+        // A count claiming more clients than the update holds
+        assert!(decode_update(&[0xff, 0xff, 0xff, 0x0f]).is_none());
+        // State that isn't JSON
+        assert!(decode_update(&[1, 1, 1, 3, b'{', b'{', b'{']).is_none());
+    }
+
+    #[test]
+    fn test_merge_follows_clocks() {
+        let alice = Uuid::new_v4();
+        let mut manager = AwarenessManager::new();
+
+        assert_eq!(manager.apply(alice, vec![client(1, 2, Some(5))]), [1]);
+        // Same or older clocks are stale
+        assert!(manager.apply(alice, vec![client(1, 2, Some(6))]).is_empty());
+        assert!(manager.apply(alice, vec![client(1, 1, Some(7))]).is_empty());
+        let cursor = CursorState::from_state(manager.state(1).unwrap()).unwrap();
+        assert_eq!((cursor.line, cursor.column), (5, 2));
+
+        // Removing at the same clock applies
+        assert_eq!(manager.apply(alice, vec![client(1, 2, None)]), [1]);
+        assert!(manager.state(1).is_none());
+        assert!(manager.encode_all().is_none());
+    }
+
+    #[test]
+    fn test_removed_owner_clients_bump_clock() {
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let mut manager = AwarenessManager::new();
+        manager.apply(alice, vec![client(1, 4, Some(1))]);
+        manager.apply(bob, vec![client(2, 1, Some(1))]);
+
+        assert_eq!(manager.remove(&alice), [1]);
+        assert_eq!(
+            decode_update(&manager.encode(&[1])).unwrap(),
+            [client(1, 5, None)]
+        );
+        assert!(manager.state(2).is_some());
+
+        // A reconnecting client can take its id back with a newer clock
+        assert_eq!(manager.apply(bob, vec![client(1, 6, Some(2))]), [1]);
+    }
+}
@@ -1,40 +1,98 @@
 //! CRDT document management.
 
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use tokio::sync::RwLock;
 use uuid::Uuid;
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
-use yrs::{Doc, GetString, ReadTxn, Text, Transact, Update};
+use yrs::{Doc, GetString, ReadTxn, Subscription, Text, Transact, Update};
+
+/// Client id of the text a document was seeded with; editors get random ids.
+const SEED_CLIENT_ID: u64 = 0;
+
+/// Text field holding a file's content; editors that only edit one text
+/// use it.
+pub const DEFAULT_FIELD: &str = "content";
+
+/// Default cap on checkpoints kept per document.
+pub const DEFAULT_MAX_CHECKPOINTS: usize = 50;
+
+/// Handle for a callback registered with [`CollabDocument::on_update`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UpdateSubscriptionId(u64);
 
 /// A collaborative document backed by a Yjs CRDT.
 pub struct CollabDocument {
     id: Uuid,
     doc: Arc<RwLock<Doc>>,
+    subscriptions: Arc<Mutex<HashMap<UpdateSubscriptionId, Subscription>>>,
+    next_subscription: Arc<AtomicU64>,
+    /// Named snapshots of the document state.
+    checkpoints: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
 }
 
 impl CollabDocument {
     /// Create a new empty document.
     pub fn new(id: Uuid) -> Self {
+        Self::from_doc(id, Doc::new())
+    }
+
+    fn from_doc(id: Uuid, doc: Doc) -> Self {
         Self {
             id,
-            doc: Arc::new(RwLock::new(Doc::new())),
+            doc: Arc::new(RwLock::new(doc)),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            next_subscription: Arc::new(AtomicU64::new(0)),
+            checkpoints: Arc::new(Mutex::new(BTreeMap::new())),
         }
     }
 
-    /// Create a document with initial content.
-    pub fn with_content(id: Uuid, content: &str) -> Self {
+    /// Create a document with initial content in `field`.
+    pub fn with_content(id: Uuid, field: &str, content: &str) -> Self {
         let doc = Doc::new();
         {
-            let text = doc.get_or_insert_text("content");
+            let text = doc.get_or_insert_text(field);
             let mut txn = doc.transact_mut();
             text.insert(&mut txn, 0, content);
         }
-        Self {
-            id,
-            doc: Arc::new(RwLock::new(doc)),
+        Self::from_doc(id, doc)
+    }
+
+    /// Create a document holding a stored file's text in [`DEFAULT_FIELD`].
+    ///
+    /// The text is inserted under a fixed client id, so two rooms seeded from
+    /// the same text produce the same update and merging both is harmless.
+    pub fn seeded(id: Uuid, content: &str) -> Self {
+        let doc = Doc::with_client_id(SEED_CLIENT_ID);
+        {
+            let text = doc.get_or_insert_text(DEFAULT_FIELD);
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, content);
+        }
+        Self::from_doc(id, doc)
+    }
+
+    /// Rebuild a document by applying logged updates in order.
+    ///
+    /// A compacted log starts with a snapshot, which is itself an update
+    /// encoding the state it replaced.
+    pub async fn from_updates<I>(id: Uuid, updates: I) -> Result<Self, yrs::encoding::read::Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let document = Self::new(id);
+        for update in updates {
+            document.apply_update(update.as_ref()).await?;
         }
+        Ok(document)
     }
 
     /// Get the document ID.
@@ -66,33 +124,114 @@ impl CollabDocument {
     }
 
     /// Encode state as update based on a client's state vector (for sync step 2).
-    pub async fn encode_diff(&self, state_vector: &[u8]) -> Result<Vec<u8>, yrs::encoding::read::Error> {
+    pub async fn encode_diff(
+        &self,
+        state_vector: &[u8],
+    ) -> Result<Vec<u8>, yrs::encoding::read::Error> {
         let doc = self.doc.read().await;
         let txn = doc.transact();
         let sv = yrs::StateVector::decode_v1(state_vector)?;
         Ok(txn.encode_state_as_update_v1(&sv))
     }
 
-    /// Get the document content as plain text.
-    pub async fn get_content(&self) -> String {
+    /// Get a text field's content as plain text; empty if the field has
+    /// never been written.
+    pub async fn get_content(&self, field: &str) -> String {
         let doc = self.doc.read().await;
-        let text = doc.get_or_insert_text("content");
+        let text = doc.get_or_insert_text(field);
         let txn = doc.transact();
         text.get_string(&txn)
     }
 
+    /// Insert `chunk` into a text field at character `index`, creating the
+    /// field if needed. Update callbacks see the change like a client's.
+    pub async fn insert_text(&self, field: &str, index: u32, chunk: &str) {
+        let doc = self.doc.write().await;
+        let text = doc.get_or_insert_text(field);
+        let mut txn = doc.transact_mut();
+        text.insert(&mut txn, index, chunk);
+    }
+
+    /// Snapshot the current state as a checkpoint called `name`, replacing
+    /// any checkpoint of that name. Returns the snapshot for storing, or
+    /// `None` if `name` is new and the document already has `max`
+    /// checkpoints; 0 is unlimited.
+    pub async fn checkpoint(&self, name: &str, max: usize) -> Option<Vec<u8>> {
+        let state = self.encode_state().await;
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        if max > 0 && checkpoints.len() >= max && !checkpoints.contains_key(name) {
+            return None;
+        }
+        checkpoints.insert(name.to_string(), state.clone());
+        Some(state)
+    }
+
+    /// Register a checkpoint stored earlier.
+    pub fn load_checkpoint(&self, name: &str, state: Vec<u8>) {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), state);
+    }
+
+    /// Names of the document's checkpoints, sorted.
+    pub fn list_checkpoints(&self) -> Vec<String> {
+        self.checkpoints.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Reset the text in [`DEFAULT_FIELD`] to what it was at checkpoint
+    /// `name`, returning the update that did so; `None` if there is no such
+    /// checkpoint.
+    ///
+    /// A CRDT can't drop the edits made since, so the restore is itself an
+    /// edit replacing the current text, and merges with clients like one.
+    pub async fn restore_checkpoint(
+        &self,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>, yrs::encoding::read::Error> {
+        let Some(state) = self.checkpoints.lock().unwrap().get(name).cloned() else {
+            return Ok(None);
+        };
+        let content = Self::from_updates(self.id, [state])
+            .await?
+            .get_content(DEFAULT_FIELD)
+            .await;
+
+        let before = self.state_vector().await;
+        {
+            let doc = self.doc.write().await;
+            let text = doc.get_or_insert_text(DEFAULT_FIELD);
+            let mut txn = doc.transact_mut();
+            let len = text.len(&txn);
+            text.remove_range(&mut txn, 0, len);
+            text.insert(&mut txn, 0, &content);
+        }
+        Ok(Some(self.encode_diff(&before).await?))
+    }
+
     /// Subscribe to document updates.
-    pub async fn on_update<F>(&self, callback: F)
+    ///
+    /// The callback stays registered until [`Self::unsubscribe`] is called
+    /// with the returned id, or the last clone of the document is dropped.
+    pub async fn on_update<F>(&self, callback: F) -> UpdateSubscriptionId
     where
         F: Fn(&[u8]) + Send + Sync + 'static,
     {
         let doc = self.doc.write().await;
-        let _sub = doc
+        let subscription = doc
             .observe_update_v1(move |_, event| {
                 callback(&event.update);
             })
             .unwrap();
-        // Note: In production, store subscription to prevent it from being dropped
+
+        let id = UpdateSubscriptionId(self.next_subscription.fetch_add(1, Ordering::Relaxed));
+        self.subscriptions.lock().unwrap().insert(id, subscription);
+        id
+    }
+
+    /// Remove an update callback. Returns false if it was already removed.
+    pub fn unsubscribe(&self, id: UpdateSubscriptionId) -> bool {
+        self.subscriptions.lock().unwrap().remove(&id).is_some()
     }
 }
 
@@ -101,6 +240,9 @@ impl Clone for CollabDocument {
         Self {
             id: self.id,
             doc: Arc::clone(&self.doc),
+            subscriptions: Arc::clone(&self.subscriptions),
+            next_subscription: Arc::clone(&self.next_subscription),
+            checkpoints: Arc::clone(&self.checkpoints),
         }
     }
 }
@@ -2,30 +2,35 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::document::CollabDocument;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::document::{CollabDocument, DEFAULT_FIELD};
     use uuid::Uuid;
 
     #[tokio::test]
     async fn test_new_document() {
         let doc = CollabDocument::new(Uuid::new_v4());
-        let content = doc.get_content().await;
+        let content = doc.get_content(DEFAULT_FIELD).await;
         assert_eq!(content, "");
     }
 
     #[tokio::test]
     async fn test_document_with_content() {
         let id = Uuid::new_v4();
-        let doc = CollabDocument::with_content(id, "Hello, World!");
+        let doc = CollabDocument::with_content(id, DEFAULT_FIELD, "Hello, World!");
 
         assert_eq!(doc.id(), id);
 
-        let content = doc.get_content().await;
+        let content = doc.get_content(DEFAULT_FIELD).await;
         assert_eq!(content, "Hello, World!");
     }
 
     #[tokio::test]
     async fn test_encode_state() {
-        let doc = CollabDocument::with_content(Uuid::new_v4(), "Test content");
+        let doc = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "Test content");
         let state = doc.encode_state().await;
 
         // State should be non-empty binary data
@@ -34,7 +39,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_state_vector() {
-        let doc = CollabDocument::with_content(Uuid::new_v4(), "Test");
+        let doc = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "Test");
         let sv = doc.state_vector().await;
 
         assert!(!sv.is_empty());
@@ -42,7 +47,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_apply_update() {
-        let doc1 = CollabDocument::with_content(Uuid::new_v4(), "Hello");
+        let doc1 = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "Hello");
         let doc2 = CollabDocument::new(Uuid::new_v4());
 
         // Get update from doc1
@@ -52,15 +57,120 @@ mod tests {
         doc2.apply_update(&update).await.unwrap();
 
         // Content should match
-        assert_eq!(doc2.get_content().await, "Hello");
+        assert_eq!(doc2.get_content(DEFAULT_FIELD).await, "Hello");
     }
 
     #[tokio::test]
     async fn test_clone_document() {
-        let doc = CollabDocument::with_content(Uuid::new_v4(), "Test");
+        let doc = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "Test");
         let cloned = doc.clone();
 
         assert_eq!(doc.id(), cloned.id());
-        assert_eq!(doc.get_content().await, cloned.get_content().await);
+        assert_eq!(
+            doc.get_content(DEFAULT_FIELD).await,
+            cloned.get_content(DEFAULT_FIELD).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_named_fields_independent() {
+        let doc = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "fn main() {}");
+        doc.insert_text("scratch", 0, "todo: tests").await;
+        doc.insert_text("scratch", 0, "- ").await;
+
+        assert_eq!(doc.get_content(DEFAULT_FIELD).await, "fn main() {}");
+        assert_eq!(doc.get_content("scratch").await, "- todo: tests");
+        assert_eq!(doc.get_content("cell-2").await, "");
+
+        // Both fields travel in the same sync update
+        let copy = CollabDocument::new(Uuid::new_v4());
+        copy.apply_update(&doc.encode_state().await).await.unwrap();
+        assert_eq!(copy.get_content(DEFAULT_FIELD).await, "fn main() {}");
+        assert_eq!(copy.get_content("scratch").await, "- todo: tests");
+    }
+
+    #[tokio::test]
+    async fn test_on_update_callback_invoked() {
+        let doc = CollabDocument::new(Uuid::new_v4());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let counter = calls.clone();
+        let subscription = doc
+            .on_update(move |update| {
+                assert!(!update.is_empty());
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .await;
+
+        let update = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "Hello")
+            .encode_state()
+            .await;
+        doc.apply_update(&update).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // No further calls once unsubscribed
+        assert!(doc.unsubscribe(subscription));
+        let update = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "World")
+            .encode_state()
+            .await;
+        doc.apply_update(&update).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(!doc.unsubscribe(subscription));
+    }
+
+    #[tokio::test]
+    async fn test_replaying_update_log() {
+        use yrs::{Doc, ReadTxn, Text, Transact};
+
+        // A client making edits; each one is logged as an incremental update
+        let client = Doc::new();
+        let text = client.get_or_insert_text("content");
+        let mut log = Vec::new();
+        for chunk in ["fn main() {", "\n    run();", "\n}"] {
+            let before = client.transact().state_vector();
+            {
+                let mut txn = client.transact_mut();
+                let end = text.len(&txn);
+                text.insert(&mut txn, end, chunk);
+            }
+            log.push(client.transact().encode_state_as_update_v1(&before));
+        }
+
+        let replayed = CollabDocument::from_updates(Uuid::new_v4(), &log)
+            .await
+            .unwrap();
+        assert_eq!(
+            replayed.get_content(DEFAULT_FIELD).await,
+            "fn main() {\n    run();\n}"
+        );
+
+        // Compacting a prefix into a snapshot preserves the content
+        let snapshot = CollabDocument::from_updates(Uuid::new_v4(), &log[..2])
+            .await
+            .unwrap()
+            .encode_state()
+            .await;
+        let compacted = std::iter::once(snapshot).chain(log[2..].iter().cloned());
+        let restored = CollabDocument::from_updates(Uuid::new_v4(), compacted)
+            .await
+            .unwrap();
+        assert_eq!(
+            restored.get_content(DEFAULT_FIELD).await,
+            replayed.get_content(DEFAULT_FIELD).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_capped_per_document() {
+        let doc = CollabDocument::seeded(Uuid::new_v4(), "v1");
+
+        assert!(doc.checkpoint("first", 2).await.is_some());
+        assert!(doc.checkpoint("second", 2).await.is_some());
+        assert!(doc.checkpoint("third", 2).await.is_none());
+
+        // An existing checkpoint can still be replaced
+        doc.insert_text(DEFAULT_FIELD, 2, "!").await;
+        assert!(doc.checkpoint("second", 2).await.is_some());
+        assert_eq!(doc.list_checkpoints(), ["first", "second"]);
     }
 }
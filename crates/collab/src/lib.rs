@@ -5,9 +5,14 @@
 
 pub mod awareness;
 pub mod document;
+pub mod multiplex;
 pub mod room;
 pub mod sync;
 
-pub use document::CollabDocument;
-pub use room::{CollabRoom, RoomManager};
+pub use document::{CollabDocument, DEFAULT_FIELD};
+pub use multiplex::{DocFrame, MultiplexedConnection};
+pub use room::{
+    AwarenessError, CollabRoom, ConnectionId, FollowedCursor, RoomBroadcast, RoomClosed, RoomError,
+    RoomLimitExceeded, RoomManager, RoomReceiver,
+};
 pub use sync::SyncProtocol;
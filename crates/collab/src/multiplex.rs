@@ -0,0 +1,148 @@
+//! Multiple document rooms over a single connection.
+
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::{sync::mpsc, task::JoinHandle};
+use uuid::Uuid;
+
+use crate::room::{CollabRoom, ConnectionId, RoomError, RoomManager};
+
+/// A broadcast frame from one of the connection's documents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocFrame {
+    pub doc_id: Uuid,
+    pub data: Vec<u8>,
+}
+
+struct Subscription {
+    room: Arc<CollabRoom>,
+    connection_id: ConnectionId,
+    forwarder: JoinHandle<()>,
+}
+
+/// A client connection subscribed to any number of document rooms.
+///
+/// Each joined room's broadcasts are forwarded into one queue tagged with
+/// the document id, so a split-view editor needs a single socket.
+pub struct MultiplexedConnection {
+    user_id: Uuid,
+    username: String,
+    rooms: HashMap<Uuid, Subscription>,
+    tx: mpsc::UnboundedSender<DocFrame>,
+    rx: mpsc::UnboundedReceiver<DocFrame>,
+}
+
+impl MultiplexedConnection {
+    /// Create a connection for a user with no documents joined.
+    pub fn new(user_id: Uuid, username: String) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            user_id,
+            username,
+            rooms: HashMap::new(),
+            tx,
+            rx,
+        }
+    }
+
+    /// Join a document's room, creating it if needed.
+    ///
+    /// Fails if the user is already in as many rooms as the manager allows,
+    /// or the room is full.
+    pub fn join(
+        &mut self,
+        manager: &RoomManager,
+        doc_id: Uuid,
+    ) -> Result<Arc<CollabRoom>, RoomError> {
+        if let Some(subscription) = self.rooms.get(&doc_id) {
+            return Ok(subscription.room.clone());
+        }
+
+        let (room, mut receiver) = manager.join(doc_id, self.user_id, self.username.clone())?;
+        let connection_id = receiver.connection_id();
+        let tx = self.tx.clone();
+
+        let forwarder = tokio::spawn(async move {
+            while let Ok(data) = receiver.recv().await {
+                if tx.send(DocFrame { doc_id, data }).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.rooms.insert(
+            doc_id,
+            Subscription {
+                room: room.clone(),
+                connection_id,
+                forwarder,
+            },
+        );
+        Ok(room)
+    }
+
+    /// Leave a document's room, removing the room if it is now empty.
+    pub fn leave(&mut self, manager: &RoomManager, doc_id: Uuid) {
+        if let Some(subscription) = self.rooms.remove(&doc_id) {
+            subscription.forwarder.abort();
+            manager.leave(doc_id, self.user_id);
+        }
+    }
+
+    /// Leave every joined room.
+    pub fn leave_all(&mut self, manager: &RoomManager) {
+        let doc_ids: Vec<Uuid> = self.rooms.keys().copied().collect();
+        for doc_id in doc_ids {
+            self.leave(manager, doc_id);
+        }
+    }
+
+    /// Record that the user was heard from in every joined room.
+    ///
+    /// Returns the documents whose room evicted the user for missing
+    /// heartbeats; they should be left.
+    pub fn touch(&self) -> Vec<Uuid> {
+        self.rooms
+            .iter()
+            .filter(|(_, subscription)| !subscription.room.touch(&self.user_id))
+            .map(|(doc_id, _)| *doc_id)
+            .collect()
+    }
+
+    /// Room for a joined document.
+    pub fn room(&self, doc_id: &Uuid) -> Option<&Arc<CollabRoom>> {
+        self.rooms
+            .get(doc_id)
+            .map(|subscription| &subscription.room)
+    }
+
+    /// Broadcast an update made over this connection to everyone else in a
+    /// joined document's room. Returns `false` if the document isn't joined.
+    pub fn broadcast(&self, doc_id: &Uuid, update: Vec<u8>) -> bool {
+        let Some(subscription) = self.rooms.get(doc_id) else {
+            return false;
+        };
+        subscription
+            .room
+            .broadcast_from(subscription.connection_id, update);
+        true
+    }
+
+    /// Receive the next frame from any joined document.
+    pub async fn recv(&mut self) -> Option<DocFrame> {
+        self.rx.recv().await
+    }
+
+    /// The connection's user.
+    pub fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+}
+
+impl Drop for MultiplexedConnection {
+    fn drop(&mut self) {
+        for subscription in self.rooms.values() {
+            subscription.forwarder.abort();
+        }
+    }
+}
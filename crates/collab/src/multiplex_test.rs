@@ -0,0 +1,71 @@
+//! Tests for multi-document connections.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use crate::{
+        document::{CollabDocument, DEFAULT_FIELD},
+        multiplex::{DocFrame, MultiplexedConnection},
+        room::RoomManager,
+    };
+
+    #[tokio::test]
+    async fn test_two_documents_over_one_connection() {
+        let manager = RoomManager::new();
+        let doc_a = Uuid::new_v4();
+        let doc_b = Uuid::new_v4();
+
+        let mut alice = MultiplexedConnection::new(Uuid::new_v4(), "alice".into());
+        let mut bob = MultiplexedConnection::new(Uuid::new_v4(), "bob".into());
+        for doc_id in [doc_a, doc_b] {
+            alice.join(&manager, doc_id).unwrap();
+            bob.join(&manager, doc_id).unwrap();
+        }
+
+        // Alice edits document B only
+        let update = CollabDocument::with_content(Uuid::new_v4(), DEFAULT_FIELD, "split view")
+            .encode_state()
+            .await;
+        let room = alice.room(&doc_b).unwrap();
+        room.document.apply_update(&update).await.unwrap();
+        assert!(alice.broadcast(&doc_b, update.clone()));
+
+        let frame = tokio::time::timeout(Duration::from_secs(1), bob.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            frame,
+            DocFrame {
+                doc_id: doc_b,
+                data: update
+            }
+        );
+
+        let content = |doc_id| {
+            let room = manager.get(&doc_id).unwrap();
+            async move { room.document.get_content(DEFAULT_FIELD).await }
+        };
+        assert_eq!(content(doc_b).await, "split view");
+        assert_eq!(content(doc_a).await, "");
+
+        // Alice never sees her own update
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), alice.recv())
+                .await
+                .is_err()
+        );
+
+        // Leaving one document keeps the other joined
+        alice.leave(&manager, doc_a);
+        assert!(alice.room(&doc_a).is_none());
+        assert!(alice.room(&doc_b).is_some());
+        assert_eq!(manager.room_count(), 2);
+
+        bob.leave_all(&manager);
+        assert_eq!(manager.room_count(), 1);
+    }
+}
@@ -1,18 +1,147 @@
 //! Collaboration room management.
 
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
 
 use dashmap::DashMap;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
-use crate::document::CollabDocument;
+use crate::{
+    awareness::{self, AwarenessManager, CursorState},
+    document::{CollabDocument, DEFAULT_FIELD},
+    sync,
+};
+
+/// Default window over which awareness updates are merged.
+pub const DEFAULT_AWARENESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Default cap on a client's binary awareness frame.
+pub const DEFAULT_MAX_AWARENESS_BYTES: usize = 16 * 1024;
+
+/// A client's awareness update was rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum AwarenessError {
+    #[error("Awareness update too large ({size} bytes, max {max})")]
+    TooLarge { size: usize, max: usize },
+    #[error("Malformed awareness update")]
+    Malformed,
+}
+
+/// Default cap on rooms one user may be in at once.
+pub const DEFAULT_MAX_ROOMS_PER_USER: usize = 50;
+
+/// A user tried to join more rooms than allowed.
+#[derive(Debug, thiserror::Error)]
+#[error("Too many open documents (max {max})")]
+pub struct RoomLimitExceeded {
+    pub max: usize,
+}
+
+/// Default cap on participants in one room.
+pub const DEFAULT_MAX_PARTICIPANTS: usize = 100;
+
+/// Why a user could not join a room.
+#[derive(Debug, thiserror::Error)]
+pub enum RoomError {
+    #[error("Room is full (max {max} participants)")]
+    Full { max: usize },
+    #[error(transparent)]
+    TooManyRooms(#[from] RoomLimitExceeded),
+}
+
+/// Identifies one connection to a room, allocated by [`CollabRoom::join`].
+///
+/// A user may be connected more than once, e.g. from two tabs, so updates
+/// are attributed to connections rather than users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionId(u64);
+
+/// A message broadcast to room participants.
+#[derive(Debug, Clone)]
+pub struct RoomBroadcast {
+    /// Connection that authored the message; `None` for server messages.
+    pub origin: Option<ConnectionId>,
+    pub data: Vec<u8>,
+}
+
+/// A connection's view of the room broadcast.
+///
+/// Messages authored over the connection itself are skipped, so clients
+/// never receive (and re-apply) their own updates; the same user's other
+/// connections still do.
+pub struct RoomReceiver {
+    connection_id: ConnectionId,
+    rx: broadcast::Receiver<RoomBroadcast>,
+    /// Marks the participant's connection as live until this receiver drops.
+    _alive: Arc<()>,
+}
+
+impl RoomReceiver {
+    /// The connection this receiver belongs to, to pass to
+    /// [`CollabRoom::broadcast_from`].
+    pub fn connection_id(&self) -> ConnectionId {
+        self.connection_id
+    }
+
+    /// Receive the next message authored over another connection.
+    pub async fn recv(&mut self) -> Result<Vec<u8>, broadcast::error::RecvError> {
+        loop {
+            let message = self.rx.recv().await?;
+            if message.origin != Some(self.connection_id) {
+                return Ok(message.data);
+            }
+        }
+    }
+
+    /// Receive a pending message authored over another connection without
+    /// waiting.
+    pub fn try_recv(&mut self) -> Result<Vec<u8>, broadcast::error::TryRecvError> {
+        loop {
+            let message = self.rx.try_recv()?;
+            if message.origin != Some(self.connection_id) {
+                return Ok(message.data);
+            }
+        }
+    }
+}
+
+/// Cursor of a followed participant, delivered to followers immediately.
+#[derive(Debug, Clone)]
+pub struct FollowedCursor {
+    pub user_id: Uuid,
+    pub cursor: Option<CursorState>,
+}
+
+struct Follow {
+    target: Uuid,
+    tx: mpsc::UnboundedSender<FollowedCursor>,
+}
 
 /// A collaboration room for a single document.
 pub struct CollabRoom {
     pub document: CollabDocument,
-    pub broadcast: broadcast::Sender<Vec<u8>>,
+    pub broadcast: broadcast::Sender<RoomBroadcast>,
     participants: DashMap<Uuid, ParticipantInfo>,
+    awareness_interval: Duration,
+    awareness: Arc<Mutex<AwarenessManager>>,
+    /// Clients whose awareness changed since the last broadcast.
+    pending_awareness: Arc<Mutex<HashSet<u64>>>,
+    /// Follow relationships, keyed by follower.
+    follows: DashMap<Uuid, Follow>,
+    /// Liveness of each participant's receivers, one per connection.
+    receivers: DashMap<Uuid, Vec<Weak<()>>>,
+    /// Distinct participants allowed at once; 0 means unlimited.
+    max_participants: usize,
+    next_connection: AtomicU64,
+    /// Created from an already-loaded document rather than empty on join.
+    loaded: bool,
 }
 
 /// Information about a room participant.
@@ -21,35 +150,190 @@ pub struct ParticipantInfo {
     pub user_id: Uuid,
     pub username: String,
     pub cursor_position: Option<u32>,
+    /// When the participant was last heard from.
+    pub last_seen: Instant,
 }
 
 impl CollabRoom {
     /// Create a new collaboration room.
     pub fn new(document: CollabDocument) -> Self {
+        Self::with_awareness_interval(document, DEFAULT_AWARENESS_INTERVAL)
+    }
+
+    /// Create a room that merges awareness updates over `interval`.
+    pub fn with_awareness_interval(document: CollabDocument, interval: Duration) -> Self {
         let (broadcast, _) = broadcast::channel(1024);
         Self {
             document,
             broadcast,
             participants: DashMap::new(),
+            awareness_interval: interval,
+            awareness: Arc::new(Mutex::new(AwarenessManager::new())),
+            pending_awareness: Arc::new(Mutex::new(HashSet::new())),
+            follows: DashMap::new(),
+            receivers: DashMap::new(),
+            max_participants: DEFAULT_MAX_PARTICIPANTS,
+            next_connection: AtomicU64::new(0),
+            loaded: false,
         }
     }
 
+    /// Cap the distinct participants in the room; 0 disables the cap.
+    pub fn with_max_participants(mut self, max: usize) -> Self {
+        self.max_participants = max;
+        self
+    }
+
     /// Add a participant to the room.
-    pub fn join(&self, user_id: Uuid, username: String) -> broadcast::Receiver<Vec<u8>> {
+    ///
+    /// Fails once the room holds as many participants as it allows; another
+    /// connection from someone already in the room always succeeds.
+    pub fn join(&self, user_id: Uuid, username: String) -> Result<RoomReceiver, RoomError> {
+        if self.max_participants > 0
+            && !self.participants.contains_key(&user_id)
+            && self.participants.len() >= self.max_participants
+        {
+            return Err(RoomError::Full {
+                max: self.max_participants,
+            });
+        }
+
         self.participants.insert(
             user_id,
             ParticipantInfo {
                 user_id,
                 username,
                 cursor_position: None,
+                last_seen: Instant::now(),
             },
         );
-        self.broadcast.subscribe()
+        let alive = Arc::new(());
+        self.receivers
+            .entry(user_id)
+            .or_default()
+            .push(Arc::downgrade(&alive));
+        Ok(RoomReceiver {
+            connection_id: ConnectionId(self.next_connection.fetch_add(1, Ordering::Relaxed)),
+            rx: self.broadcast.subscribe(),
+            _alive: alive,
+        })
     }
 
     /// Remove a participant from the room.
+    ///
+    /// Everyone else is told straight away that the participant's awareness
+    /// clients are gone, so their cursors disappear.
     pub fn leave(&self, user_id: &Uuid) {
         self.participants.remove(user_id);
+        self.receivers.remove(user_id);
+        self.follows
+            .retain(|follower, follow| follower != user_id && follow.target != *user_id);
+
+        let update = {
+            let mut awareness = self.awareness.lock().unwrap();
+            let removed = awareness.remove(user_id);
+            (!removed.is_empty()).then(|| awareness.encode(&removed))
+        };
+        if let Some(update) = update {
+            self.broadcast_update(awareness::encode_message(&update));
+        }
+    }
+
+    /// Remove participants whose every receiver has been dropped, e.g.
+    /// because their connection task died without leaving. Returns the
+    /// pruned participants.
+    pub fn prune_stale(&self) -> Vec<Uuid> {
+        let stale: Vec<Uuid> = self
+            .participants
+            .iter()
+            .map(|participant| *participant.key())
+            .filter(|user_id| {
+                self.receivers.get_mut(user_id).is_none_or(|mut receivers| {
+                    receivers.retain(|alive| alive.strong_count() > 0);
+                    receivers.is_empty()
+                })
+            })
+            .collect();
+
+        for user_id in &stale {
+            self.leave(user_id);
+        }
+        stale
+    }
+
+    /// Record that a participant was heard from, e.g. a heartbeat.
+    ///
+    /// Returns `false` if they are no longer in the room, for instance
+    /// because [`Self::sweep_stale`] evicted them.
+    pub fn touch(&self, user_id: &Uuid) -> bool {
+        match self.participants.get_mut(user_id) {
+            Some(mut info) => {
+                info.last_seen = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove participants not heard from for `timeout`, e.g. because their
+    /// network dropped without closing the connection. Returns the evicted
+    /// participants.
+    pub fn sweep_stale(&self, timeout: Duration) -> Vec<Uuid> {
+        let stale: Vec<Uuid> = self
+            .participants
+            .iter()
+            .filter(|participant| participant.last_seen.elapsed() > timeout)
+            .map(|participant| *participant.key())
+            .collect();
+
+        for user_id in &stale {
+            self.leave(user_id);
+        }
+        stale
+    }
+
+    /// Make `follower` track `target`'s cursor.
+    ///
+    /// The returned receiver gets each of the target's cursor moves as soon
+    /// as it is reported, bypassing the awareness batching window. Returns
+    /// `None` if the target is not in the room or is the follower itself.
+    /// Following someone new replaces any previous follow.
+    pub fn follow(
+        &self,
+        follower: Uuid,
+        target: Uuid,
+    ) -> Option<mpsc::UnboundedReceiver<FollowedCursor>> {
+        if follower == target || !self.participants.contains_key(&target) {
+            return None;
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.follows.insert(follower, Follow { target, tx });
+        Some(rx)
+    }
+
+    /// Stop following.
+    pub fn unfollow(&self, follower: &Uuid) {
+        self.follows.remove(follower);
+    }
+
+    /// Participant that `follower` is following, if any.
+    pub fn following(&self, follower: &Uuid) -> Option<Uuid> {
+        self.follows.get(follower).map(|follow| follow.target)
+    }
+
+    fn notify_followers(&self, user_id: Uuid, cursor: Option<CursorState>) {
+        // Followers whose receiver is gone are dropped along the way
+        self.follows.retain(|_, follow| {
+            follow.target != user_id
+                || follow
+                    .tx
+                    .send(FollowedCursor {
+                        user_id,
+                        cursor: cursor.clone(),
+                    })
+                    .is_ok()
+        });
     }
 
     /// Update a participant's cursor position.
@@ -59,61 +343,346 @@ impl CollabRoom {
         }
     }
 
+    /// Merge a client's binary awareness update into the room's awareness.
+    ///
+    /// Changed states are rebroadcast to everyone, merged with other changes
+    /// made within the room's interval, so a burst of cursor moves costs one
+    /// frame. Followers of `origin` are sent its cursor straight away.
+    pub fn apply_awareness(
+        &self,
+        origin: Uuid,
+        update: &[u8],
+        max_bytes: usize,
+    ) -> Result<(), AwarenessError> {
+        if update.len() > max_bytes {
+            return Err(AwarenessError::TooLarge {
+                size: update.len(),
+                max: max_bytes,
+            });
+        }
+        let clients = awareness::decode_update(update).ok_or(AwarenessError::Malformed)?;
+
+        let (changed, cursors) = {
+            let mut awareness = self.awareness.lock().unwrap();
+            let changed = awareness.apply(origin, clients);
+            let cursors: Vec<Option<CursorState>> = changed
+                .iter()
+                .map(|client_id| {
+                    awareness
+                        .state(*client_id)
+                        .and_then(CursorState::from_state)
+                })
+                .collect();
+            (changed, cursors)
+        };
+        for cursor in cursors {
+            self.notify_followers(origin, cursor);
+        }
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut pending = self.pending_awareness.lock().unwrap();
+        let schedule = pending.is_empty();
+        pending.extend(changed);
+        drop(pending);
+
+        if schedule {
+            let pending = self.pending_awareness.clone();
+            let awareness = self.awareness.clone();
+            let broadcast = self.broadcast.clone();
+            let interval = self.awareness_interval;
+
+            tokio::spawn(async move {
+                tokio::time::sleep(interval).await;
+
+                let client_ids: Vec<u64> = pending.lock().unwrap().drain().collect();
+                if client_ids.is_empty() {
+                    return;
+                }
+
+                let update = awareness.lock().unwrap().encode(&client_ids);
+                let _ = broadcast.send(RoomBroadcast {
+                    origin: None,
+                    data: awareness::encode_message(&update),
+                });
+            });
+        }
+        Ok(())
+    }
+
+    /// Awareness frame with every present state, for a newly joined client;
+    /// `None` if nobody has reported any.
+    pub fn awareness_snapshot(&self) -> Option<Vec<u8>> {
+        let update = self.awareness.lock().unwrap().encode_all()?;
+        Some(awareness::encode_message(&update))
+    }
+
     /// Broadcast an update to all participants.
     pub fn broadcast_update(&self, update: Vec<u8>) {
-        let _ = self.broadcast.send(update);
+        let _ = self.broadcast.send(RoomBroadcast {
+            origin: None,
+            data: update,
+        });
+    }
+
+    /// Broadcast an update authored over connection `origin` to every other
+    /// connection, including the same user's.
+    pub fn broadcast_from(&self, origin: ConnectionId, update: Vec<u8>) {
+        let _ = self.broadcast.send(RoomBroadcast {
+            origin: Some(origin),
+            data: update,
+        });
+    }
+
+    /// Restore the document to checkpoint `name` and send every participant
+    /// the update doing so as a sync frame, returning the update; `None` if
+    /// there is no such checkpoint.
+    pub async fn restore_checkpoint(
+        &self,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>, yrs::encoding::read::Error> {
+        let update = self.document.restore_checkpoint(name).await?;
+        if let Some(update) = &update {
+            self.broadcast_update(sync::encode_update_message(update));
+        }
+        Ok(update)
     }
 
     /// Get list of participants.
     pub fn participants(&self) -> Vec<ParticipantInfo> {
-        self.participants.iter().map(|r| r.value().clone()).collect()
+        self.participants
+            .iter()
+            .map(|r| r.value().clone())
+            .collect()
     }
 
     /// Check if room is empty.
     pub fn is_empty(&self) -> bool {
         self.participants.is_empty()
     }
+
+    /// Whether the room was created by [`RoomManager::insert_document`],
+    /// rather than starting empty because its document was never loaded.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
 }
 
+/// Called with a room once its last participant has left and it has been
+/// removed, e.g. to persist the document.
+pub type RoomClosed = Arc<dyn Fn(Arc<CollabRoom>) + Send + Sync>;
+
 /// Manages all collaboration rooms.
 pub struct RoomManager {
     rooms: DashMap<Uuid, Arc<CollabRoom>>,
+    awareness_interval: Duration,
+    /// Rooms each user is in, with a count of their connections to each.
+    user_rooms: DashMap<Uuid, HashMap<Uuid, usize>>,
+    /// Distinct rooms a user may be in at once; 0 means unlimited.
+    max_rooms_per_user: usize,
+    /// Participant cap given to each new room.
+    max_participants: usize,
+    on_close: Option<RoomClosed>,
 }
 
 impl RoomManager {
     /// Create a new room manager.
     pub fn new() -> Self {
+        Self::with_awareness_interval(DEFAULT_AWARENESS_INTERVAL)
+    }
+
+    /// Create a room manager whose rooms merge awareness over `interval`.
+    pub fn with_awareness_interval(interval: Duration) -> Self {
         Self {
             rooms: DashMap::new(),
+            awareness_interval: interval,
+            user_rooms: DashMap::new(),
+            max_rooms_per_user: DEFAULT_MAX_ROOMS_PER_USER,
+            max_participants: DEFAULT_MAX_PARTICIPANTS,
+            on_close: None,
         }
     }
 
+    /// Cap the rooms one user may be in at once; 0 disables the cap.
+    pub fn with_max_rooms_per_user(mut self, max: usize) -> Self {
+        self.max_rooms_per_user = max;
+        self
+    }
+
+    /// Cap the participants in each room created from now on; 0 disables
+    /// the cap.
+    pub fn with_max_participants(mut self, max: usize) -> Self {
+        self.max_participants = max;
+        self
+    }
+
+    /// Run `on_close` with every room removed because it emptied.
+    pub fn with_on_close(mut self, on_close: RoomClosed) -> Self {
+        self.on_close = Some(on_close);
+        self
+    }
+
+    /// Join a user to a document's room, creating the room if needed.
+    ///
+    /// Fails if this would put the user in more distinct rooms than the
+    /// cap allows, or if the room is full; rejoining a room the user is
+    /// already in always succeeds.
+    pub fn join(
+        &self,
+        document_id: Uuid,
+        user_id: Uuid,
+        username: String,
+    ) -> Result<(Arc<CollabRoom>, RoomReceiver), RoomError> {
+        let mut rooms = self.user_rooms.entry(user_id).or_default();
+        if self.max_rooms_per_user > 0
+            && !rooms.contains_key(&document_id)
+            && rooms.len() >= self.max_rooms_per_user
+        {
+            return Err(RoomLimitExceeded {
+                max: self.max_rooms_per_user,
+            }
+            .into());
+        }
+
+        // Joined while the room's entry is locked, so a concurrent cleanup
+        // cannot remove the room between finding it and joining it
+        let room = self
+            .rooms
+            .entry(document_id)
+            .or_insert_with(|| self.new_room(CollabDocument::new(document_id)));
+        let receiver = match room.join(user_id, username) {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                drop(room);
+                drop(rooms);
+                self.user_rooms
+                    .remove_if(&user_id, |_, rooms| rooms.is_empty());
+                return Err(e);
+            }
+        };
+        *rooms.entry(document_id).or_default() += 1;
+        Ok((room.clone(), receiver))
+    }
+
+    /// Remove a user's connection from a room joined with [`Self::join`],
+    /// removing the room once it is empty.
+    pub fn leave(&self, document_id: Uuid, user_id: Uuid) {
+        let mut last_connection = false;
+        if let Some(mut rooms) = self.user_rooms.get_mut(&user_id) {
+            if let Some(count) = rooms.get_mut(&document_id) {
+                *count -= 1;
+                if *count == 0 {
+                    rooms.remove(&document_id);
+                    last_connection = true;
+                }
+            }
+        }
+        self.user_rooms
+            .remove_if(&user_id, |_, rooms| rooms.is_empty());
+
+        if last_connection {
+            if let Some(room) = self.get(&document_id) {
+                room.leave(&user_id);
+            }
+        }
+        self.cleanup(&document_id);
+    }
+
+    /// Prune dead participants from every room, removing rooms left empty.
+    /// Returns the number of participants pruned.
+    pub fn prune_stale(&self) -> usize {
+        self.evict(CollabRoom::prune_stale)
+    }
+
+    /// Evict participants not heard from for `timeout` from every room,
+    /// removing rooms left empty. Returns the number of participants evicted.
+    ///
+    /// Everyone else in their rooms is told their cursors are gone.
+    pub fn sweep_stale(&self, timeout: Duration) -> usize {
+        self.evict(|room| room.sweep_stale(timeout))
+    }
+
+    /// Remove the participants `evict` picks from each room, and their slots.
+    fn evict(&self, evict: impl Fn(&CollabRoom) -> Vec<Uuid>) -> usize {
+        let rooms: Vec<(Uuid, Arc<CollabRoom>)> = self
+            .rooms
+            .iter()
+            .map(|room| (*room.key(), room.value().clone()))
+            .collect();
+
+        let mut evicted = 0;
+        for (document_id, room) in rooms {
+            for user_id in evict(&room) {
+                if let Some(mut rooms) = self.user_rooms.get_mut(&user_id) {
+                    rooms.remove(&document_id);
+                }
+                self.user_rooms
+                    .remove_if(&user_id, |_, rooms| rooms.is_empty());
+                evicted += 1;
+            }
+            self.cleanup(&document_id);
+        }
+        evicted
+    }
+
+    /// Number of distinct rooms a user is in.
+    pub fn rooms_for_user(&self, user_id: &Uuid) -> usize {
+        self.user_rooms.get(user_id).map_or(0, |rooms| rooms.len())
+    }
+
     /// Get or create a room for a document.
     pub fn get_or_create(&self, document_id: Uuid, content: Option<&str>) -> Arc<CollabRoom> {
         self.rooms
             .entry(document_id)
             .or_insert_with(|| {
                 let doc = match content {
-                    Some(c) => CollabDocument::with_content(document_id, c),
+                    Some(c) => CollabDocument::with_content(document_id, DEFAULT_FIELD, c),
                     None => CollabDocument::new(document_id),
                 };
-                Arc::new(CollabRoom::new(doc))
+                self.new_room(doc)
+            })
+            .clone()
+    }
+
+    /// Create a room for an already-loaded document, e.g. one rebuilt from
+    /// its update log. An existing room for the document is kept instead.
+    pub fn insert_document(&self, document: CollabDocument) -> Arc<CollabRoom> {
+        self.rooms
+            .entry(document.id())
+            .or_insert_with(|| {
+                let mut room = self.room_for(document);
+                room.loaded = true;
+                Arc::new(room)
             })
             .clone()
     }
 
+    fn new_room(&self, document: CollabDocument) -> Arc<CollabRoom> {
+        Arc::new(self.room_for(document))
+    }
+
+    fn room_for(&self, document: CollabDocument) -> CollabRoom {
+        CollabRoom::with_awareness_interval(document, self.awareness_interval)
+            .with_max_participants(self.max_participants)
+    }
+
     /// Get a room by document ID.
     pub fn get(&self, document_id: &Uuid) -> Option<Arc<CollabRoom>> {
         self.rooms.get(document_id).map(|r| r.clone())
     }
 
-    /// Remove a room if empty.
+    /// Remove a room if empty, handing it to the close hook.
+    ///
+    /// The emptiness check and removal happen under the room's entry lock,
+    /// which [`Self::join`] also holds, so a participant joining meanwhile
+    /// either keeps the room open or gets a fresh one.
     pub fn cleanup(&self, document_id: &Uuid) {
-        if let Some(room) = self.rooms.get(document_id) {
-            if room.is_empty() {
-                drop(room);
-                self.rooms.remove(document_id);
-            }
+        let Some((_, room)) = self.rooms.remove_if(document_id, |_, room| room.is_empty()) else {
+            return;
+        };
+        if let Some(on_close) = &self.on_close {
+            on_close(room);
         }
     }
 
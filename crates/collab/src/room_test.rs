@@ -0,0 +1,375 @@
+//! Tests for collaboration rooms.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use serde_json::{json, Value};
+    use tokio::sync::broadcast::error::TryRecvError;
+    use uuid::Uuid;
+
+    use crate::{
+        awareness::{decode_update, encode_update, ClientAwareness, MSG_AWARENESS},
+        document::{CollabDocument, DEFAULT_FIELD},
+        room::{AwarenessError, CollabRoom, RoomClosed, RoomError, RoomLimitExceeded, RoomManager},
+        sync::{MSG_SYNC, SYNC_UPDATE},
+    };
+
+    /// Awareness update moving client `client_id`'s cursor to `line`.
+    fn cursor(client_id: u64, clock: u64, line: u32) -> Vec<u8> {
+        encode_update(&[ClientAwareness {
+            client_id,
+            clock,
+            state: Some(json!({ "cursor": { "line": line, "column": 0 } })),
+        }])
+    }
+
+    /// States in a broadcast awareness frame, by client id.
+    fn states(frame: &[u8]) -> HashMap<u64, Option<Value>> {
+        assert_eq!(frame[0], MSG_AWARENESS);
+        assert!(frame[1] < 0x80);
+        decode_update(&frame[2..])
+            .unwrap()
+            .into_iter()
+            .map(|client| (client.client_id, client.state))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_cursor_moves_merged_into_one_broadcast() {
+        let room = CollabRoom::with_awareness_interval(
+            CollabDocument::new(Uuid::new_v4()),
+            Duration::from_millis(20),
+        );
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let mut rx = room.join(alice, "alice".into()).unwrap();
+        let _bob_rx = room.join(bob, "bob".into()).unwrap();
+
+        room.apply_awareness(alice, &cursor(1, 1, 1), 1024).unwrap();
+        room.apply_awareness(alice, &cursor(1, 2, 2), 1024).unwrap();
+        room.apply_awareness(bob, &cursor(2, 1, 7), 1024).unwrap();
+        room.apply_awareness(alice, &cursor(1, 3, 3), 1024).unwrap();
+        // Stale: an older clock than the state already held
+        room.apply_awareness(alice, &cursor(1, 2, 99), 1024)
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+
+        let frame = rx.try_recv().unwrap();
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+
+        let states = states(&frame);
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[&1].as_ref().unwrap()["cursor"]["line"], 3);
+        assert_eq!(states[&2].as_ref().unwrap()["cursor"]["line"], 7);
+    }
+
+    #[tokio::test]
+    async fn test_awareness_snapshot_and_removal_on_leave() {
+        let room = CollabRoom::new(CollabDocument::new(Uuid::new_v4()));
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let _alice_rx = room.join(alice, "alice".into()).unwrap();
+        let mut bob_rx = room.join(bob, "bob".into()).unwrap();
+        assert!(room.awareness_snapshot().is_none());
+
+        room.apply_awareness(alice, &cursor(1, 1, 4), 1024).unwrap();
+        // Bob cannot overwrite a client Alice is reporting
+        room.apply_awareness(bob, &cursor(1, 5, 9), 1024).unwrap();
+
+        let snapshot = states(&room.awareness_snapshot().unwrap());
+        assert_eq!(snapshot[&1].as_ref().unwrap()["cursor"]["line"], 4);
+
+        // Leaving tells everyone else at once that Alice's client is gone
+        room.leave(&alice);
+        let removal = states(&bob_rx.try_recv().unwrap());
+        assert_eq!(removal, HashMap::from([(1, None)]));
+        assert!(room.awareness_snapshot().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_author_does_not_receive_own_update() {
+        let room = CollabRoom::new(CollabDocument::new(Uuid::new_v4()));
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let mut alice_rx = room.join(alice, "alice".into()).unwrap();
+        let mut bob_rx = room.join(bob, "bob".into()).unwrap();
+
+        // y-websocket sync update frame: [MSG_SYNC, SYNC_UPDATE, len, payload]
+        let update = vec![0, 2, 3, 1, 2, 3];
+        room.broadcast_from(alice_rx.connection_id(), update.clone());
+
+        assert_eq!(bob_rx.try_recv().unwrap(), update);
+        assert!(matches!(alice_rx.try_recv(), Err(TryRecvError::Empty)));
+
+        // Server-originated messages still reach everyone
+        room.broadcast_update(vec![9]);
+        assert_eq!(alice_rx.try_recv().unwrap(), vec![9]);
+    }
+
+    #[tokio::test]
+    async fn test_own_other_connection_receives_update() {
+        let room = CollabRoom::new(CollabDocument::new(Uuid::new_v4()));
+        let alice = Uuid::new_v4();
+        let mut first_tab = room.join(alice, "alice".into()).unwrap();
+        let mut second_tab = room.join(alice, "alice".into()).unwrap();
+        assert_ne!(first_tab.connection_id(), second_tab.connection_id());
+
+        // An edit in one tab reaches the other, but not the tab it came from
+        let update = vec![0, 2, 3, 1, 2, 3];
+        room.broadcast_from(first_tab.connection_id(), update.clone());
+
+        assert_eq!(second_tab.try_recv().unwrap(), update);
+        assert!(matches!(first_tab.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn test_follower_receives_target_cursor_promptly() {
+        // Batched awareness would take a full second to arrive
+        let room = CollabRoom::with_awareness_interval(
+            CollabDocument::new(Uuid::new_v4()),
+            Duration::from_secs(1),
+        );
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let carol = Uuid::new_v4();
+        let _alice_rx = room.join(alice, "alice".into()).unwrap();
+        let _bob_rx = room.join(bob, "bob".into()).unwrap();
+        let _carol_rx = room.join(carol, "carol".into()).unwrap();
+
+        assert!(room.follow(bob, bob).is_none());
+        assert!(room.follow(bob, Uuid::new_v4()).is_none());
+        let mut follow_rx = room.follow(bob, alice).unwrap();
+        assert_eq!(room.following(&bob), Some(alice));
+
+        room.apply_awareness(carol, &cursor(3, 1, 9), 1024).unwrap();
+        room.apply_awareness(alice, &cursor(1, 1, 4), 1024).unwrap();
+
+        let followed = tokio::time::timeout(Duration::from_millis(50), follow_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(followed.user_id, alice);
+        assert_eq!(followed.cursor.unwrap().line, 4);
+
+        // The followed user leaving ends the follow
+        room.leave(&alice);
+        assert_eq!(room.following(&bob), None);
+        assert!(follow_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversize_awareness_not_broadcast() {
+        let room = CollabRoom::new(CollabDocument::new(Uuid::new_v4()));
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let _alice_rx = room.join(alice, "alice".into()).unwrap();
+        let mut bob_rx = room.join(bob, "bob".into()).unwrap();
+
+        let update = cursor(1, 1, 4);
+        let len = update.len();
+        assert!(matches!(
+            room.apply_awareness(alice, &update, len - 1),
+            Err(AwarenessError::TooLarge { size, max }) if size == len && max == len - 1
+        ));
+        assert!(matches!(
+            room.apply_awareness(alice, &update[..len - 1], 1024),
+            Err(AwarenessError::Malformed)
+        ));
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(matches!(bob_rx.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn test_room_cap_per_user() {
+        let manager = RoomManager::new().with_max_rooms_per_user(2);
+        let alice = Uuid::new_v4();
+        let docs = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+
+        let _first = manager.join(docs[0], alice, "alice".into()).unwrap();
+        let _second = manager.join(docs[1], alice, "alice".into()).unwrap();
+
+        let Err(err) = manager.join(docs[2], alice, "alice".into()) else {
+            panic!("third room joined past the cap");
+        };
+        assert!(matches!(
+            err,
+            RoomError::TooManyRooms(RoomLimitExceeded { max: 2 })
+        ));
+        assert_eq!(manager.rooms_for_user(&alice), 2);
+        assert!(manager.get(&docs[2]).is_none());
+
+        // A second connection to an open room doesn't count again
+        let _again = manager.join(docs[0], alice, "alice".into()).unwrap();
+
+        // Other users are unaffected
+        let _bob = manager.join(docs[2], Uuid::new_v4(), "bob".into()).unwrap();
+
+        // Leaving frees a slot only once every connection to the room is gone
+        manager.leave(docs[0], alice);
+        assert!(manager.join(docs[2], alice, "alice".into()).is_err());
+        manager.leave(docs[0], alice);
+        assert!(manager.get(&docs[0]).is_none());
+        assert!(manager.join(docs[2], alice, "alice".into()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_full_room_rejects_join() {
+        let manager = RoomManager::new().with_max_participants(2);
+        let doc = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+        let carol = Uuid::new_v4();
+
+        let _alice = manager.join(doc, alice, "alice".into()).unwrap();
+        let _bob = manager.join(doc, Uuid::new_v4(), "bob".into()).unwrap();
+
+        let Err(err) = manager.join(doc, carol, "carol".into()) else {
+            panic!("joined a full room");
+        };
+        assert!(matches!(err, RoomError::Full { max: 2 }));
+        assert_eq!(manager.get(&doc).unwrap().participants().len(), 2);
+        assert_eq!(manager.rooms_for_user(&carol), 0);
+
+        // Another connection from someone already inside still gets in
+        let _again = manager.join(doc, alice, "alice".into()).unwrap();
+
+        // Leaving frees a place
+        manager.leave(doc, alice);
+        manager.leave(doc, alice);
+        assert!(manager.join(doc, carol, "carol".into()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_silent_participant_swept() {
+        let manager = RoomManager::new();
+        let doc = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let (room, mut alice_rx) = manager.join(doc, alice, "alice".into()).unwrap();
+        let _bob = manager.join(doc, bob, "bob".into()).unwrap();
+        room.apply_awareness(bob, &cursor(2, 1, 7), 1024).unwrap();
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        alice_rx.try_recv().unwrap();
+
+        // Alice keeps sending heartbeats, Bob's network has silently dropped
+        assert!(room.touch(&alice));
+        assert_eq!(manager.sweep_stale(Duration::from_millis(50)), 1);
+
+        let participants = room.participants();
+        assert_eq!(participants.len(), 1);
+        assert_eq!(participants[0].user_id, alice);
+        assert_eq!(manager.rooms_for_user(&bob), 0);
+        // Alice is told to drop Bob's cursor
+        let removal = states(&alice_rx.try_recv().unwrap());
+        assert_eq!(removal, HashMap::from([(2, None)]));
+        // Bob's connection finds out on its next message
+        assert!(!room.touch(&bob));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_receiver_pruned() {
+        let room = CollabRoom::new(CollabDocument::new(Uuid::new_v4()));
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let _alice_rx = room.join(alice, "alice".into()).unwrap();
+        let bob_rx = room.join(bob, "bob".into()).unwrap();
+
+        assert!(room.prune_stale().is_empty());
+
+        // Bob's connection dies without leaving
+        drop(bob_rx);
+        assert_eq!(room.prune_stale(), vec![bob]);
+        assert_eq!(room.participants().len(), 1);
+        assert_eq!(room.participants()[0].user_id, alice);
+
+        // Through the manager, the emptied room and the user's slot go too
+        let manager = RoomManager::new();
+        let doc = Uuid::new_v4();
+        let joined = manager.join(doc, alice, "alice".into()).unwrap();
+        drop(joined);
+
+        assert_eq!(manager.prune_stale(), 1);
+        assert!(manager.get(&doc).is_none());
+        assert_eq!(manager.rooms_for_user(&alice), 0);
+    }
+
+    #[tokio::test]
+    async fn test_closed_room_handed_to_hook() {
+        let closed = Arc::new(Mutex::new(Vec::new()));
+        let on_close: RoomClosed = {
+            let closed = Arc::clone(&closed);
+            Arc::new(move |room: Arc<CollabRoom>| closed.lock().unwrap().push(room))
+        };
+        let manager = RoomManager::new().with_on_close(on_close);
+        let doc = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+
+        let (room, _alice_rx) = manager.join(doc, alice, "alice".into()).unwrap();
+        let _bob_rx = manager.join(doc, bob, "bob".into()).unwrap();
+        let update = CollabDocument::with_content(doc, DEFAULT_FIELD, "draft")
+            .encode_state()
+            .await;
+        room.document.apply_update(&update).await.unwrap();
+
+        // Still open while anyone is in it
+        manager.leave(doc, alice);
+        assert!(closed.lock().unwrap().is_empty());
+
+        manager.leave(doc, bob);
+        let closed_room = closed.lock().unwrap().pop().unwrap();
+        assert!(Arc::ptr_eq(&closed_room, &room));
+        assert_eq!(
+            closed_room.document.get_content(DEFAULT_FIELD).await,
+            "draft"
+        );
+
+        // Rejoining after the close starts a fresh room; the hook ran once
+        let (reopened, _rx) = manager.join(doc, alice, "alice".into()).unwrap();
+        assert!(!Arc::ptr_eq(&reopened, &room));
+        assert!(closed.lock().unwrap().is_empty());
+
+        // Only rooms opened from a loaded document count as loaded, so the
+        // hook can tell an empty room from an emptied file
+        assert!(!room.is_loaded());
+        let loaded = manager.insert_document(CollabDocument::new(Uuid::new_v4()));
+        assert!(loaded.is_loaded());
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_restore_resyncs_everyone() {
+        let doc = Uuid::new_v4();
+        let room = CollabRoom::new(CollabDocument::seeded(doc, "fn main() {}"));
+        let alice = Uuid::new_v4();
+        let mut alice_rx = room.join(alice, "alice".into()).unwrap();
+
+        room.document.checkpoint("before refactor", 0).await.unwrap();
+        room.document.insert_text(DEFAULT_FIELD, 0, "// wip\n").await;
+        assert_eq!(room.document.list_checkpoints(), ["before refactor"]);
+        let edited = room.document.encode_state().await;
+
+        assert!(room.restore_checkpoint("missing").await.unwrap().is_none());
+        let update = room
+            .restore_checkpoint("before refactor")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(room.document.get_content(DEFAULT_FIELD).await, "fn main() {}");
+
+        // Everyone gets the restoring update, the one who asked included
+        let frame = alice_rx.try_recv().unwrap();
+        assert_eq!(&frame[..2], [MSG_SYNC, SYNC_UPDATE]);
+        assert!(frame.ends_with(&update));
+
+        // A client holding the edited text is reverted by the update alone
+        let replica = CollabDocument::from_updates(doc, [edited]).await.unwrap();
+        replica.apply_update(&update).await.unwrap();
+        assert_eq!(replica.get_content(DEFAULT_FIELD).await, "fn main() {}");
+    }
+}
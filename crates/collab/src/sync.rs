@@ -2,6 +2,22 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::awareness::write_var_uint;
+
+/// y-websocket message type of a sync frame.
+pub const MSG_SYNC: u8 = 0;
+
+/// Sync frame subtype carrying an incremental update.
+pub const SYNC_UPDATE: u8 = 2;
+
+/// Wrap a document update in a y-websocket sync frame.
+pub fn encode_update_message(update: &[u8]) -> Vec<u8> {
+    let mut buf = vec![MSG_SYNC, SYNC_UPDATE];
+    write_var_uint(&mut buf, update.len() as u64);
+    buf.extend_from_slice(update);
+    buf
+}
+
 /// Messages for the Yjs sync protocol.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
@@ -1,12 +1,37 @@
 //! Database repository layer.
 
 use chrono::{DateTime, Utc};
-use sqlx::PgPool;
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::models::{File, Language, Project, User};
+use crate::models::{
+    first_match, validate_settings, AuditEvent, AuditEventType, Collaborator, DocCheckpoint,
+    DocUpdate, File, FileEncoding, FileSearchHit, FileVersion, Language, Project, SandboxSession,
+    User, VersionRetention,
+};
 use crate::{Error, Result};
 
+/// A transaction spanning several repository calls.
+///
+/// Pass `&mut tx` to the `*_tx` repository methods. Dropping the transaction
+/// without [`commit`] rolls everything back.
+pub type Tx = Transaction<'static, Postgres>;
+
+/// Begin a transaction.
+pub async fn begin(pool: &PgPool) -> Result<Tx> {
+    pool.begin()
+        .await
+        .map_err(|e| Error::Database(e.to_string()))
+}
+
+/// Commit a transaction.
+pub async fn commit(tx: Tx) -> Result<()> {
+    tx.commit()
+        .await
+        .map_err(|e| Error::Database(e.to_string()))
+}
+
 /// User repository operations.
 pub struct UserRepo;
 
@@ -37,13 +62,16 @@ impl UserRepo {
     }
 
     /// Find user by email.
+    ///
+    /// Matching ignores case so accounts stored before emails were
+    /// normalized are still found.
     pub async fn find_by_email(pool: &PgPool, email: &str) -> Result<Option<UserWithPassword>> {
         let user = sqlx::query_as!(
             UserWithPassword,
             r#"
             SELECT id, email, username, password_hash, created_at
             FROM users
-            WHERE email = $1
+            WHERE lower(email) = lower($1)
             "#,
             email
         )
@@ -72,10 +100,10 @@ impl UserRepo {
         Ok(user)
     }
 
-    /// Check if email exists.
+    /// Check if email exists, ignoring case.
     pub async fn email_exists(pool: &PgPool, email: &str) -> Result<bool> {
         let exists = sqlx::query_scalar!(
-            r#"SELECT EXISTS(SELECT 1 FROM users WHERE email = $1) as "exists!""#,
+            r#"SELECT EXISTS(SELECT 1 FROM users WHERE lower(email) = lower($1)) as "exists!""#,
             email
         )
         .fetch_one(pool)
@@ -97,6 +125,203 @@ impl UserRepo {
 
         Ok(exists)
     }
+
+    /// Get a user's editor settings; an empty object if never saved.
+    pub async fn get_settings(pool: &PgPool, user_id: Uuid) -> Result<Value> {
+        let settings = sqlx::query_scalar!(
+            r#"SELECT settings::text as "settings!" FROM user_settings WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        match settings {
+            Some(settings) => {
+                serde_json::from_str(&settings).map_err(|e| Error::Internal(e.to_string()))
+            }
+            None => Ok(Value::Object(Default::default())),
+        }
+    }
+
+    /// Replace a user's editor settings after validating them.
+    pub async fn set_settings(pool: &PgPool, user_id: Uuid, settings: &Value) -> Result<()> {
+        validate_settings(settings)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO user_settings (user_id, settings)
+            VALUES ($1, $2::text::jsonb)
+            ON CONFLICT (user_id)
+            DO UPDATE SET settings = EXCLUDED.settings, updated_at = NOW()
+            "#,
+            user_id,
+            settings.to_string()
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Refresh tokens, stored by digest.
+pub struct RefreshTokenRepo;
+
+impl RefreshTokenRepo {
+    /// Record an issued refresh token. Storing the same token again keeps
+    /// the first record.
+    pub async fn store(
+        pool: &PgPool,
+        token_hash: &str,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO refresh_tokens (token_hash, user_id, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (token_hash) DO NOTHING
+            "#,
+            token_hash,
+            user_id,
+            expires_at
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// The user a refresh token was issued to, unless it was revoked or has
+    /// expired.
+    pub async fn find_user(pool: &PgPool, token_hash: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT u.id, u.email, u.username, u.created_at
+            FROM refresh_tokens rt
+            JOIN users u ON u.id = rt.user_id
+            WHERE rt.token_hash = $1 AND rt.expires_at > NOW()
+            "#,
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(user)
+    }
+
+    /// Revoke a refresh token. Returns whether it was still stored.
+    pub async fn revoke(pool: &PgPool, token_hash: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM refresh_tokens WHERE token_hash = $1",
+            token_hash
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Sandbox session repository.
+///
+/// Live sessions are tracked in memory; these rows let a restarted gateway
+/// find the sessions, and containers, it was responsible for.
+pub struct SandboxSessionRepo;
+
+impl SandboxSessionRepo {
+    /// Record a started session.
+    pub async fn create(pool: &PgPool, session: &SandboxSession) -> Result<()> {
+        let lang_str = serde_json::to_string(&session.language)
+            .map_err(|e| Error::Internal(e.to_string()))?
+            .trim_matches('"')
+            .to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sandbox_sessions
+                (id, user_id, project_id, container_id, language, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            session.id,
+            session.user_id,
+            session.project_id,
+            session.container_id,
+            lang_str,
+            session.created_at,
+            session.expires_at
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Forget sessions that are past their lifetime or whose project was
+    /// deleted, returning their container IDs for removal.
+    pub async fn delete_stale(pool: &PgPool) -> Result<Vec<String>> {
+        let container_ids = sqlx::query_scalar!(
+            r#"
+            DELETE FROM sandbox_sessions
+            WHERE expires_at <= NOW() OR project_id IS NULL
+            RETURNING container_id
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(container_ids)
+    }
+    /// Every recorded session whose project still exists, oldest first.
+    /// Every recorded session in a project, oldest first.
+    pub async fn list(pool: &PgPool) -> Result<Vec<SandboxSession>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, user_id, project_id as "project_id!", container_id, language,
+                created_at, expires_at
+            FROM sandbox_sessions
+            WHERE project_id IS NOT NULL
+            ORDER BY created_at
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        let sessions = rows
+            .into_iter()
+            .map(|row| SandboxSession {
+                id: row.id,
+                user_id: row.user_id,
+                project_id: row.project_id,
+                container_id: row.container_id,
+                language: serde_json::from_str(&format!("\"{}\"", row.language))
+                    .unwrap_or(Language::Python),
+                created_at: row.created_at,
+                expires_at: row.expires_at,
+            })
+            .collect();
+
+        Ok(sessions)
+    }
+
+    /// Forget an ended session. Returns whether it was recorded.
+    pub async fn delete(pool: &PgPool, id: Uuid) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM sandbox_sessions WHERE id = $1", id)
+            .execute(pool)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
 }
 
 /// User with password hash for authentication.
@@ -108,6 +333,13 @@ pub struct UserWithPassword {
     pub created_at: DateTime<Utc>,
 }
 
+/// One page of a user's projects.
+pub struct ProjectPage {
+    pub projects: Vec<Project>,
+    /// Number of projects across all pages.
+    pub total_count: i64,
+}
+
 /// Project repository operations.
 pub struct ProjectRepo;
 
@@ -118,6 +350,20 @@ impl ProjectRepo {
         name: &str,
         owner_id: Uuid,
         default_language: Language,
+    ) -> Result<Project> {
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+        Self::create_tx(&mut conn, name, owner_id, default_language).await
+    }
+
+    /// Create a new project within a transaction.
+    pub async fn create_tx(
+        conn: &mut PgConnection,
+        name: &str,
+        owner_id: Uuid,
+        default_language: Language,
     ) -> Result<Project> {
         let lang_str = serde_json::to_string(&default_language)
             .map_err(|e| Error::Internal(e.to_string()))?
@@ -128,13 +374,14 @@ impl ProjectRepo {
             r#"
             INSERT INTO projects (name, owner_id, default_language)
             VALUES ($1, $2, $3)
-            RETURNING id, name, owner_id, default_language, created_at, updated_at
+            RETURNING id, name, owner_id, default_language, allowed_extensions, resource_profile,
+                max_file_versions, file_version_max_age_secs, created_at, updated_at
             "#,
             name,
             owner_id,
             lang_str
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await
         .map_err(|e| Error::Database(e.to_string()))?;
 
@@ -143,22 +390,40 @@ impl ProjectRepo {
             name: row.name,
             owner_id: row.owner_id,
             default_language,
+            allowed_extensions: row.allowed_extensions,
+            resource_profile: row.resource_profile,
+            version_retention: VersionRetention {
+                max_versions: row.max_file_versions,
+                max_age_secs: row.file_version_max_age_secs,
+            },
             created_at: row.created_at,
             updated_at: row.updated_at,
         })
     }
 
-    /// List projects for a user (owned or collaborated).
-    pub async fn list_for_user(pool: &PgPool, user_id: Uuid) -> Result<Vec<Project>> {
+    /// List a page of projects for a user (owned or collaborated), most
+    /// recently updated first.
+    pub async fn list_for_user(
+        pool: &PgPool,
+        user_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Project>> {
+        // Ties on updated_at are broken by id so pages don't overlap
         let rows = sqlx::query!(
             r#"
-            SELECT DISTINCT p.id, p.name, p.owner_id, p.default_language, p.created_at, p.updated_at
+            SELECT DISTINCT p.id, p.name, p.owner_id, p.default_language, p.allowed_extensions,
+                p.resource_profile, p.max_file_versions, p.file_version_max_age_secs, p.created_at,
+                p.updated_at
             FROM projects p
             LEFT JOIN project_collaborators pc ON p.id = pc.project_id
             WHERE p.owner_id = $1 OR pc.user_id = $1
-            ORDER BY p.updated_at DESC
+            ORDER BY p.updated_at DESC, p.id
+            LIMIT $2 OFFSET $3
             "#,
-            user_id
+            user_id,
+            limit,
+            offset
         )
         .fetch_all(pool)
         .await
@@ -175,6 +440,12 @@ impl ProjectRepo {
                     name: row.name,
                     owner_id: row.owner_id,
                     default_language,
+                    allowed_extensions: row.allowed_extensions,
+                    resource_profile: row.resource_profile,
+                    version_retention: VersionRetention {
+                        max_versions: row.max_file_versions,
+                        max_age_secs: row.file_version_max_age_secs,
+                    },
                     created_at: row.created_at,
                     updated_at: row.updated_at,
                 }
@@ -184,11 +455,41 @@ impl ProjectRepo {
         Ok(projects)
     }
 
+    /// List a page of projects for a user along with how many they can
+    /// access in total.
+    pub async fn list_for_user_paginated(
+        pool: &PgPool,
+        user_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<ProjectPage> {
+        let total_count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(DISTINCT p.id) as "count!"
+            FROM projects p
+            LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+            WHERE p.owner_id = $1 OR pc.user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        let projects = Self::list_for_user(pool, user_id, limit, offset).await?;
+
+        Ok(ProjectPage {
+            projects,
+            total_count,
+        })
+    }
+
     /// Get project by ID.
     pub async fn find_by_id(pool: &PgPool, id: Uuid) -> Result<Option<Project>> {
         let row = sqlx::query!(
             r#"
-            SELECT id, name, owner_id, default_language, created_at, updated_at
+            SELECT id, name, owner_id, default_language, allowed_extensions, resource_profile,
+                max_file_versions, file_version_max_age_secs, created_at, updated_at
             FROM projects
             WHERE id = $1
             "#,
@@ -207,6 +508,12 @@ impl ProjectRepo {
                 name: row.name,
                 owner_id: row.owner_id,
                 default_language,
+                allowed_extensions: row.allowed_extensions,
+                resource_profile: row.resource_profile,
+                version_retention: VersionRetention {
+                    max_versions: row.max_file_versions,
+                    max_age_secs: row.file_version_max_age_secs,
+                },
                 created_at: row.created_at,
                 updated_at: row.updated_at,
             }
@@ -236,7 +543,8 @@ impl ProjectRepo {
             UPDATE projects
             SET name = $1, default_language = $2, updated_at = NOW()
             WHERE id = $3
-            RETURNING id, name, owner_id, default_language, created_at, updated_at
+            RETURNING id, name, owner_id, default_language, allowed_extensions, resource_profile,
+                max_file_versions, file_version_max_age_secs, created_at, updated_at
             "#,
             new_name,
             lang_str,
@@ -251,11 +559,121 @@ impl ProjectRepo {
             name: row.name,
             owner_id: row.owner_id,
             default_language: new_lang,
+            allowed_extensions: row.allowed_extensions,
+            resource_profile: row.resource_profile,
+            version_retention: VersionRetention {
+                max_versions: row.max_file_versions,
+                max_age_secs: row.file_version_max_age_secs,
+            },
             created_at: row.created_at,
             updated_at: row.updated_at,
         })
     }
 
+    /// Set the file extensions a project may contain; `None` lifts the
+    /// restriction. Extensions are expected in canonical form (see
+    /// [`crate::models::normalize_extensions`]).
+    pub async fn set_allowed_extensions(
+        pool: &PgPool,
+        id: Uuid,
+        allowed_extensions: Option<&[String]>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE projects SET allowed_extensions = $1, updated_at = NOW() WHERE id = $2",
+            allowed_extensions as Option<&[String]>,
+            id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Set the resource profile runs in a project default to; `None`
+    /// falls back to the deployment default.
+    pub async fn set_resource_profile(
+        pool: &PgPool,
+        id: Uuid,
+        resource_profile: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE projects SET resource_profile = $1, updated_at = NOW() WHERE id = $2",
+            resource_profile,
+            id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Set how long a project keeps earlier file versions; unset limits fall
+    /// back to the deployment default.
+    pub async fn set_version_retention(
+        pool: &PgPool,
+        id: Uuid,
+        retention: VersionRetention,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE projects
+            SET max_file_versions = $1, file_version_max_age_secs = $2, updated_at = NOW()
+            WHERE id = $3
+            "#,
+            retention.max_versions,
+            retention.max_age_secs,
+            id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Fork a project into a new project owned by `owner_id`, copying all files.
+    ///
+    /// The new project and its files are created in a single transaction.
+    pub async fn fork(
+        pool: &PgPool,
+        source_id: Uuid,
+        owner_id: Uuid,
+        name: &str,
+    ) -> Result<Project> {
+        let mut tx = begin(pool).await?;
+        let project = Self::fork_tx(&mut tx, source_id, owner_id, name).await?;
+        commit(tx).await?;
+
+        Ok(project)
+    }
+
+    /// Fork a project within a transaction.
+    pub async fn fork_tx(
+        conn: &mut PgConnection,
+        source_id: Uuid,
+        owner_id: Uuid,
+        name: &str,
+    ) -> Result<Project> {
+        let source_language = sqlx::query_scalar!(
+            "SELECT default_language FROM projects WHERE id = $1",
+            source_id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?
+        .ok_or_else(|| Error::NotFound("Project not found".into()))?;
+
+        let default_language: Language =
+            serde_json::from_str(&format!("\"{}\"", source_language)).unwrap_or(Language::Python);
+
+        let project = Self::create_tx(&mut *conn, name, owner_id, default_language).await?;
+        FileRepo::copy_project_files_tx(&mut *conn, source_id, project.id).await?;
+
+        Ok(project)
+    }
+
     /// Delete project.
     pub async fn delete(pool: &PgPool, id: Uuid) -> Result<()> {
         sqlx::query!("DELETE FROM projects WHERE id = $1", id)
@@ -266,6 +684,16 @@ impl ProjectRepo {
         Ok(())
     }
 
+    /// Delete project within a transaction.
+    pub async fn delete_tx(conn: &mut PgConnection, id: Uuid) -> Result<()> {
+        sqlx::query!("DELETE FROM projects WHERE id = $1", id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
     /// Check if user has access to project.
     pub async fn user_has_access(pool: &PgPool, project_id: Uuid, user_id: Uuid) -> Result<bool> {
         let exists = sqlx::query_scalar!(
@@ -285,39 +713,170 @@ impl ProjectRepo {
 
         Ok(exists)
     }
+
+    /// Give a user access to a project. Adding an existing collaborator
+    /// leaves them as they were.
+    pub async fn add_collaborator(pool: &PgPool, project_id: Uuid, user_id: Uuid) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO project_collaborators (project_id, user_id)
+            VALUES ($1, $2)
+            ON CONFLICT (project_id, user_id) DO NOTHING
+            "#,
+            project_id,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Revoke a collaborator's access. Returns whether they were one.
+    pub async fn remove_collaborator(
+        pool: &PgPool,
+        project_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM project_collaborators WHERE project_id = $1 AND user_id = $2",
+            project_id,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// A project's collaborators, in the order they were added.
+    pub async fn list_collaborators(pool: &PgPool, project_id: Uuid) -> Result<Vec<Collaborator>> {
+        let collaborators = sqlx::query_as!(
+            Collaborator,
+            r#"
+            SELECT pc.user_id, u.username, pc.role, pc.created_at
+            FROM project_collaborators pc
+            JOIN users u ON u.id = pc.user_id
+            WHERE pc.project_id = $1
+            ORDER BY pc.created_at, u.username
+            "#,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(collaborators)
+    }
 }
 
 /// File repository operations.
 pub struct FileRepo;
 
 impl FileRepo {
-    /// Create or update a file.
+    /// Create or update a text file.
     pub async fn upsert(
         pool: &PgPool,
         project_id: Uuid,
         path: &str,
         language: Language,
         content: &str,
+    ) -> Result<File> {
+        Self::upsert_with_encoding(
+            pool,
+            project_id,
+            path,
+            language,
+            content,
+            FileEncoding::Utf8,
+        )
+        .await
+    }
+
+    /// Create or update a file whose content is stored in the given encoding.
+    pub async fn upsert_with_encoding(
+        pool: &PgPool,
+        project_id: Uuid,
+        path: &str,
+        language: Language,
+        content: &str,
+        encoding: FileEncoding,
+    ) -> Result<File> {
+        let mut tx = begin(pool).await?;
+        let file = Self::upsert_tx(&mut tx, project_id, path, language, content, encoding).await?;
+        commit(tx).await?;
+        Ok(file)
+    }
+
+    /// Create or update a file within a transaction.
+    ///
+    /// Content being replaced with something different is kept as the
+    /// file's next version. The file's row stays locked until the
+    /// transaction ends, so concurrent saves number their versions in turn.
+    pub async fn upsert_tx(
+        conn: &mut PgConnection,
+        project_id: Uuid,
+        path: &str,
+        language: Language,
+        content: &str,
+        encoding: FileEncoding,
     ) -> Result<File> {
         let lang_str = serde_json::to_string(&language)
             .map_err(|e| Error::Internal(e.to_string()))?
             .trim_matches('"')
             .to_string();
+        let encoding_str = serde_json::to_string(&encoding)
+            .map_err(|e| Error::Internal(e.to_string()))?
+            .trim_matches('"')
+            .to_string();
+
+        // Taken before reading the latest version number, as its own
+        // statement so the insert below sees versions saved while waiting
+        sqlx::query!(
+            "SELECT id FROM files WHERE project_id = $1 AND path = $2 FOR UPDATE",
+            project_id,
+            path
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO file_versions (file_id, version, content, encoding, created_at)
+            SELECT f.id,
+                COALESCE((SELECT MAX(v.version) FROM file_versions v WHERE v.file_id = f.id), 0) + 1,
+                f.content, f.encoding, f.updated_at
+            FROM files f
+            WHERE f.project_id = $1 AND f.path = $2
+              AND (f.content IS DISTINCT FROM $3 OR f.encoding <> $4)
+            "#,
+            project_id,
+            path,
+            content,
+            encoding_str
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
 
         let row = sqlx::query!(
             r#"
-            INSERT INTO files (project_id, path, language, content)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO files (project_id, path, language, content, encoding)
+            VALUES ($1, $2, $3, $4, $5)
             ON CONFLICT (project_id, path)
-            DO UPDATE SET language = $3, content = $4, updated_at = NOW()
+            DO UPDATE SET language = $3, content = $4, encoding = $5, updated_at = NOW()
             RETURNING id, project_id, path, language, created_at, updated_at
             "#,
             project_id,
             path,
             lang_str,
-            content
+            content,
+            encoding_str
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await
         .map_err(|e| Error::Database(e.to_string()))?;
 
@@ -326,16 +885,50 @@ impl FileRepo {
             project_id: row.project_id,
             path: row.path,
             language,
+            encoding,
             created_at: row.created_at,
             updated_at: row.updated_at,
         })
     }
 
+    /// Copy every file of one project into another within a transaction.
+    pub async fn copy_project_files_tx(
+        conn: &mut PgConnection,
+        from_project: Uuid,
+        to_project: Uuid,
+    ) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO files (project_id, path, language, content, encoding)
+            SELECT $1, path, language, content, encoding
+            FROM files
+            WHERE project_id = $2
+            "#,
+            to_project,
+            from_project
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete every file of a project within a transaction.
+    pub async fn delete_for_project_tx(conn: &mut PgConnection, project_id: Uuid) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM files WHERE project_id = $1", project_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
     /// List files in a project.
     pub async fn list_for_project(pool: &PgPool, project_id: Uuid) -> Result<Vec<File>> {
         let rows = sqlx::query!(
             r#"
-            SELECT id, project_id, path, language, created_at, updated_at
+            SELECT id, project_id, path, language, encoding, created_at, updated_at
             FROM files
             WHERE project_id = $1
             ORDER BY path
@@ -349,13 +942,16 @@ impl FileRepo {
         let files = rows
             .into_iter()
             .map(|row| {
-                let language: Language =
-                    serde_json::from_str(&format!("\"{}\"", row.language)).unwrap_or(Language::Python);
+                let language: Language = serde_json::from_str(&format!("\"{}\"", row.language))
+                    .unwrap_or(Language::Python);
+                let encoding: FileEncoding =
+                    serde_json::from_str(&format!("\"{}\"", row.encoding)).unwrap_or_default();
                 File {
                     id: row.id,
                     project_id: row.project_id,
                     path: row.path,
                     language,
+                    encoding,
                     created_at: row.created_at,
                     updated_at: row.updated_at,
                 }
@@ -365,6 +961,91 @@ impl FileRepo {
         Ok(files)
     }
 
+    /// List files in a project along with their content.
+    pub async fn list_with_content(pool: &PgPool, project_id: Uuid) -> Result<Vec<(File, String)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, project_id, path, language, content, encoding, created_at, updated_at
+            FROM files
+            WHERE project_id = $1
+            ORDER BY path
+            "#,
+            project_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        let files = rows
+            .into_iter()
+            .map(|row| {
+                let language: Language = serde_json::from_str(&format!("\"{}\"", row.language))
+                    .unwrap_or(Language::Python);
+                let encoding: FileEncoding =
+                    serde_json::from_str(&format!("\"{}\"", row.encoding)).unwrap_or_default();
+                (
+                    File {
+                        id: row.id,
+                        project_id: row.project_id,
+                        path: row.path,
+                        language,
+                        encoding,
+                        created_at: row.created_at,
+                        updated_at: row.updated_at,
+                    },
+                    row.content.unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        Ok(files)
+    }
+
+    /// Find the text files in a project containing `query`, ignoring case,
+    /// with the first matching line of each.
+    pub async fn search_in_project(
+        pool: &PgPool,
+        project_id: Uuid,
+        query: &str,
+    ) -> Result<Vec<FileSearchHit>> {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{}%", escaped);
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, path, content as "content!"
+            FROM files
+            WHERE project_id = $1 AND encoding = 'utf8' AND content ILIKE $2
+            ORDER BY path
+            "#,
+            project_id,
+            pattern
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        // A match spanning lines, or one only Postgres' case folding finds,
+        // has no single line to show and is left out
+        let hits = rows
+            .into_iter()
+            .filter_map(|row| {
+                let (line, snippet) = first_match(&row.content, query)?;
+                Some(FileSearchHit {
+                    file_id: row.id,
+                    path: row.path,
+                    line,
+                    snippet,
+                })
+            })
+            .collect();
+
+        Ok(hits)
+    }
+
     /// Get file by ID with content.
     pub async fn find_by_id_with_content(
         pool: &PgPool,
@@ -372,7 +1053,7 @@ impl FileRepo {
     ) -> Result<Option<(File, String)>> {
         let row = sqlx::query!(
             r#"
-            SELECT id, project_id, path, language, content, created_at, updated_at
+            SELECT id, project_id, path, language, content, encoding, created_at, updated_at
             FROM files
             WHERE id = $1
             "#,
@@ -385,12 +1066,15 @@ impl FileRepo {
         Ok(row.map(|row| {
             let language: Language =
                 serde_json::from_str(&format!("\"{}\"", row.language)).unwrap_or(Language::Python);
+            let encoding: FileEncoding =
+                serde_json::from_str(&format!("\"{}\"", row.encoding)).unwrap_or_default();
             (
                 File {
                     id: row.id,
                     project_id: row.project_id,
                     path: row.path,
                     language,
+                    encoding,
                     created_at: row.created_at,
                     updated_at: row.updated_at,
                 },
@@ -399,6 +1083,213 @@ impl FileRepo {
         }))
     }
 
+    /// A file's earlier versions, newest first.
+    pub async fn list_versions(pool: &PgPool, file_id: Uuid) -> Result<Vec<FileVersion>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT file_id, version, encoding, created_at
+            FROM file_versions
+            WHERE file_id = $1
+            ORDER BY version DESC
+            "#,
+            file_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        let versions = rows
+            .into_iter()
+            .map(|row| FileVersion {
+                file_id: row.file_id,
+                version: row.version,
+                encoding: serde_json::from_str(&format!("\"{}\"", row.encoding))
+                    .unwrap_or_default(),
+                created_at: row.created_at,
+            })
+            .collect();
+
+        Ok(versions)
+    }
+
+    /// One earlier version of a file with its content.
+    pub async fn get_version(
+        pool: &PgPool,
+        file_id: Uuid,
+        version: i32,
+    ) -> Result<Option<(FileVersion, String)>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT file_id, version, content, encoding, created_at
+            FROM file_versions
+            WHERE file_id = $1 AND version = $2
+            "#,
+            file_id,
+            version
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(row.map(|row| {
+            let version = FileVersion {
+                file_id: row.file_id,
+                version: row.version,
+                encoding: serde_json::from_str(&format!("\"{}\"", row.encoding))
+                    .unwrap_or_default(),
+                created_at: row.created_at,
+            };
+            (version, row.content.unwrap_or_default())
+        }))
+    }
+
+    /// Delete all but a file's `keep` newest versions, returning how many
+    /// were removed.
+    pub async fn prune_versions(pool: &PgPool, file_id: Uuid, keep: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM file_versions
+            WHERE file_id = $1 AND version <= (
+                SELECT version FROM file_versions
+                WHERE file_id = $1
+                ORDER BY version DESC
+                OFFSET $2 LIMIT 1
+            )
+            "#,
+            file_id,
+            keep
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete earlier versions that fall outside their project's retention,
+    /// with limits the project leaves unset taken from `defaults`. A file's
+    /// newest version is always kept. Covers every file unless `file_id` is
+    /// given; returns how many versions were removed.
+    pub async fn enforce_version_retention(
+        pool: &PgPool,
+        file_id: Option<Uuid>,
+        defaults: VersionRetention,
+    ) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM file_versions d
+            USING (
+                SELECT v.file_id, v.version, v.created_at,
+                    ROW_NUMBER() OVER (PARTITION BY v.file_id ORDER BY v.version DESC) AS rank,
+                    COALESCE(p.max_file_versions, $2, 0) AS max_versions,
+                    COALESCE(p.file_version_max_age_secs, $3, 0) AS max_age_secs
+                FROM file_versions v
+                JOIN files f ON f.id = v.file_id
+                JOIN projects p ON p.id = f.project_id
+                WHERE $1::uuid IS NULL OR v.file_id = $1
+            ) r
+            WHERE d.file_id = r.file_id AND d.version = r.version AND r.rank > 1
+              AND ((r.max_versions > 0 AND r.rank > r.max_versions)
+                OR (r.max_age_secs > 0
+                    AND r.created_at < NOW() - make_interval(secs => r.max_age_secs::float8)))
+            "#,
+            file_id,
+            defaults.max_versions,
+            defaults.max_age_secs
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Replace a text file's content, e.g. with a collaborative document's
+    /// final state. Returns false if the file is gone or stored as binary.
+    ///
+    /// Content being replaced with something different is kept as the
+    /// file's next version, as with [`Self::upsert_tx`].
+    pub async fn update_content(pool: &PgPool, id: Uuid, content: &str) -> Result<bool> {
+        let mut tx = begin(pool).await?;
+
+        let locked = sqlx::query!("SELECT encoding FROM files WHERE id = $1 FOR UPDATE", id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+        match locked {
+            Some(file) if file.encoding == "utf8" => {}
+            _ => return Ok(false),
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO file_versions (file_id, version, content, encoding, created_at)
+            SELECT f.id,
+                COALESCE((SELECT MAX(v.version) FROM file_versions v WHERE v.file_id = f.id), 0) + 1,
+                f.content, f.encoding, f.updated_at
+            FROM files f
+            WHERE f.id = $1 AND f.content IS DISTINCT FROM $2
+            "#,
+            id,
+            content
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        sqlx::query!(
+            "UPDATE files SET content = $2, updated_at = NOW() WHERE id = $1",
+            id,
+            content
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        commit(tx).await?;
+        Ok(true)
+    }
+
+    /// Override a file's language, independent of its extension.
+    pub async fn update_language(
+        pool: &PgPool,
+        id: Uuid,
+        language: Language,
+    ) -> Result<Option<File>> {
+        let lang_str = serde_json::to_string(&language)
+            .map_err(|e| Error::Internal(e.to_string()))?
+            .trim_matches('"')
+            .to_string();
+
+        let row = sqlx::query!(
+            r#"
+            UPDATE files
+            SET language = $2, updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, project_id, path, encoding, created_at, updated_at
+            "#,
+            id,
+            lang_str
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(row.map(|row| {
+            let encoding: FileEncoding =
+                serde_json::from_str(&format!("\"{}\"", row.encoding)).unwrap_or_default();
+            File {
+                id: row.id,
+                project_id: row.project_id,
+                path: row.path,
+                language,
+                encoding,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            }
+        }))
+    }
+
     /// Delete file.
     pub async fn delete(pool: &PgPool, id: Uuid) -> Result<()> {
         sqlx::query!("DELETE FROM files WHERE id = $1", id)
@@ -409,3 +1300,211 @@ impl FileRepo {
         Ok(())
     }
 }
+
+/// Append-only log of collaborative document updates.
+pub struct DocUpdateRepo;
+
+impl DocUpdateRepo {
+    /// Append an update to a file's log. Returns the number of log entries.
+    pub async fn append(pool: &PgPool, file_id: Uuid, data: &[u8]) -> Result<i64> {
+        // The inserted row is not yet visible to the count, hence the + 1
+        let count = sqlx::query_scalar!(
+            r#"
+            WITH inserted AS (
+                INSERT INTO doc_updates (file_id, data) VALUES ($1, $2)
+            )
+            SELECT COUNT(*) + 1 as "count!" FROM doc_updates WHERE file_id = $1
+            "#,
+            file_id,
+            data
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(count)
+    }
+
+    /// A file's log entries, oldest first.
+    pub async fn list(pool: &PgPool, file_id: Uuid) -> Result<Vec<DocUpdate>> {
+        let updates = sqlx::query_as!(
+            DocUpdate,
+            r#"
+            SELECT id, data, is_snapshot
+            FROM doc_updates
+            WHERE file_id = $1
+            ORDER BY id
+            "#,
+            file_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(updates)
+    }
+
+    /// Replace every entry up to and including `through_id` with `snapshot`.
+    ///
+    /// The snapshot takes over `through_id`, so entries appended while it
+    /// was being built still replay after it.
+    pub async fn compact(
+        pool: &PgPool,
+        file_id: Uuid,
+        through_id: i64,
+        snapshot: &[u8],
+    ) -> Result<()> {
+        let mut tx = begin(pool).await?;
+
+        sqlx::query!(
+            "DELETE FROM doc_updates WHERE file_id = $1 AND id <= $2",
+            file_id,
+            through_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO doc_updates (id, file_id, data, is_snapshot)
+            VALUES ($1, $2, $3, TRUE)
+            "#,
+            through_id,
+            file_id,
+            snapshot
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        commit(tx).await
+    }
+}
+
+/// Named checkpoints of collaborative documents.
+pub struct DocCheckpointRepo;
+
+impl DocCheckpointRepo {
+    /// Save a checkpoint, replacing any earlier one with the same name.
+    pub async fn save(pool: &PgPool, file_id: Uuid, name: &str, state: &[u8]) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO doc_checkpoints (file_id, name, state)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (file_id, name)
+            DO UPDATE SET state = EXCLUDED.state, created_at = NOW()
+            "#,
+            file_id,
+            name,
+            state
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// A file's checkpoints, oldest first.
+    pub async fn list(pool: &PgPool, file_id: Uuid) -> Result<Vec<DocCheckpoint>> {
+        let checkpoints = sqlx::query_as!(
+            DocCheckpoint,
+            r#"
+            SELECT name, state, created_at
+            FROM doc_checkpoints
+            WHERE file_id = $1
+            ORDER BY created_at, name
+            "#,
+            file_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(checkpoints)
+    }
+}
+
+/// Audit log of user actions.
+pub struct AuditRepo;
+
+impl AuditRepo {
+    /// Record an action, optionally scoped to a project.
+    pub async fn record(
+        pool: &PgPool,
+        actor_id: Uuid,
+        project_id: Option<Uuid>,
+        event_type: AuditEventType,
+        details: &Value,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_log (actor_id, project_id, event_type, details)
+            VALUES ($1, $2, $3, $4::text::jsonb)
+            "#,
+            actor_id,
+            project_id,
+            event_type.as_str(),
+            details.to_string()
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// A project's events, newest first.
+    ///
+    /// Only events with an id below `before` are returned when it is given,
+    /// and only the listed types when `event_types` is non-empty.
+    pub async fn list_for_project(
+        pool: &PgPool,
+        project_id: Uuid,
+        event_types: &[AuditEventType],
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>> {
+        let types: Vec<String> = event_types.iter().map(|t| t.as_str().to_string()).collect();
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, actor_id, project_id, event_type, details::text as "details!", created_at
+            FROM audit_log
+            WHERE project_id = $1
+              AND (cardinality($2::text[]) = 0 OR event_type = ANY($2))
+              AND ($3::bigint IS NULL OR id < $3)
+            ORDER BY id DESC
+            LIMIT $4
+            "#,
+            project_id,
+            &types,
+            before,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+        // Rows of a type this build no longer knows are skipped
+        let events = rows
+            .into_iter()
+            .filter_map(|row| {
+                let event_type: AuditEventType =
+                    serde_json::from_str(&format!("\"{}\"", row.event_type)).ok()?;
+                let details = serde_json::from_str(&row.details).unwrap_or(Value::Null);
+                Some(AuditEvent {
+                    id: row.id,
+                    actor_id: row.actor_id,
+                    project_id: row.project_id,
+                    event_type,
+                    details,
+                    created_at: row.created_at,
+                })
+            })
+            .collect();
+
+        Ok(events)
+    }
+}
@@ -2,8 +2,8 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::db::{UserRepo, ProjectRepo, FileRepo};
-    use crate::models::Language;
+    use crate::db::{self, AuditRepo, DocCheckpointRepo, DocUpdateRepo, RefreshTokenRepo, UserRepo, ProjectRepo, FileRepo, SandboxSessionRepo};
+    use crate::models::{AuditEventType, FileEncoding, Language, SandboxSession, VersionRetention};
     use sqlx::PgPool;
 
     // Note: These tests require a running PostgreSQL instance
@@ -40,6 +40,92 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_refresh_tokens() {
+        let pool = setup_test_db().await;
+
+        let email = format!("refresh{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("refresh{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let user = UserRepo::create(&pool, &email, &username, "hash")
+            .await
+            .unwrap();
+        let live = format!("live{}", uuid::Uuid::new_v4());
+        let expired = format!("expired{}", uuid::Uuid::new_v4());
+        let in_a_month = chrono::Utc::now() + chrono::Duration::days(30);
+
+        RefreshTokenRepo::store(&pool, &live, user.id, in_a_month).await.unwrap();
+        let an_hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+        RefreshTokenRepo::store(&pool, &expired, user.id, an_hour_ago).await.unwrap();
+
+        let found = RefreshTokenRepo::find_user(&pool, &live).await.unwrap().unwrap();
+        assert_eq!(found.id, user.id);
+        assert_eq!(found.email, email);
+        assert!(RefreshTokenRepo::find_user(&pool, &expired).await.unwrap().is_none());
+
+        // Revoked tokens stop working; revoking again finds nothing
+        assert!(RefreshTokenRepo::revoke(&pool, &live).await.unwrap());
+        assert!(RefreshTokenRepo::find_user(&pool, &live).await.unwrap().is_none());
+        assert!(!RefreshTokenRepo::revoke(&pool, &live).await.unwrap());
+
+        // Cleanup
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_sandbox_sessions() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Sessions", user.id, Language::Go)
+            .await
+            .unwrap();
+
+        let now = chrono::Utc::now();
+        let session = |container_id: &str, expires_at| SandboxSession {
+            id: uuid::Uuid::new_v4(),
+            user_id: user.id,
+            project_id: project.id,
+            container_id: format!("{}-{}", container_id, project.id),
+            language: Language::Go,
+            created_at: now,
+            expires_at,
+        };
+        let live = session("live", now + chrono::Duration::hours(1));
+        let ended = session("ended", now - chrono::Duration::minutes(1));
+        SandboxSessionRepo::create(&pool, &live).await.unwrap();
+        SandboxSessionRepo::create(&pool, &ended).await.unwrap();
+
+        // Ended sessions hand back their containers; live ones stay listed
+        let stale = SandboxSessionRepo::delete_stale(&pool).await.unwrap();
+        assert!(stale.contains(&ended.container_id));
+        assert!(!stale.contains(&live.container_id));
+        let sessions = SandboxSessionRepo::list(&pool).await.unwrap();
+        let listed = sessions.iter().find(|s| s.id == live.id).unwrap();
+        assert_eq!(listed.container_id, live.container_id);
+        assert_eq!(listed.language, Language::Go);
+        assert!(!SandboxSessionRepo::delete(&pool, ended.id).await.unwrap());
+
+        assert!(SandboxSessionRepo::delete(&pool, live.id).await.unwrap());
+        let sessions = SandboxSessionRepo::list(&pool).await.unwrap();
+        assert!(sessions.iter().all(|s| s.id != live.id));
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_email_exists() {
@@ -95,9 +181,16 @@ mod tests {
         assert_eq!(updated.name, "Updated Name");
 
         // List projects
-        let projects = ProjectRepo::list_for_user(&pool, user.id).await.unwrap();
+        let projects = ProjectRepo::list_for_user(&pool, user.id, 50, 0).await.unwrap();
         assert_eq!(projects.len(), 1);
 
+        // Pages past the end are empty but still report the total
+        let page = ProjectRepo::list_for_user_paginated(&pool, user.id, 50, 1)
+            .await
+            .unwrap();
+        assert!(page.projects.is_empty());
+        assert_eq!(page.total_count, 1);
+
         // Delete project
         ProjectRepo::delete(&pool, project.id).await.unwrap();
 
@@ -108,6 +201,94 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_project_allowed_extensions() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Extensions", user.id, Language::Python)
+            .await
+            .unwrap();
+        assert_eq!(project.allowed_extensions, None);
+
+        // Restrict file extensions
+        let allowed = vec!["py".to_string(), "txt".to_string()];
+        ProjectRepo::set_allowed_extensions(&pool, project.id, Some(&allowed))
+            .await
+            .unwrap();
+        let restricted = ProjectRepo::find_by_id(&pool, project.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(restricted.allowed_extensions, Some(allowed));
+        assert!(restricted.allows_path("main.py"));
+        assert!(!restricted.allows_path("main.rs"));
+
+        // Clearing the list lifts the restriction
+        ProjectRepo::set_allowed_extensions(&pool, project.id, None)
+            .await
+            .unwrap();
+        let cleared = ProjectRepo::find_by_id(&pool, project.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cleared.allowed_extensions, None);
+        assert!(cleared.allows_path("main.rs"));
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_project_resource_profile() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Profile", user.id, Language::Python)
+            .await
+            .unwrap();
+        assert_eq!(project.resource_profile, None);
+
+        ProjectRepo::set_resource_profile(&pool, project.id, Some("heavy-ml"))
+            .await
+            .unwrap();
+        let profiled = ProjectRepo::find_by_id(&pool, project.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(profiled.resource_profile.as_deref(), Some("heavy-ml"));
+
+        ProjectRepo::set_resource_profile(&pool, project.id, None)
+            .await
+            .unwrap();
+        let cleared = ProjectRepo::find_by_id(&pool, project.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cleared.resource_profile, None);
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_file_operations() {
@@ -157,4 +338,706 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_file_language_override() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Override", user.id, Language::Python)
+            .await
+            .unwrap();
+        let file = FileRepo::upsert(
+            &pool,
+            project.id,
+            "main.py",
+            Language::Python,
+            "print('hello')",
+        )
+        .await
+        .unwrap();
+
+        let updated = FileRepo::update_language(&pool, file.id, Language::Ruby)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.language, Language::Ruby);
+
+        let (fetched, content) = FileRepo::find_by_id_with_content(&pool, file.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.language, Language::Ruby);
+        assert_eq!(content, "print('hello')");
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_file_versions() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Versions", user.id, Language::Python)
+            .await
+            .unwrap();
+
+        let mut file = None;
+        for content in ["v1", "v2", "v2", "v3", "v4"] {
+            file = Some(
+                FileRepo::upsert(&pool, project.id, "main.py", Language::Python, content)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let file = file.unwrap();
+
+        // Saving identical content records nothing
+        let versions = FileRepo::list_versions(&pool, file.id).await.unwrap();
+        let numbers: Vec<i32> = versions.iter().map(|v| v.version).collect();
+        assert_eq!(numbers, [3, 2, 1]);
+
+        let (version, content) = FileRepo::get_version(&pool, file.id, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(version.version, 2);
+        assert_eq!(content, "v2");
+        assert!(FileRepo::get_version(&pool, file.id, 9).await.unwrap().is_none());
+
+        // Pruning keeps the newest versions and numbering carries on
+        assert_eq!(FileRepo::prune_versions(&pool, file.id, 2).await.unwrap(), 1);
+        assert_eq!(FileRepo::prune_versions(&pool, file.id, 2).await.unwrap(), 0);
+        FileRepo::upsert(&pool, project.id, "main.py", Language::Python, "v5")
+            .await
+            .unwrap();
+        let versions = FileRepo::list_versions(&pool, file.id).await.unwrap();
+        let numbers: Vec<i32> = versions.iter().map(|v| v.version).collect();
+        assert_eq!(numbers, [4, 3, 2]);
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_update_content_records_version() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Collab save", user.id, Language::Python)
+            .await
+            .unwrap();
+        let file = FileRepo::upsert(&pool, project.id, "main.py", Language::Python, "v1")
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            let saved = FileRepo::update_content(&pool, file.id, "v2").await;
+            assert!(saved.unwrap());
+        }
+
+        // The replaced text is kept once; saving it unchanged records nothing
+        let versions = FileRepo::list_versions(&pool, file.id).await.unwrap();
+        assert_eq!(versions.len(), 1);
+        let (_, content) = FileRepo::get_version(&pool, file.id, 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(content, "v1");
+
+        // Missing files are left alone
+        let missing = FileRepo::update_content(&pool, uuid::Uuid::new_v4(), "x").await;
+        assert!(!missing.unwrap());
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_concurrent_saves_number_versions_in_turn() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Concurrent", user.id, Language::Python)
+            .await
+            .unwrap();
+        let file = FileRepo::upsert(&pool, project.id, "main.py", Language::Python, "v0")
+            .await
+            .unwrap();
+
+        let saves: Vec<_> = (1..=8)
+            .map(|n| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    let content = format!("v{}", n);
+                    FileRepo::upsert(&pool, project.id, "main.py", Language::Python, &content).await
+                })
+            })
+            .collect();
+        for save in saves {
+            save.await.unwrap().unwrap();
+        }
+
+        // Every replaced content was kept, none lost to a clashing number
+        let versions = FileRepo::list_versions(&pool, file.id).await.unwrap();
+        let numbers: Vec<i32> = versions.iter().map(|v| v.version).collect();
+        assert_eq!(numbers, (1..=8).rev().collect::<Vec<_>>());
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_version_retention_prunes_oldest() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Retention", user.id, Language::Python)
+            .await
+            .unwrap();
+
+        let mut file = None;
+        for content in ["v1", "v2", "v3", "v4", "v5", "v6"] {
+            file = Some(
+                FileRepo::upsert(&pool, project.id, "main.py", Language::Python, content)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let file = file.unwrap();
+        let versions = || async {
+            let versions = FileRepo::list_versions(&pool, file.id).await.unwrap();
+            versions.iter().map(|v| v.version).collect::<Vec<i32>>()
+        };
+        let defaults = VersionRetention {
+            max_versions: Some(4),
+            max_age_secs: Some(0),
+        };
+
+        // The deployment default keeps the newest four
+        assert_eq!(
+            FileRepo::enforce_version_retention(&pool, Some(file.id), defaults)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(versions().await, [5, 4, 3, 2]);
+
+        // A project override takes precedence over the default
+        let retention = VersionRetention {
+            max_versions: Some(2),
+            max_age_secs: None,
+        };
+        ProjectRepo::set_version_retention(&pool, project.id, retention)
+            .await
+            .unwrap();
+        let project = ProjectRepo::find_by_id(&pool, project.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(project.version_retention, retention);
+        assert_eq!(
+            FileRepo::enforce_version_retention(&pool, Some(file.id), defaults)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(versions().await, [5, 4]);
+
+        // Versions past the age limit go too, except the newest
+        let retention = VersionRetention {
+            max_versions: None,
+            max_age_secs: Some(3600),
+        };
+        ProjectRepo::set_version_retention(&pool, project.id, retention)
+            .await
+            .unwrap();
+        sqlx::query!(
+            "UPDATE file_versions SET created_at = NOW() - INTERVAL '2 hours' WHERE file_id = $1",
+            file.id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            FileRepo::enforce_version_retention(&pool, Some(file.id), defaults)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(versions().await, [5]);
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_search_in_project() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Search", user.id, Language::Python)
+            .await
+            .unwrap();
+
+        let main = FileRepo::upsert(
+            &pool,
+            project.id,
+            "main.py",
+            Language::Python,
+            "import os\nprint(\"Hello\")\n",
+        )
+        .await
+        .unwrap();
+        FileRepo::upsert(
+            &pool,
+            project.id,
+            "util.py",
+            Language::Python,
+            "def hi():\n    pass\n",
+        )
+        .await
+        .unwrap();
+        FileRepo::upsert(
+            &pool,
+            project.id,
+            "rates.py",
+            Language::Python,
+            "RATE = '100%'\n",
+        )
+        .await
+        .unwrap();
+
+        let hits = FileRepo::search_in_project(&pool, project.id, "HELLO")
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].file_id, main.id);
+        assert_eq!(hits[0].path, "main.py");
+        assert_eq!(hits[0].line, 2);
+        assert_eq!(hits[0].snippet, "print(\"Hello\")");
+
+        // LIKE wildcards in the query are matched literally
+        let hits = FileRepo::search_in_project(&pool, project.id, "0%")
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, "rates.py");
+        assert!(FileRepo::search_in_project(&pool, project.id, "_")
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_project_fork() {
+        let pool = setup_test_db().await;
+
+        // Setup owner, forker and source project
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let owner = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let forker = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+
+        let source = ProjectRepo::create(&pool, "Template", owner.id, Language::Rust)
+            .await
+            .unwrap();
+        FileRepo::upsert(&pool, source.id, "main.rs", Language::Rust, "fn main() {}")
+            .await
+            .unwrap();
+        FileRepo::upsert(&pool, source.id, "lib/util.py", Language::Python, "x = 1")
+            .await
+            .unwrap();
+
+        // Fork
+        let fork = ProjectRepo::fork(&pool, source.id, forker.id, "Template (fork)")
+            .await
+            .unwrap();
+
+        assert_ne!(fork.id, source.id);
+        assert_eq!(fork.owner_id, forker.id);
+        assert_eq!(fork.name, "Template (fork)");
+        assert_eq!(fork.default_language, Language::Rust);
+
+        let files = FileRepo::list_for_project(&pool, fork.id).await.unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "lib/util.py");
+        assert_eq!(files[0].language, Language::Python);
+        assert_eq!(files[1].path, "main.rs");
+
+        let (_, content) = FileRepo::find_by_id_with_content(&pool, files[1].id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(content, "fn main() {}");
+
+        // Cleanup
+        ProjectRepo::delete(&pool, source.id).await.unwrap();
+        ProjectRepo::delete(&pool, fork.id).await.unwrap();
+        for id in [owner.id, forker.id] {
+            sqlx::query!("DELETE FROM users WHERE id = $1", id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_project_collaborators() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let owner = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let guest = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+
+        let project = ProjectRepo::create(&pool, "Shared", owner.id, Language::Rust)
+            .await
+            .unwrap();
+        assert!(!ProjectRepo::user_has_access(&pool, project.id, guest.id).await.unwrap());
+
+        // Adding twice is the same as adding once
+        ProjectRepo::add_collaborator(&pool, project.id, guest.id).await.unwrap();
+        ProjectRepo::add_collaborator(&pool, project.id, guest.id).await.unwrap();
+        assert!(ProjectRepo::user_has_access(&pool, project.id, guest.id).await.unwrap());
+
+        let collaborators = ProjectRepo::list_collaborators(&pool, project.id).await.unwrap();
+        assert_eq!(collaborators.len(), 1);
+        assert_eq!(collaborators[0].user_id, guest.id);
+        assert_eq!(collaborators[0].username, guest.username);
+        assert_eq!(collaborators[0].role, "editor");
+
+        assert!(ProjectRepo::remove_collaborator(&pool, project.id, guest.id).await.unwrap());
+        assert!(!ProjectRepo::remove_collaborator(&pool, project.id, guest.id).await.unwrap());
+        assert!(!ProjectRepo::user_has_access(&pool, project.id, guest.id).await.unwrap());
+
+        // Cleanup
+        ProjectRepo::delete(&pool, project.id).await.unwrap();
+        for id in [owner.id, guest.id] {
+            sqlx::query!("DELETE FROM users WHERE id = $1", id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_transaction_rolls_back_on_failure() {
+        let pool = setup_test_db().await;
+
+        let email = format!("test{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("user{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let user = UserRepo::create(&pool, &email, &username, "password_hash")
+            .await
+            .unwrap();
+
+        let mut tx = db::begin(&pool).await.unwrap();
+
+        // First steps succeed inside the transaction
+        let project = ProjectRepo::create_tx(&mut tx, "Atomic", user.id, Language::Rust)
+            .await
+            .unwrap();
+        FileRepo::upsert_tx(
+            &mut tx,
+            project.id,
+            "main.rs",
+            Language::Rust,
+            "fn main() {}",
+            FileEncoding::Utf8,
+        )
+        .await
+        .unwrap();
+
+        // A later step fails: the target project does not exist
+        let result = FileRepo::upsert_tx(
+            &mut tx,
+            uuid::Uuid::new_v4(),
+            "orphan.rs",
+            Language::Rust,
+            "",
+            FileEncoding::Utf8,
+        )
+        .await;
+        assert!(result.is_err());
+        drop(tx);
+
+        // Nothing from the transaction was persisted
+        assert!(ProjectRepo::find_by_id(&pool, project.id).await.unwrap().is_none());
+        assert!(ProjectRepo::list_for_user(&pool, user.id, 50, 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Cleanup
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_settings_round_trip() {
+        let pool = setup_test_db().await;
+
+        let email = format!("settings{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("settings{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let user = UserRepo::create(&pool, &email, &username, "hash")
+            .await
+            .unwrap();
+
+        let defaults = UserRepo::get_settings(&pool, user.id).await.unwrap();
+        assert_eq!(defaults, serde_json::json!({}));
+
+        let settings = serde_json::json!({
+            "tabSize": 2,
+            "theme": "solarized-dark",
+            "keybindings": "vim",
+            "minimap": { "enabled": false }
+        });
+        UserRepo::set_settings(&pool, user.id, &settings).await.unwrap();
+        assert_eq!(UserRepo::get_settings(&pool, user.id).await.unwrap(), settings);
+
+        // Saving again replaces the previous blob
+        let updated = serde_json::json!({ "tabSize": 8 });
+        UserRepo::set_settings(&pool, user.id, &updated).await.unwrap();
+        assert_eq!(UserRepo::get_settings(&pool, user.id).await.unwrap(), updated);
+
+        let invalid = serde_json::json!({ "tabSize": 0 });
+        assert!(UserRepo::set_settings(&pool, user.id, &invalid).await.is_err());
+        assert_eq!(UserRepo::get_settings(&pool, user.id).await.unwrap(), updated);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_doc_update_log_compaction() {
+        let pool = setup_test_db().await;
+
+        let email = format!("log{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("log{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let user = UserRepo::create(&pool, &email, &username, "hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Log", user.id, Language::Rust)
+            .await
+            .unwrap();
+        let file = FileRepo::upsert(&pool, project.id, "main.rs", Language::Rust, "")
+            .await
+            .unwrap();
+
+        for (i, update) in [b"a", b"b", b"c"].iter().enumerate() {
+            let count = DocUpdateRepo::append(&pool, file.id, *update).await.unwrap();
+            assert_eq!(count, i as i64 + 1);
+        }
+
+        let log = DocUpdateRepo::list(&pool, file.id).await.unwrap();
+        let data: Vec<&[u8]> = log.iter().map(|u| u.data.as_slice()).collect();
+        assert_eq!(data, [b"a", b"b", b"c"]);
+
+        // Fold the first two entries into a snapshot; the third still follows
+        DocUpdateRepo::compact(&pool, file.id, log[1].id, b"ab")
+            .await
+            .unwrap();
+
+        let compacted = DocUpdateRepo::list(&pool, file.id).await.unwrap();
+        assert_eq!(compacted.len(), 2);
+        assert!(compacted[0].is_snapshot);
+        assert_eq!(compacted[0].data, b"ab");
+        assert_eq!(compacted[1].data, b"c");
+        assert!(!compacted[1].is_snapshot);
+
+        // Cleanup
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_doc_checkpoints() {
+        let pool = setup_test_db().await;
+
+        let email = format!("ckpt{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("ckpt{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let user = UserRepo::create(&pool, &email, &username, "hash")
+            .await
+            .unwrap();
+        let project = ProjectRepo::create(&pool, "Checkpoints", user.id, Language::Rust)
+            .await
+            .unwrap();
+        let file = FileRepo::upsert(&pool, project.id, "main.rs", Language::Rust, "")
+            .await
+            .unwrap();
+
+        DocCheckpointRepo::save(&pool, file.id, "draft", b"one").await.unwrap();
+        DocCheckpointRepo::save(&pool, file.id, "final", b"two").await.unwrap();
+        // Reusing a name replaces that checkpoint
+        DocCheckpointRepo::save(&pool, file.id, "draft", b"three").await.unwrap();
+
+        let checkpoints = DocCheckpointRepo::list(&pool, file.id).await.unwrap();
+        let saved: Vec<(&str, &[u8])> = checkpoints
+            .iter()
+            .map(|c| (c.name.as_str(), c.state.as_slice()))
+            .collect();
+        assert_eq!(saved, [("final", &b"two"[..]), ("draft", &b"three"[..])]);
+
+        // Cleanup
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires database
+    async fn test_activity_scoped_to_project() {
+        let pool = setup_test_db().await;
+
+        let email = format!("feed{}@example.com", uuid::Uuid::new_v4());
+        let username = format!("feed{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        let user = UserRepo::create(&pool, &email, &username, "hash")
+            .await
+            .unwrap();
+        let ours = ProjectRepo::create(&pool, "Ours", user.id, Language::Rust)
+            .await
+            .unwrap();
+        let theirs = ProjectRepo::create(&pool, "Theirs", user.id, Language::Rust)
+            .await
+            .unwrap();
+
+        let details = serde_json::json!({ "path": "main.rs" });
+        for event in [
+            AuditEventType::FileCreated,
+            AuditEventType::FileUpdated,
+            AuditEventType::SessionStarted,
+        ] {
+            AuditRepo::record(&pool, user.id, Some(ours.id), event, &details)
+                .await
+                .unwrap();
+        }
+        AuditRepo::record(&pool, user.id, Some(theirs.id), AuditEventType::FileDeleted, &details)
+            .await
+            .unwrap();
+
+        // Newest first, and nothing from the other project
+        let events = AuditRepo::list_for_project(&pool, ours.id, &[], None, 10)
+            .await
+            .unwrap();
+        let types: Vec<_> = events.iter().map(|e| e.event_type).collect();
+        assert_eq!(
+            types,
+            [
+                AuditEventType::SessionStarted,
+                AuditEventType::FileUpdated,
+                AuditEventType::FileCreated
+            ]
+        );
+        assert!(events.iter().all(|e| e.project_id == Some(ours.id)));
+        assert_eq!(events[0].details, details);
+
+        // Type filter
+        let edits = AuditRepo::list_for_project(
+            &pool,
+            ours.id,
+            &[AuditEventType::FileUpdated, AuditEventType::FileDeleted],
+            None,
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].event_type, AuditEventType::FileUpdated);
+
+        // Pagination continues below the last id seen
+        let page = AuditRepo::list_for_project(&pool, ours.id, &[], None, 2)
+            .await
+            .unwrap();
+        let rest = AuditRepo::list_for_project(&pool, ours.id, &[], Some(page[1].id), 2)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].event_type, AuditEventType::FileCreated);
+
+        // Cleanup
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
 }
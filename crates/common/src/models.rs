@@ -2,8 +2,11 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
+use crate::error::{Error, Result};
+
 /// Supported programming languages.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -24,6 +27,25 @@ pub enum Language {
 }
 
 impl Language {
+    /// All supported languages.
+    pub fn all() -> &'static [Language] {
+        &[
+            Language::Rust,
+            Language::Python,
+            Language::JavaScript,
+            Language::TypeScript,
+            Language::Go,
+            Language::Java,
+            Language::CSharp,
+            Language::Cpp,
+            Language::C,
+            Language::Ruby,
+            Language::Php,
+            Language::Swift,
+            Language::Kotlin,
+        ]
+    }
+
     /// Get the file extension for this language.
     pub fn extension(&self) -> &'static str {
         match self {
@@ -43,13 +65,56 @@ impl Language {
         }
     }
 
+    /// Human-readable name of this language.
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::Python => "Python",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+            Language::Go => "Go",
+            Language::Java => "Java",
+            Language::CSharp => "C#",
+            Language::Cpp => "C++",
+            Language::C => "C",
+            Language::Ruby => "Ruby",
+            Language::Php => "PHP",
+            Language::Swift => "Swift",
+            Language::Kotlin => "Kotlin",
+        }
+    }
+
+    /// Language identifier sent to language servers in `textDocument/didOpen`.
+    ///
+    /// Servers ignore documents whose id they do not recognize, so these
+    /// follow the identifiers listed in the LSP specification.
+    pub fn lsp_language_id(&self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::TypeScript => "typescript",
+            Language::Go => "go",
+            Language::Java => "java",
+            Language::CSharp => "csharp",
+            Language::Cpp => "cpp",
+            Language::C => "c",
+            Language::Ruby => "ruby",
+            Language::Php => "php",
+            Language::Swift => "swift",
+            Language::Kotlin => "kotlin",
+        }
+    }
+
     /// Get the Docker image for this language's sandbox.
     /// Images are hosted in Azure Container Registry.
     pub fn docker_image(&self) -> &'static str {
         match self {
             Language::Rust => "acrustyclintprod.azurecr.io/sandbox-rust:latest",
             Language::Python => "acrustyclintprod.azurecr.io/sandbox-python:latest",
-            Language::JavaScript | Language::TypeScript => "acrustyclintprod.azurecr.io/sandbox-node:latest",
+            Language::JavaScript | Language::TypeScript => {
+                "acrustyclintprod.azurecr.io/sandbox-node:latest"
+            }
             Language::Go => "acrustyclintprod.azurecr.io/sandbox-go:latest",
             Language::Java => "acrustyclintprod.azurecr.io/sandbox-java:latest",
             Language::CSharp => "acrustyclintprod.azurecr.io/sandbox-dotnet:latest",
@@ -71,6 +136,109 @@ pub struct User {
     pub created_at: DateTime<Utc>,
 }
 
+/// Longest email address accepted, per RFC 5321 path limits.
+pub const MAX_EMAIL_LEN: usize = 254;
+
+/// Validate an email address and return its canonical form.
+///
+/// Addresses are trimmed and lowercased so case and whitespace variants of
+/// one mailbox compare equal. Validation is deliberately simple: one `@`, a
+/// dot-atom local part, and a dotted domain of letters, digits and hyphens.
+pub fn normalize_email(email: &str) -> Result<String> {
+    let email = email.trim().to_lowercase();
+    let invalid = || Error::Validation("Invalid email address".into());
+
+    if email.len() > MAX_EMAIL_LEN {
+        return Err(invalid());
+    }
+
+    let (local, domain) = email.split_once('@').ok_or_else(invalid)?;
+
+    let local_ok = !local.is_empty()
+        && local.len() <= 64
+        && local
+            .split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(is_atext));
+    let domain_ok = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    if local_ok && domain_ok {
+        Ok(email)
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Characters allowed in an unquoted local part (RFC 5322 `atext`).
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+/=?^_`{|}~-".contains(c)
+}
+
+/// Maximum serialized size of a user's editor settings.
+pub const MAX_SETTINGS_BYTES: usize = 16 * 1024;
+
+/// Check editor settings before they are stored.
+///
+/// Settings are a JSON object of arbitrary keys, but the well-known ones
+/// must have the type and range the editor expects.
+pub fn validate_settings(settings: &Value) -> Result<()> {
+    let Some(object) = settings.as_object() else {
+        return Err(Error::Validation("Settings must be a JSON object".into()));
+    };
+
+    if settings.to_string().len() > MAX_SETTINGS_BYTES {
+        return Err(Error::Validation("Settings too large (max 16KB)".into()));
+    }
+
+    let in_range = |key: &str, min: u64, max: u64| match object.get(key) {
+        None => Ok(()),
+        Some(value) => match value.as_u64() {
+            Some(n) if (min..=max).contains(&n) => Ok(()),
+            _ => Err(Error::Validation(format!(
+                "{} must be an integer between {} and {}",
+                key, min, max
+            ))),
+        },
+    };
+    in_range("tabSize", 1, 16)?;
+    in_range("fontSize", 6, 72)?;
+
+    if let Some(theme) = object.get("theme") {
+        if !theme
+            .as_str()
+            .is_some_and(|theme| !theme.is_empty() && theme.len() <= 64)
+        {
+            return Err(Error::Validation("theme must be a non-empty string".into()));
+        }
+    }
+
+    if let Some(keybindings) = object.get("keybindings") {
+        if !matches!(keybindings.as_str(), Some("default" | "vim" | "emacs")) {
+            return Err(Error::Validation(
+                "keybindings must be one of default, vim, emacs".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// A user given access to a project they don't own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collaborator {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: String,
+    /// When the user was added to the project.
+    pub created_at: DateTime<Utc>,
+}
+
 /// A collaborative project/workspace.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
@@ -78,10 +246,140 @@ pub struct Project {
     pub name: String,
     pub owner_id: Uuid,
     pub default_language: Language,
+    /// File extensions the project may contain; `None` allows any.
+    pub allowed_extensions: Option<Vec<String>>,
+    /// Resource profile runs in this project default to.
+    pub resource_profile: Option<String>,
+    /// How long earlier file versions are kept in this project.
+    pub version_retention: VersionRetention,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl Project {
+    /// Whether a file at `path` may be stored in this project.
+    ///
+    /// With a restriction in place, files without an extension are refused.
+    pub fn allows_path(&self, path: &str) -> bool {
+        let Some(allowed) = &self.allowed_extensions else {
+            return true;
+        };
+
+        let name = path.rsplit('/').next().unwrap_or(path);
+        match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => {
+                allowed.contains(&extension.to_ascii_lowercase())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Longest file extension accepted in a project's allowed list.
+pub const MAX_EXTENSION_LEN: usize = 16;
+
+/// Validate a project's allowed extensions and return their canonical form.
+///
+/// Extensions are lowercased, stripped of a leading dot, sorted and
+/// deduplicated. An empty list means no restriction and yields `None`.
+pub fn normalize_extensions(extensions: &[String]) -> Result<Option<Vec<String>>> {
+    let mut normalized = Vec::with_capacity(extensions.len());
+    for original in extensions {
+        let trimmed = original.trim();
+        let extension = trimmed
+            .strip_prefix('.')
+            .unwrap_or(trimmed)
+            .to_ascii_lowercase();
+        if extension.is_empty()
+            || extension.len() > MAX_EXTENSION_LEN
+            || !extension.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(Error::Validation(format!(
+                "Invalid file extension: {:?}",
+                original
+            )));
+        }
+        normalized.push(extension);
+    }
+
+    normalized.sort();
+    normalized.dedup();
+    Ok((!normalized.is_empty()).then_some(normalized))
+}
+
+/// Longest file path accepted in a project.
+pub const MAX_PATH_LEN: usize = 1024;
+
+/// How strictly file paths are checked before files are stored.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PathPolicy {
+    /// Refuse any empty, `.` or `..` segment, even one that would resolve
+    /// inside the project.
+    #[default]
+    Strict,
+    /// Resolve `.`, `..` and repeated slashes, refusing only paths that end
+    /// up outside the project.
+    Normalize,
+}
+
+/// Validate a project-relative file path and return its canonical form.
+///
+/// Absolute paths, backslashes and NUL bytes are always refused. Checking
+/// the resolved segments, rather than searching for `..` in the string,
+/// catches `a/../../etc/passwd` while allowing names like `notes..txt`.
+pub fn normalize_path(path: &str, policy: PathPolicy) -> Result<String> {
+    let invalid = || Error::Validation(format!("Invalid file path: {:?}", path));
+
+    if path.trim().is_empty()
+        || path.len() > MAX_PATH_LEN
+        || path.starts_with('/')
+        || path.contains('\\')
+        || path.contains('\0')
+    {
+        return Err(invalid());
+    }
+
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match (segment, policy) {
+            ("" | "." | "..", PathPolicy::Strict) => return Err(invalid()),
+            ("" | ".", PathPolicy::Normalize) => {}
+            ("..", PathPolicy::Normalize) => {
+                segments.pop().ok_or_else(invalid)?;
+            }
+            (segment, _) => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        return Err(invalid());
+    }
+    Ok(segments.join("/"))
+}
+
+/// How a file's content is stored.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileEncoding {
+    /// UTF-8 text stored as-is.
+    #[default]
+    Utf8,
+    /// Binary content stored base64-encoded.
+    Base64,
+}
+
+impl FileEncoding {
+    /// Whether bytes should be treated as binary rather than text.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.contains(&0) || std::str::from_utf8(bytes).is_err() {
+            FileEncoding::Base64
+        } else {
+            FileEncoding::Utf8
+        }
+    }
+}
+
 /// A file within a project.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct File {
@@ -89,10 +387,124 @@ pub struct File {
     pub project_id: Uuid,
     pub path: String,
     pub language: Language,
+    pub encoding: FileEncoding,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// An earlier content of a file, without the content itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileVersion {
+    pub file_id: Uuid,
+    /// Counts up from 1 with each replaced content.
+    pub version: i32,
+    pub encoding: FileEncoding,
+    /// When this content was saved.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Limits on the earlier versions kept per file. A version is pruned once it
+/// falls outside either limit, but a file's newest version is always kept.
+///
+/// On a project, unset limits fall back to the deployment default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRetention {
+    /// Newest versions kept; 0 keeps any number.
+    pub max_versions: Option<i32>,
+    /// Seconds a version is kept after it was saved; 0 keeps it at any age.
+    pub max_age_secs: Option<i64>,
+}
+
+/// An entry in a document's update log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocUpdate {
+    pub id: i64,
+    pub data: Vec<u8>,
+    /// Whether this entry is a compacted snapshot of earlier updates.
+    pub is_snapshot: bool,
+}
+
+/// A named snapshot of a collaborative document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocCheckpoint {
+    pub name: String,
+    /// Encoded document state at the time of the checkpoint.
+    pub state: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Kind of action recorded in the audit log.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    FileCreated,
+    FileUpdated,
+    FileDeleted,
+    ProjectUpdated,
+    SessionStarted,
+}
+
+impl AuditEventType {
+    /// Name stored in the database and accepted as a filter.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::FileCreated => "file_created",
+            AuditEventType::FileUpdated => "file_updated",
+            AuditEventType::FileDeleted => "file_deleted",
+            AuditEventType::ProjectUpdated => "project_updated",
+            AuditEventType::SessionStarted => "session_started",
+        }
+    }
+}
+
+/// An entry in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub actor_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub event_type: AuditEventType,
+    pub details: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl File {
+    /// Whether the file holds binary (base64-encoded) content.
+    pub fn is_binary(&self) -> bool {
+        self.encoding == FileEncoding::Base64
+    }
+}
+
+/// Longest snippet returned with a search hit, in characters.
+pub const MAX_SNIPPET_CHARS: usize = 200;
+
+/// A file whose content matches a search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSearchHit {
+    pub file_id: Uuid,
+    pub path: String,
+    /// 1-based line number of the first match.
+    pub line: u32,
+    /// The matching line, trimmed and cut to [`MAX_SNIPPET_CHARS`].
+    pub snippet: String,
+}
+
+/// Find the first line of `content` containing `query`, ignoring case,
+/// returning its 1-based number and snippet.
+pub fn first_match(content: &str, query: &str) -> Option<(u32, String)> {
+    let query = query.to_lowercase();
+    content
+        .lines()
+        .zip(1..)
+        .find(|(line, _)| line.to_lowercase().contains(&query))
+        .map(|(line, number)| {
+            (
+                number,
+                line.trim().chars().take(MAX_SNIPPET_CHARS).collect(),
+            )
+        })
+}
+
 /// Session for a user's sandbox environment.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SandboxSession {
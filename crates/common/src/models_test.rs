@@ -0,0 +1,147 @@
+//! Tests for model validation.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::models::{
+        first_match, normalize_email, normalize_extensions, normalize_path, validate_settings,
+        Language, PathPolicy, MAX_SETTINGS_BYTES, MAX_SNIPPET_CHARS,
+    };
+
+    #[test]
+    fn test_email_normalized() {
+        assert_eq!(normalize_email("  User@Example.COM ").unwrap(), "user@example.com");
+        assert_eq!(
+            normalize_email("first.last+tag@mail.example.co.uk").unwrap(),
+            "first.last+tag@mail.example.co.uk"
+        );
+        assert_eq!(
+            normalize_email("User@x.com").unwrap(),
+            normalize_email("user@x.com").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_malformed_email_rejected() {
+        for email in [
+            "",
+            "plainaddress",
+            "@example.com",
+            "user@",
+            "user@localhost",
+            "user@@example.com",
+            "a@b@example.com",
+            ".user@example.com",
+            "user.@example.com",
+            "us..er@example.com",
+            "us er@example.com",
+            "user@-example.com",
+            "user@example..com",
+            "user@exa_mple.com",
+        ] {
+            assert!(normalize_email(email).is_err(), "accepted {:?}", email);
+        }
+
+        let long = format!("{}@example.com", "a".repeat(250));
+        assert!(normalize_email(&long).is_err());
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(validate_settings(&json!({ "tabSize": 4, "theme": "dark", "custom": [1, 2] })).is_ok());
+
+        assert!(validate_settings(&json!([1, 2])).is_err());
+        assert!(validate_settings(&json!({ "tabSize": 40 })).is_err());
+        assert!(validate_settings(&json!({ "tabSize": "4" })).is_err());
+        assert!(validate_settings(&json!({ "keybindings": "nano" })).is_err());
+        assert!(validate_settings(&json!({ "theme": "" })).is_err());
+
+        let huge = json!({ "notes": "x".repeat(MAX_SETTINGS_BYTES) });
+        assert!(validate_settings(&huge).is_err());
+    }
+
+    #[test]
+    fn test_extensions_normalized() {
+        let extensions = ["Py", ".txt", "py"].map(String::from);
+        assert_eq!(
+            normalize_extensions(&extensions).unwrap(),
+            Some(vec!["py".to_string(), "txt".to_string()])
+        );
+        assert_eq!(normalize_extensions(&[]).unwrap(), None);
+
+        for invalid in ["", ".", "tar.gz", "c++", "x".repeat(17).as_str()] {
+            assert!(normalize_extensions(&[invalid.to_string()]).is_err(), "accepted {:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_first_match_ignores_case() {
+        let content = "import os\n\n    print(Config.DEBUG)\nprint(config.debug)\n";
+        assert_eq!(
+            first_match(content, "config.debug"),
+            Some((3, "print(Config.DEBUG)".to_string()))
+        );
+        assert_eq!(first_match(content, "missing"), None);
+
+        let long = format!("x = '{}'", "y".repeat(500));
+        let (_, snippet) = first_match(&long, "Y").unwrap();
+        assert_eq!(snippet.chars().count(), MAX_SNIPPET_CHARS);
+    }
+
+    #[test]
+    fn test_traversal_rejected_under_both_policies() {
+        for path in [
+            "../secret",
+            "a/../../etc/passwd",
+            "src/./../../x",
+            "/etc/passwd",
+            "..\\windows",
+            "a\0b",
+            "",
+            "  ",
+            "a/..",
+        ] {
+            for policy in [PathPolicy::Strict, PathPolicy::Normalize] {
+                assert!(normalize_path(path, policy).is_err(), "{path:?} accepted by {policy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_path_policies() {
+        // Names merely containing dots are fine
+        assert_eq!(normalize_path("notes..txt", PathPolicy::Strict).unwrap(), "notes..txt");
+        assert_eq!(normalize_path("src/lib.rs", PathPolicy::Strict).unwrap(), "src/lib.rs");
+
+        for path in ["src/../lib.rs", "./lib.rs", "src//lib.rs"] {
+            assert!(normalize_path(path, PathPolicy::Strict).is_err(), "{path:?} accepted");
+        }
+        assert_eq!(normalize_path("src/../lib.rs", PathPolicy::Normalize).unwrap(), "lib.rs");
+        assert_eq!(normalize_path("./src//a/./b.rs", PathPolicy::Normalize).unwrap(), "src/a/b.rs");
+    }
+
+    #[test]
+    fn test_lsp_language_ids() {
+        let expected = [
+            (Language::Rust, "rust"),
+            (Language::Python, "python"),
+            (Language::JavaScript, "javascript"),
+            (Language::TypeScript, "typescript"),
+            (Language::Go, "go"),
+            (Language::Java, "java"),
+            (Language::CSharp, "csharp"),
+            (Language::Cpp, "cpp"),
+            (Language::C, "c"),
+            (Language::Ruby, "ruby"),
+            (Language::Php, "php"),
+            (Language::Swift, "swift"),
+            (Language::Kotlin, "kotlin"),
+        ];
+
+        assert_eq!(expected.len(), Language::all().len());
+        for (language, id) in expected {
+            assert_eq!(language.lsp_language_id(), id, "{:?}", language);
+        }
+    }
+}
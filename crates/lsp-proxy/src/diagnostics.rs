@@ -0,0 +1,53 @@
+//! Diagnostics pushed by language servers.
+
+use std::collections::HashMap;
+
+use lsp_types::{DiagnosticSeverity, Range};
+use serde::{Deserialize, Serialize};
+
+/// A problem the server reported in a document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub range: Range,
+    #[serde(default)]
+    pub severity: Option<DiagnosticSeverity>,
+    pub message: String,
+    /// Tool that produced it, e.g. `rustc` or `pyflakes`.
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// Parameters of a `textDocument/publishDiagnostics` notification.
+///
+/// Each notification replaces every earlier diagnostic for the document;
+/// an empty list means the document is now clean.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsParams {
+    pub uri: String,
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(default)]
+    pub version: Option<i32>,
+}
+
+/// The latest diagnostics per document.
+#[derive(Debug, Default)]
+pub struct PublishedDiagnostics {
+    by_uri: HashMap<String, Vec<Diagnostic>>,
+}
+
+impl PublishedDiagnostics {
+    /// Record a notification, clearing the document if its list is empty.
+    pub fn publish(&mut self, params: &DiagnosticsParams) {
+        if params.diagnostics.is_empty() {
+            self.by_uri.remove(&params.uri);
+        } else {
+            self.by_uri
+                .insert(params.uri.clone(), params.diagnostics.clone());
+        }
+    }
+
+    /// Current diagnostics for a document.
+    pub fn get(&self, uri: &str) -> &[Diagnostic] {
+        self.by_uri.get(uri).map_or(&[], Vec::as_slice)
+    }
+}
@@ -0,0 +1,125 @@
+//! Applying LSP text and workspace edits to document content.
+
+use std::collections::HashMap;
+
+use lsp_types::{
+    DocumentChangeOperation, DocumentChanges, OneOf, Position, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::manager::LspError;
+
+/// Convert an LSP position (UTF-16 columns) to a byte offset in `content`.
+///
+/// Positions past the end of a line or the document clamp to that end, as
+/// the protocol specifies.
+fn offset_of(content: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match content[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return content.len(),
+        }
+    }
+
+    let line_end = content[line_start..]
+        .find('\n')
+        .map_or(content.len(), |newline| line_start + newline);
+
+    let mut units = 0;
+    for (index, ch) in content[line_start..line_end].char_indices() {
+        if units >= position.character as usize {
+            return line_start + index;
+        }
+        units += ch.len_utf16();
+    }
+
+    line_end
+}
+
+/// Apply text edits to a single document, returning the new content.
+///
+/// Edits are resolved against the original content and applied bottom-up so
+/// earlier offsets stay valid. Inserts at the same position keep their order;
+/// overlapping edits are rejected.
+pub fn apply_text_edits(content: &str, edits: &[TextEdit]) -> Result<String, LspError> {
+    let mut resolved: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|edit| {
+            let start = offset_of(content, edit.range.start);
+            let end = offset_of(content, edit.range.end);
+            (start, end.max(start), edit.new_text.as_str())
+        })
+        .collect();
+
+    // Stable sort keeps same-position inserts in their original order
+    resolved.sort_by_key(|&(start, end, _)| (start, end));
+
+    if resolved.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+        return Err(LspError::InvalidEdit("overlapping text edits".into()));
+    }
+
+    let mut result = content.to_string();
+    for &(start, end, new_text) in resolved.iter().rev() {
+        result.replace_range(start..end, new_text);
+    }
+
+    Ok(result)
+}
+
+/// Apply a workspace edit to a set of open documents.
+///
+/// Returns the new content of every document the edit touched. Resource
+/// operations (create, rename, delete) are not supported.
+pub fn apply_workspace_edit(
+    documents: &HashMap<Url, String>,
+    edit: &WorkspaceEdit,
+) -> Result<HashMap<Url, String>, LspError> {
+    let mut per_document: Vec<(&Url, Vec<TextEdit>)> = Vec::new();
+
+    if let Some(ref changes) = edit.changes {
+        for (uri, edits) in changes {
+            per_document.push((uri, edits.clone()));
+        }
+    }
+
+    if let Some(ref document_changes) = edit.document_changes {
+        let document_edits = match document_changes {
+            DocumentChanges::Edits(edits) => edits.iter().collect::<Vec<_>>(),
+            DocumentChanges::Operations(operations) => operations
+                .iter()
+                .map(|operation| match operation {
+                    DocumentChangeOperation::Edit(edit) => Ok(edit),
+                    DocumentChangeOperation::Op(_) => Err(LspError::InvalidEdit(
+                        "resource operations are not supported".into(),
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+
+        for document_edit in document_edits {
+            let edits = document_edit
+                .edits
+                .iter()
+                .map(|edit| match edit {
+                    OneOf::Left(edit) => edit.clone(),
+                    OneOf::Right(annotated) => annotated.text_edit.clone(),
+                })
+                .collect();
+            per_document.push((&document_edit.text_document.uri, edits));
+        }
+    }
+
+    let mut updated: HashMap<Url, String> = HashMap::new();
+    for (uri, edits) in per_document {
+        let current = match updated.get(uri) {
+            Some(content) => content,
+            None => documents
+                .get(uri)
+                .ok_or_else(|| LspError::InvalidEdit(format!("unknown document {}", uri)))?,
+        };
+        let content = apply_text_edits(current, &edits)?;
+        updated.insert(uri.clone(), content);
+    }
+
+    Ok(updated)
+}
@@ -0,0 +1,65 @@
+//! Tests for applying LSP edits.
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lsp_types::{Position, Range, TextEdit, Url, WorkspaceEdit};
+
+    use crate::edits::{apply_text_edits, apply_workspace_edit};
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextEdit {
+        TextEdit {
+            range: Range {
+                start: Position::new(start.0, start.1),
+                end: Position::new(end.0, end.1),
+            },
+            new_text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_multi_edit_workspace_edit() {
+        let uri = Url::parse("file:///code/main.rs").unwrap();
+        let source = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n";
+        let documents = HashMap::from([(uri.clone(), source.to_string())]);
+
+        // Unordered: rename both uses of `x` and add an import on top
+        let workspace_edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri.clone(),
+                vec![
+                    edit((2, 19), (2, 20), "value"),
+                    edit((0, 0), (0, 0), "use std::fmt;\n\n"),
+                    edit((1, 8), (1, 9), "value"),
+                ],
+            )])),
+            ..Default::default()
+        };
+
+        let updated = apply_workspace_edit(&documents, &workspace_edit).unwrap();
+
+        assert_eq!(
+            updated[&uri],
+            "use std::fmt;\n\nfn main() {\n    let value = 1;\n    println!(\"{}\", value);\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_edits_rejected() {
+        let result = apply_text_edits(
+            "abcdef",
+            &[edit((0, 1), (0, 4), "X"), edit((0, 3), (0, 5), "Y")],
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        // "é" is one UTF-16 unit but two bytes; "😀" is two units
+        let result = apply_text_edits("é😀x", &[edit((0, 3), (0, 4), "y")]).unwrap();
+
+        assert_eq!(result, "é😀y");
+    }
+}
@@ -0,0 +1,148 @@
+//! Content-Length framing for LSP messages over stdio.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::manager::LspError;
+
+/// Default cap on a single response body.
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 8 * 1024 * 1024;
+
+/// Longest header line accepted before the body.
+const MAX_HEADER_LINE: usize = 8 * 1024;
+
+/// Size caps for responses read from a language server.
+///
+/// Responses are rejected as soon as their `Content-Length` exceeds the
+/// cap for the request's method, before any of the body is buffered.
+#[derive(Debug, Clone)]
+pub struct ResponseLimits {
+    default_max: usize,
+    per_method: HashMap<String, usize>,
+}
+
+impl ResponseLimits {
+    /// Limits applying `default_max` to every method.
+    pub fn new(default_max: usize) -> Self {
+        Self {
+            default_max,
+            per_method: HashMap::new(),
+        }
+    }
+
+    /// Override the cap for one method, e.g. `workspace/symbol`.
+    pub fn with_method(mut self, method: impl Into<String>, max: usize) -> Self {
+        self.per_method.insert(method.into(), max);
+        self
+    }
+
+    /// Largest cap for any method, bounding every message read.
+    pub fn largest(&self) -> usize {
+        self.per_method
+            .values()
+            .copied()
+            .fold(self.default_max, usize::max)
+    }
+
+    /// Cap for responses to `method`.
+    pub fn max_for(&self, method: &str) -> usize {
+        self.per_method
+            .get(method)
+            .copied()
+            .unwrap_or(self.default_max)
+    }
+}
+
+impl Default for ResponseLimits {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_RESPONSE_BYTES)
+    }
+}
+
+/// Read one framed message, refusing bodies larger than `max_len`.
+pub async fn read_message<R>(reader: &mut R, max_len: usize) -> Result<Value, LspError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let body = read_body(reader, max_len).await?;
+    serde_json::from_slice(&body).map_err(|e| LspError::Communication(e.to_string()))
+}
+
+/// Read one framed message's raw body, refusing bodies larger than `max_len`.
+pub async fn read_body<R>(reader: &mut R, max_len: usize) -> Result<Vec<u8>, LspError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut content_length = None;
+
+    loop {
+        let mut line = Vec::new();
+        let read = (&mut *reader)
+            .take(MAX_HEADER_LINE as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(|e| LspError::Communication(e.to_string()))?;
+
+        if read == 0 {
+            return Err(LspError::Communication("connection closed".into()));
+        }
+        if line.len() > MAX_HEADER_LINE {
+            return Err(LspError::Communication("header too large".into()));
+        }
+
+        let line = std::str::from_utf8(&line)
+            .map_err(|_| LspError::Communication("invalid header".into()))?
+            .trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                let length = value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| LspError::Communication("invalid Content-Length".into()))?;
+                content_length = Some(length);
+            }
+        }
+    }
+
+    let length =
+        content_length.ok_or_else(|| LspError::Communication("missing Content-Length".into()))?;
+    if length > max_len {
+        return Err(LspError::Communication("response too large".into()));
+    }
+
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|e| LspError::Communication(e.to_string()))?;
+
+    Ok(body)
+}
+
+/// Write one message with its `Content-Length` header.
+pub async fn write_message<W>(writer: &mut W, message: &Value) -> Result<(), LspError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let body = serde_json::to_vec(message).map_err(|e| LspError::Communication(e.to_string()))?;
+    let header = format!("Content-Length: {}\r\n\r\n", body.len());
+
+    writer
+        .write_all(header.as_bytes())
+        .await
+        .map_err(|e| LspError::Communication(e.to_string()))?;
+    writer
+        .write_all(&body)
+        .await
+        .map_err(|e| LspError::Communication(e.to_string()))?;
+    writer
+        .flush()
+        .await
+        .map_err(|e| LspError::Communication(e.to_string()))
+}
@@ -0,0 +1,50 @@
+//! Tests for LSP message framing.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::io::BufReader;
+
+    use crate::{
+        framing::{read_message, write_message, ResponseLimits},
+        manager::LspError,
+    };
+
+    async fn framed(message: &serde_json::Value) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, message).await.unwrap();
+        buffer
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_within_limit() {
+        let message = json!({ "jsonrpc": "2.0", "id": 1, "result": [] });
+        let bytes = framed(&message).await;
+
+        let mut reader = BufReader::new(bytes.as_slice());
+        assert_eq!(read_message(&mut reader, 1024).await.unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_rejected() {
+        let symbols: Vec<_> = (0..1000)
+            .map(|i| json!({ "name": format!("symbol_{i}"), "kind": 12 }))
+            .collect();
+        let bytes = framed(&json!({ "jsonrpc": "2.0", "id": 7, "result": symbols })).await;
+
+        let limits = ResponseLimits::new(1024 * 1024).with_method("workspace/symbol", 4096);
+        assert_eq!(limits.max_for("textDocument/hover"), 1024 * 1024);
+
+        let mut reader = BufReader::new(bytes.as_slice());
+        let err = read_message(&mut reader, limits.max_for("workspace/symbol"))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, LspError::Communication(ref message) if message == "response too large")
+        );
+
+        // A header claiming a huge body is refused without reading it
+        let mut reader = BufReader::new(&b"Content-Length: 999999999999\r\n\r\n{}"[..]);
+        assert!(read_message(&mut reader, 4096).await.is_err());
+    }
+}
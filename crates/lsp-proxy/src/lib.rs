@@ -4,11 +4,19 @@
 //! proxying requests from the frontend to language servers
 //! running in sandbox containers.
 
+pub mod diagnostics;
+pub mod edits;
+pub mod framing;
 pub mod manager;
 pub mod proxy;
+pub mod workspace;
 
-pub use manager::LspManager;
-pub use proxy::LspProxy;
+pub use diagnostics::{Diagnostic, DiagnosticsParams};
+pub use edits::{apply_text_edits, apply_workspace_edit};
+pub use framing::ResponseLimits;
+pub use manager::{Launcher, LspManager, ShutdownReport};
+pub use proxy::{LspProxy, ServerIo};
+pub use workspace::{Workspace, WorkspaceFolder};
 
 use rustyclint_common::models::Language;
 
@@ -1,47 +1,220 @@
 //! LSP server lifecycle management.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use futures_util::future::BoxFuture;
 
 use rustyclint_common::models::Language;
+use rustyclint_sandbox::{ContainerManager, ContainerProfile, ResourceLimits};
+use tokio::{task::JoinSet, time::Instant};
 use uuid::Uuid;
 
-use crate::proxy::LspProxy;
+use crate::{
+    framing::ResponseLimits,
+    proxy::{LspProxy, ServerIo, DEFAULT_REQUEST_TIMEOUT},
+    workspace::Workspace,
+};
+
+/// Starts a language server in a container, returning its stdio.
+pub type Launcher =
+    Arc<dyn Fn(String, Language) -> BoxFuture<'static, Result<ServerIo, LspError>> + Send + Sync>;
+
+/// A running proxy and when it was last handed out.
+struct ManagedProxy {
+    proxy: LspProxy,
+    /// Value of the manager's use counter at the last hand-out.
+    last_used: u64,
+}
 
 /// Manages LSP server instances.
 pub struct LspManager {
-    proxies: HashMap<(Uuid, Language), LspProxy>,
+    proxies: HashMap<(Uuid, Language), ManagedProxy>,
+    response_limits: ResponseLimits,
+    request_timeout: Duration,
+    launcher: Option<Launcher>,
+    /// Most servers running at once across all sessions; 0 means unlimited.
+    max_proxies: usize,
+    /// Containers of evicted servers, for the caller to remove.
+    evicted_containers: Vec<String>,
+    uses: u64,
 }
 
 impl LspManager {
     /// Create a new LSP manager.
     pub fn new() -> Self {
+        Self::with_response_limits(ResponseLimits::default())
+    }
+
+    /// Create a manager whose proxies cap response sizes per method.
+    pub fn with_response_limits(response_limits: ResponseLimits) -> Self {
         Self {
             proxies: HashMap::new(),
+            response_limits,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            launcher: None,
+            max_proxies: 0,
+            evicted_containers: Vec::new(),
+            uses: 0,
         }
     }
 
+    /// Give up on language server requests not answered within `timeout`.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Run at most `max` servers at once, evicting the least recently used
+    /// to make room for a new one; 0 disables the cap.
+    pub fn with_max_proxies(mut self, max: usize) -> Self {
+        self.max_proxies = max;
+        self
+    }
+
+    /// Start servers with `launcher` instead of `docker exec`.
+    pub fn with_launcher(mut self, launcher: Launcher) -> Self {
+        self.launcher = Some(launcher);
+        self
+    }
+
+    /// Start a container for a language server using the LSP profile.
+    ///
+    /// Language servers need a writable cache and network access that the
+    /// code execution profile deliberately withholds.
+    pub async fn start_container(
+        containers: &ContainerManager,
+        language: Language,
+    ) -> Result<String, LspError> {
+        if crate::lsp_command(language).is_none() {
+            return Err(LspError::UnsupportedLanguage(language));
+        }
+
+        containers
+            .create_container_with_profile(
+                language,
+                &ResourceLimits::lsp(),
+                ContainerProfile::LanguageServer,
+            )
+            .await
+            .map_err(|e| LspError::StartFailed(e.to_string()))
+    }
+
     /// Get or create an LSP proxy for a container/language combination.
+    ///
+    /// A new proxy is initialized with the workspace's roots for its language.
+    /// A proxy whose server has crashed is dropped and started again. If the
+    /// cap is reached, the least recently used server is shut down first and
+    /// its container queued for [`Self::take_evicted_containers`].
     pub async fn get_or_create(
         &mut self,
         container_id: &str,
         session_id: Uuid,
         language: Language,
+        workspace: &Workspace,
     ) -> Result<&mut LspProxy, LspError> {
         let key = (session_id, language);
 
+        if self
+            .proxies
+            .get(&key)
+            .is_some_and(|managed| !managed.proxy.is_alive())
+        {
+            tracing::warn!("LSP server for {:?} crashed; restarting", language);
+            self.proxies.remove(&key);
+        }
+
         if !self.proxies.contains_key(&key) {
-            let proxy = LspProxy::new(container_id, language).await?;
-            self.proxies.insert(key, proxy);
+            if self.max_proxies > 0 && self.proxies.len() >= self.max_proxies {
+                self.evict_least_recently_used().await;
+            }
+
+            let limits = self.response_limits.clone();
+            let mut proxy = match &self.launcher {
+                Some(launch) => {
+                    let io = launch(container_id.to_string(), language).await?;
+                    LspProxy::connect(container_id, language, limits, io)
+                }
+                None => LspProxy::new(container_id, language, limits).await?,
+            }
+            .with_request_timeout(self.request_timeout);
+            proxy.initialize(&workspace.folders_for(language)).await?;
+            self.proxies.insert(
+                key,
+                ManagedProxy {
+                    proxy,
+                    last_used: 0,
+                },
+            );
         }
 
-        Ok(self.proxies.get_mut(&key).unwrap())
+        self.uses += 1;
+        let managed = self.proxies.get_mut(&key).unwrap();
+        managed.last_used = self.uses;
+        Ok(&mut managed.proxy)
+    }
+
+    async fn evict_least_recently_used(&mut self) {
+        let Some(key) = self
+            .proxies
+            .iter()
+            .min_by_key(|(_, managed)| managed.last_used)
+            .map(|(key, _)| *key)
+        else {
+            return;
+        };
+
+        let mut proxy = self.proxies.remove(&key).unwrap().proxy;
+        tracing::info!(
+            "Evicting LSP server for {:?} in session {} to stay within {} servers",
+            key.1,
+            key.0,
+            self.max_proxies
+        );
+        let _ = proxy.shutdown().await;
+        self.evicted_containers
+            .push(proxy.container_id().to_string());
+    }
+
+    /// Containers whose servers were evicted since the last call.
+    pub fn take_evicted_containers(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.evicted_containers)
+    }
+
+    /// Number of language servers running.
+    pub fn active_count(&self) -> usize {
+        self.proxies.len()
+    }
+
+    /// Cap on running language servers; 0 if unlimited.
+    pub fn max_proxies(&self) -> usize {
+        self.max_proxies
+    }
+
+    /// Get a running LSP proxy without starting one.
+    ///
+    /// A proxy whose server has crashed is not returned.
+    pub fn get_mut(&mut self, session_id: Uuid, language: Language) -> Option<&mut LspProxy> {
+        let managed = self
+            .proxies
+            .get_mut(&(session_id, language))
+            .filter(|managed| managed.proxy.is_alive())?;
+        self.uses += 1;
+        managed.last_used = self.uses;
+        Some(&mut managed.proxy)
+    }
+
+    /// Get the container hosting the language server for a session, if running.
+    pub fn container_id(&self, session_id: Uuid, language: Language) -> Option<String> {
+        self.proxies
+            .get(&(session_id, language))
+            .map(|managed| managed.proxy.container_id().to_string())
     }
 
     /// Stop an LSP proxy.
     pub async fn stop(&mut self, session_id: Uuid, language: Language) {
         let key = (session_id, language);
-        if let Some(mut proxy) = self.proxies.remove(&key) {
-            let _ = proxy.shutdown().await;
+        if let Some(mut managed) = self.proxies.remove(&key) {
+            let _ = managed.proxy.shutdown().await;
         }
     }
 
@@ -55,11 +228,62 @@ impl LspManager {
             .collect();
 
         for key in keys {
-            if let Some(mut proxy) = self.proxies.remove(&key) {
-                let _ = proxy.shutdown().await;
+            if let Some(mut managed) = self.proxies.remove(&key) {
+                let _ = managed.proxy.shutdown().await;
             }
         }
     }
+
+    /// Shut down every proxy concurrently, giving up on those still
+    /// running after `timeout` so a hung server cannot block shutdown.
+    ///
+    /// All proxies are removed from the manager either way.
+    pub async fn shutdown_all(&mut self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let mut report = ShutdownReport::default();
+        let mut tasks = JoinSet::new();
+
+        for (_, ManagedProxy { mut proxy, .. }) in self.proxies.drain() {
+            report.container_ids.push(proxy.container_id().to_string());
+            tasks.spawn(async move {
+                let language = proxy.language();
+                match tokio::time::timeout_at(deadline, proxy.shutdown()).await {
+                    Ok(Ok(())) => true,
+                    Ok(Err(e)) => {
+                        tracing::warn!("LSP server for {:?} failed to shut down: {}", language, e);
+                        false
+                    }
+                    Err(_) => {
+                        tracing::warn!("LSP server for {:?} did not shut down in time", language);
+                        false
+                    }
+                }
+            });
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            if matches!(result, Ok(true)) {
+                report.stopped += 1;
+            } else {
+                report.failed += 1;
+            }
+        }
+
+        report.container_ids.sort();
+        report.container_ids.dedup();
+        report
+    }
+}
+
+/// Outcome of [`LspManager::shutdown_all`].
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    /// Servers that were sent shutdown and exit.
+    pub stopped: usize,
+    /// Servers that failed or missed the deadline.
+    pub failed: usize,
+    /// Containers that hosted the servers, for the caller to remove.
+    pub container_ids: Vec<String>,
 }
 
 impl Default for LspManager {
@@ -79,4 +303,10 @@ pub enum LspError {
 
     #[error("LSP communication error: {0}")]
     Communication(String),
+
+    #[error("Language server crashed")]
+    ServerCrashed,
+
+    #[error("Invalid workspace edit: {0}")]
+    InvalidEdit(String),
 }
@@ -0,0 +1,209 @@
+//! Tests for LSP server management.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use rustyclint_common::models::Language;
+    use serde_json::json;
+    use tokio::io::BufReader;
+    use uuid::Uuid;
+
+    use crate::{
+        framing::{read_message, write_message},
+        manager::{Launcher, LspManager},
+        proxy::ServerIo,
+        workspace::{Workspace, WorkspaceFolder},
+    };
+
+    /// Launch an in-process server answering every request with `null`.
+    fn fake_servers() -> Launcher {
+        Arc::new(|_container_id, _language| {
+            Box::pin(async {
+                let (client, server) = tokio::io::duplex(64 * 1024);
+                tokio::spawn(async move {
+                    let (reader, mut writer) = tokio::io::split(server);
+                    let mut reader = BufReader::new(reader);
+                    while let Ok(message) = read_message(&mut reader, 1024 * 1024).await {
+                        if message["method"] == "exit" {
+                            break;
+                        }
+                        if let Some(id) = message.get("id") {
+                            let reply = json!({ "jsonrpc": "2.0", "id": id, "result": null });
+                            let _ = write_message(&mut writer, &reply).await;
+                        }
+                    }
+                });
+
+                let (output, input) = tokio::io::split(client);
+                Ok(ServerIo {
+                    input: Box::pin(input),
+                    output: Box::pin(output),
+                })
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_languages_in_one_session_get_own_proxy_and_root() {
+        let workspace = Workspace::detect(
+            "file:///code",
+            ["backend/Cargo.toml", "backend/src/main.rs", "web/package.json", "web/tsconfig.json"],
+        );
+        let session_id = Uuid::new_v4();
+        let mut manager = LspManager::new().with_launcher(fake_servers());
+
+        let rust = manager
+            .get_or_create("container-a", session_id, Language::Rust, &workspace)
+            .await
+            .unwrap();
+        assert_eq!(rust.language(), Language::Rust);
+        assert_eq!(
+            rust.workspace_folders(),
+            [WorkspaceFolder::new("file:///code/backend")]
+        );
+
+        let ts = manager
+            .get_or_create("container-b", session_id, Language::TypeScript, &workspace)
+            .await
+            .unwrap();
+        assert_eq!(ts.language(), Language::TypeScript);
+        assert_eq!(ts.workspace_folders(), [WorkspaceFolder::new("file:///code/web")]);
+
+        assert_eq!(
+            manager.container_id(session_id, Language::Rust).as_deref(),
+            Some("container-a")
+        );
+        assert_eq!(
+            manager.container_id(session_id, Language::TypeScript).as_deref(),
+            Some("container-b")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_all_stops_every_proxy() {
+        let workspace = Workspace::detect("file:///code", ["main.py", "index.js"]);
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut manager = LspManager::new().with_launcher(fake_servers());
+
+        for (container, session, language) in [
+            ("container-a", first, Language::Python),
+            ("container-b", first, Language::JavaScript),
+            ("container-c", second, Language::Python),
+        ] {
+            manager
+                .get_or_create(container, session, language, &workspace)
+                .await
+                .unwrap();
+        }
+
+        let report = manager.shutdown_all(Duration::from_secs(5)).await;
+
+        assert_eq!(report.stopped, 3);
+        assert_eq!(report.failed, 0);
+        assert_eq!(
+            report.container_ids,
+            ["container-a", "container-b", "container-c"]
+        );
+        assert!(manager.container_id(first, Language::Python).is_none());
+        assert!(manager.container_id(second, Language::Python).is_none());
+
+        // Nothing left to stop
+        let report = manager.shutdown_all(Duration::from_secs(5)).await;
+        assert_eq!(report.stopped + report.failed, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cap_evicts_least_recently_used() {
+        let workspace = Workspace::detect("file:///code", ["main.py"]);
+        let sessions = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let mut manager = LspManager::new()
+            .with_launcher(fake_servers())
+            .with_max_proxies(2);
+
+        for (container, session) in [("container-a", sessions[0]), ("container-b", sessions[1])] {
+            manager
+                .get_or_create(container, session, Language::Python, &workspace)
+                .await
+                .unwrap();
+        }
+        // The first session's server is used again, leaving the second idle
+        assert!(manager.get_mut(sessions[0], Language::Python).is_some());
+        assert!(manager.take_evicted_containers().is_empty());
+
+        manager
+            .get_or_create("container-c", sessions[2], Language::Python, &workspace)
+            .await
+            .unwrap();
+
+        assert_eq!(manager.active_count(), 2);
+        assert!(manager.container_id(sessions[1], Language::Python).is_none());
+        assert!(manager.container_id(sessions[0], Language::Python).is_some());
+        assert_eq!(manager.take_evicted_containers(), ["container-b"]);
+        assert!(manager.take_evicted_containers().is_empty());
+    }
+
+    /// Launch servers that answer `initialize` and then crash, counting
+    /// launches.
+    fn crashing_servers(launches: Arc<AtomicUsize>) -> Launcher {
+        Arc::new(move |_container_id, _language| {
+            launches.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                let (client, server) = tokio::io::duplex(64 * 1024);
+                tokio::spawn(async move {
+                    let (reader, mut writer) = tokio::io::split(server);
+                    let mut reader = BufReader::new(reader);
+                    if let Ok(message) = read_message(&mut reader, 1024 * 1024).await {
+                        let reply = json!({ "jsonrpc": "2.0", "id": message["id"], "result": {} });
+                        let _ = write_message(&mut writer, &reply).await;
+                    }
+                    // Dropping both halves closes the proxy's output
+                });
+
+                let (output, input) = tokio::io::split(client);
+                Ok(ServerIo {
+                    input: Box::pin(input),
+                    output: Box::pin(output),
+                })
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_crashed_proxy_restarted() {
+        let launches = Arc::new(AtomicUsize::new(0));
+        let workspace = Workspace::detect("file:///code", ["main.py"]);
+        let session_id = Uuid::new_v4();
+        let mut manager = LspManager::new().with_launcher(crashing_servers(launches.clone()));
+
+        manager
+            .get_or_create("container-a", session_id, Language::Python, &workspace)
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while manager.get_mut(session_id, Language::Python).is_some() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("crash was not detected");
+        // The container is still known so the server can be restarted in it
+        assert_eq!(
+            manager.container_id(session_id, Language::Python).as_deref(),
+            Some("container-a")
+        );
+
+        manager
+            .get_or_create("container-a", session_id, Language::Python, &workspace)
+            .await
+            .unwrap();
+        assert_eq!(launches.load(Ordering::SeqCst), 2);
+    }
+}
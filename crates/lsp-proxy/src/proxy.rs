@@ -1,22 +1,196 @@
 //! LSP proxy for communication with language servers.
 
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bollard::{
+    container::LogOutput,
+    exec::{CreateExecOptions, StartExecResults},
+    Docker,
+};
+use futures_util::StreamExt;
+use lsp_types::{CodeActionContext, Range};
 use rustyclint_common::models::Language;
 use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::{broadcast, mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticsParams, PublishedDiagnostics},
+    framing::{read_body, write_message, ResponseLimits},
+    manager::LspError,
+    workspace::WorkspaceFolder,
+};
+
+/// Bytes buffered between the exec's stdout and the framed reader.
+const STDOUT_PIPE_BYTES: usize = 64 * 1024;
+
+/// How long a request waits for its response by default.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Server notifications buffered for the proxy's owner; more are dropped
+/// until it catches up.
+const NOTIFICATION_CAPACITY: usize = 256;
+
+/// Diagnostics notifications buffered per subscriber; a subscriber that
+/// falls further behind skips ahead.
+const DIAGNOSTICS_CAPACITY: usize = 64;
+
+/// The server's stdin, shared with the reader task for replies to server
+/// requests.
+type Input = Arc<tokio::sync::Mutex<Pin<Box<dyn AsyncWrite + Send>>>>;
+
+/// A request waiting for its response.
+struct PendingReply {
+    /// Cap for the response, from the request's method.
+    max_len: usize,
+    reply: oneshot::Sender<Result<Value, LspError>>,
+}
+
+/// Where the reader task sends what the server pushes.
+struct Notifications {
+    all: mpsc::Sender<Value>,
+    diagnostics: broadcast::Sender<DiagnosticsParams>,
+    published: Arc<Mutex<PublishedDiagnostics>>,
+}
+
+impl Notifications {
+    fn route(&self, method: &str, message: Value) {
+        if method == "textDocument/publishDiagnostics" {
+            match serde_json::from_value::<DiagnosticsParams>(message["params"].clone()) {
+                Ok(params) => {
+                    self.published.lock().unwrap().publish(&params);
+                    // No subscribers is fine; the latest state is kept above
+                    let _ = self.diagnostics.send(params);
+                }
+                Err(e) => tracing::warn!("Skipping malformed diagnostics: {}", e),
+            }
+        }
+
+        if self.all.try_send(message).is_err() {
+            tracing::debug!("Dropping LSP notification {}", method);
+        }
+    }
+}
 
-use crate::manager::LspError;
+/// Requests in flight by id. Once the server's output ends the map is
+/// closed, failing current and later requests instead of leaving them to
+/// time out.
+#[derive(Default)]
+struct Pending {
+    closed: bool,
+    replies: HashMap<i64, PendingReply>,
+}
+
+/// A running language server's stdin and stdout.
+pub struct ServerIo {
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+    pub output: Pin<Box<dyn AsyncRead + Send>>,
+}
+
+impl ServerIo {
+    /// Start a language server with `docker exec`, attached to its stdio.
+    ///
+    /// Stderr is only logged. Stdout arrives as a stream of chunks that may
+    /// split messages anywhere, so it is piped into a reader the framing
+    /// code can buffer.
+    pub async fn exec(container_id: &str, language: Language) -> Result<Self, LspError> {
+        let (cmd, args) =
+            crate::lsp_command(language).ok_or(LspError::UnsupportedLanguage(language))?;
+        let start_failed = |e: bollard::errors::Error| LspError::StartFailed(e.to_string());
+
+        let docker = Docker::connect_with_local_defaults().map_err(start_failed)?;
+        let exec = docker
+            .create_exec(
+                container_id,
+                CreateExecOptions {
+                    cmd: Some(std::iter::once(cmd).chain(args).map(String::from).collect()),
+                    attach_stdin: Some(true),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    working_dir: Some("/code".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(start_failed)?;
+
+        let StartExecResults::Attached { input, mut output } = docker
+            .start_exec(&exec.id, None)
+            .await
+            .map_err(start_failed)?
+        else {
+            return Err(LspError::StartFailed("exec started detached".into()));
+        };
+
+        let (mut stdout, reader) = tokio::io::duplex(STDOUT_PIPE_BYTES);
+        tokio::spawn(async move {
+            while let Some(Ok(chunk)) = output.next().await {
+                match chunk {
+                    // The proxy was dropped
+                    LogOutput::StdOut { message } if stdout.write_all(&message).await.is_err() => {
+                        break;
+                    }
+                    LogOutput::StdErr { message } => {
+                        tracing::debug!(
+                            "{:?} language server: {}",
+                            language,
+                            String::from_utf8_lossy(&message).trim_end()
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(Self {
+            input,
+            output: Box::pin(reader),
+        })
+    }
+}
 
 /// Proxy for communicating with an LSP server in a container.
+///
+/// A background task reads everything the server sends: responses go to
+/// the request with the matching id, so several requests may be in flight
+/// and answered in any order, and notifications go to
+/// [`Self::notifications`]. Diagnostics are also decoded and broadcast to
+/// [`Self::diagnostics`] subscribers.
 pub struct LspProxy {
     language: Language,
     container_id: String,
-    request_id: i64,
+    request_id: AtomicI64,
+    workspace_folders: Vec<WorkspaceFolder>,
+    response_limits: ResponseLimits,
+    request_timeout: Duration,
+    input: Input,
+    pending: Arc<Mutex<Pending>>,
+    notifications: mpsc::Receiver<Value>,
+    diagnostics: broadcast::Sender<DiagnosticsParams>,
+    published: Arc<Mutex<PublishedDiagnostics>>,
+    reader: JoinHandle<()>,
 }
 
 impl LspProxy {
     /// Create a new LSP proxy and start the language server.
-    pub async fn new(container_id: &str, language: Language) -> Result<Self, LspError> {
-        let (cmd, _args) = crate::lsp_command(language)
-            .ok_or(LspError::UnsupportedLanguage(language))?;
+    pub async fn new(
+        container_id: &str,
+        language: Language,
+        response_limits: ResponseLimits,
+    ) -> Result<Self, LspError> {
+        let (cmd, _args) =
+            crate::lsp_command(language).ok_or(LspError::UnsupportedLanguage(language))?;
 
         tracing::info!(
             "Starting LSP server {} for {:?} in container {}",
@@ -25,58 +199,174 @@ impl LspProxy {
             container_id
         );
 
-        // TODO: Start LSP server process in container
-        // This would use Docker exec to run the LSP server
-        // and establish stdin/stdout communication
+        let io = ServerIo::exec(container_id, language).await?;
+        Ok(Self::connect(container_id, language, response_limits, io))
+    }
 
-        Ok(Self {
+    /// Create a proxy over an already running language server.
+    pub fn connect(
+        container_id: &str,
+        language: Language,
+        response_limits: ResponseLimits,
+        io: ServerIo,
+    ) -> Self {
+        let input: Input = Arc::new(tokio::sync::Mutex::new(io.input));
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let (all, notifications) = mpsc::channel(NOTIFICATION_CAPACITY);
+        let (diagnostics, _) = broadcast::channel(DIAGNOSTICS_CAPACITY);
+        let published = Arc::new(Mutex::new(PublishedDiagnostics::default()));
+
+        let reader = tokio::spawn(dispatch_messages(
+            BufReader::new(io.output),
+            response_limits.largest(),
+            input.clone(),
+            pending.clone(),
+            Notifications {
+                all,
+                diagnostics: diagnostics.clone(),
+                published: published.clone(),
+            },
+        ));
+
+        Self {
             language,
             container_id: container_id.to_string(),
-            request_id: 0,
-        })
+            request_id: AtomicI64::new(0),
+            workspace_folders: Vec::new(),
+            response_limits,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            input,
+            pending,
+            notifications,
+            diagnostics,
+            published,
+            reader,
+        }
+    }
+
+    /// Give up on requests not answered within `timeout`.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
     }
 
-    /// Send a request to the LSP server.
-    pub async fn request(&mut self, method: &str, params: Value) -> Result<Value, LspError> {
-        self.request_id += 1;
+    /// Send a request to the LSP server and wait for its response.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value, LspError> {
+        let id = self.request_id.fetch_add(1, Ordering::Relaxed) + 1;
 
         let request = serde_json::json!({
             "jsonrpc": "2.0",
-            "id": self.request_id,
+            "id": id,
             "method": method,
             "params": params
         });
 
-        tracing::debug!("LSP request: {}", request);
+        // Registered before sending so a fast response is not missed
+        let (reply, response) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(LspError::ServerCrashed);
+            }
+            pending.replies.insert(
+                id,
+                PendingReply {
+                    max_len: self.response_limit(method),
+                    reply,
+                },
+            );
+        }
+
+        // Params carry document text; only log the method
+        tracing::debug!("LSP request {} ({})", method, id);
+        if let Err(e) = self.send(&request).await {
+            self.forget(id);
+            return Err(e);
+        }
 
-        // TODO: Send request to LSP server via Docker exec
-        // and read response
+        let message = match tokio::time::timeout(self.request_timeout, response).await {
+            Ok(Ok(message)) => message?,
+            Ok(Err(_)) => return Err(LspError::ServerCrashed),
+            Err(_) => {
+                self.forget(id);
+                tracing::warn!("LSP request {} ({}) timed out", method, id);
+                return Err(LspError::Communication("timeout".into()));
+            }
+        };
 
-        Ok(Value::Null)
+        if let Some(error) = message.get("error") {
+            return Err(LspError::Communication(format!(
+                "{} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            )));
+        }
+
+        Ok(message.get("result").cloned().unwrap_or(Value::Null))
     }
 
     /// Send a notification to the LSP server.
-    pub async fn notify(&mut self, method: &str, params: Value) -> Result<(), LspError> {
+    pub async fn notify(&self, method: &str, params: Value) -> Result<(), LspError> {
         let notification = serde_json::json!({
             "jsonrpc": "2.0",
             "method": method,
             "params": params
         });
 
-        tracing::debug!("LSP notification: {}", notification);
+        tracing::debug!("LSP notification {}", method);
+        self.send(&notification).await
+    }
+
+    /// Notifications the server has sent, oldest first.
+    pub fn notifications(&mut self) -> &mut mpsc::Receiver<Value> {
+        &mut self.notifications
+    }
+
+    /// Whether the server is still running.
+    ///
+    /// Turns false once its output stream closes, i.e. the server exited or
+    /// crashed; every request then fails with [`LspError::ServerCrashed`].
+    pub fn is_alive(&self) -> bool {
+        !self.pending.lock().unwrap().closed
+    }
+
+    /// Subscribe to diagnostics published from now on.
+    ///
+    /// An empty list for a uri clears its earlier diagnostics.
+    pub fn diagnostics(&self) -> broadcast::Receiver<DiagnosticsParams> {
+        self.diagnostics.subscribe()
+    }
+
+    /// Diagnostics the server last published for a document.
+    pub fn current_diagnostics(&self, uri: &str) -> Vec<Diagnostic> {
+        self.published.lock().unwrap().get(uri).to_vec()
+    }
 
-        // TODO: Send notification to LSP server
+    async fn send(&self, message: &Value) -> Result<(), LspError> {
+        write_message(&mut *self.input.lock().await, message).await
+    }
 
-        Ok(())
+    /// Stop waiting for a request's response.
+    fn forget(&self, id: i64) {
+        self.pending.lock().unwrap().replies.remove(&id);
     }
 
-    /// Initialize the LSP server for a workspace.
-    pub async fn initialize(&mut self, root_uri: &str) -> Result<Value, LspError> {
+    /// Initialize the LSP server for one or more workspace folders.
+    ///
+    /// The first folder is also sent as `rootUri` for servers that predate
+    /// multi-root support.
+    pub async fn initialize(&mut self, folders: &[WorkspaceFolder]) -> Result<Value, LspError> {
+        self.workspace_folders = folders.to_vec();
+
         self.request(
             "initialize",
             serde_json::json!({
-                "rootUri": root_uri,
+                "rootUri": folders.first().map(|folder| folder.uri.as_str()),
+                "workspaceFolders": folders,
                 "capabilities": {
+                    "workspace": {
+                        "workspaceFolders": true
+                    },
                     "textDocument": {
                         "completion": {
                             "completionItem": {
@@ -97,9 +387,19 @@ impl LspProxy {
         .await
     }
 
+    /// Largest response body accepted for a request method.
+    pub fn response_limit(&self, method: &str) -> usize {
+        self.response_limits.max_for(method)
+    }
+
+    /// Workspace folders the server was initialized with.
+    pub fn workspace_folders(&self) -> &[WorkspaceFolder] {
+        &self.workspace_folders
+    }
+
     /// Request completions at a position.
     pub async fn completion(
-        &mut self,
+        &self,
         uri: &str,
         line: u32,
         character: u32,
@@ -115,12 +415,7 @@ impl LspProxy {
     }
 
     /// Request hover information at a position.
-    pub async fn hover(
-        &mut self,
-        uri: &str,
-        line: u32,
-        character: u32,
-    ) -> Result<Value, LspError> {
+    pub async fn hover(&self, uri: &str, line: u32, character: u32) -> Result<Value, LspError> {
         self.request(
             "textDocument/hover",
             serde_json::json!({
@@ -133,7 +428,7 @@ impl LspProxy {
 
     /// Go to definition.
     pub async fn definition(
-        &mut self,
+        &self,
         uri: &str,
         line: u32,
         character: u32,
@@ -148,14 +443,103 @@ impl LspProxy {
         .await
     }
 
+    /// Find references to the symbol at a position.
+    pub async fn references(
+        &self,
+        uri: &str,
+        line: u32,
+        character: u32,
+        include_declaration: bool,
+    ) -> Result<Value, LspError> {
+        self.request(
+            "textDocument/references",
+            serde_json::json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": include_declaration }
+            }),
+        )
+        .await
+    }
+
+    /// Rename the symbol at a position.
+    ///
+    /// The result is a `WorkspaceEdit` to apply with
+    /// [`crate::apply_workspace_edit`].
+    pub async fn rename(
+        &self,
+        uri: &str,
+        line: u32,
+        character: u32,
+        new_name: &str,
+    ) -> Result<Value, LspError> {
+        self.request(
+            "textDocument/rename",
+            serde_json::json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+                "newName": new_name
+            }),
+        )
+        .await
+    }
+
+    /// Format a whole document, returning the text edits.
+    pub async fn formatting(
+        &self,
+        uri: &str,
+        tab_size: u32,
+        insert_spaces: bool,
+    ) -> Result<Value, LspError> {
+        self.request(
+            "textDocument/formatting",
+            serde_json::json!({
+                "textDocument": { "uri": uri },
+                "options": { "tabSize": tab_size, "insertSpaces": insert_spaces }
+            }),
+        )
+        .await
+    }
+
+    /// Request code actions for a range, e.g. quick fixes for its diagnostics.
+    pub async fn code_action(
+        &self,
+        uri: &str,
+        range: Range,
+        context: CodeActionContext,
+    ) -> Result<Value, LspError> {
+        self.request(
+            "textDocument/codeAction",
+            serde_json::json!({
+                "textDocument": { "uri": uri },
+                "range": range,
+                "context": context
+            }),
+        )
+        .await
+    }
+
+    /// Resolve a code action's edit and command.
+    ///
+    /// Servers may return code actions lazily; the resolved action carries
+    /// the `WorkspaceEdit` to apply with [`crate::apply_workspace_edit`].
+    pub async fn resolve_code_action(&self, action: Value) -> Result<Value, LspError> {
+        self.request("codeAction/resolve", action).await
+    }
+
     /// Notify that a document was opened.
-    pub async fn did_open(&mut self, uri: &str, language_id: &str, text: &str) -> Result<(), LspError> {
+    pub async fn did_open(
+        &self,
+        uri: &str,
+        language: Language,
+        text: &str,
+    ) -> Result<(), LspError> {
         self.notify(
             "textDocument/didOpen",
             serde_json::json!({
                 "textDocument": {
                     "uri": uri,
-                    "languageId": language_id,
+                    "languageId": language.lsp_language_id(),
                     "version": 1,
                     "text": text
                 }
@@ -165,12 +549,7 @@ impl LspProxy {
     }
 
     /// Notify that a document changed.
-    pub async fn did_change(
-        &mut self,
-        uri: &str,
-        version: i32,
-        text: &str,
-    ) -> Result<(), LspError> {
+    pub async fn did_change(&self, uri: &str, version: i32, text: &str) -> Result<(), LspError> {
         self.notify(
             "textDocument/didChange",
             serde_json::json!({
@@ -197,3 +576,95 @@ impl LspProxy {
         &self.container_id
     }
 }
+
+impl Drop for LspProxy {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Read every message the server sends until its output ends, routing
+/// responses to their waiting requests.
+///
+/// Messages are read up to the largest configured cap; a response is then
+/// held to its own method's cap.
+async fn dispatch_messages(
+    mut output: BufReader<Pin<Box<dyn AsyncRead + Send>>>,
+    max_len: usize,
+    input: Input,
+    pending: Arc<Mutex<Pending>>,
+    notifications: Notifications,
+) {
+    loop {
+        let body = match read_body(&mut output, max_len).await {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::debug!("Language server output ended: {}", e);
+                break;
+            }
+        };
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::warn!("Skipping malformed LSP message: {}", e);
+                continue;
+            }
+        };
+
+        match (
+            message.get("method").and_then(Value::as_str),
+            message.get("id"),
+        ) {
+            (Some(method), Some(id)) => {
+                let reply = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": server_request_result(method, &message["params"])
+                });
+                if write_message(&mut *input.lock().await, &reply)
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            (Some(method), None) => {
+                let method = method.to_string();
+                notifications.route(&method, message);
+            }
+            (None, id) => {
+                let id = id.and_then(Value::as_i64);
+                let waiting = id.and_then(|id| pending.lock().unwrap().replies.remove(&id));
+                match waiting {
+                    Some(waiting) if body.len() > waiting.max_len => {
+                        let too_large = LspError::Communication("response too large".into());
+                        let _ = waiting.reply.send(Err(too_large));
+                    }
+                    Some(waiting) => {
+                        let _ = waiting.reply.send(Ok(message));
+                    }
+                    None => tracing::debug!("Skipping stale LSP response {:?}", id),
+                }
+            }
+        }
+    }
+
+    // Dropping the senders fails every request still waiting
+    let mut pending = pending.lock().unwrap();
+    pending.closed = true;
+    pending.replies.clear();
+}
+
+/// Result sent back for a request the server makes of the client.
+///
+/// No client settings are kept, so every configuration item is `null`;
+/// other requests, such as capability registration, are accepted as-is.
+fn server_request_result(method: &str, params: &Value) -> Value {
+    match method {
+        "workspace/configuration" => {
+            let items = params["items"].as_array().map_or(0, Vec::len);
+            Value::Array(vec![Value::Null; items])
+        }
+        _ => Value::Null,
+    }
+}
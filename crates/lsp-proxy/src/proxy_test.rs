@@ -0,0 +1,342 @@
+//! Tests for the LSP proxy's stdio protocol.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use lsp_types::{CodeActionContext, Position, Range};
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf};
+
+    use rustyclint_common::models::Language;
+
+    use crate::{
+        diagnostics::DiagnosticsParams,
+        framing::{read_message, write_message, ResponseLimits},
+        manager::LspError,
+        proxy::{LspProxy, ServerIo},
+    };
+
+    struct Server {
+        reader: BufReader<ReadHalf<DuplexStream>>,
+        writer: WriteHalf<DuplexStream>,
+    }
+
+    impl Server {
+        async fn receive(&mut self) -> Value {
+            read_message(&mut self.reader, 1024 * 1024).await.unwrap()
+        }
+
+        /// Send a message a few bytes at a time, so frames arrive split.
+        async fn send_split(&mut self, message: Value) {
+            let mut bytes = Vec::new();
+            write_message(&mut bytes, &message).await.unwrap();
+            for chunk in bytes.chunks(7) {
+                self.writer.write_all(chunk).await.unwrap();
+                self.writer.flush().await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        }
+    }
+
+    fn connected(limits: ResponseLimits) -> (LspProxy, Server) {
+        let (client, server) = tokio::io::duplex(1024);
+        let (output, input) = tokio::io::split(client);
+        let proxy = LspProxy::connect(
+            "container",
+            Language::Python,
+            limits,
+            ServerIo {
+                input: Box::pin(input),
+                output: Box::pin(output),
+            },
+        );
+        let (reader, writer) = tokio::io::split(server);
+        let server = Server {
+            reader: BufReader::new(reader),
+            writer,
+        };
+        (proxy, server)
+    }
+
+    #[tokio::test]
+    async fn test_request_waits_for_matching_response() {
+        let (proxy, mut server) = connected(ResponseLimits::default());
+
+        let serve = async {
+            let request = server.receive().await;
+            assert_eq!(request["method"], "textDocument/hover");
+            let id = request["id"].clone();
+
+            server
+                .send_split(json!({
+                    "jsonrpc": "2.0",
+                    "method": "window/logMessage",
+                    "params": { "type": 3, "message": "indexing" }
+                }))
+                .await;
+            server
+                .send_split(json!({ "jsonrpc": "2.0", "id": 99, "result": "stale" }))
+                .await;
+            server
+                .send_split(
+                    json!({ "jsonrpc": "2.0", "id": id, "result": { "contents": "fn main()" } }),
+                )
+                .await;
+        };
+
+        let (result, ()) = tokio::join!(proxy.hover("file:///code/main.py", 0, 3), serve);
+        assert_eq!(result.unwrap(), json!({ "contents": "fn main()" }));
+    }
+
+    #[tokio::test]
+    async fn test_editing_requests_send_lsp_params() {
+        let (proxy, mut server) = connected(ResponseLimits::default());
+        let uri = "file:///code/main.py";
+
+        let serve = async {
+            let mut methods = Vec::new();
+            for _ in 0..4 {
+                let request = server.receive().await;
+                methods.push(request["method"].as_str().unwrap().to_string());
+                match request["method"].as_str().unwrap() {
+                    "textDocument/references" => {
+                        assert_eq!(
+                            request["params"]["position"],
+                            json!({ "line": 2, "character": 4 })
+                        );
+                        assert_eq!(request["params"]["context"]["includeDeclaration"], true);
+                    }
+                    "textDocument/rename" => assert_eq!(request["params"]["newName"], "total"),
+                    "textDocument/formatting" => {
+                        assert_eq!(
+                            request["params"]["options"],
+                            json!({ "tabSize": 4, "insertSpaces": true })
+                        );
+                    }
+                    "textDocument/codeAction" => {
+                        assert_eq!(
+                            request["params"]["range"]["end"],
+                            json!({ "line": 3, "character": 0 })
+                        );
+                        assert_eq!(request["params"]["context"]["diagnostics"], json!([]));
+                    }
+                    other => panic!("unexpected method {}", other),
+                }
+                assert_eq!(request["params"]["textDocument"]["uri"], uri);
+                server
+                    .send_split(json!({ "jsonrpc": "2.0", "id": request["id"], "result": [] }))
+                    .await;
+            }
+            methods
+        };
+
+        let requests = async {
+            let range = Range::new(Position::new(2, 0), Position::new(3, 0));
+            proxy.references(uri, 2, 4, true).await.unwrap();
+            proxy.rename(uri, 2, 4, "total").await.unwrap();
+            proxy.formatting(uri, 4, true).await.unwrap();
+            proxy
+                .code_action(uri, range, CodeActionContext::default())
+                .await
+                .unwrap();
+        };
+
+        let (methods, ()) = tokio::join!(serve, requests);
+        assert_eq!(
+            methods,
+            [
+                "textDocument/references",
+                "textDocument/rename",
+                "textDocument/formatting",
+                "textDocument/codeAction"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_requests_answered() {
+        let (proxy, mut server) = connected(ResponseLimits::default());
+
+        let serve = async {
+            let request = server.receive().await;
+            server
+                .send_split(json!({
+                    "jsonrpc": "2.0",
+                    "id": "config-1",
+                    "method": "workspace/configuration",
+                    "params": { "items": [{ "section": "pylsp" }, { "section": "python" }] }
+                }))
+                .await;
+
+            let reply = server.receive().await;
+            assert_eq!(reply["id"], "config-1");
+            assert_eq!(reply["result"], json!([null, null]));
+
+            server
+                .send_split(json!({ "jsonrpc": "2.0", "id": request["id"], "result": null }))
+                .await;
+        };
+
+        let (result, ()) = tokio::join!(proxy.request("textDocument/completion", json!({})), serve);
+        assert_eq!(result.unwrap(), Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_error_response_and_notifications() {
+        let (proxy, mut server) = connected(ResponseLimits::default());
+
+        proxy
+            .did_open("file:///code/main.py", Language::Python, "print(1)")
+            .await
+            .unwrap();
+        let notification = server.receive().await;
+        assert_eq!(notification["method"], "textDocument/didOpen");
+        assert_eq!(notification["params"]["textDocument"]["languageId"], "python");
+        assert!(notification.get("id").is_none());
+
+        let serve = async {
+            let request = server.receive().await;
+            server
+                .send_split(json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": { "code": -32601, "message": "method not found" }
+                }))
+                .await;
+        };
+
+        let (result, ()) = tokio::join!(proxy.request("custom/method", json!({})), serve);
+        assert!(result.unwrap_err().to_string().contains("method not found"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_answered_out_of_order() {
+        let (mut proxy, mut server) = connected(ResponseLimits::default());
+
+        let serve = async {
+            let first = server.receive().await;
+            let second = server.receive().await;
+            server
+                .send_split(json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": "file:///code/main.py", "diagnostics": [] }
+                }))
+                .await;
+            for request in [second, first] {
+                server
+                    .send_split(json!({ "jsonrpc": "2.0", "id": request["id"], "result": request["method"] }))
+                    .await;
+            }
+        };
+
+        let (hover, definition, ()) = tokio::join!(
+            proxy.hover("file:///code/main.py", 0, 0),
+            proxy.definition("file:///code/main.py", 0, 0),
+            serve
+        );
+        assert_eq!(hover.unwrap(), "textDocument/hover");
+        assert_eq!(definition.unwrap(), "textDocument/definition");
+
+        let notification = proxy.notifications().recv().await.unwrap();
+        assert_eq!(notification["method"], "textDocument/publishDiagnostics");
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_request_times_out_and_crash_fails_fast() {
+        let (proxy, mut server) = connected(ResponseLimits::default());
+        let proxy = proxy.with_request_timeout(Duration::from_millis(100));
+
+        let (result, _) = tokio::join!(
+            proxy.request("textDocument/hover", json!({})),
+            server.receive()
+        );
+        assert!(matches!(result, Err(LspError::Communication(e)) if e == "timeout"));
+
+        drop(server);
+        let result = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match proxy.request("textDocument/hover", json!({})).await {
+                    Err(LspError::ServerCrashed) => break,
+                    _ => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await;
+        assert!(
+            result.is_ok(),
+            "request did not fail after the server exited"
+        );
+        assert!(!proxy.is_alive());
+    }
+
+    #[tokio::test]
+    async fn test_response_held_to_method_cap() {
+        let limits = ResponseLimits::default().with_method("workspace/symbol", 64);
+        let (proxy, mut server) = connected(limits);
+
+        let serve = async {
+            let request = server.receive().await;
+            let symbols: Vec<_> = (0..20).map(|i| format!("symbol{}", i)).collect();
+            server
+                .send_split(json!({ "jsonrpc": "2.0", "id": request["id"], "result": symbols }))
+                .await;
+        };
+
+        let (result, ()) = tokio::join!(proxy.request("workspace/symbol", json!({})), serve);
+        assert!(result.unwrap_err().to_string().contains("too large"));
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_published_and_cleared() {
+        let (proxy, mut server) = connected(ResponseLimits::default());
+        let mut diagnostics = proxy.diagnostics();
+        let uri = "file:///code/main.py";
+
+        server
+            .send_split(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {
+                    "uri": uri,
+                    "diagnostics": [{
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": 0, "character": 5 }
+                        },
+                        "severity": 1,
+                        "message": "undefined name 'prnt'",
+                        "source": "pyflakes"
+                    }]
+                }
+            }))
+            .await;
+
+        let published = diagnostics.recv().await.unwrap();
+        assert_eq!(published.uri, uri);
+        assert_eq!(published.diagnostics[0].message, "undefined name 'prnt'");
+        assert_eq!(published.diagnostics[0].source.as_deref(), Some("pyflakes"));
+        assert_eq!(proxy.current_diagnostics(uri), published.diagnostics);
+
+        // Fixing the error publishes an empty list, which clears the uri
+        server
+            .send_split(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri, "diagnostics": [] }
+            }))
+            .await;
+
+        let cleared = diagnostics.recv().await.unwrap();
+        assert_eq!(
+            cleared,
+            DiagnosticsParams {
+                uri: uri.to_string(),
+                diagnostics: vec![],
+                version: None,
+            }
+        );
+        assert!(proxy.current_diagnostics(uri).is_empty());
+    }
+}
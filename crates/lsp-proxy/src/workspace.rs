@@ -0,0 +1,146 @@
+//! Multi-root workspaces for projects spanning several languages.
+
+use rustyclint_common::models::Language;
+use serde::{Deserialize, Serialize};
+
+/// URI of the project directory inside sandbox containers.
+pub const DEFAULT_ROOT_URI: &str = "file:///code";
+
+/// A workspace folder as sent in `initialize`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceFolder {
+    pub uri: String,
+    pub name: String,
+}
+
+impl WorkspaceFolder {
+    /// Folder for a URI, named after its last path segment.
+    pub fn new(uri: impl Into<String>) -> Self {
+        let uri = uri.into().trim_end_matches('/').to_string();
+        let name = uri.rsplit('/').next().unwrap_or_default().to_string();
+        Self { uri, name }
+    }
+}
+
+/// Manifest files that mark the root of a language's project.
+fn markers(language: Language) -> &'static [&'static str] {
+    match language {
+        Language::Rust => &["Cargo.toml"],
+        Language::Python => &["pyproject.toml", "setup.py", "requirements.txt"],
+        Language::JavaScript => &["package.json"],
+        Language::TypeScript => &["tsconfig.json", "package.json"],
+        Language::Go => &["go.mod"],
+        Language::Java => &["pom.xml", "build.gradle"],
+        Language::Kotlin => &["build.gradle.kts"],
+        Language::CSharp => &[".csproj", ".sln"],
+        Language::Cpp | Language::C => &["CMakeLists.txt", "compile_commands.json"],
+        Language::Ruby => &["Gemfile"],
+        Language::Php => &["composer.json"],
+        Language::Swift => &["Package.swift"],
+    }
+}
+
+/// The language-specific roots of one project.
+///
+/// Each language server is initialized with only the roots for its language,
+/// so a Rust crate and a TypeScript frontend in one repository each get a
+/// server scoped to their own directory.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root_uri: String,
+    roots: Vec<(Language, WorkspaceFolder)>,
+}
+
+impl Workspace {
+    /// A workspace whose only root is the project directory.
+    pub fn new(root_uri: impl Into<String>) -> Self {
+        Self {
+            root_uri: root_uri.into().trim_end_matches('/').to_string(),
+            roots: Vec::new(),
+        }
+    }
+
+    /// Detect language roots from the project's file paths (relative to
+    /// `root_uri`) by looking for manifest files.
+    pub fn detect<'a>(
+        root_uri: impl Into<String>,
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let mut workspace = Self::new(root_uri);
+
+        for path in paths {
+            let path = path.trim_start_matches('/');
+            let (dir, file) = match path.rsplit_once('/') {
+                Some((dir, file)) => (dir, file),
+                None => ("", path),
+            };
+
+            for &language in Language::all() {
+                if markers(language)
+                    .iter()
+                    .any(|marker| match marker.strip_prefix('.') {
+                        Some(extension) => file.ends_with(&format!(".{}", extension)),
+                        None => file == *marker,
+                    })
+                {
+                    let uri = if dir.is_empty() {
+                        workspace.root_uri.clone()
+                    } else {
+                        format!("{}/{}", workspace.root_uri, dir)
+                    };
+                    workspace.add_root(language, WorkspaceFolder::new(uri));
+                }
+            }
+        }
+
+        workspace
+    }
+
+    /// Register a root for a language.
+    pub fn add_root(&mut self, language: Language, folder: WorkspaceFolder) {
+        if !self
+            .roots
+            .iter()
+            .any(|(l, f)| *l == language && *f == folder)
+        {
+            self.roots.push((language, folder));
+        }
+    }
+
+    /// Folders to initialize a language's server with; the project root if
+    /// no language-specific root was found.
+    pub fn folders_for(&self, language: Language) -> Vec<WorkspaceFolder> {
+        let folders: Vec<WorkspaceFolder> = self
+            .roots
+            .iter()
+            .filter(|(l, _)| *l == language)
+            .map(|(_, folder)| folder.clone())
+            .collect();
+
+        if folders.is_empty() {
+            vec![WorkspaceFolder::new(self.root_uri.clone())]
+        } else {
+            folders
+        }
+    }
+
+    /// Innermost root of a language containing a document.
+    pub fn root_for(&self, language: Language, document_uri: &str) -> Option<&WorkspaceFolder> {
+        self.roots
+            .iter()
+            .filter(|(l, folder)| {
+                *l == language
+                    && document_uri
+                        .strip_prefix(folder.uri.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map(|(_, folder)| folder)
+            .max_by_key(|folder| folder.uri.len())
+    }
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::new(DEFAULT_ROOT_URI)
+    }
+}
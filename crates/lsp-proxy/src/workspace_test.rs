@@ -0,0 +1,49 @@
+//! Tests for multi-root workspaces.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+
+    use crate::workspace::{Workspace, WorkspaceFolder};
+
+    #[test]
+    fn test_documents_routed_to_innermost_root() {
+        let workspace = Workspace::detect(
+            "file:///code/",
+            ["Cargo.toml", "tools/gen/Cargo.toml", "frontend/package.json"],
+        );
+
+        let root = workspace
+            .root_for(Language::Rust, "file:///code/tools/gen/src/lib.rs")
+            .unwrap();
+        assert_eq!(root.uri, "file:///code/tools/gen");
+        assert_eq!(root.name, "gen");
+
+        let root = workspace
+            .root_for(Language::TypeScript, "file:///code/frontend/app.ts")
+            .unwrap();
+        assert_eq!(root.uri, "file:///code/frontend");
+
+        // A sibling sharing a name prefix is not inside the frontend root
+        assert!(workspace
+            .root_for(Language::TypeScript, "file:///code/frontend-old/app.ts")
+            .is_none());
+        assert!(workspace
+            .root_for(Language::Go, "file:///code/tools/gen/main.go")
+            .is_none());
+    }
+
+    #[test]
+    fn test_language_without_root_uses_project_root() {
+        let workspace = Workspace::detect("file:///code", ["web/package.json"]);
+
+        assert_eq!(
+            workspace.folders_for(Language::TypeScript),
+            vec![WorkspaceFolder::new("file:///code/web")]
+        );
+        assert_eq!(
+            workspace.folders_for(Language::Go),
+            vec![WorkspaceFolder::new("file:///code")]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Stripping of ANSI escape sequences from program output.
+
+/// Parser state while scanning for escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Plain text.
+    Ground,
+    /// After ESC, waiting for the sequence type.
+    Escape,
+    /// After ESC followed by intermediate bytes, waiting for the final byte.
+    EscapeIntermediate,
+    /// Inside a control sequence (`ESC [` or CSI).
+    Csi,
+    /// Inside an operating system command (`ESC ]` or OSC), ended by BEL or ST.
+    Osc,
+    /// Inside a DCS, SOS, PM or APC string, ended by ST.
+    Str,
+    /// Saw ESC inside an OSC or string; a following `\` completes ST.
+    StrEscape,
+}
+
+/// Remove ANSI escape sequences (colors, cursor movement, titles,
+/// hyperlinks) from text, leaving all other characters intact.
+///
+/// Works on `char`s rather than bytes so multi-byte UTF-8 text is never split,
+/// and recognizes both 7-bit (`ESC [`) and 8-bit (CSI) introducers.
+pub fn strip_ansi(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut state = State::Ground;
+
+    for c in input.chars() {
+        state = match state {
+            State::Ground => match c {
+                '\x1b' => State::Escape,
+                '\u{9b}' => State::Csi,
+                '\u{9d}' => State::Osc,
+                '\u{90}' | '\u{98}' | '\u{9e}' | '\u{9f}' => State::Str,
+                _ => {
+                    output.push(c);
+                    State::Ground
+                }
+            },
+            State::Escape => match c {
+                '[' => State::Csi,
+                ']' => State::Osc,
+                'P' | 'X' | '^' | '_' => State::Str,
+                '\x1b' => State::Escape,
+                '\x20'..='\x2f' => State::EscapeIntermediate,
+                // Final byte of a two-character sequence, or an invalid one
+                _ => State::Ground,
+            },
+            State::EscapeIntermediate => match c {
+                '\x20'..='\x2f' => State::EscapeIntermediate,
+                '\x1b' => State::Escape,
+                _ => State::Ground,
+            },
+            State::Csi => match c {
+                // Parameter and intermediate bytes
+                '\x20'..='\x3f' => State::Csi,
+                // Final byte
+                '\x40'..='\x7e' => State::Ground,
+                '\x1b' => State::Escape,
+                // Malformed sequence: drop it and keep the character
+                _ => {
+                    output.push(c);
+                    State::Ground
+                }
+            },
+            State::Osc | State::Str => match c {
+                '\x07' if state == State::Osc => State::Ground,
+                '\u{9c}' => State::Ground,
+                '\x1b' => State::StrEscape,
+                _ => state,
+            },
+            State::StrEscape => match c {
+                '\\' => State::Ground,
+                // An unterminated string followed by a new sequence
+                '[' => State::Csi,
+                ']' => State::Osc,
+                _ => State::Ground,
+            },
+        };
+    }
+
+    output
+}
@@ -0,0 +1,32 @@
+//! Tests for ANSI escape stripping.
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::strip_ansi;
+
+    #[test]
+    fn test_colored_compiler_output_stripped() {
+        let output = "\x1b[0m\x1b[1m\x1b[38;5;9merror[E0308]\x1b[0m\x1b[0m\x1b[1m: mismatched types\x1b[0m\n\
+                      \x1b[0m \x1b[0m\x1b[0m\x1b[1m\x1b[38;5;12m--> \x1b[0m\x1b[0mmain.rs:2:18\x1b[0m\n\
+                      \x1b[1;32m✓\x1b[0m naïve → 日本語\n";
+
+        assert_eq!(
+            strip_ansi(output),
+            "error[E0308]: mismatched types\n --> main.rs:2:18\n✓ naïve → 日本語\n"
+        );
+    }
+
+    #[test]
+    fn test_osc_hyperlinks_and_titles_stripped() {
+        let output = "\x1b]0;build\x07\x1b]8;;https://doc.rust-lang.org\x1b\\docs\x1b]8;;\x1b\\ ok";
+
+        assert_eq!(strip_ansi(output), "docs ok");
+    }
+
+    #[test]
+    fn test_plain_text_and_eight_bit_csi() {
+        assert_eq!(strip_ansi("plain\ttext\r\n"), "plain\ttext\r\n");
+        assert_eq!(strip_ansi("\u{9b}31mred\u{9b}0m"), "red");
+        assert_eq!(strip_ansi("\x1b(Bcharset"), "charset");
+    }
+}
@@ -1,94 +1,238 @@
 //! Docker container management.
 
-use std::collections::HashMap;
+use std::{future::Future, time::Duration};
 
 use bollard::{
     container::{
-        Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions,
+        Config, CreateContainerOptions, LogOutput, RemoveContainerOptions, StartContainerOptions,
         StopContainerOptions,
     },
+    exec::{CreateExecOptions, StartExecResults},
     image::CreateImageOptions,
+    models::CreateImageInfo,
     secret::{HostConfig, ResourcesUlimits},
     Docker,
 };
+use futures_util::{Stream, StreamExt};
 use rustyclint_common::models::Language;
 use uuid::Uuid;
 
-use crate::limits::ResourceLimits;
+use crate::{
+    error::SandboxError,
+    executor::{wait_for_exit_code, EXIT_CODE_RETRIES, EXIT_CODE_RETRY_DELAY},
+    images::{ImageOverrides, ImageRef},
+    limits::{ContainerProfile, ResourceLimits},
+};
+
+/// Host configuration enforcing `limits` for a container with `profile`.
+///
+/// All capabilities are dropped; `limits.cap_add` may only add back entries
+/// from the allowlist, and anything else is rejected.
+pub fn host_config(
+    limits: &ResourceLimits,
+    profile: ContainerProfile,
+) -> Result<HostConfig, String> {
+    let cap_add = limits.capabilities()?;
+
+    Ok(HostConfig {
+        memory: Some(limits.memory_bytes as i64),
+        memory_swap: Some(limits.memory_bytes as i64), // No swap
+        cpu_quota: Some(limits.cpu_quota),
+        cpu_period: Some(100000),
+        pids_limit: Some(limits.pids_limit),
+        network_mode: Some(if limits.network_enabled {
+            "bridge".to_string()
+        } else {
+            "none".to_string()
+        }),
+        readonly_rootfs: Some(true),
+        cap_drop: Some(vec!["ALL".to_string()]),
+        cap_add: Some(cap_add),
+        security_opt: Some(vec!["no-new-privileges:true".to_string()]),
+        ulimits: Some(vec![
+            ResourcesUlimits {
+                name: Some("nofile".to_string()),
+                soft: Some(1024),
+                hard: Some(1024),
+            },
+            ResourcesUlimits {
+                name: Some("nproc".to_string()),
+                soft: Some(limits.pids_limit),
+                hard: Some(limits.pids_limit),
+            },
+        ]),
+        tmpfs: Some(profile.tmpfs_mounts()),
+        ..Default::default()
+    })
+}
+
+/// Default time an image pull may go without progress before it is aborted.
+pub const DEFAULT_PULL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Drive an image pull stream to completion.
+///
+/// Fails with a `TimedOut` I/O error if the stream produces no progress for
+/// `stall_timeout`. The stream is dropped on return, which cancels the pull;
+/// layers that finished downloading stay cached, so the next attempt only
+/// fetches what is missing.
+pub async fn drain_pull<S>(stream: S, stall_timeout: Duration) -> Result<(), bollard::errors::Error>
+where
+    S: Stream<Item = Result<CreateImageInfo, bollard::errors::Error>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    loop {
+        match tokio::time::timeout(stall_timeout, stream.next()).await {
+            Ok(Some(result)) => {
+                result?;
+            }
+            Ok(None) => return Ok(()),
+            Err(_) => {
+                return Err(bollard::errors::Error::IOError {
+                    err: std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!(
+                            "Image pull stalled for more than {}s",
+                            stall_timeout.as_secs_f32()
+                        ),
+                    ),
+                })
+            }
+        }
+    }
+}
+
+/// Check that a started container will not run code as root.
+///
+/// `probe` reports the output of `id -u` run in the container as user code
+/// would be. An image without the sandbox user can leave Docker running
+/// everything as root, so uid 0 is refused; output that isn't a uid is
+/// refused too, since the user cannot be verified.
+pub async fn ensure_not_root<F, Fut>(probe: F) -> Result<(), SandboxError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String, bollard::errors::Error>>,
+{
+    let output = probe().await?;
+    match output.trim().parse::<u32>() {
+        Ok(0) => Err(SandboxError::InsecureContainer(
+            "container runs as root; does the image define the sandbox user?".into(),
+        )),
+        Ok(_) => Ok(()),
+        Err(_) => Err(SandboxError::InsecureContainer(format!(
+            "could not determine the container's user from {:?}",
+            output.trim()
+        ))),
+    }
+}
 
 /// Manages Docker containers for sandbox execution.
 pub struct ContainerManager {
     docker: Docker,
+    images: ImageOverrides,
+    pull_timeout: Duration,
+    /// Skip the check that containers don't run as root.
+    allow_root: bool,
 }
 
 impl ContainerManager {
     /// Create a new container manager.
     pub fn new() -> Result<Self, bollard::errors::Error> {
+        Self::with_images(ImageOverrides::default())
+    }
+
+    /// Create a container manager using the given image overrides.
+    pub fn with_images(images: ImageOverrides) -> Result<Self, bollard::errors::Error> {
         let docker = Docker::connect_with_local_defaults()?;
-        Ok(Self { docker })
+        Ok(Self {
+            docker,
+            images,
+            pull_timeout: DEFAULT_PULL_TIMEOUT,
+            allow_root: false,
+        })
+    }
+
+    /// Set how long an image pull may stall before it is aborted.
+    pub fn with_pull_timeout(mut self, pull_timeout: Duration) -> Self {
+        self.pull_timeout = pull_timeout;
+        self
+    }
+
+    /// Allow containers whose code runs as root, e.g. for a trusted custom
+    /// image that has no sandbox user.
+    pub fn with_allow_root(mut self, allow_root: bool) -> Self {
+        self.allow_root = allow_root;
+        self
+    }
+
+    /// Resolve the image for a language to a reference and digest.
+    ///
+    /// Pinned images report their configured digest; tag references are
+    /// looked up in the local image store, and have no digest if missing.
+    pub async fn resolve_image(&self, language: Language) -> ImageRef {
+        let mut image = self.images.resolve(language);
+
+        if image.digest.is_none() {
+            if let Ok(inspect) = self.docker.inspect_image(&image.image).await {
+                image.digest = inspect
+                    .repo_digests
+                    .unwrap_or_default()
+                    .first()
+                    .and_then(|repo_digest| ImageRef::parse(repo_digest).digest);
+            }
+        }
+
+        image
     }
 
     /// Pull the sandbox image for a language if not present.
+    ///
+    /// Aborts with a timeout error if the registry stops sending progress
+    /// for longer than the configured pull timeout.
     pub async fn ensure_image(&self, language: Language) -> Result<(), bollard::errors::Error> {
-        let image = language.docker_image();
+        let image = self.images.image(language);
 
         let options = CreateImageOptions {
             from_image: image,
             ..Default::default()
         };
 
-        use futures_util::StreamExt;
-        let mut stream = self.docker.create_image(Some(options), None, None);
-        while let Some(result) = stream.next().await {
-            result?;
-        }
-
-        Ok(())
+        let stream = self.docker.create_image(Some(options), None, None);
+        drain_pull(stream, self.pull_timeout).await
     }
 
-    /// Create and start a new sandbox container.
+    /// Create and start a new sandbox container for code execution.
     pub async fn create_container(
         &self,
         language: Language,
         limits: &ResourceLimits,
-    ) -> Result<String, bollard::errors::Error> {
+    ) -> Result<String, SandboxError> {
+        self.create_container_with_profile(language, limits, ContainerProfile::Execution)
+            .await
+    }
+
+    /// Create and start a new sandbox container with the given filesystem profile.
+    ///
+    /// Fails with [`SandboxError::ImageUnavailable`] if the language's image
+    /// is not present on the Docker host, and with
+    /// [`SandboxError::InsecureContainer`] if the container runs as root
+    /// without that being allowed.
+    pub async fn create_container_with_profile(
+        &self,
+        language: Language,
+        limits: &ResourceLimits,
+        profile: ContainerProfile,
+    ) -> Result<String, SandboxError> {
         let container_name = format!("rustyclint-{}-{}", language.extension(), Uuid::new_v4());
 
-        let host_config = HostConfig {
-            memory: Some(limits.memory_bytes as i64),
-            memory_swap: Some(limits.memory_bytes as i64), // No swap
-            cpu_quota: Some(limits.cpu_quota),
-            cpu_period: Some(100000),
-            pids_limit: Some(limits.pids_limit),
-            network_mode: Some(if limits.network_enabled {
-                "bridge".to_string()
-            } else {
-                "none".to_string()
-            }),
-            readonly_rootfs: Some(true),
-            cap_drop: Some(vec!["ALL".to_string()]),
-            security_opt: Some(vec!["no-new-privileges:true".to_string()]),
-            ulimits: Some(vec![
-                ResourcesUlimits {
-                    name: Some("nofile".to_string()),
-                    soft: Some(1024),
-                    hard: Some(1024),
-                },
-                ResourcesUlimits {
-                    name: Some("nproc".to_string()),
-                    soft: Some(limits.pids_limit),
-                    hard: Some(limits.pids_limit),
-                },
-            ]),
-            tmpfs: Some(HashMap::from([
-                ("/tmp".to_string(), "rw,noexec,nosuid,size=64m,mode=1777".to_string()),
-                ("/code".to_string(), "rw,nosuid,size=32m,mode=1777".to_string()),
-            ])),
-            ..Default::default()
-        };
+        let host_config = host_config(limits, profile).map_err(|message| {
+            bollard::errors::Error::DockerResponseServerError {
+                status_code: 400,
+                message,
+            }
+        })?;
 
         let config = Config {
-            image: Some(language.docker_image().to_string()),
+            image: Some(self.images.image(language).to_string()),
             host_config: Some(host_config),
             working_dir: Some("/code".to_string()),
             user: Some("sandbox".to_string()),
@@ -102,24 +246,119 @@ impl ContainerManager {
             platform: None,
         };
 
-        let response = self.docker.create_container(Some(options), config).await?;
+        let response = self
+            .docker
+            .create_container(Some(options), config)
+            .await
+            .map_err(|e| SandboxError::from_create(language, e))?;
 
         self.docker
             .start_container(&response.id, None::<StartContainerOptions<String>>)
             .await?;
 
+        if let Some(command) = limits.egress_shaping_command() {
+            if let Err(e) = self.shape_egress(&response.id, command).await {
+                // Never hand out a networked container without its limit
+                let _ = self.remove_container(&response.id).await;
+                return Err(e.into());
+            }
+        }
+
+        if !self.allow_root {
+            let probe = || self.exec_stdout(&response.id, vec!["id".into(), "-u".into()]);
+            if let Err(e) = ensure_not_root(probe).await {
+                let _ = self.remove_container(&response.id).await;
+                return Err(e);
+            }
+        }
+
         Ok(response.id)
     }
 
+    /// Run `command` as the container's user and collect its stdout.
+    async fn exec_stdout(
+        &self,
+        container_id: &str,
+        command: Vec<String>,
+    ) -> Result<String, bollard::errors::Error> {
+        let exec = self
+            .docker
+            .create_exec(
+                container_id,
+                CreateExecOptions {
+                    cmd: Some(command),
+                    attach_stdout: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        let mut stdout = Vec::new();
+        if let StartExecResults::Attached { mut output, .. } =
+            self.docker.start_exec(&exec.id, None).await?
+        {
+            while let Some(log) = output.next().await {
+                if let LogOutput::StdOut { message } | LogOutput::Console { message } = log? {
+                    stdout.extend_from_slice(&message);
+                }
+            }
+        }
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    /// Install an egress limit by running `command` as a privileged exec.
+    ///
+    /// Privileges apply to this exec only; user code runs later without them.
+    async fn shape_egress(
+        &self,
+        container_id: &str,
+        command: Vec<String>,
+    ) -> Result<(), bollard::errors::Error> {
+        let exec = self
+            .docker
+            .create_exec(
+                container_id,
+                CreateExecOptions {
+                    cmd: Some(command),
+                    user: Some("root".to_string()),
+                    privileged: Some(true),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        if let StartExecResults::Attached { mut output, .. } =
+            self.docker.start_exec(&exec.id, None).await?
+        {
+            while output.next().await.is_some() {}
+        }
+
+        let exit_code = wait_for_exit_code(
+            || async {
+                let inspect = self.docker.inspect_exec(&exec.id).await?;
+                Ok::<_, bollard::errors::Error>(inspect.exit_code)
+            },
+            EXIT_CODE_RETRIES,
+            EXIT_CODE_RETRY_DELAY,
+        )
+        .await?;
+        match exit_code {
+            Some(0) => Ok(()),
+            code => Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 500,
+                message: format!("Failed to apply egress limit (exit code {:?})", code),
+            }),
+        }
+    }
+
     /// Stop and remove a container.
     pub async fn remove_container(&self, container_id: &str) -> Result<(), bollard::errors::Error> {
         // Stop container with short timeout
         let _ = self
             .docker
-            .stop_container(
-                container_id,
-                Some(StopContainerOptions { t: 5 }),
-            )
+            .stop_container(container_id, Some(StopContainerOptions { t: 5 }))
             .await;
 
         // Remove container
@@ -0,0 +1,102 @@
+//! Tests for container configuration.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bollard::models::CreateImageInfo;
+    use futures_util::{stream, StreamExt};
+
+    use crate::{
+        container::{drain_pull, ensure_not_root, host_config},
+        error::SandboxError,
+        limits::{ContainerProfile, ResourceLimits},
+    };
+
+    #[test]
+    fn test_default_drops_all_capabilities() {
+        let config = host_config(&ResourceLimits::default(), ContainerProfile::Execution).unwrap();
+
+        assert_eq!(config.cap_drop, Some(vec!["ALL".to_string()]));
+        assert_eq!(config.cap_add, Some(vec![]));
+    }
+
+    #[test]
+    fn test_allowed_capability_applied() {
+        let limits = ResourceLimits {
+            cap_add: vec!["CAP_NET_BIND_SERVICE".into()],
+            ..ResourceLimits::default()
+        };
+
+        let config = host_config(&limits, ContainerProfile::Execution).unwrap();
+
+        assert_eq!(config.cap_add, Some(vec!["NET_BIND_SERVICE".to_string()]));
+        assert_eq!(config.cap_drop, Some(vec!["ALL".to_string()]));
+    }
+
+    #[test]
+    fn test_dangerous_capability_rejected() {
+        let limits = ResourceLimits {
+            cap_add: vec!["NET_BIND_SERVICE".into(), "SYS_ADMIN".into()],
+            ..ResourceLimits::default()
+        };
+
+        let error = host_config(&limits, ContainerProfile::Execution).unwrap_err();
+
+        assert!(error.contains("SYS_ADMIN"));
+    }
+
+    #[test]
+    fn test_privilege_capabilities_rejected() {
+        for cap in ["SETUID", "CAP_DAC_OVERRIDE", "SETGID", "CHOWN", "FOWNER"] {
+            let limits = ResourceLimits {
+                cap_add: vec![cap.into()],
+                ..ResourceLimits::default()
+            };
+
+            let error = host_config(&limits, ContainerProfile::Execution).unwrap_err();
+
+            assert!(error.contains(cap), "{} was not rejected", cap);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_pull_times_out() {
+        // One progress event, then the registry goes silent
+        let progress = stream::iter(vec![Ok(CreateImageInfo::default())]);
+        let slow = progress.chain(stream::pending());
+
+        let error = drain_pull(slow, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+
+        match error {
+            bollard::errors::Error::IOError { err } => {
+                assert_eq!(err.kind(), std::io::ErrorKind::TimedOut)
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completed_pull_succeeds() {
+        let events = stream::iter((0..3).map(|_| Ok(CreateImageInfo::default())));
+
+        assert!(drain_pull(events, Duration::from_millis(50)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_root_container_rejected() {
+        let id_u = |output: &'static str| move || async move { Ok(output.to_string()) };
+
+        assert!(ensure_not_root(id_u("1000\n")).await.is_ok());
+
+        let error = ensure_not_root(id_u("0\n")).await.unwrap_err();
+        assert!(matches!(error, SandboxError::InsecureContainer(_)));
+        assert!(error.to_string().contains("root"));
+
+        // A user that can't be verified is refused too
+        let error = ensure_not_root(id_u("id: not found")).await.unwrap_err();
+        assert!(matches!(error, SandboxError::InsecureContainer(_)));
+    }
+}
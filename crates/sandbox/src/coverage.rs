@@ -0,0 +1,152 @@
+//! Line coverage of sandboxed runs.
+//!
+//! Coverage is collected with the language's usual tool, when one works on
+//! a single source file: coverage.py for Python and nyc for JavaScript.
+//! Other languages, and images without the tool, run normally and report no
+//! coverage.
+
+use std::collections::BTreeMap;
+
+use rustyclint_common::models::Language;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Largest coverage report read back from the container.
+pub const MAX_COVERAGE_REPORT_BYTES: usize = 1024 * 1024;
+
+/// Whether one line of the program ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineCoverage {
+    /// 1-based line number.
+    pub line: u32,
+    pub executed: bool,
+}
+
+/// Command running `filename` under the language's coverage tool.
+pub fn coverage_command(language: Language, filename: &str) -> Option<Vec<String>> {
+    let cmd = match language {
+        Language::Python => vec![
+            "python3".to_string(),
+            "-m".to_string(),
+            "coverage".to_string(),
+            "run".to_string(),
+            "--data-file=/tmp/.coverage".to_string(),
+            filename.to_string(),
+        ],
+        Language::JavaScript => vec![
+            "nyc".to_string(),
+            "--silent".to_string(),
+            "--reporter=json".to_string(),
+            "--report-dir=/tmp/coverage".to_string(),
+            "--temp-dir=/tmp/.nyc_output".to_string(),
+            "node".to_string(),
+            filename.to_string(),
+        ],
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+/// Command that succeeds only if the language's coverage tool is installed,
+/// so runs in images without it can fall back to running normally.
+pub fn tool_check_command(language: Language) -> Option<Vec<String>> {
+    let script = match language {
+        Language::Python => "python3 -c 'import coverage'",
+        Language::JavaScript => "command -v nyc",
+        _ => return None,
+    };
+    Some(vec!["sh".to_string(), "-c".to_string(), script.to_string()])
+}
+
+/// Command printing the JSON report of a run started with
+/// [`coverage_command`].
+pub fn report_command(language: Language) -> Option<Vec<String>> {
+    let script = match language {
+        Language::Python => {
+            "python3 -m coverage json -q --data-file=/tmp/.coverage -o /tmp/coverage.json \
+             && cat /tmp/coverage.json"
+        }
+        Language::JavaScript => "cat /tmp/coverage/coverage-final.json",
+        _ => return None,
+    };
+    Some(vec!["sh".to_string(), "-c".to_string(), script.to_string()])
+}
+
+/// Parse a coverage report for the program in `filename`.
+///
+/// Returns `None` if the report is missing, malformed, or does not cover
+/// the file.
+pub fn parse_report(language: Language, filename: &str, report: &str) -> Option<Vec<LineCoverage>> {
+    let report: Value = serde_json::from_str(report).ok()?;
+    match language {
+        Language::Python => parse_coverage_py(&report, filename),
+        Language::JavaScript => parse_istanbul(&report, filename),
+        _ => None,
+    }
+}
+
+/// coverage.py lists executed and missing lines per file.
+fn parse_coverage_py(report: &Value, filename: &str) -> Option<Vec<LineCoverage>> {
+    let (_, file) = report
+        .get("files")?
+        .as_object()?
+        .iter()
+        .find(|(path, _)| is_program(path, filename))?;
+
+    let lines = |key: &str| -> Option<Vec<u32>> {
+        file.get(key)?
+            .as_array()?
+            .iter()
+            .map(|line| line.as_u64().and_then(|line| u32::try_from(line).ok()))
+            .collect()
+    };
+
+    let mut coverage: Vec<_> = lines("executed_lines")?
+        .into_iter()
+        .map(|line| LineCoverage {
+            line,
+            executed: true,
+        })
+        .chain(
+            lines("missing_lines")?
+                .into_iter()
+                .map(|line| LineCoverage {
+                    line,
+                    executed: false,
+                }),
+        )
+        .collect();
+    coverage.sort_by_key(|entry| entry.line);
+    Some(coverage)
+}
+
+/// Istanbul (nyc) reports hit counts per statement; a line ran if any
+/// statement starting on it did.
+fn parse_istanbul(report: &Value, filename: &str) -> Option<Vec<LineCoverage>> {
+    let (_, file) = report
+        .as_object()?
+        .iter()
+        .find(|(path, _)| is_program(path, filename))?;
+    let statements = file.get("statementMap")?.as_object()?;
+    let hits = file.get("s")?.as_object()?;
+
+    let mut lines = BTreeMap::new();
+    for (id, statement) in statements {
+        let line = statement.pointer("/start/line")?.as_u64()?;
+        let line = u32::try_from(line).ok()?;
+        let executed = hits.get(id).and_then(Value::as_u64).unwrap_or(0) > 0;
+        *lines.entry(line).or_insert(false) |= executed;
+    }
+
+    Some(
+        lines
+            .into_iter()
+            .map(|(line, executed)| LineCoverage { line, executed })
+            .collect(),
+    )
+}
+
+/// Reports may key files by relative or absolute path.
+fn is_program(path: &str, filename: &str) -> bool {
+    path == filename || path.ends_with(&format!("/{}", filename))
+}
@@ -0,0 +1,104 @@
+//! Tests for line coverage.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+    use serde_json::json;
+
+    use crate::{
+        coverage::{
+            coverage_command, parse_report, report_command, tool_check_command, LineCoverage,
+        },
+        ExecutionRequest, SandboxExecutor,
+    };
+
+    fn lines(entries: &[(u32, bool)]) -> Vec<LineCoverage> {
+        entries
+            .iter()
+            .map(|&(line, executed)| LineCoverage { line, executed })
+            .collect()
+    }
+
+    #[test]
+    fn test_coverage_py_report_parsed() {
+        let report = json!({
+            "meta": { "version": "7.4.0" },
+            "files": {
+                "other.py": { "executed_lines": [1], "missing_lines": [] },
+                "/code/main.py": { "executed_lines": [1, 2, 4], "missing_lines": [3] }
+            }
+        });
+
+        let coverage = parse_report(Language::Python, "main.py", &report.to_string()).unwrap();
+        assert_eq!(coverage, lines(&[(1, true), (2, true), (3, false), (4, true)]));
+    }
+
+    #[test]
+    fn test_istanbul_report_parsed() {
+        let report = json!({
+            "/code/main.js": {
+                "statementMap": {
+                    "0": { "start": { "line": 1, "column": 0 }, "end": { "line": 1, "column": 9 } },
+                    "1": { "start": { "line": 3, "column": 2 }, "end": { "line": 3, "column": 9 } },
+                    "2": { "start": { "line": 3, "column": 11 }, "end": { "line": 3, "column": 20 } },
+                    "3": { "start": { "line": 5, "column": 2 }, "end": { "line": 5, "column": 9 } }
+                },
+                "s": { "0": 1, "1": 0, "2": 2, "3": 0 }
+            }
+        });
+
+        // A line with any executed statement counts as executed
+        let coverage =
+            parse_report(Language::JavaScript, "main.js", &report.to_string()).unwrap();
+        assert_eq!(coverage, lines(&[(1, true), (3, true), (5, false)]));
+    }
+
+    #[test]
+    fn test_unusable_reports_yield_none() {
+        assert!(parse_report(Language::Python, "main.py", "not json").is_none());
+        assert!(parse_report(Language::Python, "main.py", r#"{"files":{}}"#).is_none());
+        assert!(parse_report(Language::Rust, "main.rs", "{}").is_none());
+    }
+
+    #[test]
+    fn test_only_supported_languages_have_commands() {
+        assert!(coverage_command(Language::Python, "main.py").is_some());
+        assert!(coverage_command(Language::JavaScript, "main.js").is_some());
+        assert!(coverage_command(Language::Rust, "main.rs").is_none());
+        assert!(report_command(Language::Go).is_none());
+        assert!(tool_check_command(Language::Python).is_some());
+        assert!(tool_check_command(Language::Ruby).is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker
+    async fn test_python_snippet_coverage() {
+        let executor = SandboxExecutor::new().unwrap();
+
+        let result = executor
+            .execute(ExecutionRequest {
+                code: "x = 1\nif x > 1:\n    print('big')\nprint('done')\n".into(),
+                language: Language::Python,
+                stdin: None,
+                stdin_encoding: Default::default(),
+                line_endings: Default::default(),
+                args: vec![],
+                post_run: None,
+                memory_bytes: None,
+                strip_ansi: None,
+                callback_url: None,
+                profile: None,
+                seed: None,
+                coverage: true,
+                combined_output: false,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.stdout.trim_end(), "done");
+        assert_eq!(
+            result.coverage,
+            Some(lines(&[(1, true), (2, true), (3, false), (4, true)]))
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Sandbox error types.
+
+use rustyclint_common::models::Language;
+
+/// Errors from running code in a sandbox.
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    /// The language's sandbox image is not present on the Docker host.
+    #[error("runtime for {} is not installed", .0.display_name())]
+    ImageUnavailable(Language),
+    /// The execution was cancelled before it finished.
+    #[error("execution was cancelled")]
+    Cancelled,
+    /// The request's stdin could not be decoded.
+    #[error("invalid stdin: {0}")]
+    InvalidStdin(String),
+    /// A request named a resource profile that is not configured.
+    #[error("unknown resource profile {0:?}")]
+    UnknownProfile(String),
+    /// A started container would run code as root, or its user could not
+    /// be checked.
+    #[error("refusing to run code: {0}")]
+    InsecureContainer(String),
+    /// Any other Docker failure.
+    #[error(transparent)]
+    Docker(#[from] bollard::errors::Error),
+}
+
+impl SandboxError {
+    /// Classify a Docker error from creating a container for `language`.
+    ///
+    /// Docker answers 404 when the container's image does not exist locally,
+    /// which is an operator misconfiguration rather than a daemon failure.
+    pub fn from_create(language: Language, error: bollard::errors::Error) -> Self {
+        match error {
+            bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            } => Self::ImageUnavailable(language),
+            error => Self::Docker(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for SandboxError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Docker(error.into())
+    }
+}
@@ -0,0 +1,33 @@
+//! Tests for sandbox error classification.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+
+    use crate::error::SandboxError;
+
+    #[test]
+    fn test_missing_image_is_image_unavailable() {
+        let missing = bollard::errors::Error::DockerResponseServerError {
+            status_code: 404,
+            message: "No such image: acrustyclintprod.azurecr.io/sandbox-rust:latest".into(),
+        };
+
+        let error = SandboxError::from_create(Language::Rust, missing);
+
+        assert!(matches!(error, SandboxError::ImageUnavailable(Language::Rust)));
+        assert_eq!(error.to_string(), "runtime for Rust is not installed");
+    }
+
+    #[test]
+    fn test_other_docker_errors_pass_through() {
+        let failure = bollard::errors::Error::DockerResponseServerError {
+            status_code: 500,
+            message: "driver failed".into(),
+        };
+
+        let error = SandboxError::from_create(Language::Rust, failure);
+
+        assert!(matches!(error, SandboxError::Docker(_)));
+    }
+}
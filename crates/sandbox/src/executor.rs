@@ -1,12 +1,34 @@
 //! Code execution within sandbox containers.
 
-use std::time::{Duration, Instant};
+use std::{
+    collections::HashMap,
+    future::Future,
+    time::{Duration, Instant},
+};
 
 use bollard::exec::{CreateExecOptions, StartExecResults};
 use rustyclint_common::models::Language;
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use uuid::Uuid;
 
-use crate::{container::ContainerManager, limits::ResourceLimits};
+use crate::{
+    ansi::strip_ansi,
+    container::ContainerManager,
+    coverage::{
+        coverage_command, parse_report, report_command, tool_check_command, LineCoverage,
+        MAX_COVERAGE_REPORT_BYTES,
+    },
+    error::SandboxError,
+    images::{ImageOverrides, ImageRef},
+    limits::ResourceLimits,
+    output::{CappedOutput, CombinedOutput, OutputChunk, StdStream, TRUNCATION_MARKER},
+    pool::{reset_command, ContainerPool},
+    seed::{seed_env, seeded_command},
+    stdin::{decode_stdin, LineEndings, StdinEncoding},
+    toolchain::{parse_probe_output, probe_command, ToolchainInfo},
+    tracker::{ExecutionHandle, ExecutionTracker},
+};
 
 /// Request to execute code in a sandbox.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,7 +36,54 @@ pub struct ExecutionRequest {
     pub code: String,
     pub language: Language,
     pub stdin: Option<String>,
+    /// How `stdin` is encoded; base64 allows binary input.
+    #[serde(default)]
+    pub stdin_encoding: StdinEncoding,
+    /// Line-ending normalization applied to the decoded input.
+    #[serde(default)]
+    pub line_endings: LineEndings,
     pub args: Vec<String>,
+    /// Command run in the same container after the main program, e.g. to
+    /// collect coverage. Its output is reported separately.
+    #[serde(default)]
+    pub post_run: Option<Vec<String>>,
+    /// Explicit memory limit in bytes; overrides the per-language default.
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+    /// Strip ANSI escape sequences from stdout and stderr. Output is
+    /// returned unchanged by default.
+    #[serde(default)]
+    pub strip_ansi: Option<bool>,
+    /// URL the gateway POSTs the signed result to when the run completes.
+    /// Not used by the executor itself.
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Named resource-limit profile to run under, resolved against the
+    /// executor's configured profiles. Unset uses the executor's limits.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Fix sources of nondeterminism where the language allows; see
+    /// [`crate::seed`] for what is covered.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Run under the language's coverage tool and report which lines ran;
+    /// see [`crate::coverage`] for the supported languages.
+    #[serde(default)]
+    pub coverage: bool,
+    /// Also report stdout and stderr interleaved in arrival order, in
+    /// [`ExecutionResult::combined_output`].
+    #[serde(default)]
+    pub combined_output: bool,
+}
+
+impl ExecutionRequest {
+    /// Decoded and normalized bytes to feed the program, if any.
+    pub fn stdin_bytes(&self) -> Result<Option<Vec<u8>>, String> {
+        self.stdin
+            .as_deref()
+            .map(|stdin| decode_stdin(stdin, self.stdin_encoding, self.line_endings))
+            .transpose()
+    }
 }
 
 /// Result of code execution.
@@ -22,15 +91,291 @@ pub struct ExecutionRequest {
 pub struct ExecutionResult {
     pub stdout: String,
     pub stderr: String,
-    pub exit_code: i64,
+    /// Program exit code; `None` if the daemon never reported one, e.g.
+    /// because the container died or the run timed out.
+    pub exit_code: Option<i64>,
     pub execution_time_ms: u64,
     pub timed_out: bool,
+    /// Whether stdout, stderr or the compiler's output hit
+    /// `max_output_bytes`, or the result as a whole hit the executor's
+    /// result size cap, and was cut short.
+    #[serde(default)]
+    pub truncated: bool,
+    /// Per-line coverage when requested and supported for the language.
+    #[serde(default)]
+    pub coverage: Option<Vec<LineCoverage>>,
+    /// Compiler diagnostics, for languages compiled before they run.
+    #[serde(default)]
+    pub compile_stderr: Option<String>,
+    /// Compilation failed, so the program never ran; `exit_code` is the
+    /// compiler's.
+    #[serde(default)]
+    pub compile_failed: bool,
+    /// Combined stdout and stderr of the post-run command, if one was given.
+    #[serde(default)]
+    pub post_run_output: Option<String>,
+    /// Image reference the program ran on.
+    #[serde(default)]
+    pub image: String,
+    /// Content digest of that image, for reproducing the run.
+    #[serde(default)]
+    pub image_digest: Option<String>,
+    /// Program output from both streams in the order it was written, when
+    /// requested. `stdout` and `stderr` are still filled in; this is empty if
+    /// the program never ran or timed out.
+    #[serde(default)]
+    pub combined_output: Option<Vec<OutputChunk>>,
+}
+
+impl ExecutionResult {
+    /// Bytes of output the result carries across all its fields.
+    pub fn output_bytes(&self) -> usize {
+        let texts = [
+            Some(&self.stdout),
+            Some(&self.stderr),
+            self.compile_stderr.as_ref(),
+            self.post_run_output.as_ref(),
+        ];
+        let combined = self.combined_output.iter().flatten();
+        texts.into_iter().flatten().map(String::len).sum::<usize>()
+            + combined.map(|chunk| chunk.data.len()).sum::<usize>()
+    }
+
+    /// Cut the output down to `max_bytes` in total, setting `truncated` if
+    /// anything was cut.
+    ///
+    /// Output is kept in order of importance: stdout, stderr, compiler
+    /// diagnostics, post-run output, then the combined output, which only
+    /// repeats the two streams.
+    pub fn cap_size(&mut self, max_bytes: usize) {
+        if self.output_bytes() <= max_bytes {
+            return;
+        }
+
+        let mut budget = max_bytes;
+        let texts = [
+            Some(&mut self.stdout),
+            Some(&mut self.stderr),
+            self.compile_stderr.as_mut(),
+            self.post_run_output.as_mut(),
+        ];
+        for text in texts.into_iter().flatten() {
+            truncate_text(text, &mut budget);
+        }
+        if let Some(chunks) = &mut self.combined_output {
+            chunks.retain_mut(|chunk| {
+                chunk.data.truncate(budget);
+                budget -= chunk.data.len();
+                !chunk.data.is_empty()
+            });
+        }
+        self.truncated = true;
+    }
+}
+
+/// Cut `text` to fit in `budget` bytes, marker included, and deduct what
+/// it keeps from the budget. Once a text is cut the budget is spent, so
+/// later output doesn't keep fragments.
+fn truncate_text(text: &mut String, budget: &mut usize) {
+    if text.len() <= *budget {
+        *budget -= text.len();
+        return;
+    }
+
+    // Too little room left to say the text was cut, so drop it all
+    if *budget < TRUNCATION_MARKER.len() {
+        text.clear();
+    } else {
+        let mut end = *budget - TRUNCATION_MARKER.len();
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str(TRUNCATION_MARKER);
+    }
+    *budget = 0;
+}
+
+/// Time limits for each phase of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseTimeouts {
+    /// Compile step, for languages that have one.
+    pub compile: Option<Duration>,
+    pub run: Duration,
+}
+
+/// Time limits a run of `language` gets under `limits`.
+///
+/// Each phase has its own budget, so a slow compile does not eat into the
+/// time the program has to run. Interpreted languages only get the run
+/// timeout.
+pub fn phase_timeouts(language: Language, limits: &ResourceLimits) -> PhaseTimeouts {
+    let compiles = compile_command(language, &format!("main.{}", language.extension())).is_some();
+    PhaseTimeouts {
+        compile: compiles.then(|| limits.compile_timeout()),
+        run: limits.run_timeout(),
+    }
+}
+
+/// Command compiling `filename` for languages built before they run.
+///
+/// Its output goes to [`ExecutionResult::compile_stderr`]; the matching run
+/// command only starts the built program.
+pub fn compile_command(language: Language, filename: &str) -> Option<Vec<String>> {
+    let cmd = match language {
+        Language::Rust => vec![
+            "rustc".to_string(),
+            filename.to_string(),
+            "-o".to_string(),
+            "/tmp/out".to_string(),
+        ],
+        Language::Java => vec!["javac".to_string(), filename.to_string()],
+        Language::Cpp => vec![
+            "g++".to_string(),
+            filename.to_string(),
+            "-o".to_string(),
+            "/tmp/out".to_string(),
+        ],
+        Language::C => vec![
+            "gcc".to_string(),
+            filename.to_string(),
+            "-o".to_string(),
+            "/tmp/out".to_string(),
+        ],
+        Language::Kotlin => vec![
+            "kotlinc".to_string(),
+            filename.to_string(),
+            "-include-runtime".to_string(),
+            "-d".to_string(),
+            "/tmp/out.jar".to_string(),
+        ],
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+/// Output chunks buffered for a slow streaming consumer before the
+/// execution waits for it.
+pub const OUTPUT_CHANNEL_CAPACITY: usize = 64;
+
+/// How an exec's streams are connected.
+#[derive(Default)]
+struct ExecIo<'a> {
+    /// Bytes written to stdin, which is then closed.
+    stdin: Option<Vec<u8>>,
+    /// Receives output chunks as they arrive.
+    chunks: Option<&'a mpsc::Sender<OutputChunk>>,
+    /// Execution whose tail records the output.
+    handle: Option<&'a ExecutionHandle>,
+    /// Also keep both streams interleaved in arrival order.
+    combined: bool,
+}
+
+/// An exec's output read so far, kept apart from the reading so a deadline
+/// cutting the read short leaves it intact.
+struct CollectedOutput {
+    stdout: CappedOutput,
+    stderr: CappedOutput,
+    combined: Option<CombinedOutput>,
+}
+
+impl CollectedOutput {
+    fn new(max_bytes: usize, combined: bool) -> Self {
+        Self {
+            stdout: CappedOutput::new(max_bytes),
+            stderr: CappedOutput::new(max_bytes),
+            combined: combined.then(CombinedOutput::new),
+        }
+    }
+
+    /// Stdout, stderr, the interleaved streams if kept, and whether either
+    /// stream was truncated.
+    fn finish(self) -> (String, String, Option<Vec<OutputChunk>>, bool) {
+        let (stdout, stdout_truncated) = self.stdout.finish();
+        let (stderr, stderr_truncated) = self.stderr.finish();
+        (
+            stdout,
+            stderr,
+            self.combined.map(CombinedOutput::finish),
+            stdout_truncated || stderr_truncated,
+        )
+    }
+}
+
+/// Outcome of one exec run to completion or to the deadline.
+struct ExecOutput {
+    stdout: String,
+    stderr: String,
+    /// Both streams in arrival order, if requested.
+    combined: Option<Vec<OutputChunk>>,
+    exit_code: Option<i64>,
+    truncated: bool,
+    timed_out: bool,
+}
+
+/// Extra inspections when the daemon has not recorded an exit code yet.
+pub const EXIT_CODE_RETRIES: u32 = 3;
+
+/// Delay between exit code inspections.
+pub const EXIT_CODE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Inspect an exec until it reports an exit code, retrying a few times
+/// since the daemon can lag behind the end of the output stream.
+pub async fn wait_for_exit_code<F, Fut, E>(
+    mut inspect: F,
+    retries: u32,
+    delay: Duration,
+) -> Result<Option<i64>, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<i64>, E>>,
+{
+    for attempt in 0..=retries {
+        if let Some(code) = inspect().await? {
+            return Ok(Some(code));
+        }
+        if attempt < retries {
+            tokio::time::sleep(delay).await;
+        }
+    }
+    Ok(None)
+}
+
+/// Timeout for the post-run command, independent of the main timeout.
+pub const POST_RUN_TIMEOUT_SECS: u64 = 5;
+
+/// Maximum total size of a post-run command line.
+pub const MAX_POST_RUN_BYTES: usize = 4096;
+
+/// Validate a post-run command before it is executed.
+pub fn validate_post_run(command: &[String]) -> Result<(), String> {
+    match command.first() {
+        None => return Err("Post-run command cannot be empty".into()),
+        Some(program) if program.trim().is_empty() => {
+            return Err("Post-run command cannot be empty".into())
+        }
+        _ => {}
+    }
+
+    if command.iter().map(|arg| arg.len()).sum::<usize>() > MAX_POST_RUN_BYTES {
+        return Err("Post-run command too large (max 4KB)".into());
+    }
+
+    if command.iter().any(|arg| arg.contains('\0')) {
+        return Err("Post-run command contains NUL bytes".into());
+    }
+
+    Ok(())
 }
 
 /// Executes code in sandbox containers.
 pub struct SandboxExecutor {
     manager: ContainerManager,
     limits: ResourceLimits,
+    profiles: HashMap<String, ResourceLimits>,
+    tracker: ExecutionTracker,
+    pool: Option<ContainerPool>,
+    max_result_bytes: Option<usize>,
 }
 
 impl SandboxExecutor {
@@ -39,6 +384,10 @@ impl SandboxExecutor {
         Ok(Self {
             manager: ContainerManager::new()?,
             limits: ResourceLimits::default(),
+            profiles: HashMap::new(),
+            tracker: ExecutionTracker::new(),
+            pool: None,
+            max_result_bytes: None,
         })
     }
 
@@ -47,29 +396,524 @@ impl SandboxExecutor {
         Ok(Self {
             manager: ContainerManager::new()?,
             limits,
+            profiles: HashMap::new(),
+            tracker: ExecutionTracker::new(),
+            pool: None,
+            max_result_bytes: None,
+        })
+    }
+
+    /// Create an executor with custom limits and image overrides.
+    pub fn with_images(
+        limits: ResourceLimits,
+        images: ImageOverrides,
+    ) -> Result<Self, bollard::errors::Error> {
+        Ok(Self {
+            manager: ContainerManager::with_images(images)?,
+            limits,
+            profiles: HashMap::new(),
+            tracker: ExecutionTracker::new(),
+            pool: None,
+            max_result_bytes: None,
         })
     }
 
+    /// Named resource-limit profiles requests may select.
+    pub fn with_profiles(mut self, profiles: HashMap<String, ResourceLimits>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// Registry that user-attributed executions are tracked in.
+    pub fn with_tracker(mut self, tracker: ExecutionTracker) -> Self {
+        self.tracker = tracker;
+        self
+    }
+
+    /// Allow containers whose code runs as root; see
+    /// [`ContainerManager::with_allow_root`].
+    pub fn with_allow_root(mut self, allow_root: bool) -> Self {
+        self.manager = self.manager.with_allow_root(allow_root);
+        self
+    }
+
+    /// Set how long an image pull may stall; see
+    /// [`ContainerManager::with_pull_timeout`].
+    pub fn with_pull_timeout(mut self, pull_timeout: Duration) -> Self {
+        self.manager = self.manager.with_pull_timeout(pull_timeout);
+        self
+    }
+
+    /// Keep up to `size` used containers per language warm for later runs
+    /// instead of removing them; 0 disables pooling.
+    pub fn with_pool(mut self, size: usize) -> Self {
+        self.pool = (size > 0).then(|| ContainerPool::new(size));
+        self
+    }
+
+    /// Cap each result's output at `max_bytes` in total, see
+    /// [`ExecutionResult::cap_size`]; 0 leaves results uncapped.
+    pub fn with_max_result_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_result_bytes = (max_bytes > 0).then_some(max_bytes);
+        self
+    }
+
+    /// Limits a request runs under: its named profile, or the executor's
+    /// limits if it names none, adjusted for the language.
+    pub fn limits_for(&self, request: &ExecutionRequest) -> Result<ResourceLimits, SandboxError> {
+        let base = match request.profile.as_deref() {
+            Some(name) => ResourceLimits::profile(Some(name), &self.profiles)?,
+            None => self.limits.clone(),
+        };
+        Ok(base.for_language(request.language, request.memory_bytes))
+    }
+
     /// Execute code and return results.
     pub async fn execute(
         &self,
         request: ExecutionRequest,
-    ) -> Result<ExecutionResult, bollard::errors::Error> {
+    ) -> Result<ExecutionResult, SandboxError> {
+        // The buffered result carries the output; nobody reads the chunks
+        let (_, execution) = self.execute_streaming(request);
+        execution.await
+    }
+
+    /// Execute code, streaming the program's output as it arrives.
+    ///
+    /// Returns the chunk receiver and the execution, which must be driven
+    /// for chunks to flow; it resolves to the same result as
+    /// [`Self::execute`], buffered output included. Only the program's own
+    /// output is streamed, not compiler diagnostics or the post-run
+    /// command, and a stream stops once it reaches `max_output_bytes`.
+    pub fn execute_streaming(
+        &self,
+        request: ExecutionRequest,
+    ) -> (
+        mpsc::Receiver<OutputChunk>,
+        impl Future<Output = Result<ExecutionResult, SandboxError>> + '_,
+    ) {
+        let (sender, receiver) = mpsc::channel(OUTPUT_CHANNEL_CAPACITY);
+        // The sender is dropped when the run ends, closing the receiver
+        let execution = async move { self.run(request, None, Some(&sender)).await };
+        (receiver, execution)
+    }
+
+    /// Execute code on behalf of a user, tracked so that
+    /// [`ExecutionTracker::cancel_user`] can stop it.
+    ///
+    /// A cancelled run returns [`SandboxError::Cancelled`]; its container is
+    /// left to whoever cancelled it.
+    pub async fn execute_for_user(
+        &self,
+        user_id: Uuid,
+        request: ExecutionRequest,
+    ) -> Result<ExecutionResult, SandboxError> {
+        self.execute_tracked(self.tracker.register(user_id), request)
+            .await
+    }
+
+    /// Like [`Self::execute_for_user`], under a handle registered with the
+    /// executor's tracker beforehand so its id can be given out while the
+    /// run is in flight, e.g. to read its tail.
+    pub async fn execute_tracked(
+        &self,
+        handle: ExecutionHandle,
+        request: ExecutionRequest,
+    ) -> Result<ExecutionResult, SandboxError> {
+        self.run_tracked(request, handle, None).await
+    }
+
+    /// [`Self::execute_streaming`] for an execution registered with the
+    /// executor's tracker.
+    pub fn execute_streaming_tracked(
+        &self,
+        request: ExecutionRequest,
+        handle: ExecutionHandle,
+    ) -> (
+        mpsc::Receiver<OutputChunk>,
+        impl Future<Output = Result<ExecutionResult, SandboxError>> + '_,
+    ) {
+        let (sender, receiver) = mpsc::channel(OUTPUT_CHANNEL_CAPACITY);
+        let execution = async move { self.run_tracked(request, handle, Some(&sender)).await };
+        (receiver, execution)
+    }
+
+    async fn run_tracked(
+        &self,
+        request: ExecutionRequest,
+        handle: ExecutionHandle,
+        chunks: Option<&mpsc::Sender<OutputChunk>>,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let cancelled = async {
+            handle.cancelled().await;
+            // Until a container is attached, `run` notices the cancellation
+            // itself and removes anything it created
+            if !handle.has_container() {
+                std::future::pending::<()>().await;
+            }
+        };
+        tokio::select! {
+            result = self.run(request, Some(&handle), chunks) => result,
+            _ = cancelled => Err(SandboxError::Cancelled),
+        }
+    }
+
+    async fn run(
+        &self,
+        request: ExecutionRequest,
+        handle: Option<&ExecutionHandle>,
+        chunks: Option<&mpsc::Sender<OutputChunk>>,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let mut result = self.run_program(request, handle, chunks).await?;
+        if let Some(max_bytes) = self.max_result_bytes {
+            result.cap_size(max_bytes);
+        }
+        Ok(result)
+    }
+
+    async fn run_program(
+        &self,
+        request: ExecutionRequest,
+        handle: Option<&ExecutionHandle>,
+        chunks: Option<&mpsc::Sender<OutputChunk>>,
+    ) -> Result<ExecutionResult, SandboxError> {
         let start = Instant::now();
+        let limits = self.limits_for(&request)?;
+        let stdin = request
+            .stdin_bytes()
+            .map_err(SandboxError::InvalidStdin)?
+            .unwrap_or_default();
+        let image = self.manager.resolve_image(request.language).await;
 
-        // Create container
-        let container_id = self
-            .manager
-            .create_container(request.language, &self.limits)
-            .await?;
+        let container_id = self.acquire_container(request.language, &limits).await?;
 
-        // Write code to container
-        let filename = format!("main.{}", request.language.extension());
-        let write_cmd = vec![
-            "sh".to_string(),
-            "-c".to_string(),
-            format!("cat > /code/{}", filename),
-        ];
+        // Cancelled while the container was being created
+        if handle.is_some_and(|handle| !handle.attach_container(&container_id)) {
+            let _ = self.manager.remove_container(&container_id).await;
+            return Err(SandboxError::Cancelled);
+        }
+
+        // Anything failing from here on must not leak the container
+        let language = request.language;
+        let outcome = async {
+            // Write code to container. Anything already at the path is removed
+            // first, so a symlink planted there is replaced rather than followed
+            let filename = format!("main.{}", request.language.extension());
+            let write_cmd = vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!("rm -f /code/{0} && cat > /code/{0}", filename),
+            ];
+
+            let exec = self
+                .manager
+                .docker()
+                .create_exec(
+                    &container_id,
+                    CreateExecOptions {
+                        cmd: Some(write_cmd),
+                        attach_stdin: Some(true),
+                        attach_stdout: Some(true),
+                        attach_stderr: Some(true),
+                        working_dir: Some("/code".to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+            if let StartExecResults::Attached {
+                mut input,
+                mut output,
+            } = self.manager.docker().start_exec(&exec.id, None).await?
+            {
+                use futures_util::StreamExt;
+                use tokio::io::AsyncWriteExt;
+
+                // Write code to stdin
+                input.write_all(request.code.as_bytes()).await?;
+                input.shutdown().await?;
+
+                // Wait for the write command to complete by consuming the output stream
+                while let Some(_) = output.next().await {}
+            }
+
+            let timeouts = phase_timeouts(request.language, &limits);
+            let strip = request.strip_ansi.unwrap_or(false);
+
+            // Compile as a separate step so diagnostics are reported apart from
+            // the program's output
+            let mut compile_stderr = None;
+            let mut compile_truncated = false;
+            if let (Some(compile_cmd), Some(compile_timeout)) = (
+                compile_command(request.language, &filename),
+                timeouts.compile,
+            ) {
+                let compiled = self
+                    .exec_until(
+                        &container_id,
+                        compile_cmd,
+                        None,
+                        ExecIo::default(),
+                        limits.max_output_bytes,
+                        tokio::time::Instant::now() + compile_timeout,
+                    )
+                    .await?;
+
+                let compile_failed = !compiled.timed_out && compiled.exit_code != Some(0);
+                let output = if strip {
+                    strip_ansi(&compiled.stderr)
+                } else {
+                    compiled.stderr
+                };
+
+                if compiled.timed_out || compile_failed {
+                    // A timed-out compile reports like a timed-out run
+                    let (stderr, compile_stderr) = if compile_failed {
+                        (String::new(), Some(output))
+                    } else {
+                        (output, None)
+                    };
+                    let result = ExecutionResult {
+                        stdout: String::new(),
+                        stderr,
+                        exit_code: compiled.exit_code,
+                        execution_time_ms: start.elapsed().as_millis() as u64,
+                        timed_out: compiled.timed_out,
+                        truncated: compiled.truncated,
+                        coverage: None,
+                        compile_stderr,
+                        compile_failed,
+                        post_run_output: None,
+                        image: image.image,
+                        image_digest: image.digest,
+                        combined_output: request.combined_output.then(Vec::new),
+                    };
+                    return Ok((result, !compiled.timed_out));
+                }
+                compile_stderr = Some(output);
+                compile_truncated = compiled.truncated;
+            }
+
+            // Build execution command based on language
+            let coverage = request.coverage
+                && self
+                    .coverage_available(&container_id, request.language)
+                    .await;
+            let run_cmd = self.build_run_command(&request, &filename, coverage);
+            let ExecOutput {
+                stdout,
+                stderr,
+                combined,
+                exit_code,
+                truncated,
+                timed_out,
+            } = self
+                .exec_until(
+                    &container_id,
+                    run_cmd,
+                    request.seed.map(|seed| seed_env(request.language, seed)),
+                    ExecIo {
+                        stdin: Some(stdin),
+                        chunks,
+                        handle,
+                        combined: request.combined_output,
+                    },
+                    limits.max_output_bytes,
+                    tokio::time::Instant::now() + timeouts.run,
+                )
+                .await?;
+
+            let (stdout, stderr) = if strip {
+                (strip_ansi(&stdout), strip_ansi(&stderr))
+            } else {
+                (stdout, stderr)
+            };
+            let combined_output = request.combined_output.then(|| {
+                let mut chunks = combined.unwrap_or_default();
+                if strip {
+                    // Only chunks of whole characters; others are left as they are
+                    for chunk in &mut chunks {
+                        if let Ok(text) = std::str::from_utf8(&chunk.data) {
+                            chunk.data = strip_ansi(text).into_bytes();
+                        }
+                    }
+                }
+                chunks
+            });
+
+            // A timed-out run never wrote its coverage data
+            let coverage = if coverage && !timed_out {
+                self.collect_coverage(&container_id, request.language, &filename)
+                    .await
+            } else {
+                None
+            };
+
+            // Run post-run command in the same container
+            let post_run_output = match request.post_run {
+                Some(post_run) => Some(
+                    self.run_post_command(&container_id, post_run, limits.max_output_bytes)
+                        .await?,
+                ),
+                None => None,
+            };
+
+            let execution_time_ms = start.elapsed().as_millis() as u64;
+
+            let result = ExecutionResult {
+                stdout,
+                stderr,
+                exit_code,
+                execution_time_ms,
+                timed_out,
+                truncated: truncated || compile_truncated,
+                coverage,
+                compile_stderr,
+                compile_failed: false,
+                post_run_output,
+                image: image.image,
+                image_digest: image.digest,
+                combined_output,
+            };
+            // A timed-out program may still be running, so never reuse its container
+            Ok((result, !timed_out))
+        }
+        .await;
+
+        match outcome {
+            Ok((result, reusable)) => {
+                self.release_container(language, &limits, container_id, reusable, handle)
+                    .await;
+                Ok(result)
+            }
+            Err(e) => {
+                self.release_container(language, &limits, container_id, false, handle)
+                    .await;
+                Err(e)
+            }
+        }
+    }
+
+    /// A running container for `language` with `limits`: a pooled one if
+    /// available, else a new one.
+    async fn acquire_container(
+        &self,
+        language: Language,
+        limits: &ResourceLimits,
+    ) -> Result<String, SandboxError> {
+        if let Some(pool) = &self.pool {
+            while let Some(container_id) = pool.take(language, limits) {
+                // Pooled containers can die while idle, e.g. on a daemon restart
+                let running = self
+                    .manager
+                    .docker()
+                    .inspect_container(&container_id, None)
+                    .await
+                    .ok()
+                    .and_then(|inspect| inspect.state?.running)
+                    .unwrap_or(false);
+                if running {
+                    return Ok(container_id);
+                }
+                let _ = self.manager.remove_container(&container_id).await;
+            }
+        }
+
+        self.manager.create_container(language, limits).await
+    }
+
+    /// Return a finished execution's container to the pool, or remove it.
+    ///
+    /// Only `reusable` containers that reset cleanly are pooled.
+    async fn release_container(
+        &self,
+        language: Language,
+        limits: &ResourceLimits,
+        container_id: String,
+        reusable: bool,
+        handle: Option<&ExecutionHandle>,
+    ) {
+        // A cancelled execution's container is removed by the canceller
+        let detached = handle.is_none_or(ExecutionHandle::detach_container);
+        let container_id = match &self.pool {
+            Some(pool) if reusable && detached && self.reset_container(&container_id).await => {
+                match pool.put(language, limits.clone(), container_id) {
+                    Some(container_id) => container_id,
+                    None => return,
+                }
+            }
+            _ => container_id,
+        };
+
+        let _ = self.manager.remove_container(&container_id).await;
+    }
+
+    /// Clear a used container for the next execution; false if that failed.
+    async fn reset_container(&self, container_id: &str) -> bool {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(POST_RUN_TIMEOUT_SECS);
+        match self
+            // Output is discarded; only the exit code matters
+            .exec_until(
+                container_id,
+                reset_command(),
+                None,
+                ExecIo::default(),
+                1024,
+                deadline,
+            )
+            .await
+        {
+            Ok(output) => output.exit_code == Some(0),
+            Err(e) => {
+                tracing::warn!("Failed to reset container {}: {}", container_id, e);
+                false
+            }
+        }
+    }
+
+    /// Fill the pool for `language` with containers under the executor's
+    /// own limits, returning how many were added.
+    pub async fn prewarm(&self, language: Language) -> Result<usize, SandboxError> {
+        let Some(pool) = &self.pool else {
+            return Ok(0);
+        };
+
+        let limits = self.limits.for_language(language, None);
+        let mut added = 0;
+        while pool.idle(language) < pool.size() {
+            let container_id = self.manager.create_container(language, &limits).await?;
+            if let Some(container_id) = pool.put(language, limits.clone(), container_id) {
+                let _ = self.manager.remove_container(&container_id).await;
+                break;
+            }
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Remove every pooled container, e.g. on shutdown.
+    pub async fn drain_pool(&self) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        for container_id in pool.drain() {
+            let _ = self.manager.remove_container(&container_id).await;
+        }
+    }
+
+    /// Resolve the image used for a language.
+    pub async fn resolve_image(&self, language: Language) -> ImageRef {
+        self.manager.resolve_image(language).await
+    }
+
+    /// Report OS and toolchain versions inside a language's sandbox image.
+    pub async fn probe_environment(
+        &self,
+        language: Language,
+    ) -> Result<ToolchainInfo, SandboxError> {
+        let image = self.manager.resolve_image(language).await;
+        let limits = self.limits.for_language(language, None);
+        let container_id = self.manager.create_container(language, &limits).await?;
 
         let exec = self
             .manager
@@ -77,43 +921,117 @@ impl SandboxExecutor {
             .create_exec(
                 &container_id,
                 CreateExecOptions {
-                    cmd: Some(write_cmd),
-                    attach_stdin: Some(true),
+                    cmd: Some(probe_command(language)),
                     attach_stdout: Some(true),
                     attach_stderr: Some(true),
-                    working_dir: Some("/code".to_string()),
                     ..Default::default()
                 },
             )
-            .await?;
+            .await;
 
-        if let StartExecResults::Attached { mut input, mut output } = self
-            .manager
-            .docker()
-            .start_exec(&exec.id, None)
-            .await?
-        {
-            use futures_util::StreamExt;
-            use tokio::io::AsyncWriteExt;
+        let output = match exec {
+            Ok(exec) => {
+                let timeout = Duration::from_secs(limits.timeout_secs.max(10));
+                match tokio::time::timeout(
+                    timeout,
+                    self.collect_output(&exec.id, limits.max_output_bytes, ExecIo::default()),
+                )
+                .await
+                {
+                    Ok(result) => result.map(|(stdout, _, _, _)| stdout),
+                    Err(_) => Ok(String::new()),
+                }
+            }
+            Err(e) => Err(e),
+        };
+
+        let _ = self.manager.remove_container(&container_id).await;
 
-            // Write code to stdin
-            input.write_all(request.code.as_bytes()).await?;
-            input.shutdown().await?;
+        Ok(parse_probe_output(
+            language,
+            image.image,
+            image.digest,
+            &output?,
+        ))
+    }
 
-            // Wait for the write command to complete by consuming the output stream
-            while let Some(_) = output.next().await {}
+    /// Whether the container has the language's coverage tool; runs without
+    /// it go ahead uncovered.
+    async fn coverage_available(&self, container_id: &str, language: Language) -> bool {
+        let Some(check) = tool_check_command(language) else {
+            return false;
+        };
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(POST_RUN_TIMEOUT_SECS);
+        let checked = self
+            .exec_until(container_id, check, None, ExecIo::default(), 1024, deadline)
+            .await;
+        let available = matches!(checked, Ok(output) if output.exit_code == Some(0));
+        if !available {
+            tracing::warn!("No coverage tool for {:?}, running without it", language);
         }
+        available
+    }
 
-        // Build execution command based on language
-        let run_cmd = self.build_run_command(&request.language, &filename, &request.args);
+    /// Read back the coverage report of a run, if the language has one.
+    ///
+    /// Failures are logged and reported as no coverage rather than failing
+    /// the run.
+    async fn collect_coverage(
+        &self,
+        container_id: &str,
+        language: Language,
+        filename: &str,
+    ) -> Option<Vec<LineCoverage>> {
+        let exec = self
+            .manager
+            .docker()
+            .create_exec(
+                container_id,
+                CreateExecOptions {
+                    cmd: Some(report_command(language)?),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    working_dir: Some("/code".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .ok()?;
+
+        let timeout = Duration::from_secs(POST_RUN_TIMEOUT_SECS);
+        let collected = tokio::time::timeout(
+            timeout,
+            self.collect_output(&exec.id, MAX_COVERAGE_REPORT_BYTES, ExecIo::default()),
+        )
+        .await;
+        match collected {
+            Ok(Ok((report, _, _, false))) => {
+                let coverage = parse_report(language, filename, &report);
+                if coverage.is_none() {
+                    tracing::warn!("Unreadable coverage report for {:?}", language);
+                }
+                coverage
+            }
+            _ => {
+                tracing::warn!("Could not collect coverage for {:?}", language);
+                None
+            }
+        }
+    }
 
+    async fn run_post_command(
+        &self,
+        container_id: &str,
+        command: Vec<String>,
+        max_output_bytes: usize,
+    ) -> Result<String, bollard::errors::Error> {
         let exec = self
             .manager
             .docker()
             .create_exec(
-                &container_id,
+                container_id,
                 CreateExecOptions {
-                    cmd: Some(run_cmd),
+                    cmd: Some(command),
                     attach_stdout: Some(true),
                     attach_stderr: Some(true),
                     working_dir: Some("/code".to_string()),
@@ -122,81 +1040,69 @@ impl SandboxExecutor {
             )
             .await?;
 
-        // Execute with timeout
-        let timeout = Duration::from_secs(self.limits.timeout_secs);
-        let (stdout, stderr, timed_out) = match tokio::time::timeout(
+        let timeout = Duration::from_secs(POST_RUN_TIMEOUT_SECS);
+        match tokio::time::timeout(
             timeout,
-            self.collect_output(&exec.id),
+            self.collect_output(&exec.id, max_output_bytes, ExecIo::default()),
         )
         .await
         {
             Ok(result) => {
-                let (stdout, stderr) = result?;
-                (stdout, stderr, false)
+                let (stdout, stderr, _, _) = result?;
+                Ok(stdout + &stderr)
             }
-            Err(_) => {
-                // Timeout occurred
-                (String::new(), "Execution timed out".to_string(), true)
-            }
-        };
-
-        // Get exit code
-        let inspect = self.manager.docker().inspect_exec(&exec.id).await?;
-        let exit_code = inspect.exit_code.unwrap_or(-1);
-
-        // Clean up container
-        let _ = self.manager.remove_container(&container_id).await;
-
-        let execution_time_ms = start.elapsed().as_millis() as u64;
-
-        Ok(ExecutionResult {
-            stdout,
-            stderr,
-            exit_code,
-            execution_time_ms,
-            timed_out,
-        })
+            Err(_) => Ok("Post-run command timed out".to_string()),
+        }
     }
 
     fn build_run_command(
         &self,
-        language: &Language,
+        request: &ExecutionRequest,
         filename: &str,
-        args: &[String],
+        coverage: bool,
     ) -> Vec<String> {
+        let language = &request.language;
+        let args = &request.args;
+
+        // Coverage takes precedence over the seeding wrapper
+        let wrapped = if coverage {
+            coverage_command(*language, filename)
+        } else {
+            None
+        }
+        .or_else(|| {
+            request
+                .seed
+                .and_then(|_| seeded_command(*language, filename))
+        });
+        if let Some(mut cmd) = wrapped {
+            cmd.extend(args.iter().cloned());
+            return cmd;
+        }
+
         let mut cmd = match language {
             Language::Python => vec!["python3".to_string(), filename.to_string()],
             Language::JavaScript => vec!["node".to_string(), filename.to_string()],
-            Language::TypeScript => vec!["npx".to_string(), "ts-node".to_string(), filename.to_string()],
-            Language::Rust => vec![
-                "sh".to_string(),
-                "-c".to_string(),
-                format!("rustc {} -o /tmp/out && /tmp/out", filename),
+            Language::TypeScript => vec![
+                "npx".to_string(),
+                "ts-node".to_string(),
+                filename.to_string(),
             ],
+            Language::Rust | Language::Cpp | Language::C => vec!["/tmp/out".to_string()],
             Language::Go => vec!["go".to_string(), "run".to_string(), filename.to_string()],
-            Language::Java => vec![
-                "sh".to_string(),
-                "-c".to_string(),
-                format!("javac {} && java Main", filename),
-            ],
-            Language::CSharp => vec!["dotnet".to_string(), "script".to_string(), filename.to_string()],
-            Language::Cpp => vec![
-                "sh".to_string(),
-                "-c".to_string(),
-                format!("g++ {} -o /tmp/out && /tmp/out", filename),
-            ],
-            Language::C => vec![
-                "sh".to_string(),
-                "-c".to_string(),
-                format!("gcc {} -o /tmp/out && /tmp/out", filename),
+            Language::Java => vec!["java".to_string(), "Main".to_string()],
+            Language::CSharp => vec![
+                "dotnet".to_string(),
+                "script".to_string(),
+                filename.to_string(),
             ],
             Language::Ruby => vec!["ruby".to_string(), filename.to_string()],
             Language::Php => vec!["php".to_string(), filename.to_string()],
             Language::Swift => vec!["swift".to_string(), filename.to_string()],
             Language::Kotlin => vec![
-                "sh".to_string(),
-                "-c".to_string(),
-                format!("kotlinc {} -include-runtime -d /tmp/out.jar && java -jar /tmp/out.jar", filename),
+                "java".to_string(),
+                "-jar".to_string(),
+                "/tmp/out.jar".to_string(),
             ],
         };
 
@@ -204,38 +1110,173 @@ impl SandboxExecutor {
         cmd
     }
 
+    /// Run a command in the container until it exits or `deadline` passes.
+    async fn exec_until(
+        &self,
+        container_id: &str,
+        cmd: Vec<String>,
+        env: Option<Vec<String>>,
+        io: ExecIo<'_>,
+        max_bytes: usize,
+        deadline: tokio::time::Instant,
+    ) -> Result<ExecOutput, SandboxError> {
+        let exec = self
+            .manager
+            .docker()
+            .create_exec(
+                container_id,
+                CreateExecOptions {
+                    cmd: Some(cmd),
+                    env,
+                    attach_stdin: Some(io.stdin.is_some()),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    working_dir: Some("/code".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        // Output read before the deadline is kept even if the run times out
+        let mut collected = CollectedOutput::new(max_bytes, io.combined);
+        let read = self.collect_into(&exec.id, io, &mut collected);
+        let timed_out = match tokio::time::timeout_at(deadline, read).await {
+            Ok(result) => {
+                result?;
+                false
+            }
+            Err(_) => true,
+        };
+        let (stdout, mut stderr, combined, truncated) = collected.finish();
+        if timed_out {
+            if !stderr.is_empty() && !stderr.ends_with('\n') {
+                stderr.push('\n');
+            }
+            stderr.push_str("Execution timed out");
+        }
+
+        // Get exit code; a timed-out exec is still running, so don't wait for it
+        let retries = if timed_out { 0 } else { EXIT_CODE_RETRIES };
+        let exit_code = wait_for_exit_code(
+            || async {
+                let inspect = self.manager.docker().inspect_exec(&exec.id).await?;
+                Ok::<_, bollard::errors::Error>(inspect.exit_code)
+            },
+            retries,
+            EXIT_CODE_RETRY_DELAY,
+        )
+        .await?;
+        if exit_code.is_none() && !timed_out {
+            tracing::warn!("No exit code reported for exec {}", exec.id);
+        }
+
+        Ok(ExecOutput {
+            stdout,
+            stderr,
+            combined,
+            exit_code,
+            truncated,
+            timed_out,
+        })
+    }
+
+    /// Read an exec's stdout and stderr, each capped at `max_bytes`.
+    ///
+    /// With `io.stdin`, the exec must have been created with stdin attached;
+    /// the bytes are written and the stream closed so readers see EOF, even
+    /// when there is nothing to send. With `io.chunks`, output is also
+    /// forwarded as it arrives until its stream hits the cap. Reading stops
+    /// early once either output stream has hit the cap, so a program
+    /// flooding one stream is not read until its deadline. With
+    /// `io.combined`, both streams are also returned interleaved in arrival
+    /// order. The flag reports whether either was truncated.
     async fn collect_output(
         &self,
         exec_id: &str,
-    ) -> Result<(String, String), bollard::errors::Error> {
+        max_bytes: usize,
+        io: ExecIo<'_>,
+    ) -> Result<(String, String, Option<Vec<OutputChunk>>, bool), bollard::errors::Error> {
+        let mut collected = CollectedOutput::new(max_bytes, io.combined);
+        self.collect_into(exec_id, io, &mut collected).await?;
+        Ok(collected.finish())
+    }
+
+    /// [`Self::collect_output`] into `collected`, which keeps what was read
+    /// if this is dropped partway.
+    async fn collect_into(
+        &self,
+        exec_id: &str,
+        io: ExecIo<'_>,
+        collected: &mut CollectedOutput,
+    ) -> Result<(), bollard::errors::Error> {
         use futures_util::StreamExt;
+        use tokio::io::AsyncWriteExt;
 
-        let mut stdout = Vec::new();
-        let mut stderr = Vec::new();
+        let CollectedOutput {
+            stdout,
+            stderr,
+            combined,
+        } = collected;
 
-        if let StartExecResults::Attached { mut output, .. } = self
-            .manager
-            .docker()
-            .start_exec(exec_id, None)
-            .await?
+        if let StartExecResults::Attached {
+            mut output,
+            mut input,
+        } = self.manager.docker().start_exec(exec_id, None).await?
         {
+            // Written concurrently, so a program that never reads its input
+            // cannot stall output collection
+            let writer = io.stdin.map(|bytes| {
+                tokio::spawn(async move {
+                    if let Err(e) = input.write_all(&bytes).await {
+                        tracing::debug!("Writing stdin failed: {}", e);
+                    }
+                    let _ = input.shutdown().await;
+                })
+            });
+
             while let Some(Ok(chunk)) = output.next().await {
-                match chunk {
+                let (stream, message) = match chunk {
                     bollard::container::LogOutput::StdOut { message } => {
-                        stdout.extend_from_slice(&message);
+                        (StdStream::Stdout, message)
                     }
                     bollard::container::LogOutput::StdErr { message } => {
-                        stderr.extend_from_slice(&message);
+                        (StdStream::Stderr, message)
                     }
-                    _ => {}
+                    _ => continue,
+                };
+                let captured = match stream {
+                    StdStream::Stdout => &mut *stdout,
+                    StdStream::Stderr => &mut *stderr,
+                };
+
+                if let Some(handle) = io.handle.filter(|_| !captured.is_truncated()) {
+                    handle.record_output(stream, &message);
+                }
+                if let Some(chunks) = io.chunks.filter(|_| !captured.is_truncated()) {
+                    // A dropped receiver stops the stream, not the capture
+                    let _ = chunks
+                        .send(OutputChunk {
+                            stream,
+                            data: message.to_vec(),
+                        })
+                        .await;
                 }
+                if let Some(combined) = combined.as_mut().filter(|_| !captured.is_truncated()) {
+                    combined.push(stream, &message);
+                }
+                captured.push(&message);
+
+                if stdout.is_truncated() || stderr.is_truncated() {
+                    break;
+                }
+            }
+
+            if let Some(writer) = writer {
+                writer.abort();
             }
         }
 
-        Ok((
-            String::from_utf8_lossy(&stdout).to_string(),
-            String::from_utf8_lossy(&stderr).to_string(),
-        ))
+        Ok(())
     }
 }
 
@@ -0,0 +1,295 @@
+//! Tests for sandbox execution.
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashMap, time::Duration};
+
+    use rustyclint_common::models::Language;
+
+    use crate::{
+        error::SandboxError,
+        executor::{
+            compile_command, phase_timeouts, validate_post_run, wait_for_exit_code,
+            ExecutionRequest, ExecutionResult, SandboxExecutor,
+        },
+        limits::ResourceLimits,
+        output::{OutputChunk, StdStream, TRUNCATION_MARKER},
+    };
+
+    #[test]
+    fn test_post_run_validation() {
+        assert!(validate_post_run(&["cat".into(), "out.txt".into()]).is_ok());
+        assert!(validate_post_run(&[]).is_err());
+        assert!(validate_post_run(&[" ".into()]).is_err());
+        assert!(validate_post_run(&["echo".into(), "x".repeat(5000)]).is_err());
+    }
+
+    #[test]
+    fn test_oversized_result_truncated() {
+        let mut result = ExecutionResult {
+            stdout: "é".repeat(300),
+            stderr: "warning\n".into(),
+            exit_code: Some(0),
+            execution_time_ms: 5,
+            timed_out: false,
+            truncated: false,
+            coverage: None,
+            compile_stderr: None,
+            compile_failed: false,
+            post_run_output: Some("report".into()),
+            image: "python:3.12-slim".into(),
+            image_digest: None,
+            combined_output: Some(vec![OutputChunk {
+                stream: StdStream::Stdout,
+                data: vec![b'x'; 100],
+            }]),
+        };
+        assert_eq!(result.output_bytes(), 600 + 8 + 6 + 100);
+
+        let untouched = result.clone();
+        result.cap_size(10_000);
+        assert!(!result.truncated);
+        assert_eq!(result.stdout, untouched.stdout);
+
+        // stdout keeps its first 500 bytes, cut on a character boundary
+        result.cap_size(500);
+        assert!(result.truncated);
+        assert!(result.output_bytes() <= 500);
+        assert!(result.stdout.ends_with(TRUNCATION_MARKER));
+        assert!(result.stdout.starts_with(&"é".repeat(239)));
+        assert!(result.stderr.is_empty());
+        assert_eq!(result.post_run_output.as_deref(), Some(""));
+        assert_eq!(result.combined_output, Some(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_missing_exit_code_not_reported_as_minus_one() {
+        let inspections = Cell::new(0);
+        let inspect = || {
+            inspections.set(inspections.get() + 1);
+            async { Ok::<Option<i64>, ()>(None) }
+        };
+
+        let exit_code = wait_for_exit_code(inspect, 3, Duration::from_millis(1)).await.unwrap();
+
+        assert_eq!(exit_code, None);
+        assert_eq!(inspections.get(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_exit_code_retried() {
+        let inspections = Cell::new(0);
+        let inspect = || {
+            inspections.set(inspections.get() + 1);
+            let code = (inspections.get() == 2).then_some(137);
+            async move { Ok::<_, ()>(code) }
+        };
+
+        let exit_code = wait_for_exit_code(inspect, 3, Duration::from_millis(1)).await.unwrap();
+
+        assert_eq!(exit_code, Some(137));
+        assert_eq!(inspections.get(), 2);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker
+    async fn test_post_run_reads_program_output_file() {
+        let executor = SandboxExecutor::new().unwrap();
+
+        let result = executor
+            .execute(ExecutionRequest {
+                code: "open('/code/report.txt', 'w').write('covered: 3/3')".into(),
+                language: Language::Python,
+                stdin: None,
+                stdin_encoding: Default::default(),
+                line_endings: Default::default(),
+                args: vec![],
+                memory_bytes: None,
+                strip_ansi: None,
+                callback_url: None,
+                profile: None,
+                seed: None,
+                coverage: false,
+                combined_output: false,
+                post_run: Some(vec!["cat".into(), "/code/report.txt".into()]),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.exit_code, Some(0));
+        assert_eq!(result.post_run_output.as_deref(), Some("covered: 3/3"));
+    }
+
+    #[test]
+    fn test_compile_step_only_for_compiled_languages() {
+        for language in [Language::Rust, Language::C, Language::Cpp, Language::Java, Language::Kotlin] {
+            assert!(compile_command(language, "main.x").is_some(), "{:?}", language);
+        }
+        for language in [Language::Python, Language::JavaScript, Language::Go, Language::Ruby] {
+            assert!(compile_command(language, "main.x").is_none(), "{:?}", language);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker
+    async fn test_compile_error_reported_separately() {
+        let executor = SandboxExecutor::new().unwrap();
+        let request = |code: &str| ExecutionRequest {
+            code: code.into(),
+            language: Language::C,
+            stdin: None,
+            stdin_encoding: Default::default(),
+            line_endings: Default::default(),
+            args: vec![],
+            post_run: None,
+            memory_bytes: None,
+            strip_ansi: None,
+            callback_url: None,
+            profile: None,
+            seed: None,
+            coverage: false,
+            combined_output: false,
+        };
+
+        let result = executor.execute(request("int main() { return 0 }")).await.unwrap();
+        assert!(result.compile_failed);
+        assert!(result.compile_stderr.unwrap().contains("error"));
+        assert!(result.stderr.is_empty());
+
+        // A runtime failure leaves the compile step clean
+        let result = executor.execute(request("int main() { return 3; }")).await.unwrap();
+        assert!(!result.compile_failed);
+        assert_eq!(result.compile_stderr.as_deref(), Some(""));
+        assert_eq!(result.exit_code, Some(3));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker
+    async fn test_output_streamed_before_exit() {
+        let executor = SandboxExecutor::new().unwrap();
+        let (mut chunks, execution) = executor.execute_streaming(ExecutionRequest {
+            code: "import time\nprint('first', flush=True)\ntime.sleep(2)\nprint('second')".into(),
+            language: Language::Python,
+            stdin: None,
+            stdin_encoding: Default::default(),
+            line_endings: Default::default(),
+            args: vec![],
+            post_run: None,
+            memory_bytes: None,
+            strip_ansi: None,
+            callback_url: None,
+            profile: None,
+            seed: None,
+            coverage: false,
+            combined_output: false,
+        });
+        tokio::pin!(execution);
+
+        // The first line arrives while the program is still sleeping
+        let first = tokio::select! {
+            chunk = chunks.recv() => chunk.unwrap(),
+            _ = &mut execution => panic!("finished before streaming any output"),
+        };
+        assert_eq!(first.stream, StdStream::Stdout);
+        assert_eq!(first.data, b"first\n");
+
+        let (result, _) = tokio::join!(execution, async { while chunks.recv().await.is_some() {} });
+        assert_eq!(result.unwrap().stdout, "first\nsecond\n");
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker
+    async fn test_stdin_reaches_program() {
+        let executor = SandboxExecutor::new().unwrap();
+        let request = |stdin: Option<&str>| ExecutionRequest {
+            code: "print(input())".into(),
+            language: Language::Python,
+            stdin: stdin.map(String::from),
+            stdin_encoding: Default::default(),
+            line_endings: Default::default(),
+            args: vec![],
+            post_run: None,
+            memory_bytes: None,
+            strip_ansi: None,
+            callback_url: None,
+            profile: None,
+            seed: None,
+            coverage: false,
+            combined_output: false,
+        };
+
+        let result = executor.execute(request(Some("hello"))).await.unwrap();
+        assert_eq!(result.stdout.trim_end(), "hello");
+
+        // No stdin still closes the stream: input() fails with EOF instead
+        // of waiting for the timeout
+        let result = executor.execute(request(None)).await.unwrap();
+        assert!(!result.timed_out);
+        assert!(result.stderr.contains("EOFError"));
+    }
+
+    #[test]
+    fn test_named_profile_applied() {
+        let exam = ResourceLimits {
+            timeout_secs: 5,
+            pids_limit: 8,
+            ..ResourceLimits::snippet()
+        };
+        let executor = SandboxExecutor::with_limits(ResourceLimits::snippet())
+            .unwrap()
+            .with_profiles(HashMap::from([("exam".to_string(), exam)]));
+
+        let mut request = ExecutionRequest {
+            code: "print(1)".into(),
+            language: Language::Python,
+            stdin: None,
+            stdin_encoding: Default::default(),
+            line_endings: Default::default(),
+            args: vec![],
+            post_run: None,
+            memory_bytes: None,
+            strip_ansi: None,
+            callback_url: None,
+            profile: Some("exam".into()),
+            seed: None,
+            coverage: false,
+            combined_output: false,
+        };
+        let limits = executor.limits_for(&request).unwrap();
+        assert_eq!(limits.timeout_secs, 5);
+        assert_eq!(limits.pids_limit, 8);
+
+        // Unset falls back to the executor's snippet limits
+        request.profile = None;
+        assert_eq!(executor.limits_for(&request).unwrap().timeout_secs, 10);
+
+        request.profile = Some("heavy-ml".into());
+        assert!(matches!(
+            executor.limits_for(&request),
+            Err(SandboxError::UnknownProfile(name)) if name == "heavy-ml"
+        ));
+    }
+
+    #[test]
+    fn test_compile_and_run_timeouts_distinct() {
+        let limits = ResourceLimits {
+            compile_timeout_secs: Some(60),
+            run_timeout_secs: Some(5),
+            ..ResourceLimits::snippet()
+        };
+
+        let rust = phase_timeouts(Language::Rust, &limits);
+        assert_eq!(rust.compile, Some(Duration::from_secs(60)));
+        assert_eq!(rust.run, Duration::from_secs(5));
+
+        // Interpreted languages only run
+        let python = phase_timeouts(Language::Python, &limits);
+        assert_eq!(python.compile, None);
+        assert_eq!(python.run, Duration::from_secs(5));
+
+        // Unset phases fall back to the overall timeout
+        let snippet = phase_timeouts(Language::Rust, &ResourceLimits::snippet());
+        assert_eq!(snippet.compile, Some(Duration::from_secs(10)));
+        assert_eq!(snippet.run, Duration::from_secs(10));
+    }
+}
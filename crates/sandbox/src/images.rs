@@ -0,0 +1,57 @@
+//! Sandbox image selection and digest pinning.
+
+use std::collections::HashMap;
+
+use rustyclint_common::models::Language;
+use serde::{Deserialize, Serialize};
+
+/// Per-language image overrides.
+///
+/// Overrides replace the built-in `:latest` images, and are normally pinned
+/// by digest (`registry/image@sha256:...`) so a redeploy cannot silently
+/// change what user code runs on. Pinned references are used verbatim.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageOverrides(HashMap<Language, String>);
+
+impl ImageOverrides {
+    /// Create overrides from a language to image map.
+    pub fn new(images: HashMap<Language, String>) -> Self {
+        Self(images)
+    }
+
+    /// Image reference to use for a language.
+    pub fn image(&self, language: Language) -> &str {
+        self.0
+            .get(&language)
+            .map(String::as_str)
+            .unwrap_or_else(|| language.docker_image())
+    }
+
+    /// Image reference for a language, with its digest if pinned.
+    pub fn resolve(&self, language: Language) -> ImageRef {
+        ImageRef::parse(self.image(language))
+    }
+}
+
+/// An image reference and the content digest it resolves to, if known.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRef {
+    pub image: String,
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    /// Parse an image reference, extracting a `@sha256:` digest if present.
+    pub fn parse(image: &str) -> Self {
+        let digest = image
+            .split_once('@')
+            .map(|(_, digest)| digest)
+            .filter(|digest| digest.starts_with("sha256:"))
+            .map(str::to_string);
+
+        Self {
+            image: image.to_string(),
+            digest,
+        }
+    }
+}
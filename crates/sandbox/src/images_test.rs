@@ -0,0 +1,68 @@
+//! Tests for sandbox image selection.
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rustyclint_common::models::Language;
+
+    use crate::{
+        executor::{ExecutionRequest, SandboxExecutor},
+        images::{ImageOverrides, ImageRef},
+    };
+
+    const PINNED: &str = "acrustyclintprod.azurecr.io/sandbox-python@sha256:\
+        4f2a9c1e0b7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f2a1b0c9d8e7f6a5b4c3d2e1f";
+
+    #[test]
+    fn test_pinned_image_used_verbatim() {
+        let images = ImageOverrides::new(HashMap::from([(Language::Python, PINNED.to_string())]));
+
+        let resolved = images.resolve(Language::Python);
+
+        assert_eq!(images.image(Language::Python), PINNED);
+        assert_eq!(resolved.image, PINNED);
+        assert_eq!(
+            resolved.digest.as_deref(),
+            Some("sha256:4f2a9c1e0b7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f2a1b0c9d8e7f6a5b4c3d2e1f")
+        );
+    }
+
+    #[test]
+    fn test_unpinned_image_falls_back_to_default() {
+        let images = ImageOverrides::default();
+
+        assert_eq!(images.image(Language::Rust), Language::Rust.docker_image());
+        assert_eq!(ImageRef::parse(Language::Rust.docker_image()).digest, None);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker and registry access
+    async fn test_pinned_digest_recorded_in_result() {
+        let images = ImageOverrides::new(HashMap::from([(Language::Python, PINNED.to_string())]));
+        let executor = SandboxExecutor::with_images(Default::default(), images).unwrap();
+
+        let result = executor
+            .execute(ExecutionRequest {
+                code: "print('pinned')".into(),
+                language: Language::Python,
+                stdin: None,
+                stdin_encoding: Default::default(),
+                line_endings: Default::default(),
+                args: vec![],
+                memory_bytes: None,
+                strip_ansi: None,
+                callback_url: None,
+                profile: None,
+                seed: None,
+                coverage: false,
+                combined_output: false,
+                post_run: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.image, PINNED);
+        assert_eq!(result.image_digest, ImageRef::parse(PINNED).digest);
+    }
+}
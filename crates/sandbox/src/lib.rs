@@ -3,10 +3,38 @@
 //! This crate manages Docker containers for isolated code execution,
 //! providing security through resource limits, network isolation, and timeouts.
 
+pub mod ansi;
 pub mod container;
+pub mod coverage;
+pub mod error;
 pub mod executor;
+pub mod images;
 pub mod limits;
+pub mod output;
+pub mod pool;
+pub mod precheck;
+pub mod pty;
+pub mod report;
+pub mod seed;
+pub mod session;
+pub mod stdin;
+pub mod toolchain;
+pub mod tracker;
+pub mod utf8;
 
 pub use container::ContainerManager;
+pub use coverage::LineCoverage;
+pub use error::SandboxError;
 pub use executor::{ExecutionRequest, ExecutionResult, SandboxExecutor};
-pub use limits::ResourceLimits;
+pub use images::{ImageOverrides, ImageRef};
+pub use limits::{ContainerProfile, ResourceLimits};
+pub use output::{OutputChunk, OutputTail, StdStream};
+pub use pool::ContainerPool;
+pub use precheck::{Complexity, PrecheckMode};
+pub use pty::{PtySession, TerminalSize};
+pub use report::{CaseReport, TestReport};
+pub use session::{ExpiryReason, SessionPolicy, SessionRegistry};
+pub use stdin::{LineEndings, StdinEncoding};
+pub use toolchain::ToolchainInfo;
+pub use tracker::{CancelledExecutions, ExecutionHandle, ExecutionTracker};
+pub use utf8::Utf8StreamDecoder;
@@ -1,9 +1,14 @@
 //! Resource limits for sandbox containers.
 
+use std::{collections::HashMap, time::Duration};
+
+use rustyclint_common::models::Language;
 use serde::{Deserialize, Serialize};
 
+use crate::error::SandboxError;
+
 /// Resource limits applied to sandbox containers.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ResourceLimits {
     /// Maximum memory in bytes (default: 256MB).
     pub memory_bytes: u64,
@@ -14,25 +19,65 @@ pub struct ResourceLimits {
     /// Maximum number of processes/threads.
     pub pids_limit: i64,
 
-    /// Execution timeout in seconds.
+    /// Execution timeout in seconds, applied to each phase of a run that
+    /// has no timeout of its own.
     pub timeout_secs: u64,
 
+    /// Time allowed for the compile step of compiled languages.
+    #[serde(default)]
+    pub compile_timeout_secs: Option<u64>,
+
+    /// Time allowed for the program itself to run, after any compile step.
+    #[serde(default)]
+    pub run_timeout_secs: Option<u64>,
+
     /// Maximum output size in bytes.
     pub max_output_bytes: usize,
 
     /// Whether to enable network access (default: false).
     pub network_enabled: bool,
+
+    /// Capabilities added back after dropping all (default: none).
+    /// Only entries in [`ALLOWED_CAPABILITIES`] are accepted.
+    #[serde(default)]
+    pub cap_add: Vec<String>,
+
+    /// Egress bandwidth cap in bytes per second when the network is enabled
+    /// (default: unlimited).
+    ///
+    /// Applied with a `tc` token bucket filter on the container's `eth0`,
+    /// installed by a privileged exec before any user code runs; the
+    /// container itself never gets `NET_ADMIN`. The image must ship
+    /// `iproute2` (the sandbox base image does), or container creation
+    /// fails. Only outgoing traffic is
+    /// shaped, and short bursts of up to [`Self::egress_burst_bytes`] pass at
+    /// line rate.
+    #[serde(default)]
+    pub egress_bytes_per_sec: Option<u64>,
 }
 
+/// Capabilities that may be granted to sandbox containers.
+///
+/// Anything that allows escaping or administering the host (`SYS_ADMIN`,
+/// `SYS_PTRACE`, `NET_ADMIN`, `SYS_MODULE`, ...) is deliberately absent, as
+/// is anything that bypasses file permissions or changes identity
+/// (`DAC_OVERRIDE`, `CHOWN`, `FOWNER`, `SETUID`, `SETGID`, ...), which would
+/// undo running code as the unprivileged sandbox user.
+pub const ALLOWED_CAPABILITIES: &[&str] = &["KILL", "NET_BIND_SERVICE"];
+
 impl Default for ResourceLimits {
     fn default() -> Self {
         Self {
             memory_bytes: 256 * 1024 * 1024, // 256 MB
-            cpu_quota: 50000,                 // 50% of one CPU
+            cpu_quota: 50000,                // 50% of one CPU
             pids_limit: 64,
             timeout_secs: 30,
+            compile_timeout_secs: None,
+            run_timeout_secs: None,
             max_output_bytes: 1024 * 1024, // 1 MB
             network_enabled: false,
+            cap_add: Vec::new(),
+            egress_bytes_per_sec: None,
         }
     }
 }
@@ -45,8 +90,12 @@ impl ResourceLimits {
             cpu_quota: 25000,
             pids_limit: 32,
             timeout_secs: 10,
+            compile_timeout_secs: None,
+            run_timeout_secs: None,
             max_output_bytes: 64 * 1024,
             network_enabled: false,
+            cap_add: Vec::new(),
+            egress_bytes_per_sec: None,
         }
     }
 
@@ -54,11 +103,180 @@ impl ResourceLimits {
     pub fn project() -> Self {
         Self {
             memory_bytes: 1024 * 1024 * 1024, // 1 GB
-            cpu_quota: 100000,                 // 100% of one CPU
+            cpu_quota: 100000,                // 100% of one CPU
             pids_limit: 256,
             timeout_secs: 300,
+            compile_timeout_secs: None,
+            run_timeout_secs: None,
             max_output_bytes: 10 * 1024 * 1024, // 10 MB
             network_enabled: true,              // Allow package downloads
+            cap_add: Vec::new(),
+            egress_bytes_per_sec: None,
+        }
+    }
+
+    /// Limits for running `language`, with compiler-aware memory headroom.
+    ///
+    /// Compilers such as rustc and javac need far more memory than an
+    /// interpreter and OOM on trivial programs under the snippet limit. Unless
+    /// the caller sets memory explicitly, the language default is used as a
+    /// floor; an explicit value is always taken as-is.
+    pub fn for_language(&self, language: Language, explicit_memory: Option<u64>) -> Self {
+        let memory_bytes = match explicit_memory {
+            Some(memory_bytes) => memory_bytes,
+            None => self.memory_bytes.max(default_memory_for(language)),
+        };
+
+        Self {
+            memory_bytes,
+            ..self.clone()
         }
     }
+
+    /// Time allowed for a compile step.
+    pub fn compile_timeout(&self) -> Duration {
+        Duration::from_secs(self.compile_timeout_secs.unwrap_or(self.timeout_secs))
+    }
+
+    /// Time allowed for the program to run.
+    pub fn run_timeout(&self) -> Duration {
+        Duration::from_secs(self.run_timeout_secs.unwrap_or(self.timeout_secs))
+    }
+
+    /// Look up a profile named by a client.
+    ///
+    /// Only operator-defined `profiles` and the built-in `snippet` can be
+    /// named; the built-in `project` limits enable the network and are not
+    /// offered to clients. Requests that name no profile get `snippet`.
+    pub fn profile(
+        name: Option<&str>,
+        profiles: &HashMap<String, ResourceLimits>,
+    ) -> Result<Self, SandboxError> {
+        let Some(name) = name else {
+            return Ok(Self::snippet());
+        };
+
+        match (profiles.get(name), name) {
+            (Some(limits), _) => Ok(limits.clone()),
+            (None, "snippet") => Ok(Self::snippet()),
+            (None, _) => Err(SandboxError::UnknownProfile(name.to_string())),
+        }
+    }
+
+    /// Validate and normalize `cap_add` against the allowlist.
+    ///
+    /// Names are accepted with or without the `CAP_` prefix.
+    pub fn capabilities(&self) -> Result<Vec<String>, String> {
+        self.cap_add
+            .iter()
+            .map(|cap| {
+                let upper = cap.trim().to_ascii_uppercase();
+                let name = upper.strip_prefix("CAP_").unwrap_or(&upper);
+                if ALLOWED_CAPABILITIES.contains(&name) {
+                    Ok(name.to_string())
+                } else {
+                    Err(format!("Capability {} is not allowed", cap))
+                }
+            })
+            .collect()
+    }
+
+    /// Token bucket size for the egress limit: a quarter second of traffic,
+    /// but at least 32 KiB so the filter still admits full-size packets.
+    pub fn egress_burst_bytes(&self) -> Option<u64> {
+        self.egress_bytes_per_sec
+            .map(|rate| (rate / 4).max(32 * 1024))
+    }
+
+    /// Command that installs the egress limit inside the container, or
+    /// `None` if there is nothing to shape.
+    pub fn egress_shaping_command(&self) -> Option<Vec<String>> {
+        if !self.network_enabled {
+            return None;
+        }
+        let rate = self.egress_bytes_per_sec?;
+        let burst = self.egress_burst_bytes()?;
+
+        let rate = format!("{}bps", rate); // tc's "bps" is bytes per second
+        let burst = format!("{}b", burst);
+        let args = [
+            "tc", "qdisc", "replace", "dev", "eth0", "root", "tbf", "rate", &rate, "burst", &burst,
+            "latency", "50ms",
+        ];
+
+        Some(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    /// Create limits for long-lived language server containers.
+    ///
+    /// Language servers differ from code execution: they run for the whole
+    /// editing session, index every file in the workspace, and tools like
+    /// rust-analyzer resolve crate metadata from the network. They get more
+    /// memory and processes, network access, and no practical timeout, while
+    /// untrusted user code is never run through this profile.
+    pub fn lsp() -> Self {
+        Self {
+            memory_bytes: 1024 * 1024 * 1024, // 1 GB
+            cpu_quota: 100000,                // 100% of one CPU
+            pids_limit: 128,
+            timeout_secs: 0, // Lives as long as the session
+            compile_timeout_secs: None,
+            run_timeout_secs: None,
+            max_output_bytes: 10 * 1024 * 1024,
+            network_enabled: true, // Crate/package metadata lookups
+            cap_add: Vec::new(),
+            egress_bytes_per_sec: None,
+        }
+    }
+}
+
+/// Default memory for executing a language when none is set explicitly.
+pub fn default_memory_for(language: Language) -> u64 {
+    const MB: u64 = 1024 * 1024;
+
+    match language {
+        Language::Rust | Language::Java | Language::Kotlin | Language::CSharp | Language::Swift => {
+            512 * MB
+        }
+        Language::Cpp | Language::C | Language::Go | Language::TypeScript => 256 * MB,
+        Language::Python | Language::JavaScript | Language::Ruby | Language::Php => 128 * MB,
+    }
+}
+
+/// Filesystem layout of a sandbox container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerProfile {
+    /// Short-lived container running untrusted user code.
+    Execution,
+    /// Long-lived container hosting a language server.
+    LanguageServer,
+}
+
+impl ContainerProfile {
+    /// Writable tmpfs mounts on top of the read-only root filesystem.
+    ///
+    /// Language servers additionally get a cache directory, since they
+    /// persist indexes and downloaded metadata under `$HOME/.cache`.
+    pub fn tmpfs_mounts(&self) -> HashMap<String, String> {
+        let mut mounts = HashMap::from([
+            (
+                "/tmp".to_string(),
+                "rw,noexec,nosuid,size=64m,mode=1777".to_string(),
+            ),
+            (
+                "/code".to_string(),
+                "rw,nosuid,size=32m,mode=1777".to_string(),
+            ),
+        ]);
+
+        if *self == ContainerProfile::LanguageServer {
+            mounts.insert(
+                "/home/sandbox/.cache".to_string(),
+                "rw,nosuid,size=512m,mode=1777".to_string(),
+            );
+        }
+
+        mounts
+    }
 }
@@ -2,7 +2,11 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::limits::ResourceLimits;
+    use std::collections::HashMap;
+
+    use rustyclint_common::models::Language;
+
+    use crate::limits::{ContainerProfile, ResourceLimits};
 
     #[test]
     fn test_default_limits() {
@@ -33,4 +37,99 @@ mod tests {
         assert_eq!(limits.timeout_secs, 300);
         assert!(limits.network_enabled);
     }
+
+    #[test]
+    fn test_lsp_limits() {
+        let lsp = ResourceLimits::lsp();
+        let snippet = ResourceLimits::snippet();
+
+        assert!(lsp.network_enabled);
+        assert!(!snippet.network_enabled);
+        assert!(lsp.memory_bytes > snippet.memory_bytes);
+    }
+
+    #[test]
+    fn test_lsp_profile_has_cache_dir() {
+        let execution = ContainerProfile::Execution.tmpfs_mounts();
+        let lsp = ContainerProfile::LanguageServer.tmpfs_mounts();
+
+        assert!(!execution.contains_key("/home/sandbox/.cache"));
+        assert!(lsp.contains_key("/home/sandbox/.cache"));
+        assert!(lsp.contains_key("/code"));
+        assert_eq!(execution.get("/tmp"), lsp.get("/tmp"));
+    }
+
+    #[test]
+    fn test_rust_snippet_gets_compiler_memory() {
+        let snippet = ResourceLimits::snippet();
+
+        let rust = snippet.for_language(Language::Rust, None);
+        let python = snippet.for_language(Language::Python, None);
+
+        assert_eq!(rust.memory_bytes, 512 * 1024 * 1024);
+        assert_eq!(python.memory_bytes, snippet.memory_bytes);
+        assert_eq!(rust.timeout_secs, snippet.timeout_secs);
+    }
+
+    #[test]
+    fn test_explicit_memory_is_authoritative() {
+        let limits = ResourceLimits::snippet().for_language(Language::Rust, Some(64 * 1024 * 1024));
+
+        assert_eq!(limits.memory_bytes, 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_egress_limit_shapes_networked_containers() {
+        let limits = ResourceLimits {
+            egress_bytes_per_sec: Some(1_000_000),
+            ..ResourceLimits::project()
+        };
+
+        let command = limits.egress_shaping_command().unwrap();
+
+        assert_eq!(&command[..3], ["tc", "qdisc", "replace"]);
+        let rate = command.iter().position(|arg| arg == "rate").unwrap();
+        assert_eq!(command[rate + 1], "1000000bps");
+        let burst = command.iter().position(|arg| arg == "burst").unwrap();
+        assert_eq!(command[burst + 1], "250000b");
+    }
+
+    #[test]
+    fn test_egress_unlimited_by_default() {
+        assert_eq!(ResourceLimits::project().egress_shaping_command(), None);
+
+        // No network, nothing to shape
+        let offline = ResourceLimits {
+            egress_bytes_per_sec: Some(1_000_000),
+            ..ResourceLimits::snippet()
+        };
+        assert_eq!(offline.egress_shaping_command(), None);
+    }
+
+    #[test]
+    fn test_profile_lookup() {
+        let heavy = ResourceLimits {
+            memory_bytes: 4 * 1024 * 1024 * 1024,
+            ..ResourceLimits::project()
+        };
+        let profiles = HashMap::from([("heavy-ml".to_string(), heavy)]);
+
+        let resolved = ResourceLimits::profile(Some("heavy-ml"), &profiles).unwrap();
+        assert_eq!(resolved.memory_bytes, 4 * 1024 * 1024 * 1024);
+        assert_eq!(
+            ResourceLimits::profile(None, &profiles).unwrap().timeout_secs,
+            ResourceLimits::snippet().timeout_secs
+        );
+        assert!(ResourceLimits::profile(Some("exam"), &profiles).is_err());
+    }
+
+    #[test]
+    fn test_builtin_project_profile_not_offered() {
+        assert!(ResourceLimits::profile(Some("project"), &HashMap::new()).is_err());
+
+        // Operators may still define a profile of that name
+        let profiles = HashMap::from([("project".to_string(), ResourceLimits::snippet())]);
+        let limits = ResourceLimits::profile(Some("project"), &profiles).unwrap();
+        assert!(!limits.network_enabled);
+    }
 }
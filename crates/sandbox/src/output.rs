@@ -0,0 +1,210 @@
+//! Capped capture of program output.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utf8::Utf8StreamDecoder;
+
+/// Which output stream a chunk came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StdStream {
+    Stdout,
+    Stderr,
+}
+
+/// Raw program output, forwarded as it arrives.
+///
+/// Chunks are not aligned to characters; decode them with a
+/// [`Utf8StreamDecoder`] per stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    pub stream: StdStream,
+    pub data: Vec<u8>,
+}
+
+/// Marker appended to a stream that was cut off at its byte limit.
+pub const TRUNCATION_MARKER: &str = "\n... output truncated";
+
+/// One output stream, decoded incrementally and capped at a byte limit.
+///
+/// Once the limit is reached further chunks are dropped, so a program
+/// printing in a loop cannot grow the buffer without bound.
+#[derive(Debug)]
+pub struct CappedOutput {
+    text: String,
+    decoder: Utf8StreamDecoder,
+    max_bytes: usize,
+    truncated: bool,
+}
+
+impl CappedOutput {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            text: String::new(),
+            decoder: Utf8StreamDecoder::new(),
+            max_bytes,
+            truncated: false,
+        }
+    }
+
+    /// Append a chunk; anything past the limit is discarded.
+    pub fn push(&mut self, chunk: &[u8]) {
+        if self.truncated {
+            return;
+        }
+        let text = self.decoder.push(chunk);
+        self.append(&text);
+    }
+
+    /// Whether the limit has been reached.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Finish the stream, returning its text and whether it was cut.
+    pub fn finish(mut self) -> (String, bool) {
+        if !self.truncated {
+            let rest = self.decoder.finish();
+            self.append(&rest);
+        }
+        if self.truncated {
+            self.text.push_str(TRUNCATION_MARKER);
+        }
+        (self.text, self.truncated)
+    }
+
+    fn append(&mut self, text: &str) {
+        let room = self.max_bytes - self.text.len();
+        if text.len() <= room {
+            self.text.push_str(text);
+            return;
+        }
+
+        // Cut on a character boundary so the text stays valid
+        let mut end = room;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.text.push_str(&text[..end]);
+        self.truncated = true;
+    }
+}
+
+/// Both output streams in the order their chunks arrived.
+///
+/// Consecutive chunks from the same stream are merged, so the result
+/// alternates between streams. Callers stop pushing a stream once its
+/// [`CappedOutput`] is truncated, which bounds the size.
+#[derive(Debug, Default)]
+pub struct CombinedOutput {
+    chunks: Vec<OutputChunk>,
+}
+
+impl CombinedOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a chunk after everything pushed before it.
+    pub fn push(&mut self, stream: StdStream, data: &[u8]) {
+        match self.chunks.last_mut() {
+            Some(last) if last.stream == stream => last.data.extend_from_slice(data),
+            _ => self.chunks.push(OutputChunk {
+                stream,
+                data: data.to_vec(),
+            }),
+        }
+    }
+
+    pub fn finish(self) -> Vec<OutputChunk> {
+        self.chunks
+    }
+}
+
+/// Most lines kept in an [`OutputTail`].
+pub const TAIL_MAX_LINES: usize = 500;
+
+/// Longest line kept in an [`OutputTail`]; the rest of a longer line is
+/// dropped.
+pub const TAIL_MAX_LINE_BYTES: usize = 1024;
+
+/// The most recent lines of a running program's output.
+///
+/// Both streams feed one ring buffer in arrival order. Older lines are
+/// evicted past `max_lines`, so memory stays bounded however long the
+/// program runs.
+#[derive(Debug)]
+pub struct OutputTail {
+    lines: VecDeque<String>,
+    max_lines: usize,
+    stdout: PartialLine,
+    stderr: PartialLine,
+}
+
+/// A stream's decoder and its line still waiting for a newline.
+#[derive(Debug, Default)]
+struct PartialLine {
+    decoder: Utf8StreamDecoder,
+    line: String,
+}
+
+impl OutputTail {
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(max_lines.min(TAIL_MAX_LINES)),
+            max_lines,
+            stdout: PartialLine::default(),
+            stderr: PartialLine::default(),
+        }
+    }
+
+    /// Append a chunk of output from one stream.
+    pub fn push(&mut self, stream: StdStream, chunk: &[u8]) {
+        let partial = match stream {
+            StdStream::Stdout => &mut self.stdout,
+            StdStream::Stderr => &mut self.stderr,
+        };
+        let text = partial.decoder.push(chunk);
+
+        let mut pieces = text.split('\n').peekable();
+        while let Some(piece) = pieces.next() {
+            append_capped(&mut partial.line, piece.strip_suffix('\r').unwrap_or(piece));
+            if pieces.peek().is_some() {
+                let line = std::mem::take(&mut partial.line);
+                if self.lines.len() == self.max_lines {
+                    self.lines.pop_front();
+                }
+                if self.max_lines > 0 {
+                    self.lines.push_back(line);
+                }
+            }
+        }
+    }
+
+    /// The last `n` lines, oldest first, including any unterminated line.
+    pub fn last(&self, n: usize) -> Vec<String> {
+        let pending = [&self.stdout.line, &self.stderr.line]
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .cloned();
+        let lines: Vec<String> = self.lines.iter().cloned().chain(pending).collect();
+        lines[lines.len().saturating_sub(n)..].to_vec()
+    }
+}
+
+impl Default for OutputTail {
+    fn default() -> Self {
+        Self::new(TAIL_MAX_LINES)
+    }
+}
+
+fn append_capped(line: &mut String, text: &str) {
+    let room = TAIL_MAX_LINE_BYTES.saturating_sub(line.len());
+    let mut end = room.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    line.push_str(&text[..end]);
+}
@@ -0,0 +1,111 @@
+//! Tests for capped output capture.
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{
+        CappedOutput, CombinedOutput, OutputChunk, OutputTail, StdStream, TAIL_MAX_LINE_BYTES,
+        TRUNCATION_MARKER,
+    };
+
+    #[test]
+    fn test_output_within_limit_untouched() {
+        let mut output = CappedOutput::new(16);
+        output.push(b"hello ");
+        output.push(b"world");
+
+        assert_eq!(output.finish(), ("hello world".to_string(), false));
+    }
+
+    #[test]
+    fn test_streams_capped_independently() {
+        let mut stdout = CappedOutput::new(1024);
+        let mut stderr = CappedOutput::new(1024);
+
+        for _ in 0..10_000 {
+            stdout.push(b"spam spam spam spam\n");
+        }
+        stderr.push(b"warning: something odd\n");
+
+        assert!(stdout.is_truncated());
+        assert!(!stderr.is_truncated());
+
+        let (stdout, truncated) = stdout.finish();
+        assert!(truncated);
+        assert_eq!(stdout.len(), 1024 + TRUNCATION_MARKER.len());
+        assert!(stdout.ends_with(TRUNCATION_MARKER));
+
+        assert_eq!(stderr.finish(), ("warning: something odd\n".to_string(), false));
+    }
+
+    #[test]
+    fn test_cut_on_character_boundary() {
+        let mut output = CappedOutput::new(5);
+        output.push("abcdé".as_bytes());
+
+        let (text, truncated) = output.finish();
+        assert!(truncated);
+        assert_eq!(text, format!("abcd{}", TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn test_stream_names() {
+        assert_eq!(serde_json::to_string(&StdStream::Stdout).unwrap(), r#""stdout""#);
+        assert_eq!(serde_json::to_string(&StdStream::Stderr).unwrap(), r#""stderr""#);
+    }
+
+    #[test]
+    fn test_tail_keeps_most_recent_lines_within_bound() {
+        let mut tail = OutputTail::new(3);
+        for i in 1..=10 {
+            tail.push(StdStream::Stdout, format!("line {}\n", i).as_bytes());
+        }
+
+        assert_eq!(tail.last(2), ["line 9", "line 10"]);
+        // Only the bound is kept however many are asked for
+        assert_eq!(tail.last(100), ["line 8", "line 9", "line 10"]);
+    }
+
+    #[test]
+    fn test_tail_joins_split_chunks_and_pending_lines() {
+        let mut tail = OutputTail::new(10);
+        tail.push(StdStream::Stdout, b"progr");
+        tail.push(StdStream::Stderr, b"warning\r\n");
+        tail.push(StdStream::Stdout, b"ess 50%\n\xc3");
+        tail.push(StdStream::Stdout, b"\xa9tape");
+
+        assert_eq!(tail.last(10), ["warning", "progress 50%", "\u{e9}tape"]);
+    }
+
+    #[test]
+    fn test_tail_caps_long_lines() {
+        let mut tail = OutputTail::new(10);
+        tail.push(StdStream::Stdout, &vec![b'x'; TAIL_MAX_LINE_BYTES * 3]);
+        tail.push(StdStream::Stdout, b"\nnext\n");
+
+        let lines = tail.last(10);
+        assert_eq!(lines[0].len(), TAIL_MAX_LINE_BYTES);
+        assert_eq!(lines[1], "next");
+    }
+
+    #[test]
+    fn test_combined_keeps_arrival_order() {
+        let mut combined = CombinedOutput::new();
+        combined.push(StdStream::Stdout, b"a");
+        combined.push(StdStream::Stdout, b"b\n");
+        combined.push(StdStream::Stderr, b"oops\n");
+        combined.push(StdStream::Stdout, b"c\n");
+
+        let chunk = |stream, data: &[u8]| OutputChunk {
+            stream,
+            data: data.to_vec(),
+        };
+        assert_eq!(
+            combined.finish(),
+            [
+                chunk(StdStream::Stdout, b"ab\n"),
+                chunk(StdStream::Stderr, b"oops\n"),
+                chunk(StdStream::Stdout, b"c\n"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Warm containers reused across executions.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use rustyclint_common::models::Language;
+
+use crate::limits::ResourceLimits;
+
+struct IdleContainer {
+    id: String,
+    limits: ResourceLimits,
+}
+
+/// Idle containers kept warm between executions, up to `size` per language.
+///
+/// Memory, CPU, network and capabilities are fixed when a container is
+/// created, so a pooled container is only handed out for the exact limits it
+/// was created with.
+pub struct ContainerPool {
+    size: usize,
+    idle: Mutex<HashMap<Language, Vec<IdleContainer>>>,
+}
+
+impl ContainerPool {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Idle containers kept per language.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Take an idle container created with `limits`, if there is one.
+    pub fn take(&self, language: Language, limits: &ResourceLimits) -> Option<String> {
+        let mut idle = self.idle.lock().unwrap();
+        let containers = idle.get_mut(&language)?;
+        let index = containers
+            .iter()
+            .position(|container| container.limits == *limits)?;
+        Some(containers.swap_remove(index).id)
+    }
+
+    /// Return a reset container to the pool.
+    ///
+    /// If the language already has `size` idle containers the id is handed
+    /// back, and the caller must remove the container.
+    pub fn put(
+        &self,
+        language: Language,
+        limits: ResourceLimits,
+        container_id: String,
+    ) -> Option<String> {
+        let mut idle = self.idle.lock().unwrap();
+        let containers = idle.entry(language).or_default();
+        if containers.len() >= self.size {
+            return Some(container_id);
+        }
+        containers.push(IdleContainer {
+            id: container_id,
+            limits,
+        });
+        None
+    }
+
+    /// Number of idle containers for a language.
+    pub fn idle(&self, language: Language) -> usize {
+        self.idle.lock().unwrap().get(&language).map_or(0, Vec::len)
+    }
+
+    /// Empty the pool, returning the containers the caller must remove.
+    pub fn drain(&self) -> Vec<String> {
+        self.idle
+            .lock()
+            .unwrap()
+            .drain()
+            .flat_map(|(_, containers)| containers)
+            .map(|container| container.id)
+            .collect()
+    }
+}
+
+/// Command clearing a used container for the next execution.
+///
+/// Runs as the sandbox user: `kill -9 -1` ends everything the previous
+/// program left running (the container's init ignores it), then `/code` and
+/// `/tmp` are emptied. Permissions are restored first so files the program
+/// locked down can still be deleted.
+pub fn reset_command() -> Vec<String> {
+    vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        "kill -9 -1 2>/dev/null; \
+         chmod -R u+rwX /code /tmp 2>/dev/null; \
+         find /code /tmp -mindepth 1 -delete"
+            .to_string(),
+    ]
+}
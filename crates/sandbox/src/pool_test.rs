@@ -0,0 +1,44 @@
+//! Tests for the warm container pool.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+    use crate::{limits::ResourceLimits, pool::ContainerPool};
+
+    #[test]
+    fn test_container_reused_for_matching_limits() {
+        let pool = ContainerPool::new(2);
+        let snippet = ResourceLimits::snippet();
+
+        assert!(pool.put(Language::Python, snippet.clone(), "c1".into()).is_none());
+        assert_eq!(pool.idle(Language::Python), 1);
+
+        // Other languages and other limits get a fresh container
+        assert!(pool.take(Language::Ruby, &snippet).is_none());
+        assert!(pool.take(Language::Python, &ResourceLimits::project()).is_none());
+        let more_memory = snippet.for_language(Language::Python, Some(512 * 1024 * 1024));
+        assert!(pool.take(Language::Python, &more_memory).is_none());
+
+        assert_eq!(pool.take(Language::Python, &snippet).as_deref(), Some("c1"));
+        assert!(pool.take(Language::Python, &snippet).is_none());
+    }
+
+    #[test]
+    fn test_full_pool_hands_container_back() {
+        let pool = ContainerPool::new(1);
+        let limits = ResourceLimits::snippet();
+
+        assert!(pool.put(Language::Go, limits.clone(), "c1".into()).is_none());
+        assert_eq!(
+            pool.put(Language::Go, limits.clone(), "c2".into()).as_deref(),
+            Some("c2")
+        );
+        // The size applies per language
+        assert!(pool.put(Language::C, limits, "c3".into()).is_none());
+
+        let mut drained = pool.drain();
+        drained.sort();
+        assert_eq!(drained, vec!["c1", "c3"]);
+        assert_eq!(pool.idle(Language::Go), 0);
+    }
+}
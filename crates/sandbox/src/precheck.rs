@@ -0,0 +1,129 @@
+//! Cheap static checks run on submissions before a container is started.
+
+use rustyclint_common::models::Language;
+use serde::{Deserialize, Serialize};
+
+/// How pre-check findings are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrecheckMode {
+    /// Skip the pre-check entirely.
+    #[default]
+    Off,
+    /// Run the submission but report findings as warnings.
+    Advisory,
+    /// Reject submissions with any finding.
+    Strict,
+}
+
+/// Maximum number of lines before a submission is considered oversized.
+pub const MAX_LINES: usize = 2000;
+
+/// Maximum block nesting depth before a submission is flagged.
+pub const MAX_NESTING: usize = 12;
+
+/// Loop headers that never terminate on their own, with whitespace removed.
+const UNBOUNDED_LOOPS: &[&str] = &[
+    "whiletrue",
+    "while(true)",
+    "while1:",
+    "while(1)",
+    "for(;;)",
+    "loop{",
+    "for{",
+    "whileTrue:",
+];
+
+/// Rough size and shape of a submission.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Complexity {
+    pub lines: usize,
+    pub max_nesting: usize,
+    pub unbounded_loop: bool,
+}
+
+impl Complexity {
+    /// Estimate the complexity of source code.
+    ///
+    /// Nesting is measured by indentation for Python and by braces elsewhere;
+    /// an unbounded loop counts only if the code never uses `break`.
+    pub fn estimate(code: &str, language: Language) -> Self {
+        let lines = code.lines().count();
+
+        let max_nesting = match language {
+            Language::Python => indent_depth(code),
+            _ => brace_depth(code),
+        };
+
+        let has_unbounded = code.lines().any(|line| {
+            let compact: String = line.split_whitespace().collect();
+            UNBOUNDED_LOOPS
+                .iter()
+                .any(|pattern| compact.starts_with(pattern))
+        });
+
+        Self {
+            lines,
+            max_nesting,
+            unbounded_loop: has_unbounded && !code.contains("break"),
+        }
+    }
+
+    /// Human-readable findings; empty if the submission is within budget.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if self.lines > MAX_LINES {
+            warnings.push(format!(
+                "Submission has {} lines (budget {})",
+                self.lines, MAX_LINES
+            ));
+        }
+
+        if self.max_nesting > MAX_NESTING {
+            warnings.push(format!(
+                "Blocks nested {} levels deep (budget {})",
+                self.max_nesting, MAX_NESTING
+            ));
+        }
+
+        if self.unbounded_loop {
+            warnings.push("Loop without an exit condition will likely run until timeout".into());
+        }
+
+        warnings
+    }
+}
+
+fn brace_depth(code: &str) -> usize {
+    let mut depth: usize = 0;
+    let mut max = 0;
+
+    for c in code.chars() {
+        match c {
+            '{' => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    max
+}
+
+fn indent_depth(code: &str) -> usize {
+    code.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let width: usize = line
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .map(|c| if c == '\t' { 4 } else { 1 })
+                .sum();
+            width / 4
+        })
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,40 @@
+//! Tests for submission pre-checks.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+
+    use crate::precheck::{Complexity, MAX_NESTING};
+
+    #[test]
+    fn test_infinite_loop_flagged() {
+        let code = "count = 0\nwhile True:\n    count += 1\n";
+
+        let complexity = Complexity::estimate(code, Language::Python);
+
+        assert!(complexity.unbounded_loop);
+        assert_eq!(complexity.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_loop_with_break_not_flagged() {
+        let code = "fn main() {\n    let mut i = 0;\n    loop {\n        i += 1;\n        if i > 3 { break; }\n    }\n}\n";
+
+        let complexity = Complexity::estimate(code, Language::Rust);
+
+        assert!(!complexity.unbounded_loop);
+        assert_eq!(complexity.max_nesting, 3);
+        assert!(complexity.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_deep_nesting_flagged() {
+        let depth = MAX_NESTING + 1;
+        let code = format!("{}{}", "{".repeat(depth), "}".repeat(depth));
+
+        let complexity = Complexity::estimate(&code, Language::JavaScript);
+
+        assert_eq!(complexity.max_nesting, depth);
+        assert!(complexity.warnings()[0].contains("nested"));
+    }
+}
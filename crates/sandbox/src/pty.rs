@@ -0,0 +1,123 @@
+//! Interactive programs attached to a container's TTY.
+
+use std::pin::Pin;
+
+use bollard::{
+    container::LogOutput,
+    exec::{CreateExecOptions, ResizeExecOptions, StartExecResults},
+};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWrite;
+
+use crate::container::ContainerManager;
+
+/// Largest number of columns or rows a terminal may report.
+pub const MAX_TERMINAL_DIMENSION: u16 = 1000;
+
+/// Size of a terminal in character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for TerminalSize {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+impl TerminalSize {
+    /// Whether both dimensions are between 1 and [`MAX_TERMINAL_DIMENSION`].
+    pub fn is_valid(&self) -> bool {
+        (1..=MAX_TERMINAL_DIMENSION).contains(&self.cols)
+            && (1..=MAX_TERMINAL_DIMENSION).contains(&self.rows)
+    }
+}
+
+/// Exec options running `command` on a TTY of `size`.
+///
+/// The Docker API version bollard speaks can't size an exec's TTY when it
+/// is created, and resizing once it has started races with programs that
+/// read the size on startup. So the command runs behind `stty`, which sizes
+/// the TTY before the program is exec'd.
+pub fn pty_exec_options(command: &[String], size: TerminalSize) -> CreateExecOptions<String> {
+    let script = format!(
+        "stty cols {} rows {} 2>/dev/null; exec \"$@\"",
+        size.cols, size.rows
+    );
+    let mut cmd = vec!["sh".to_string(), "-c".to_string(), script, "sh".to_string()];
+    cmd.extend_from_slice(command);
+
+    CreateExecOptions {
+        cmd: Some(cmd),
+        tty: Some(true),
+        attach_stdin: Some(true),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        env: Some(vec!["TERM=xterm-256color".to_string()]),
+        ..Default::default()
+    }
+}
+
+/// A program running on a container TTY.
+pub struct PtySession {
+    pub exec_id: String,
+    /// Everything the program writes to the TTY.
+    pub output: Pin<Box<dyn Stream<Item = Result<LogOutput, bollard::errors::Error>> + Send>>,
+    /// The TTY's input.
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+impl ContainerManager {
+    /// Start `command` in a container on a TTY already sized to `size`.
+    pub async fn attach_pty(
+        &self,
+        container_id: &str,
+        command: &[String],
+        size: TerminalSize,
+    ) -> Result<PtySession, bollard::errors::Error> {
+        let exec = self
+            .docker()
+            .create_exec(container_id, pty_exec_options(command, size))
+            .await?;
+
+        match self.docker().start_exec(&exec.id, None).await? {
+            StartExecResults::Attached { output, input } => Ok(PtySession {
+                exec_id: exec.id,
+                output,
+                input,
+            }),
+            StartExecResults::Detached => Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 500,
+                message: "Terminal exec started detached".to_string(),
+            }),
+        }
+    }
+
+    /// Resize a running TTY, e.g. after the client's window changed.
+    pub async fn resize_pty(
+        &self,
+        exec_id: &str,
+        size: TerminalSize,
+    ) -> Result<(), bollard::errors::Error> {
+        self.docker()
+            .resize_exec(
+                exec_id,
+                ResizeExecOptions {
+                    width: size.cols,
+                    height: size.rows,
+                },
+            )
+            .await
+    }
+
+    /// Exit code of a finished exec, if Docker reports one.
+    pub async fn exec_exit_code(
+        &self,
+        exec_id: &str,
+    ) -> Result<Option<i64>, bollard::errors::Error> {
+        Ok(self.docker().inspect_exec(exec_id).await?.exit_code)
+    }
+}
@@ -0,0 +1,73 @@
+//! Tests for terminal sessions.
+
+#[cfg(test)]
+mod tests {
+    use bollard::container::LogOutput;
+    use futures_util::StreamExt;
+    use rustyclint_common::models::Language;
+
+    use crate::{
+        pty::{pty_exec_options, TerminalSize, MAX_TERMINAL_DIMENSION},
+        ContainerManager, ResourceLimits,
+    };
+
+    #[test]
+    fn test_exec_created_with_initial_size() {
+        let size = TerminalSize {
+            cols: 132,
+            rows: 43,
+        };
+        let options = pty_exec_options(&["htop".to_string()], size);
+
+        assert_eq!(options.tty, Some(true));
+        assert_eq!(options.attach_stdin, Some(true));
+
+        // The TTY is sized before the program starts
+        let cmd = options.cmd.unwrap();
+        assert_eq!(&cmd[..2], ["sh", "-c"]);
+        assert!(cmd[2].starts_with("stty cols 132 rows 43"));
+        assert!(cmd[2].ends_with("exec \"$@\""));
+        assert_eq!(&cmd[3..], ["sh", "htop"]);
+    }
+
+    #[test]
+    fn test_terminal_size_bounds() {
+        assert!(TerminalSize::default().is_valid());
+        assert!(!TerminalSize { cols: 0, rows: 24 }.is_valid());
+        assert!(!TerminalSize {
+            cols: 80,
+            rows: MAX_TERMINAL_DIMENSION + 1
+        }
+        .is_valid());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker
+    async fn test_pty_reports_initial_size() {
+        let containers = ContainerManager::new().unwrap();
+        let container_id = containers
+            .create_container(Language::Python, &ResourceLimits::default())
+            .await
+            .unwrap();
+
+        let size = TerminalSize {
+            cols: 120,
+            rows: 40,
+        };
+        let command = ["stty".to_string(), "size".to_string()];
+        let mut pty = containers
+            .attach_pty(&container_id, &command, size)
+            .await
+            .unwrap();
+
+        let mut output = Vec::new();
+        while let Some(Ok(log)) = pty.output.next().await {
+            if let LogOutput::Console { message } = log {
+                output.extend_from_slice(&message);
+            }
+        }
+        containers.remove_container(&container_id).await.unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output).trim(), "40 120");
+    }
+}
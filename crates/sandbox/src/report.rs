@@ -0,0 +1,151 @@
+//! Machine-readable test reports built from execution results.
+
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::executor::ExecutionResult;
+
+/// Outcome of one test case.
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseReport {
+    pub name: String,
+    pub passed: bool,
+    pub duration_ms: u64,
+    /// Why the case failed, if it did.
+    pub message: Option<String>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CaseReport {
+    /// Grade a case: it passes if the program exited cleanly in time and,
+    /// when an expected output is given, printed it (ignoring trailing
+    /// whitespace).
+    pub fn grade(name: String, expected_stdout: Option<&str>, result: &ExecutionResult) -> Self {
+        let message = if result.timed_out {
+            Some("Execution timed out".to_string())
+        } else if result.compile_failed {
+            Some("Compilation failed".to_string())
+        } else if result.exit_code.is_none() {
+            Some("No exit code reported (the container may have crashed)".to_string())
+        } else if result.exit_code != Some(0) {
+            Some(format!(
+                "Exited with code {}",
+                result.exit_code.unwrap_or_default()
+            ))
+        } else {
+            match expected_stdout {
+                Some(expected) if expected.trim_end() != result.stdout.trim_end() => Some(format!(
+                    "Expected output {:?}, got {:?}",
+                    expected.trim_end(),
+                    result.stdout.trim_end()
+                )),
+                _ => None,
+            }
+        };
+
+        Self {
+            name,
+            passed: message.is_none(),
+            duration_ms: result.execution_time_ms,
+            message,
+            stdout: result.stdout.clone(),
+            stderr: result.stderr.clone(),
+        }
+    }
+}
+
+/// A suite of graded cases, serializable as JSON or JUnit XML.
+#[derive(Debug, Clone, Serialize)]
+pub struct TestReport {
+    pub name: String,
+    pub tests: usize,
+    pub failures: usize,
+    pub duration_ms: u64,
+    pub cases: Vec<CaseReport>,
+}
+
+impl TestReport {
+    /// Build a report from graded cases.
+    pub fn new(name: String, cases: Vec<CaseReport>) -> Self {
+        Self {
+            name,
+            tests: cases.len(),
+            failures: cases.iter().filter(|case| !case.passed).count(),
+            duration_ms: cases.iter().map(|case| case.duration_ms).sum(),
+            cases,
+        }
+    }
+
+    /// Render the report as a JUnit XML document.
+    pub fn to_junit_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{}\">",
+            escape_xml(&self.name),
+            self.tests,
+            self.failures,
+            seconds(self.duration_ms)
+        );
+
+        for case in &self.cases {
+            let _ = write!(
+                xml,
+                "  <testcase name=\"{}\" classname=\"{}\" time=\"{}\"",
+                escape_xml(&case.name),
+                escape_xml(&self.name),
+                seconds(case.duration_ms)
+            );
+
+            match &case.message {
+                None => xml.push_str("/>\n"),
+                Some(message) => {
+                    xml.push_str(">\n");
+                    let _ = writeln!(xml, "    <failure message=\"{}\"/>", escape_xml(message));
+                    if !case.stdout.is_empty() {
+                        let _ = writeln!(
+                            xml,
+                            "    <system-out>{}</system-out>",
+                            escape_xml(&case.stdout)
+                        );
+                    }
+                    if !case.stderr.is_empty() {
+                        let _ = writeln!(
+                            xml,
+                            "    <system-err>{}</system-err>",
+                            escape_xml(&case.stderr)
+                        );
+                    }
+                    xml.push_str("  </testcase>\n");
+                }
+            }
+        }
+
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.3}", ms as f64 / 1000.0)
+}
+
+/// Escape text for XML, dropping control characters XML 1.0 cannot represent.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
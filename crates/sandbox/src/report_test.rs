@@ -0,0 +1,74 @@
+//! Tests for test report generation.
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::ExecutionResult,
+        report::{CaseReport, TestReport},
+    };
+
+    fn result(stdout: &str, exit_code: i64, execution_time_ms: u64) -> ExecutionResult {
+        ExecutionResult {
+            stdout: stdout.into(),
+            stderr: String::new(),
+            exit_code: Some(exit_code),
+            execution_time_ms,
+            timed_out: false,
+            truncated: false,
+            coverage: None,
+            compile_stderr: None,
+            compile_failed: false,
+            post_run_output: None,
+            image: "sandbox-python:latest".into(),
+            image_digest: None,
+            combined_output: None,
+        }
+    }
+
+    fn report() -> TestReport {
+        TestReport::new(
+            "sum".into(),
+            vec![
+                CaseReport::grade("small".into(), Some("3"), &result("3\n", 0, 120)),
+                CaseReport::grade("large".into(), Some("<3>"), &result("4\n", 0, 380)),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_report_counts_pass_and_fail() {
+        let report = report();
+
+        assert_eq!(report.tests, 2);
+        assert_eq!(report.failures, 1);
+        assert_eq!(report.duration_ms, 500);
+        assert!(report.cases[0].passed);
+        assert!(report.cases[0].message.is_none());
+        assert!(!report.cases[1].passed);
+        assert!(report.cases[1].message.as_ref().unwrap().contains("Expected output"));
+
+        let failed_exit = CaseReport::grade("crash".into(), None, &result("", 1, 10));
+        assert_eq!(failed_exit.message.as_deref(), Some("Exited with code 1"));
+
+        let compile_error = ExecutionResult {
+            compile_failed: true,
+            compile_stderr: Some("error: expected `;`".into()),
+            ..result("", 1, 10)
+        };
+        let failed_compile = CaseReport::grade("typo".into(), None, &compile_error);
+        assert_eq!(failed_compile.message.as_deref(), Some("Compilation failed"));
+    }
+
+    #[test]
+    fn test_junit_xml_structure() {
+        let xml = report().to_junit_xml();
+
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        assert!(xml.contains("<testsuite name=\"sum\" tests=\"2\" failures=\"1\" time=\"0.500\">"));
+        assert!(xml.contains("<testcase name=\"small\" classname=\"sum\" time=\"0.120\"/>"));
+        assert!(xml.contains("<testcase name=\"large\" classname=\"sum\" time=\"0.380\">"));
+        assert!(xml.contains("<failure message=\"Expected output &quot;&lt;3&gt;&quot;, got &quot;4&quot;\"/>"));
+        assert!(xml.contains("<system-out>4\n</system-out>"));
+        assert!(xml.trim_end().ends_with("</testsuite>"));
+    }
+}
@@ -0,0 +1,60 @@
+//! Deterministic seeding of sandboxed runs.
+//!
+//! A seed fixes what can be fixed from outside the program:
+//!
+//! - Every language gets `RUSTYCLINT_SEED`, for programs that seed their
+//!   own generators.
+//! - Python also gets `PYTHONHASHSEED`, and its `random` module is seeded
+//!   before the program starts.
+//! - Ruby's `Kernel#rand` is seeded before the program starts.
+//!
+//! Other languages only see `RUSTYCLINT_SEED`. Full determinism is not
+//! guaranteed: time, thread scheduling, addresses and unseeded generators
+//! still vary between runs.
+
+use rustyclint_common::models::Language;
+
+/// Environment variable carrying the seed into every run.
+pub const SEED_ENV_VAR: &str = "RUSTYCLINT_SEED";
+
+/// Environment (`KEY=value`) that applies a seed for a language.
+pub fn seed_env(language: Language, seed: u64) -> Vec<String> {
+    let mut env = vec![format!("{}={}", SEED_ENV_VAR, seed)];
+    if language == Language::Python {
+        // PYTHONHASHSEED only accepts 0..=4294967295
+        env.push(format!("PYTHONHASHSEED={}", seed % (1 << 32)));
+    }
+    env
+}
+
+/// Command that seeds the standard library RNG and then runs `filename`,
+/// for languages where such a wrapper is possible.
+///
+/// The wrapper reads the seed from [`SEED_ENV_VAR`] and leaves the
+/// program's name and arguments as they would be without it.
+pub fn seeded_command(language: Language, filename: &str) -> Option<Vec<String>> {
+    let (interpreter, flag, script) = match language {
+        Language::Python => (
+            "python3",
+            "-c",
+            format!(
+                "import os, random, runpy, sys; random.seed(int(os.environ['{var}'])); \
+                 sys.argv[0] = '{file}'; runpy.run_path('{file}', run_name='__main__')",
+                var = SEED_ENV_VAR,
+                file = filename,
+            ),
+        ),
+        Language::Ruby => (
+            "ruby",
+            "-e",
+            format!(
+                "srand(Integer(ENV['{var}'])); $0 = '{file}'; load '{file}'",
+                var = SEED_ENV_VAR,
+                file = filename,
+            ),
+        ),
+        _ => return None,
+    };
+
+    Some(vec![interpreter.to_string(), flag.to_string(), script])
+}
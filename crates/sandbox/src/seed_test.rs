@@ -0,0 +1,39 @@
+//! Tests for deterministic seeding.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+
+    use crate::seed::{seed_env, seeded_command, SEED_ENV_VAR};
+
+    #[test]
+    fn test_python_hash_seed_set() {
+        let env = seed_env(Language::Python, 42);
+
+        assert!(env.contains(&"PYTHONHASHSEED=42".to_string()));
+        assert!(env.contains(&format!("{}=42", SEED_ENV_VAR)));
+
+        // Out-of-range seeds are folded into what Python accepts
+        let env = seed_env(Language::Python, u64::MAX);
+        assert!(env.contains(&"PYTHONHASHSEED=4294967295".to_string()));
+    }
+
+    #[test]
+    fn test_other_languages_only_get_generic_seed() {
+        assert_eq!(seed_env(Language::Go, 7), vec![format!("{}=7", SEED_ENV_VAR)]);
+    }
+
+    #[test]
+    fn test_rng_wrapper_where_feasible() {
+        let cmd = seeded_command(Language::Python, "main.py").unwrap();
+        assert_eq!(cmd[..2], ["python3", "-c"]);
+        assert!(cmd[2].contains("random.seed"));
+        assert!(cmd[2].contains("run_path('main.py'"));
+
+        let cmd = seeded_command(Language::Ruby, "main.rb").unwrap();
+        assert_eq!(cmd[..2], ["ruby", "-e"]);
+        assert!(cmd[2].contains("srand"));
+
+        assert!(seeded_command(Language::Rust, "main.rs").is_none());
+    }
+}
@@ -0,0 +1,173 @@
+//! Sandbox session tracking and expiry.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use rustyclint_common::models::{Language, SandboxSession};
+use serde::Serialize;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Why a session was ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryReason {
+    /// No activity within the idle timeout.
+    Idle,
+    /// The session reached its absolute maximum lifetime.
+    MaxLifetime,
+    /// The session was stopped explicitly.
+    Stopped,
+}
+
+impl ExpiryReason {
+    /// Human-readable message for clients attached to the session.
+    pub fn message(&self) -> &'static str {
+        match self {
+            ExpiryReason::Idle => "Session expired due to inactivity",
+            ExpiryReason::MaxLifetime => "Session reached its maximum lifetime",
+            ExpiryReason::Stopped => "Session was stopped",
+        }
+    }
+}
+
+/// Expiry policy applied by the session reaper.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionPolicy {
+    /// Sessions without activity for this long are reaped.
+    pub idle_timeout: Duration,
+    /// Sessions older than this are reaped regardless of activity.
+    pub max_lifetime: Duration,
+}
+
+struct TrackedSession {
+    session: SandboxSession,
+    last_active: DateTime<Utc>,
+    expired: watch::Sender<Option<ExpiryReason>>,
+}
+
+/// Tracks live sandbox sessions and expires them.
+pub struct SessionRegistry {
+    policy: SessionPolicy,
+    sessions: Mutex<HashMap<Uuid, TrackedSession>>,
+}
+
+impl SessionRegistry {
+    /// Create a registry with the given expiry policy.
+    pub fn new(policy: SessionPolicy) -> Self {
+        Self {
+            policy,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register a new session for a running container.
+    pub fn create(
+        &self,
+        user_id: Uuid,
+        project_id: Uuid,
+        container_id: String,
+        language: Language,
+        now: DateTime<Utc>,
+    ) -> SandboxSession {
+        let session = SandboxSession {
+            id: Uuid::new_v4(),
+            user_id,
+            project_id,
+            container_id,
+            language,
+            created_at: now,
+            expires_at: now + self.policy.max_lifetime,
+        };
+
+        self.restore(session.clone(), now);
+        session
+    }
+
+    /// Track a session created earlier, e.g. one recorded before a restart.
+    /// Its idle time counts from `now`; its lifetime still ends at
+    /// `expires_at`.
+    pub fn restore(&self, session: SandboxSession, now: DateTime<Utc>) {
+        let (expired, _) = watch::channel(None);
+        self.sessions.lock().unwrap().insert(
+            session.id,
+            TrackedSession {
+                session,
+                last_active: now,
+                expired,
+            },
+        );
+    }
+
+    /// Record activity on a session.
+    pub fn touch(&self, id: Uuid, now: DateTime<Utc>) {
+        if let Some(tracked) = self.sessions.lock().unwrap().get_mut(&id) {
+            tracked.last_active = now;
+        }
+    }
+
+    /// Get a live session.
+    pub fn get(&self, id: Uuid) -> Option<SandboxSession> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|tracked| tracked.session.clone())
+    }
+
+    /// List live sessions owned by a user.
+    pub fn list_for_user(&self, user_id: Uuid) -> Vec<SandboxSession> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|tracked| tracked.session.user_id == user_id)
+            .map(|tracked| tracked.session.clone())
+            .collect()
+    }
+
+    /// Watch a session for expiry. Resolves to the reason once it ends.
+    pub fn watch(&self, id: Uuid) -> Option<watch::Receiver<Option<ExpiryReason>>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|tracked| tracked.expired.subscribe())
+    }
+
+    /// End a session, notifying anything attached to it.
+    pub fn remove(&self, id: Uuid, reason: ExpiryReason) -> Option<SandboxSession> {
+        let tracked = self.sessions.lock().unwrap().remove(&id)?;
+        let _ = tracked.expired.send(Some(reason));
+        Some(tracked.session)
+    }
+
+    /// Expire idle sessions and sessions past their absolute lifetime.
+    ///
+    /// The lifetime cap applies even to sessions with recent activity.
+    pub fn reap(&self, now: DateTime<Utc>) -> Vec<(SandboxSession, ExpiryReason)> {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let expired: Vec<(Uuid, ExpiryReason)> = sessions
+            .values()
+            .filter_map(|tracked| {
+                if now >= tracked.session.expires_at {
+                    Some((tracked.session.id, ExpiryReason::MaxLifetime))
+                } else if now - tracked.last_active >= self.policy.idle_timeout {
+                    Some((tracked.session.id, ExpiryReason::Idle))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|(id, reason)| {
+                let tracked = sessions.remove(&id)?;
+                let _ = tracked.expired.send(Some(reason));
+                Some((tracked.session, reason))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,84 @@
+//! Tests for sandbox session expiry.
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use rustyclint_common::models::Language;
+    use uuid::Uuid;
+
+    use crate::session::{ExpiryReason, SessionPolicy, SessionRegistry};
+
+    fn registry() -> SessionRegistry {
+        SessionRegistry::new(SessionPolicy {
+            idle_timeout: Duration::seconds(300),
+            max_lifetime: Duration::seconds(3600),
+        })
+    }
+
+    #[test]
+    fn test_idle_session_reaped() {
+        let registry = registry();
+        let start = Utc::now();
+        let session = registry.create(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "container".into(),
+            Language::Python,
+            start,
+        );
+
+        assert!(registry.reap(start + Duration::seconds(299)).is_empty());
+
+        let reaped = registry.reap(start + Duration::seconds(300));
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].0.id, session.id);
+        assert_eq!(reaped[0].1, ExpiryReason::Idle);
+    }
+
+    #[test]
+    fn test_session_past_lifetime_reaped_even_if_active() {
+        let registry = registry();
+        let start = Utc::now();
+        let session = registry.create(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "container".into(),
+            Language::Rust,
+            start,
+        );
+        let watcher = registry.watch(session.id).unwrap();
+
+        // Keep the session busy right up to the cap
+        registry.touch(session.id, start + Duration::seconds(3590));
+
+        let reaped = registry.reap(start + Duration::seconds(3600));
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].1, ExpiryReason::MaxLifetime);
+        assert!(registry.get(session.id).is_none());
+        assert_eq!(*watcher.borrow(), Some(ExpiryReason::MaxLifetime));
+    }
+
+    #[test]
+    fn test_restored_session_keeps_its_lifetime() {
+        let start = Utc::now();
+        let session = registry().create(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "container".into(),
+            Language::Go,
+            start,
+        );
+
+        // A fresh registry, as after a restart
+        let registry = registry();
+        let restarted = start + Duration::seconds(3000);
+        registry.restore(session.clone(), restarted);
+
+        assert_eq!(registry.get(session.id).unwrap().container_id, "container");
+        assert!(registry.reap(restarted + Duration::seconds(299)).is_empty());
+
+        let reaped = registry.reap(start + Duration::seconds(3600));
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].1, ExpiryReason::MaxLifetime);
+    }
+}
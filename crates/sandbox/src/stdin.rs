@@ -0,0 +1,57 @@
+//! Decoding and normalization of program input.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+/// How a request's `stdin` string encodes the program's input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StdinEncoding {
+    /// The string is the input, as UTF-8.
+    #[default]
+    Text,
+    /// The string is base64 of arbitrary bytes, for binary input.
+    Base64,
+}
+
+/// Line-ending handling applied to program input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    /// Pass input through unchanged.
+    #[default]
+    Preserve,
+    /// Convert CRLF to LF, for input pasted from Windows clients.
+    Lf,
+}
+
+/// Bytes to feed a program for `stdin` in the given encoding.
+pub fn decode_stdin(
+    stdin: &str,
+    encoding: StdinEncoding,
+    line_endings: LineEndings,
+) -> Result<Vec<u8>, String> {
+    let bytes = match encoding {
+        StdinEncoding::Text => stdin.as_bytes().to_vec(),
+        StdinEncoding::Base64 => STANDARD
+            .decode(stdin.trim())
+            .map_err(|e| format!("Invalid base64 stdin: {}", e))?,
+    };
+
+    Ok(match line_endings {
+        LineEndings::Preserve => bytes,
+        LineEndings::Lf => crlf_to_lf(&bytes),
+    })
+}
+
+fn crlf_to_lf(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        if byte == b'\r' && iter.peek() == Some(&&b'\n') {
+            continue;
+        }
+        out.push(byte);
+    }
+    out
+}
@@ -0,0 +1,36 @@
+//! Tests for program input decoding.
+
+#[cfg(test)]
+mod tests {
+    use crate::stdin::{decode_stdin, LineEndings, StdinEncoding};
+
+    #[test]
+    fn test_crlf_normalized_on_request() {
+        let input = "3\r\n1 2 3\r\nlone\rcarriage\n";
+
+        assert_eq!(
+            decode_stdin(input, StdinEncoding::Text, LineEndings::Lf).unwrap(),
+            b"3\n1 2 3\nlone\rcarriage\n"
+        );
+        assert_eq!(
+            decode_stdin(input, StdinEncoding::Text, LineEndings::Preserve).unwrap(),
+            input.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_binary_stdin_intact() {
+        // NUL, high bytes and a CRLF pair that must survive untouched
+        let bytes = decode_stdin("AP8NCoB/", StdinEncoding::Base64, LineEndings::Preserve).unwrap();
+
+        assert_eq!(bytes, [0x00, 0xff, b'\r', b'\n', 0x80, 0x7f]);
+    }
+
+    #[test]
+    fn test_invalid_base64_rejected() {
+        let error = decode_stdin("not base64!", StdinEncoding::Base64, LineEndings::Preserve)
+            .unwrap_err();
+
+        assert!(error.contains("base64"));
+    }
+}
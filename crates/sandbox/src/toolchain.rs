@@ -0,0 +1,89 @@
+//! Toolchain and environment probing for sandbox images.
+
+use std::collections::BTreeMap;
+
+use rustyclint_common::models::Language;
+use serde::{Deserialize, Serialize};
+
+/// Versions of the OS and tools available in a language's sandbox image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolchainInfo {
+    pub language: Language,
+    pub image: String,
+    pub image_digest: Option<String>,
+    pub os: Option<String>,
+    /// Tool name to the first line of its version output.
+    pub tools: BTreeMap<String, String>,
+}
+
+/// Tools whose versions are reported for a language.
+fn probed_tools(language: Language) -> &'static [(&'static str, &'static str)] {
+    match language {
+        Language::Rust => &[("rustc", "rustc --version"), ("cargo", "cargo --version")],
+        Language::Python => &[("python3", "python3 --version"), ("pip3", "pip3 --version")],
+        Language::JavaScript => &[("node", "node --version"), ("npm", "npm --version")],
+        Language::TypeScript => &[
+            ("node", "node --version"),
+            ("ts-node", "npx ts-node --version"),
+        ],
+        Language::Go => &[("go", "go version")],
+        Language::Java => &[("java", "java -version"), ("javac", "javac -version")],
+        Language::CSharp => &[("dotnet", "dotnet --version")],
+        Language::Cpp => &[("g++", "g++ --version")],
+        Language::C => &[("gcc", "gcc --version")],
+        Language::Ruby => &[("ruby", "ruby --version")],
+        Language::Php => &[("php", "php --version")],
+        Language::Swift => &[("swift", "swift --version")],
+        Language::Kotlin => &[("kotlinc", "kotlinc -version"), ("java", "java -version")],
+    }
+}
+
+/// Shell command printing one `name: version` line per probed tool.
+pub fn probe_command(language: Language) -> Vec<String> {
+    let mut script = String::from("echo \"os: $(uname -a)\"");
+    for (name, command) in probed_tools(language) {
+        script.push_str(&format!(
+            "; echo \"{}: $({} 2>&1 | head -n 1)\"",
+            name, command
+        ));
+    }
+
+    vec!["sh".to_string(), "-c".to_string(), script]
+}
+
+/// Parse the output of [`probe_command`].
+///
+/// Tools that are missing or print nothing are left out.
+pub fn parse_probe_output(
+    language: Language,
+    image: String,
+    image_digest: Option<String>,
+    output: &str,
+) -> ToolchainInfo {
+    let mut os = None;
+    let mut tools = BTreeMap::new();
+
+    for line in output.lines() {
+        let Some((name, version)) = line.split_once(": ") else {
+            continue;
+        };
+        let version = version.trim();
+        if version.is_empty() || version.contains("not found") {
+            continue;
+        }
+
+        if name == "os" {
+            os = Some(version.to_string());
+        } else {
+            tools.insert(name.to_string(), version.to_string());
+        }
+    }
+
+    ToolchainInfo {
+        language,
+        image,
+        image_digest,
+        os,
+        tools,
+    }
+}
@@ -0,0 +1,50 @@
+//! Tests for toolchain probing.
+
+#[cfg(test)]
+mod tests {
+    use rustyclint_common::models::Language;
+
+    use crate::{
+        executor::SandboxExecutor,
+        toolchain::{parse_probe_output, probe_command},
+    };
+
+    #[test]
+    fn test_probe_command_covers_language_tools() {
+        let command = probe_command(Language::Python);
+
+        assert_eq!(command[0], "sh");
+        assert!(command[2].contains("uname -a"));
+        assert!(command[2].contains("python3 --version"));
+    }
+
+    #[test]
+    fn test_python_versions_parsed() {
+        let output = "os: Linux 2f9c 6.1.0 #1 SMP x86_64 GNU/Linux\n\
+                      python3: Python 3.12.4\n\
+                      pip3: sh: 1: pip3: not found\n";
+
+        let info = parse_probe_output(
+            Language::Python,
+            "sandbox-python:latest".into(),
+            Some("sha256:abc".into()),
+            output,
+        );
+
+        assert_eq!(info.os.as_deref(), Some("Linux 2f9c 6.1.0 #1 SMP x86_64 GNU/Linux"));
+        assert_eq!(info.tools.get("python3").map(String::as_str), Some("Python 3.12.4"));
+        assert!(!info.tools.contains_key("pip3"));
+        assert_eq!(info.image_digest.as_deref(), Some("sha256:abc"));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires Docker
+    async fn test_probe_environment_reports_python_version() {
+        let executor = SandboxExecutor::new().unwrap();
+
+        let info = executor.probe_environment(Language::Python).await.unwrap();
+
+        assert!(info.tools["python3"].starts_with("Python 3"));
+        assert!(info.os.is_some());
+    }
+}
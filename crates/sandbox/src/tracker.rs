@@ -0,0 +1,203 @@
+//! Tracking of in-flight executions by user.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::output::{OutputTail, StdStream};
+
+#[derive(Default)]
+struct Entry {
+    cancelled: bool,
+    attached: bool,
+    container_id: Option<String>,
+}
+
+struct Shared {
+    entry: Mutex<Entry>,
+    notify: Notify,
+    tail: Mutex<OutputTail>,
+}
+
+/// Executions keyed by user, then by execution id.
+type Registry = HashMap<Uuid, HashMap<Uuid, Arc<Shared>>>;
+
+/// Running executions grouped by user, so all of a user's work can be
+/// cancelled at once.
+///
+/// Cloning shares the underlying registry.
+#[derive(Clone, Default)]
+pub struct ExecutionTracker {
+    running: Arc<Mutex<Registry>>,
+}
+
+impl ExecutionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an execution for a user. It is deregistered when the
+    /// returned handle is dropped.
+    pub fn register(&self, user_id: Uuid) -> ExecutionHandle {
+        let id = Uuid::new_v4();
+        let shared = Arc::new(Shared {
+            entry: Mutex::new(Entry::default()),
+            notify: Notify::new(),
+            tail: Mutex::new(OutputTail::default()),
+        });
+
+        self.running
+            .lock()
+            .unwrap()
+            .entry(user_id)
+            .or_default()
+            .insert(id, shared.clone());
+
+        ExecutionHandle {
+            tracker: self.clone(),
+            user_id,
+            id,
+            shared,
+        }
+    }
+
+    /// Cancel every execution the user has in flight.
+    ///
+    /// The returned containers must be removed by the caller. Executions
+    /// that finish concurrently are either already deregistered or still
+    /// reported; removing an already removed container is harmless, so
+    /// calling this repeatedly is safe.
+    pub fn cancel_user(&self, user_id: Uuid) -> CancelledExecutions {
+        let executions = self.running.lock().unwrap().remove(&user_id);
+
+        let mut cancelled = CancelledExecutions::default();
+        for shared in executions.into_iter().flat_map(HashMap::into_values) {
+            let container_id = {
+                let mut entry = shared.entry.lock().unwrap();
+                entry.cancelled = true;
+                entry.container_id.take()
+            };
+            shared.notify.notify_one();
+            cancelled.executions += 1;
+            cancelled.container_ids.extend(container_id);
+        }
+        cancelled
+    }
+
+    /// The last `lines` lines a user's running execution has printed.
+    ///
+    /// Returns `None` once the execution has finished, or if it belongs to
+    /// another user.
+    pub fn tail(&self, user_id: Uuid, execution_id: Uuid, lines: usize) -> Option<Vec<String>> {
+        let shared = self
+            .running
+            .lock()
+            .unwrap()
+            .get(&user_id)?
+            .get(&execution_id)?
+            .clone();
+        let tail = shared.tail.lock().unwrap().last(lines);
+        Some(tail)
+    }
+
+    /// Number of executions a user has in flight.
+    pub fn running_for_user(&self, user_id: Uuid) -> usize {
+        self.running
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map_or(0, HashMap::len)
+    }
+}
+
+/// Outcome of [`ExecutionTracker::cancel_user`].
+#[derive(Debug, Default)]
+pub struct CancelledExecutions {
+    /// Number of executions cancelled.
+    pub executions: usize,
+    /// Containers the cancelled executions had started.
+    pub container_ids: Vec<String>,
+}
+
+/// A registered execution.
+pub struct ExecutionHandle {
+    tracker: ExecutionTracker,
+    user_id: Uuid,
+    id: Uuid,
+    shared: Arc<Shared>,
+}
+
+impl ExecutionHandle {
+    /// Id the execution is registered under.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Keep a chunk of output in the execution's tail.
+    pub fn record_output(&self, stream: StdStream, chunk: &[u8]) {
+        self.shared.tail.lock().unwrap().push(stream, chunk);
+    }
+
+    /// Record the container the execution runs in.
+    ///
+    /// Returns false if the execution was cancelled first; the caller then
+    /// owns the container and must remove it itself.
+    pub fn attach_container(&self, container_id: &str) -> bool {
+        let mut entry = self.shared.entry.lock().unwrap();
+        if entry.cancelled {
+            return false;
+        }
+        entry.attached = true;
+        entry.container_id = Some(container_id.to_string());
+        true
+    }
+
+    /// Take the container back from the tracker, e.g. to reuse it.
+    ///
+    /// Returns false if the execution was cancelled; the container is then
+    /// being removed by whoever cancelled it.
+    pub fn detach_container(&self) -> bool {
+        let mut entry = self.shared.entry.lock().unwrap();
+        if entry.cancelled {
+            return false;
+        }
+        entry.container_id = None;
+        true
+    }
+
+    /// Whether a container was attached before any cancellation.
+    pub fn has_container(&self) -> bool {
+        self.shared.entry.lock().unwrap().attached
+    }
+
+    /// Whether the execution has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.entry.lock().unwrap().cancelled
+    }
+
+    /// Resolve once the execution is cancelled.
+    pub async fn cancelled(&self) {
+        if self.is_cancelled() {
+            return;
+        }
+        // notify_one stores a permit, so a cancel between the check and the
+        // await is not missed
+        self.shared.notify.notified().await;
+    }
+}
+
+impl Drop for ExecutionHandle {
+    fn drop(&mut self) {
+        let mut running = self.tracker.running.lock().unwrap();
+        if let Some(executions) = running.get_mut(&self.user_id) {
+            executions.remove(&self.id);
+            if executions.is_empty() {
+                running.remove(&self.user_id);
+            }
+        }
+    }
+}
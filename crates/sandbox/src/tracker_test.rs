@@ -0,0 +1,94 @@
+//! Tests for execution tracking.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use crate::{output::StdStream, tracker::ExecutionTracker};
+
+    #[tokio::test]
+    async fn test_cancel_user_cancels_all_in_flight() {
+        let tracker = ExecutionTracker::new();
+        let user = Uuid::new_v4();
+        let other = Uuid::new_v4();
+
+        let first = tracker.register(user);
+        let second = tracker.register(user);
+        let pending = tracker.register(user);
+        let unrelated = tracker.register(other);
+        assert!(first.attach_container("c1"));
+        assert!(second.attach_container("c2"));
+        assert!(unrelated.attach_container("c3"));
+        assert_eq!(tracker.running_for_user(user), 3);
+
+        let cancelled = tracker.cancel_user(user);
+        assert_eq!(cancelled.executions, 3);
+        let mut container_ids = cancelled.container_ids;
+        container_ids.sort();
+        assert_eq!(container_ids, vec!["c1", "c2"]);
+
+        for handle in [&first, &second, &pending] {
+            assert!(handle.is_cancelled());
+            tokio::time::timeout(Duration::from_secs(1), handle.cancelled())
+                .await
+                .expect("cancellation was not signalled");
+        }
+        assert!(first.has_container());
+        assert!(!pending.has_container());
+        assert!(!unrelated.is_cancelled());
+        assert_eq!(tracker.running_for_user(other), 1);
+
+        // A container started after the cancel belongs to the execution
+        assert!(!pending.attach_container("c4"));
+
+        // Cancelling again finds nothing left to do
+        let again = tracker.cancel_user(user);
+        assert_eq!(again.executions, 0);
+        assert!(again.container_ids.is_empty());
+    }
+
+    #[test]
+    fn test_dropped_handle_deregisters() {
+        let tracker = ExecutionTracker::new();
+        let user = Uuid::new_v4();
+
+        let handle = tracker.register(user);
+        assert_eq!(tracker.running_for_user(user), 1);
+        drop(handle);
+
+        assert_eq!(tracker.running_for_user(user), 0);
+        assert_eq!(tracker.cancel_user(user).executions, 0);
+    }
+
+    #[test]
+    fn test_detached_container_not_reported() {
+        let tracker = ExecutionTracker::new();
+        let user = Uuid::new_v4();
+
+        let finished = tracker.register(user);
+        assert!(finished.attach_container("c1"));
+        assert!(finished.detach_container());
+        // Detached containers are no longer the canceller's to remove
+        let cancelled = tracker.register(user);
+        assert!(cancelled.attach_container("c2"));
+        assert_eq!(tracker.cancel_user(user).container_ids, vec!["c2"]);
+        assert!(!cancelled.detach_container());
+    }
+
+    #[test]
+    fn test_tail_visible_to_owner_while_running() {
+        let tracker = ExecutionTracker::new();
+        let user = Uuid::new_v4();
+
+        let handle = tracker.register(user);
+        handle.record_output(StdStream::Stdout, b"one\ntwo\nthree\n");
+        assert_eq!(tracker.tail(user, handle.id(), 2).unwrap(), ["two", "three"]);
+        assert!(tracker.tail(Uuid::new_v4(), handle.id(), 2).is_none());
+
+        let id = handle.id();
+        drop(handle);
+        assert!(tracker.tail(user, id, 2).is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! Incremental UTF-8 decoding of chunked program output.
+
+/// Decodes a byte stream that may split multi-byte characters across
+/// chunks.
+///
+/// Bytes of an incomplete trailing character are held back until the next
+/// chunk completes them, so only whole characters are emitted. Invalid
+/// sequences are replaced with U+FFFD, as `String::from_utf8_lossy` would.
+#[derive(Debug, Default)]
+pub struct Utf8StreamDecoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode a chunk, returning every character it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> String {
+        self.pending.extend_from_slice(chunk);
+
+        let mut output = String::with_capacity(self.pending.len());
+        let mut consumed = 0;
+        loop {
+            match std::str::from_utf8(&self.pending[consumed..]) {
+                Ok(text) => {
+                    output.push_str(text);
+                    consumed = self.pending.len();
+                    break;
+                }
+                Err(e) => {
+                    let valid = consumed + e.valid_up_to();
+                    // Bytes up to `valid_up_to` are known to be valid
+                    output.push_str(std::str::from_utf8(&self.pending[consumed..valid]).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            output.push(char::REPLACEMENT_CHARACTER);
+                            consumed = valid + len;
+                        }
+                        // Incomplete character at the end; wait for more bytes
+                        None => {
+                            consumed = valid;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending.drain(..consumed);
+        output
+    }
+
+    /// Flush the end of the stream; a character left incomplete becomes U+FFFD.
+    pub fn finish(&mut self) -> String {
+        let output = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        output
+    }
+}
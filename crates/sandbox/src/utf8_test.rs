@@ -0,0 +1,39 @@
+//! Tests for incremental UTF-8 decoding.
+
+#[cfg(test)]
+mod tests {
+    use crate::utf8::Utf8StreamDecoder;
+
+    #[test]
+    fn test_character_split_across_chunks() {
+        let bytes = "héllo → 世界".as_bytes();
+        // Split inside the three-byte arrow
+        let split = "héllo ".len() + 1;
+
+        let mut decoder = Utf8StreamDecoder::new();
+        let first = decoder.push(&bytes[..split]);
+        assert_eq!(first, "héllo ");
+
+        let second = decoder.push(&bytes[split..]);
+        assert_eq!(second, "→ 世界");
+        assert_eq!(decoder.finish(), "");
+    }
+
+    #[test]
+    fn test_one_byte_at_a_time() {
+        let text = "naïve café 🦀";
+        let mut decoder = Utf8StreamDecoder::new();
+        let decoded: String = text.bytes().map(|b| decoder.push(&[b])).collect();
+        assert_eq!(decoded, text);
+    }
+
+    #[test]
+    fn test_invalid_and_truncated_bytes_replaced() {
+        let mut decoder = Utf8StreamDecoder::new();
+        assert_eq!(decoder.push(b"a\xffb"), "a\u{FFFD}b");
+
+        // A character cut off by the end of the stream
+        assert_eq!(decoder.push(&"é".as_bytes()[..1]), "");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+    }
+}
@@ -8,6 +8,22 @@ import { useAuthStore } from '../stores/auth'
 import { MONACO_LANGUAGES } from '../types'
 import type { Language } from '../types'
 
+/**
+ * WebSocket that authenticates before anything else is sent: the
+ * collaboration server expects `Auth` as the first message.
+ */
+function authenticatedWebSocket(token: string | null): typeof WebSocket {
+  return class extends WebSocket {
+    constructor(url: string | URL, protocols?: string | string[]) {
+      super(url, protocols)
+      // Registered before y-websocket's onopen, so it is sent first
+      this.addEventListener('open', () => {
+        this.send(JSON.stringify({ type: 'Auth', token }))
+      })
+    }
+  }
+}
+
 interface CodeEditorProps {
   fileId: string
   language: Language
@@ -25,7 +41,7 @@ export default function CodeEditor({
   const [ydoc] = useState(() => new Y.Doc())
   const [provider, setProvider] = useState<WebsocketProvider | null>(null)
   const [binding, setBinding] = useState<MonacoBinding | null>(null)
-  const { user } = useAuthStore()
+  const { user, token } = useAuthStore()
   const { setCollaborators, addCollaborator, removeCollaborator, updateCollaboratorCursor } =
     useEditorStore()
 
@@ -45,7 +61,8 @@ export default function CodeEditor({
     const wsProvider = new WebsocketProvider(
       `${wsBaseUrl}/collab`,
       fileId,
-      ydoc
+      ydoc,
+      { WebSocketPolyfill: authenticatedWebSocket(token) }
     )
 
     // Set user awareness info
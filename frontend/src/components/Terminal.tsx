@@ -1,6 +1,7 @@
 import { useEffect, useRef, useState } from 'react'
 import { X, Maximize2, Minimize2 } from 'lucide-react'
 import clsx from 'clsx'
+import { useAuthStore } from '../stores/auth'
 
 interface TerminalProps {
   sessionId: string
@@ -15,6 +16,7 @@ export default function Terminal({ sessionId, onClose }: TerminalProps) {
   const wsRef = useRef<WebSocket | null>(null)
   const terminalRef = useRef<HTMLDivElement>(null)
   const inputRef = useRef<HTMLInputElement>(null)
+  const { token } = useAuthStore()
 
   useEffect(() => {
     // Connect to terminal WebSocket
@@ -23,6 +25,8 @@ export default function Terminal({ sessionId, onClose }: TerminalProps) {
     wsRef.current = ws
 
     ws.onopen = () => {
+      // The server expects Auth before anything else
+      ws.send(JSON.stringify({ type: 'Auth', token }))
       setIsConnected(true)
       setLines((prev) => [...prev, '$ Terminal connected'])
     }
@@ -44,7 +48,7 @@ export default function Terminal({ sessionId, onClose }: TerminalProps) {
     return () => {
       ws.close()
     }
-  }, [sessionId])
+  }, [sessionId, token])
 
   // Auto-scroll to bottom
   useEffect(() => {
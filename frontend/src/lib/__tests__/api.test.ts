@@ -1,5 +1,5 @@
 import { describe, it, expect, beforeEach } from 'vitest'
-import { login, register, listProjects, createProject, runCode } from '../api'
+import { login, register, refreshAccessToken, listProjects, createProject, runCode } from '../api'
 
 describe('API client', () => {
   beforeEach(() => {
@@ -26,6 +26,12 @@ describe('API client', () => {
       expect(result.user.email).toBe('new@example.com')
       expect(result.user.username).toBe('newuser')
     })
+
+    it('refresh token renews the access token', async () => {
+      const { refresh_token } = await login('test@example.com', 'password123')
+
+      expect(await refreshAccessToken(refresh_token)).toBe('mock-token')
+    })
   })
 
   describe('projects', () => {
@@ -1,5 +1,5 @@
 import axios from 'axios'
-import type { User, Project, File, ExecutionResult, Language } from '../types'
+import type { User, Project, ProjectPage, File, ExecutionResult, Language } from '../types'
 
 const api = axios.create({
   baseURL: import.meta.env.VITE_API_URL || '/api/v1',
@@ -30,8 +30,14 @@ api.interceptors.response.use(
 )
 
 // Auth
+interface AuthResponse {
+  token: string
+  refresh_token: string
+  user: User
+}
+
 export async function register(email: string, username: string, password: string) {
-  const { data } = await api.post<{ token: string; user: User }>('/auth/register', {
+  const { data } = await api.post<AuthResponse>('/auth/register', {
     email,
     username,
     password,
@@ -40,13 +46,24 @@ export async function register(email: string, username: string, password: string
 }
 
 export async function login(email: string, password: string) {
-  const { data } = await api.post<{ token: string; user: User }>('/auth/login', {
+  const { data } = await api.post<AuthResponse>('/auth/login', {
     email,
     password,
   })
   return data
 }
 
+export async function refreshAccessToken(refreshToken: string) {
+  const { data } = await api.post<{ token: string }>('/auth/refresh', {
+    refresh_token: refreshToken,
+  })
+  return data.token
+}
+
+export async function logout(refreshToken: string) {
+  await api.post('/auth/logout', { refresh_token: refreshToken })
+}
+
 export async function getMe() {
   const { data } = await api.get<User>('/auth/me')
   return data
@@ -54,8 +71,8 @@ export async function getMe() {
 
 // Projects
 export async function listProjects() {
-  const { data } = await api.get<Project[]>('/projects')
-  return data
+  const { data } = await api.get<ProjectPage>('/projects')
+  return data.projects
 }
 
 export async function createProject(name: string, default_language: Language) {
@@ -6,6 +6,7 @@ export const handlers = [
     const body = await request.json() as any
     return HttpResponse.json({
       token: 'mock-token',
+      refresh_token: 'mock-refresh-token',
       user: {
         id: '123',
         email: body.email,
@@ -19,6 +20,7 @@ export const handlers = [
     if (body.email === 'test@example.com' && body.password === 'password123') {
       return HttpResponse.json({
         token: 'mock-token',
+        refresh_token: 'mock-refresh-token',
         user: {
           id: '123',
           email: body.email,
@@ -29,6 +31,18 @@ export const handlers = [
     return HttpResponse.json({ error: 'Invalid credentials' }, { status: 401 })
   }),
 
+  http.post('/api/v1/auth/refresh', async ({ request }) => {
+    const body = await request.json() as any
+    if (body.refresh_token === 'mock-refresh-token') {
+      return HttpResponse.json({ token: 'mock-token' })
+    }
+    return HttpResponse.json({ error: 'Invalid or expired refresh token' }, { status: 401 })
+  }),
+
+  http.post('/api/v1/auth/logout', () => {
+    return new HttpResponse(null, { status: 204 })
+  }),
+
   http.get('/api/v1/auth/me', () => {
     return HttpResponse.json({
       id: '123',
@@ -39,16 +53,21 @@ export const handlers = [
 
   // Projects handlers
   http.get('/api/v1/projects', () => {
-    return HttpResponse.json([
-      {
-        id: 'proj-1',
-        name: 'Test Project',
-        owner_id: '123',
-        default_language: 'python',
-        created_at: '2024-01-01T00:00:00Z',
-        updated_at: '2024-01-01T00:00:00Z',
-      },
-    ])
+    return HttpResponse.json({
+      projects: [
+        {
+          id: 'proj-1',
+          name: 'Test Project',
+          owner_id: '123',
+          default_language: 'python',
+          created_at: '2024-01-01T00:00:00Z',
+          updated_at: '2024-01-01T00:00:00Z',
+        },
+      ],
+      total_count: 1,
+      limit: 50,
+      offset: 0,
+    })
   }),
 
   http.post('/api/v1/projects', async ({ request }) => {
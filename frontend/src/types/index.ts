@@ -28,6 +28,13 @@ export interface Project {
   updated_at: string
 }
 
+export interface ProjectPage {
+  projects: Project[]
+  total_count: number
+  limit: number
+  offset: number
+}
+
 export interface File {
   id: string
   project_id: string
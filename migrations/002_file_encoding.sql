@@ -0,0 +1,2 @@
+-- Binary file support: binary content is stored base64-encoded
+ALTER TABLE files ADD COLUMN encoding VARCHAR(16) NOT NULL DEFAULT 'utf8';
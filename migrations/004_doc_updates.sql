@@ -0,0 +1,11 @@
+-- Append-only log of collaborative document updates, replayed to rebuild a
+-- document. Compaction replaces a prefix of the log with one snapshot entry.
+CREATE TABLE doc_updates (
+    id BIGSERIAL PRIMARY KEY,
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    data BYTEA NOT NULL,
+    is_snapshot BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_doc_updates_file ON doc_updates(file_id, id);
@@ -0,0 +1,3 @@
+-- Optional whitelist of file extensions a project may contain, stored
+-- lowercase without the leading dot. NULL allows any extension.
+ALTER TABLE projects ADD COLUMN allowed_extensions TEXT[];
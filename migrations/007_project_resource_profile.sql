@@ -0,0 +1,3 @@
+-- Named resource profile runs in a project use when they do not pick one.
+-- NULL falls back to the deployment default.
+ALTER TABLE projects ADD COLUMN resource_profile VARCHAR(64);
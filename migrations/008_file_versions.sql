@@ -0,0 +1,11 @@
+-- Earlier contents of files, recorded whenever an upsert replaces them.
+-- Versions are numbered from 1 per file; created_at is when that content
+-- was saved.
+CREATE TABLE file_versions (
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    content TEXT,
+    encoding VARCHAR(16) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (file_id, version)
+);
@@ -0,0 +1,9 @@
+-- Named snapshots of a collaborative document that users can restore.
+-- Saving a checkpoint under an existing name replaces it.
+CREATE TABLE doc_checkpoints (
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    state BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (file_id, name)
+);
@@ -0,0 +1,4 @@
+-- Per-project overrides of how many and how old earlier file versions are
+-- kept. NULL falls back to the deployment default; 0 lifts the limit.
+ALTER TABLE projects ADD COLUMN max_file_versions INTEGER;
+ALTER TABLE projects ADD COLUMN file_version_max_age_secs BIGINT;